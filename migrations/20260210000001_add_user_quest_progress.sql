@@ -0,0 +1,16 @@
+-- Adds per-user quest stage progress, used to check whether a stage can be skipped.
+
+drop table if exists user_quest_progress cascade;
+create table user_quest_progress
+(
+  user_id     bigint  not null references users (id) on delete restrict,
+  -- See [crate::user::quest_progress::QuestKind]
+  quest_kind  integer not null,
+  quest_id    bigint  not null,
+  clear_count integer not null default 0,
+  -- "Crowns", main mission and two sub missions
+  task1       boolean not null default false,
+  task2       boolean not null default false,
+  task3       boolean not null default false,
+  primary key (user_id, quest_kind, quest_id)
+);
@@ -0,0 +1,43 @@
+-- Adds per-user event state: points, single boss progress, boss counter rewards and event stage clears.
+
+drop table if exists user_events cascade;
+create table user_events
+(
+  user_id  bigint not null references users (id) on delete restrict,
+  event_id bigint not null,
+  points   bigint not null default 0,
+  primary key (user_id, event_id)
+);
+
+drop table if exists user_event_bosses cascade;
+create table user_event_bosses
+(
+  user_id        bigint  not null references users (id) on delete restrict,
+  event_id       bigint  not null,
+  quest_id       bigint  not null,
+  -- Damage dealt since the boss was last defeated, capped at the boss HP from [battle_enemy] master
+  damage         bigint  not null default 0,
+  kill_count     integer not null default 0,
+  -- Boss Ticket multiplier of the current attempt
+  ticket_ratio   integer not null default 1,
+  -- Set when a battle is started, each is cleared once by the battle log and the battle result
+  log_pending    boolean not null default false,
+  result_pending boolean not null default false,
+  primary key (user_id, event_id, quest_id)
+);
+
+drop table if exists user_event_boss_count_rewards cascade;
+create table user_event_boss_count_rewards
+(
+  user_id     bigint      not null references users (id) on delete restrict,
+  event_id    bigint      not null,
+  -- See [event_boss_count] master
+  count_id    bigint      not null,
+  received_at timestamptz not null default now(),
+  primary key (user_id, event_id, count_id)
+);
+
+-- Event stages are listed per event, zero for other quests
+alter table user_quest_progress
+  add column event_id bigint not null default 0;
+create index user_quest_progress_event_id_idx on user_quest_progress (user_id, event_id);
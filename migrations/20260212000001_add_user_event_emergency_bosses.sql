@@ -0,0 +1,17 @@
+-- Adds emergency bosses that appear for a single user after event stage clears.
+
+drop table if exists user_event_emergency_bosses cascade;
+create table user_event_emergency_bosses
+(
+  user_id           bigint      not null references users (id) on delete restrict,
+  event_id          bigint      not null,
+  -- See [event_emergency_boss] master
+  emergency_boss_id bigint      not null,
+  -- Emergency stage from [event_marathon_quest_stage_boss_single] master
+  quest_id          bigint      not null,
+  kill_count        integer     not null default 0,
+  spawned_at        timestamptz not null default now(),
+  -- Boss can be fought until then, defeating it expires the boss immediately
+  expires_at        timestamptz not null,
+  primary key (user_id, event_id, emergency_boss_id)
+);
@@ -0,0 +1,14 @@
+-- Adds per-user mission progress, see [mission] and [event_mission] masters.
+
+drop table if exists user_missions cascade;
+create table user_missions
+(
+  user_id      bigint      not null references users (id) on delete restrict,
+  mission_id   bigint      not null,
+  progress     integer     not null default 0,
+  completed_at timestamptz null,
+  received     boolean     not null default false,
+  -- Daily missions updated before the last daily reset are treated as not started
+  updated_at   timestamptz not null default now(),
+  primary key (user_id, mission_id)
+);
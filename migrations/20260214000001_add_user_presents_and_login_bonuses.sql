@@ -0,0 +1,44 @@
+-- Adds the present box and per-user login bonus state: calendar days, and omikuji / random / roulette results.
+
+drop table if exists user_presents cascade;
+create table user_presents
+(
+  id          bigserial primary key,
+  user_id     bigint      not null references users (id) on delete restrict,
+  item_type   integer     not null,
+  item_id     bigint      not null,
+  item_num    integer     not null,
+  message     text        not null,
+  sent_at     timestamptz not null default now(),
+  -- Never expires if null
+  expires_at  timestamptz null,
+  received_at timestamptz null
+);
+create index user_presents_user_id_idx on user_presents (user_id, received_at);
+
+drop table if exists user_login_bonuses cascade;
+create table user_login_bonuses
+(
+  user_id       bigint      not null references users (id) on delete restrict,
+  -- See [loginbonus] master
+  loginbonus_id bigint      not null,
+  -- Last received day, starting from 1
+  day_count     integer     not null,
+  received_at   timestamptz not null default now(),
+  primary key (user_id, loginbonus_id)
+);
+
+drop table if exists user_login_bonus_results cascade;
+create table user_login_bonus_results
+(
+  user_id     bigint      not null references users (id) on delete restrict,
+  -- 1 - omikuji, 2 - random login bonus, 3 - roulette login bonus
+  kind        integer     not null,
+  -- [omikuji_id], [random_loginbonus_id] or [roulette_loginbonus_id]
+  bonus_id    bigint      not null,
+  day         integer     not null,
+  -- Fortune ID for omikuji, result pattern ID otherwise
+  result_id   bigint      not null,
+  received_at timestamptz not null default now(),
+  primary key (user_id, kind, bonus_id, day)
+);
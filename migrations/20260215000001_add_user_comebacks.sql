@@ -0,0 +1,12 @@
+-- Adds received comeback presents, see [comeback_present] master.
+
+drop table if exists user_comebacks cascade;
+create table user_comebacks
+(
+  user_id             bigint      not null references users (id) on delete restrict,
+  comeback_present_id bigint      not null,
+  -- Most recent [user_devices.last_used] before the comeback login, identifies the absence
+  absent_since        timestamptz not null,
+  received_at         timestamptz not null default now(),
+  primary key (user_id, comeback_present_id, absent_since)
+);
@@ -0,0 +1,12 @@
+-- Adds per-user shop buy counts, see [shop_item] master.
+
+drop table if exists user_shop_purchases cascade;
+create table user_shop_purchases
+(
+  user_id        bigint      not null references users (id) on delete restrict,
+  shop_item_id   bigint      not null,
+  -- Purchases made in the limit period of [last_bought_at], see [limit_type]
+  buy_count      integer     not null,
+  last_bought_at timestamptz not null default now(),
+  primary key (user_id, shop_item_id)
+);
@@ -0,0 +1,20 @@
+-- Adds in-app purchases made through a purchase provider, see [shop_item] master.
+
+drop table if exists user_purchases cascade;
+create table user_purchases
+(
+  id           bigserial primary key,
+  user_id      bigint      not null references users (id) on delete restrict,
+  shop_item_id bigint      not null,
+  -- [external_id] of the shop item
+  product_id   text        not null,
+  receipt      text        null,
+  -- Yen, counted towards the charge total once credited
+  price        integer     not null,
+  -- Pending if null, set by the purchase provider or by an administrator
+  approved     boolean     null,
+  created_at   timestamptz not null default now(),
+  -- Items were granted, happens only once
+  credited_at  timestamptz null
+);
+create index user_purchases_user_id_idx on user_purchases (user_id, credited_at);
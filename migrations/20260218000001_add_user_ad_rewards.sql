@@ -0,0 +1,12 @@
+-- Adds per-user ad reward claims, see [ad_reward] master.
+
+drop table if exists user_ad_rewards cascade;
+create table user_ad_rewards
+(
+  user_id         bigint      not null references users (id) on delete restrict,
+  ad_reward_id    bigint      not null,
+  -- Claims made on the day of [last_claimed_at]
+  claim_count     integer     not null,
+  last_claimed_at timestamptz not null default now(),
+  primary key (user_id, ad_reward_id)
+);
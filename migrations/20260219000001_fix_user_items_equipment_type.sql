@@ -0,0 +1,11 @@
+-- Equipment item types are 5 (weapon) and 6 (accessory), not 4 (member) and 5.
+
+alter table user_items_equipment
+  drop constraint if exists chk_user_items_equipment_type;
+alter table user_items_equipment
+  add constraint chk_user_items_equipment_type check (item_type = 5 or item_type = 6);
+
+alter table user_items
+  drop constraint if exists chk_user_items_no_equipment;
+alter table user_items
+  add constraint chk_user_items_no_equipment check (item_type != 5 and item_type != 6);
@@ -0,0 +1,12 @@
+-- Adds per-user medal exchange counts, see [exchange_item] master.
+
+drop table if exists user_exchanges cascade;
+create table user_exchanges
+(
+  user_id           bigint      not null references users (id) on delete restrict,
+  exchange_item_id  bigint      not null,
+  -- Exchanges made in the limit period of [last_exchanged_at], see [limit_type]
+  exchange_count    integer     not null,
+  last_exchanged_at timestamptz not null default now(),
+  primary key (user_id, exchange_item_id)
+);
@@ -0,0 +1,9 @@
+-- Adds per-user inventory expansions, see [crate::inventory].
+
+drop table if exists user_inventory_expansions cascade;
+create table user_inventory_expansions
+(
+  user_id                   bigint  not null primary key references users (id) on delete restrict,
+  equipment_expansions      integer not null default 0,
+  reserve_member_expansions integer not null default 0
+);
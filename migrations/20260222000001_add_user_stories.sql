@@ -0,0 +1,15 @@
+-- Adds per-user read stories and their dialogue selections, see [story_*] masters.
+
+drop table if exists user_stories cascade;
+create table user_stories
+(
+  user_id       bigint      not null references users (id) on delete restrict,
+  -- See [crate::api::story::StoryType]
+  story_type    integer     not null,
+  story_id      bigint      not null,
+  -- Selected choices of the last read, one array per selection point
+  selections    jsonb       not null default '[]',
+  first_read_at timestamptz not null default now(),
+  last_read_at  timestamptz not null default now(),
+  primary key (user_id, story_type, story_id)
+);
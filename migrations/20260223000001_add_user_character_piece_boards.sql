@@ -0,0 +1,22 @@
+-- Adds per-user released Potential board stages and reached board rewards, see [character_piece_board*] masters.
+
+drop table if exists user_character_piece_board_stages cascade;
+create table user_character_piece_board_stages
+(
+  user_id      bigint      not null references users (id) on delete restrict,
+  character_id bigint      not null,
+  board_id     bigint      not null,
+  stage_id     bigint      not null,
+  released_at  timestamptz not null default now(),
+  primary key (user_id, stage_id)
+);
+
+drop table if exists user_character_piece_board_rewards cascade;
+create table user_character_piece_board_rewards
+(
+  user_id      bigint      not null references users (id) on delete restrict,
+  character_id bigint      not null,
+  reward_id    bigint      not null,
+  reached_at   timestamptz not null default now(),
+  primary key (user_id, reward_id)
+);
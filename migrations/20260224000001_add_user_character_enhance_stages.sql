@@ -0,0 +1,22 @@
+-- Adds per-user released Trial of the Ancients stages, see [character_enhance] master, and started trials.
+
+drop table if exists user_character_enhance_stages cascade;
+create table user_character_enhance_stages
+(
+  user_id      bigint      not null references users (id) on delete restrict,
+  character_id bigint      not null,
+  root_id      integer     not null,
+  stage_id     bigint      not null,
+  released_at  timestamptz not null default now(),
+  primary key (user_id, stage_id)
+);
+
+-- Trial started by [character_enhance_battle_start], a win is only accepted for the started stage
+drop table if exists user_character_enhance_battles cascade;
+create table user_character_enhance_battles
+(
+  user_id    bigint      not null references users (id) on delete restrict,
+  stage_id   bigint      not null,
+  started_at timestamptz not null default now(),
+  primary key (user_id)
+);
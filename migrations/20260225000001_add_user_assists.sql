@@ -0,0 +1,28 @@
+-- Adds per-user assists and assist gacha history, see [assist] master.
+
+drop table if exists user_assists cascade;
+create table user_assists
+(
+  user_id    bigint      not null references users (id) on delete restrict,
+  assist_id  bigint      not null,
+  level      integer     not null default 1,
+  created_at timestamptz not null default now(),
+  primary key (user_id, assist_id)
+);
+
+drop table if exists user_assist_gacha_log cascade;
+create table user_assist_gacha_log
+(
+  id        bigserial primary key,
+  user_id   bigint      not null references users (id) on delete restrict,
+  gacha_id  bigint      not null,
+  item_type integer     not null,
+  item_id   bigint      not null,
+  item_num  integer     not null,
+  pulled_at timestamptz not null default now()
+);
+
+alter table user_parties
+  drop column if exists sub_assist_ids;
+alter table user_parties
+  add column sub_assist_ids bigint[] not null default '{}';
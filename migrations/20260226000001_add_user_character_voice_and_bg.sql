@@ -0,0 +1,8 @@
+-- Adds selected home voice and background to user characters, see [interaction] endpoint.
+
+alter table user_characters
+  drop column if exists voice,
+  drop column if exists bg;
+alter table user_characters
+  add column voice text not null default '',
+  add column bg    text not null default '';
@@ -0,0 +1,283 @@
+//! Ad rewards from `ad_reward` master, claimed without watching ads since there is no ad network.
+//!
+//! Each slot can be claimed up to its `limit` times per game day, with `recast_time` between claims.
+//! All slots in the master have already ended, so the most recent slot of each reward type stays
+//! available when none is open, see [get_active_ad_rewards].
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use tokio_postgres::Statement;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::quest::QuestRewardItem;
+use crate::api::RemoteDataItemType;
+use crate::database::QueryExecutor;
+use crate::mission::daily_reset_at;
+use crate::settings::{AdRewardSettings, DailyResetSettings};
+use crate::shop::get_pack_items;
+use crate::user::id::UserId;
+
+/// `reward_type` column.
+pub const AD_REWARD_TYPE_QUARTZ: i32 = 1;
+pub const AD_REWARD_TYPE_STAMINA: i32 = 3;
+
+/// `status` of [crate::api::ad_reward::AdvertisementData].
+/// Client hides the ad button for anything but [AdRewardStatus::Available].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(i32)]
+pub enum AdRewardStatus {
+  Available = 0,
+  Recasting = 1,
+  LimitReached = 2,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdRewardPrototype {
+  pub id: i64,
+  pub reward_type: i32,
+  /// Pack ID
+  pub value: i64,
+  pub recast_time: TimeDelta,
+  pub limit: i32,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+}
+
+impl AdRewardPrototype {
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+
+  pub fn daily_limit(&self, settings: &AdRewardSettings) -> i32 {
+    settings.daily_limit.unwrap_or(self.limit)
+  }
+
+  pub fn status(
+    &self,
+    settings: &AdRewardSettings,
+    reset: &DailyResetSettings,
+    claim: Option<&UserAdReward>,
+    now: DateTime<Utc>,
+  ) -> AdRewardStatus {
+    if !settings.enabled {
+      return AdRewardStatus::LimitReached;
+    }
+
+    let Some(claim) = claim.filter(|claim| claim.last_claimed_at >= daily_reset_at(now, reset)) else {
+      return AdRewardStatus::Available;
+    };
+
+    if claim.claim_count >= self.daily_limit(settings) {
+      AdRewardStatus::LimitReached
+    } else if now < claim.last_claimed_at + self.recast_time {
+      AdRewardStatus::Recasting
+    } else {
+      AdRewardStatus::Available
+    }
+  }
+
+  /// Items received for a single claim, from the pack if its contents are known,
+  /// otherwise quartz or stamina amount from [AdRewardSettings].
+  pub fn rewards(&self, settings: &AdRewardSettings) -> Vec<QuestRewardItem> {
+    let items = get_pack_items(self.value);
+    if !items.is_empty() {
+      return items.to_vec();
+    }
+
+    let (item_type, item_num) = match self.reward_type {
+      AD_REWARD_TYPE_QUARTZ => (RemoteDataItemType::RealMoneyFree, settings.quartz),
+      AD_REWARD_TYPE_STAMINA => (RemoteDataItemType::Stamina, settings.stamina),
+      _ => return Vec::new(),
+    };
+    vec![QuestRewardItem {
+      item_type: item_type.into(),
+      item_id: 0,
+      item_num,
+      item_rare: false,
+      probability: None,
+    }]
+  }
+}
+
+/// Parses `recast_time` column, e.g. `0:30:00`.
+fn parse_recast_time(value: &str) -> TimeDelta {
+  let time = NaiveTime::parse_from_str(value, "%H:%M:%S").unwrap();
+  time - NaiveTime::MIN
+}
+
+/// Returns all ad rewards, parsed once.
+pub fn get_ad_rewards() -> &'static [AdRewardPrototype] {
+  static AD_REWARDS: OnceLock<Vec<AdRewardPrototype>> = OnceLock::new();
+
+  AD_REWARDS.get_or_init(|| {
+    get_master_manager()
+      .get_master("ad_reward")
+      .iter()
+      .map(|ad_reward| AdRewardPrototype {
+        id: parse_i64(&ad_reward["id"]),
+        reward_type: parse_i64(&ad_reward["reward_type"]) as i32,
+        value: parse_i64(&ad_reward["value"]),
+        recast_time: parse_recast_time(ad_reward["recast_time"].as_str().unwrap()),
+        limit: parse_i64(&ad_reward["limit"]) as i32,
+        start_at: parse_date(ad_reward["start_at"].as_str().unwrap()),
+        end_at: parse_date(ad_reward["end_at"].as_str().unwrap()),
+      })
+      .collect()
+  })
+}
+
+/// Returns slots that can be claimed at [now]: open ones, or the most recent slot of each reward type
+/// if none of that type is open.
+pub fn get_active_ad_rewards(now: NaiveDateTime) -> Vec<&'static AdRewardPrototype> {
+  let mut active: Vec<&AdRewardPrototype> = Vec::new();
+  for prototype in get_ad_rewards() {
+    match active
+      .iter_mut()
+      .find(|other| other.reward_type == prototype.reward_type)
+    {
+      Some(other) if !other.is_open(now) && (prototype.is_open(now) || prototype.start_at > other.start_at) => {
+        *other = prototype;
+      }
+      Some(_) => {}
+      None => active.push(prototype),
+    }
+  }
+  active
+}
+
+pub fn get_active_ad_reward(id: i64, now: NaiveDateTime) -> Option<&'static AdRewardPrototype> {
+  get_active_ad_rewards(now)
+    .into_iter()
+    .find(|prototype| prototype.id == id)
+}
+
+#[derive(Debug, Clone)]
+pub struct UserAdReward {
+  pub claim_count: i32,
+  pub last_claimed_at: DateTime<Utc>,
+}
+
+pub struct FetchUserAdRewards<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserAdRewards<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select ad_reward_id, claim_count, last_claimed_at
+        from user_ad_rewards
+        where user_id = $1
+        for update
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<HashMap<i64, UserAdReward>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| {
+          let claim = UserAdReward {
+            claim_count: row.get("claim_count"),
+            last_claimed_at: row.get("last_claimed_at"),
+          };
+          (row.get("ad_reward_id"), claim)
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Counts a claim, starting over if the last one was before [reset_at].
+pub struct ClaimUserAdReward<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ClaimUserAdReward<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_ad_rewards (user_id, ad_reward_id, claim_count)
+        values ($1, $2, 1)
+        on conflict (user_id, ad_reward_id)
+          do update
+          set claim_count = case
+                when user_ad_rewards.last_claimed_at < $3 then 1
+                else user_ad_rewards.claim_count + 1
+              end,
+              last_claimed_at = now()
+        returning claim_count, last_claimed_at
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, ad_reward_id: i64, reset_at: DateTime<Utc>) -> anyhow::Result<UserAdReward> {
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&user_id, &ad_reward_id, &reset_at])
+      .await?;
+    Ok(UserAdReward {
+      claim_count: row.get("claim_count"),
+      last_claimed_at: row.get("last_claimed_at"),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  #[test]
+  fn test_status() {
+    let settings = AdRewardSettings::default();
+    let reset = DailyResetSettings::default();
+    let prototype = AdRewardPrototype {
+      id: 1,
+      reward_type: AD_REWARD_TYPE_STAMINA,
+      value: 0,
+      recast_time: parse_recast_time("0:30:00"),
+      limit: 2,
+      start_at: None,
+      end_at: None,
+    };
+    let now = Utc.with_ymd_and_hms(2026, 1, 2, 12, 0, 0).unwrap();
+    let claim = |claim_count, last_claimed_at| UserAdReward {
+      claim_count,
+      last_claimed_at,
+    };
+
+    assert_eq!(
+      prototype.status(&settings, &reset, None, now),
+      AdRewardStatus::Available
+    );
+    assert_eq!(
+      prototype.status(&settings, &reset, Some(&claim(1, now - TimeDelta::minutes(10))), now),
+      AdRewardStatus::Recasting
+    );
+    assert_eq!(
+      prototype.status(&settings, &reset, Some(&claim(2, now - TimeDelta::hours(1))), now),
+      AdRewardStatus::LimitReached
+    );
+    // Claimed yesterday
+    assert_eq!(
+      prototype.status(&settings, &reset, Some(&claim(2, now - TimeDelta::days(1))), now),
+      AdRewardStatus::Available
+    );
+  }
+}
@@ -1,11 +1,30 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tracing::warn;
+use tracing::{info, warn};
 
-use crate::api::master_all::get_masters;
-use crate::call::CallCustom;
+use crate::ad_reward::{
+  get_active_ad_reward, get_active_ad_rewards, AdRewardStatus, ClaimUserAdReward, FetchUserAdRewards,
+};
+use crate::api::battle::grant_rewards;
+use crate::api::quest::QuestRewardItem;
+use crate::api::RemoteDataItemType;
+use crate::blob::IntoRemoteData;
+use crate::call::{
+  CallCustom, CallResponse, STATUS_AD_REWARD_LIMIT, STATUS_AD_REWARD_NOT_AVAILABLE, STATUS_AD_REWARD_RECASTING,
+  STATUS_SHOP_NOT_FOR_SALE,
+};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
+use crate::item::{spend_items, CountedItem, FetchUserItemCount, ItemCost};
+use crate::mission::daily_reset_at;
+use crate::shop::{
+  get_shop_item, get_shop_items, FetchUserShopPurchases, ShopMoneyType, UpdateUserShopPurchase, MAX_BUY_COUNT,
+};
+use crate::user::session::Session;
+use crate::AppState;
 
 // See [Wonder_Api_AdvertisementRewardStatusResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -28,27 +47,118 @@ pub struct AdvertisementRewardStatusRequest {
   pub reward_type_list: Vec<i32>,
 }
 
+/// Returns the slot and its status for the user, for [advertisement_reward_status] and home.
+pub async fn get_advertisement_data(
+  state: &AppState,
+  session: &Session,
+  reward_types: Option<&[i32]>,
+) -> anyhow::Result<Vec<AdvertisementData>> {
+  let client = state.get_database_client().await?;
+  let claims = FetchUserAdRewards::new(&client).await?.run(session.user_id).await?;
+  let now = Utc::now();
+
+  Ok(
+    get_active_ad_rewards(now.naive_utc())
+      .into_iter()
+      .filter(|prototype| reward_types.is_none_or(|reward_types| reward_types.contains(&prototype.reward_type)))
+      .map(|prototype| AdvertisementData {
+        id: prototype.id as i32,
+        reward_type: prototype.reward_type,
+        status: prototype.status(
+          &state.settings.ad_reward,
+          &state.settings.daily_reset,
+          claims.get(&prototype.id),
+          now,
+        ) as i32,
+      })
+      .collect(),
+  )
+}
+
 // reward_type_list=[3]
 pub async fn advertisement_reward_status(
+  state: Arc<AppState>,
+  session: Arc<Session>,
   Params(params): Params<AdvertisementRewardStatusRequest>,
 ) -> impl IntoHandlerResponse {
-  let masters = get_masters().await;
-  let ad_rewards: Vec<Value> = serde_json::from_str(&masters["ad_reward"].master_decompressed).unwrap();
-
-  warn!(?params, "encountered stub: advertisement_reward_status");
-
   Ok(Unsigned(AdvertisementRewardStatus {
-    advertisement_data_list: ad_rewards
-      .iter()
-      .map(|ad_reward| AdvertisementData {
-        id: ad_reward["id"].as_str().unwrap().parse().unwrap(),
-        reward_type: ad_reward["reward_type"].as_str().unwrap().parse().unwrap(),
-        status: 0,
-      })
-      .collect(),
+    advertisement_data_list: get_advertisement_data(&state, &session, Some(&params.reward_type_list)).await?,
   }))
 }
 
+// See [Wonder_Api_AdvertisementRewardResponseDto_Fields]
+#[derive(Debug, Serialize)]
+pub struct AdvertisementReward {
+  pub advertisement_data: AdvertisementData,
+}
+
+impl CallCustom for AdvertisementReward {}
+
+#[derive(Debug, Deserialize)]
+pub struct AdvertisementRewardRequest {
+  pub id: i32,
+}
+
+pub async fn advertisement_reward(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<AdvertisementRewardRequest>,
+) -> impl IntoHandlerResponse {
+  let settings = &state.settings.ad_reward;
+  let now = Utc::now();
+  let Some(prototype) = get_active_ad_reward(params.id as i64, now.naive_utc()).filter(|_| settings.enabled) else {
+    warn!(?params, "ad reward is not available");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_AD_REWARD_NOT_AVAILABLE)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let claims = FetchUserAdRewards::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?;
+  match prototype.status(settings, &state.settings.daily_reset, claims.get(&prototype.id), now) {
+    AdRewardStatus::Available => {}
+    AdRewardStatus::Recasting => return Ok(Unsigned(CallResponse::new_error(STATUS_AD_REWARD_RECASTING))),
+    AdRewardStatus::LimitReached => return Ok(Unsigned(CallResponse::new_error(STATUS_AD_REWARD_LIMIT))),
+  }
+
+  let claim = ClaimUserAdReward::new(&transaction)
+    .await?
+    .run(
+      session.user_id,
+      prototype.id,
+      daily_reset_at(now, &state.settings.daily_reset),
+    )
+    .await?;
+  // The first claim of the day inserts a row that was not locked by the fetch above
+  if claim.claim_count > prototype.daily_limit(settings) {
+    warn!(?prototype.id, claim_count = claim.claim_count, "ad reward was claimed concurrently");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_AD_REWARD_LIMIT)));
+  }
+  let remote_data = grant_rewards(
+    &transaction,
+    &state.settings.inventory,
+    &session,
+    &prototype.rewards(settings),
+  )
+  .await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?prototype.id, claim_count = claim.claim_count, "claimed ad reward");
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(AdvertisementReward {
+    advertisement_data: AdvertisementData {
+      id: prototype.id as i32,
+      reward_type: prototype.reward_type,
+      status: prototype.status(settings, &state.settings.daily_reset, Some(&claim), now) as i32,
+    },
+  }));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
+}
+
 // See [Wonder_Api_ShopitemlistResponseDto_Fields]
 #[derive(Debug, Serialize)]
 pub struct ShopItemList {
@@ -71,18 +181,28 @@ pub struct ShopItemListRequest {
 }
 
 // shop_master_id=4
-pub async fn shop_item_list(Params(params): Params<ShopItemListRequest>) -> impl IntoHandlerResponse {
-  let masters = get_masters().await;
-  let shop_items: Vec<Value> = serde_json::from_str(&masters["shop_item"].master_decompressed).unwrap();
+pub async fn shop_item_list(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<ShopItemListRequest>,
+) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let purchases = FetchUserShopPurchases::new(&client).await?.run(session.user_id).await?;
+  let now = Utc::now();
 
   Ok(Unsigned(ShopItemList {
-    items: shop_items
+    items: get_shop_items()
       .iter()
-      .filter(|item| item["shop_id"].as_str().unwrap().parse::<i32>().unwrap() == params.shop_master_id)
+      .filter(|item| item.shop_id == params.shop_master_id as i64)
       .map(|item| ShopItem {
-        shop_item_master_id: item["id"].as_str().unwrap().parse().unwrap(),
-        interval_time: 0,
-        buycount: 0,
+        shop_item_master_id: item.id as i32,
+        interval_time: item
+          .limit_kind
+          .next_reset(now, &state.settings.daily_reset)
+          .map_or(0, |reset| (reset - now).num_seconds() as i32),
+        buycount: purchases.get(&item.id).map_or(0, |purchase| {
+          purchase.current_count(item, now, &state.settings.daily_reset)
+        }),
       })
       .collect(),
   }))
@@ -111,14 +231,148 @@ pub struct BuyRequest {
 // money_type=2
 // count=2
 // shop_item_master_id=11000
-pub async fn buy(Params(params): Params<BuyRequest>) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: ad_reward::buy");
-  Ok(Unsigned(BuyResponse {
-    buymoney: 0,
-    buyrealmoney: 0,
-    buyrealmoneyfree: 0,
-    money: 0,
-    realmoney: 0,
-    realmoneyfree: 0,
-  }))
+pub async fn buy(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<BuyRequest>,
+) -> impl IntoHandlerResponse {
+  let now = Utc::now();
+  let Some(prototype) = get_shop_item(params.shop_item_master_id as i64)
+    .filter(|prototype| prototype.is_open(now.naive_utc()) && !prototype.is_real_money_sale())
+  else {
+    warn!(?params, "shop item is not for sale");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_SHOP_NOT_FOR_SALE)));
+  };
+  let Some((money_type, price)) = ShopMoneyType::try_from(params.money_type)
+    .ok()
+    .and_then(|money_type| prototype.price(money_type).map(|price| (money_type, price)))
+  else {
+    warn!(?params, "shop item can not be bought with this money type");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_SHOP_NOT_FOR_SALE)));
+  };
+  if !(1..=MAX_BUY_COUNT).contains(&params.count) {
+    warn!(?params, "invalid shop item count");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_SHOP_NOT_FOR_SALE)));
+  }
+  // Contents of packs that are not dumped are unknown, do not take money for nothing
+  let bundle = prototype.bundle();
+  if bundle.is_empty() {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_SHOP_NOT_FOR_SALE)));
+  }
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let bought = FetchUserShopPurchases::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .get(&prototype.id)
+    .map_or(0, |purchase| {
+      purchase.current_count(prototype, now, &state.settings.daily_reset)
+    });
+  let Some(total_count) = bought.checked_add(params.count) else {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_SHOP_NOT_FOR_SALE)));
+  };
+  if prototype.limit().is_some_and(|limit| total_count > limit) {
+    return Ok(Unsigned(CallResponse::new_error(prototype.limit_kind.limit_status())));
+  }
+
+  let Some(cost) = price.total(bought, params.count) else {
+    warn!(?params, "shop item price overflows");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_SHOP_NOT_FOR_SALE)));
+  };
+  let Some(items) = bundle
+    .into_iter()
+    .map(|item| {
+      Some(QuestRewardItem {
+        item_num: item.item_num.checked_mul(params.count)?,
+        ..item
+      })
+    })
+    .collect::<Option<Vec<_>>>()
+  else {
+    warn!(?params, "shop item bundle amount overflows");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_SHOP_NOT_FOR_SALE)));
+  };
+  let fetch_count = FetchUserItemCount::new(&transaction).await?;
+  let money = fetch_count.run(session.user_id, (RemoteDataItemType::Money, 0)).await?;
+  let realmoney = fetch_count
+    .run(session.user_id, (RemoteDataItemType::RealMoney, 0))
+    .await?;
+  let realmoneyfree = fetch_count
+    .run(session.user_id, (RemoteDataItemType::RealMoneyFree, 0))
+    .await?;
+
+  let spend = match money_type {
+    ShopMoneyType::Money => ItemCost {
+      money: cost,
+      ..Default::default()
+    },
+    ShopMoneyType::RealMoney => ItemCost {
+      quartz: cost,
+      ..Default::default()
+    },
+    ShopMoneyType::RealMoneyPaid => ItemCost::items(vec![realmoney.item.into_counted(cost)]),
+  };
+  let spent = match spend_items(&transaction, session.user_id, &spend).await? {
+    Ok(spent) => spent,
+    Err(error) => {
+      warn!(?params, %error, "not enough money");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+  // Amounts of Eris, paid quartz and free quartz spent
+  let spent_amount = |before: &CountedItem| {
+    spent
+      .iter()
+      .find(|item| item.item == before.item)
+      .map_or(0, |item| before.quantity - item.quantity)
+  };
+  let (buymoney, buyrealmoney, buyrealmoneyfree) = (
+    spent_amount(&money),
+    spent_amount(&realmoney),
+    spent_amount(&realmoneyfree),
+  );
+  let mut remote_data = spent
+    .into_iter()
+    .flat_map(IntoRemoteData::into_remote_data)
+    .collect::<Vec<_>>();
+
+  UpdateUserShopPurchase::new(&transaction)
+    .await?
+    .run(session.user_id, prototype.id, total_count)
+    .await?;
+
+  remote_data.extend(grant_rewards(&transaction, &state.settings.inventory, &session, &items).await?);
+
+  // Bundle may contain Eris or quartz as well
+  let money = fetch_count.run(session.user_id, (RemoteDataItemType::Money, 0)).await?;
+  let realmoney = fetch_count
+    .run(session.user_id, (RemoteDataItemType::RealMoney, 0))
+    .await?;
+  let realmoneyfree = fetch_count
+    .run(session.user_id, (RemoteDataItemType::RealMoneyFree, 0))
+    .await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(
+    ?prototype.id,
+    count = params.count,
+    ?buymoney,
+    ?buyrealmoney,
+    ?buyrealmoneyfree,
+    "bought shop item"
+  );
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(BuyResponse {
+    buymoney,
+    buyrealmoney,
+    buyrealmoneyfree,
+    money: money.quantity,
+    realmoney: realmoney.quantity,
+    realmoneyfree: realmoneyfree.quantity,
+  }));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
 }
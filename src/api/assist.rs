@@ -1,36 +1,35 @@
 //! Reference: https://youtu.be/b2S0_Q12axI and https://youtu.be/MQ9VOLhVRbE
 
-use crate::api::master_all::get_master_manager;
-use crate::api::{RemoteData, RemoteDataCommand, RemoteDataItemType};
-use crate::call::{CallCustom, CallResponse};
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::assist::{
+  get_assist, get_assist_by_unique_id, get_assists, get_max_assist_level, level_up_cost, AddUserAssist,
+  FetchUserAssists, SetUserAssistLevel,
+};
+use crate::blob::IntoRemoteData;
+use crate::call::{CallCustom, CallResponse, STATUS_ERROR, STATUS_MAX_LEVEL};
+use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
-use serde::Serialize;
+use crate::item::spend_items;
+use crate::user::session::Session;
+use crate::AppState;
 
-pub async fn assist_make_notice() -> impl IntoHandlerResponse {
-  let assists = get_master_manager().get_master("assist_details");
+/// Sends owned assists.
+pub async fn assist_make_notice(state: Arc<AppState>, session: Arc<Session>) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let assists = FetchUserAssists::new(&client).await?.run(session.user_id).await?;
 
   let mut response = CallResponse::new_success_empty();
   // Same as with equipment, item-id is assist_details, i.e. assist + level combined.
-  // TODO: This sends same assists every time
   response.add_remote_data(
     assists
       .iter()
-      .map(|assist| {
-        let id = assist["id"].as_str().unwrap().parse::<i64>().unwrap();
-        RemoteData {
-          cmd: RemoteDataCommand::UserParamAdd as i32,
-          uid: None,
-          item_type: RemoteDataItemType::Assist.into(),
-          item_id: id,
-          item_num: 1,
-          uniqid: id as i32,
-          lv: 0,
-          tag: String::from(""),
-          member_parameter: None,
-          character_parameter: None,
-          is_trial: None,
-        }
-      })
+      .flat_map(|assist| assist.to_remote().into_remote_data())
       .collect(),
   );
 
@@ -45,8 +44,124 @@ pub struct AssistMakeList {
 
 impl CallCustom for AssistMakeList {}
 
-pub async fn assist_make_list() -> impl IntoHandlerResponse {
+/// Lists craftable assists that are not owned yet.
+pub async fn assist_make_list(state: Arc<AppState>, session: Arc<Session>) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let owned = FetchUserAssists::new(&client).await?.run(session.user_id).await?;
+
+  let now = Utc::now().naive_utc();
   Ok(Unsigned(AssistMakeList {
-    assist_detail_id_list: vec![],
+    assist_detail_id_list: get_assists()
+      .iter()
+      .filter(|assist| assist.is_craftable() && assist.is_displayed(now))
+      .filter(|assist| !owned.iter().any(|owned| owned.assist_id == assist.assist_id))
+      .map(|assist| assist.unique_id())
+      .collect(),
   }))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct AssistMakeRequest {
+  pub assist_detail_id: i64,
+}
+
+pub async fn assist_make(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<AssistMakeRequest>,
+) -> impl IntoHandlerResponse {
+  let Some(assist) = get_assist_by_unique_id(params.assist_detail_id)
+    .filter(|assist| assist.is_craftable() && assist.is_displayed(Utc::now().naive_utc()))
+  else {
+    warn!(?params, "unknown or not craftable assist");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let Some(added) = AddUserAssist::new(&transaction)
+    .await?
+    .run(session.user_id, assist.assist_id)
+    .await?
+  else {
+    warn!(?params, "assist is already owned");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+  let mut remote_data = match spend_items(&transaction, session.user_id, &assist.make_cost).await? {
+    Ok(spent) => spent
+      .into_iter()
+      .flat_map(IntoRemoteData::into_remote_data)
+      .collect::<Vec<_>>(),
+    Err(error) => {
+      warn!(?params, %error, "not enough items to craft assist");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+  remote_data.extend(added.to_remote().into_remote_data());
+
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?added, "crafted assist");
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssistLevelUpRequest {
+  pub assist_unique_id: i64,
+}
+
+pub async fn assist_level_up(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<AssistLevelUpRequest>,
+) -> impl IntoHandlerResponse {
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let Some(mut owned) = FetchUserAssists::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .into_iter()
+    .find(|assist| assist.unique_id() == params.assist_unique_id)
+  else {
+    warn!(?params, "assist is not owned");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+  let assist = get_assist(owned.assist_id).context("owned assist is not in master")?;
+
+  if owned.level >= get_max_assist_level(owned.assist_id) {
+    warn!(?params, ?owned, "assist is already at max level");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_MAX_LEVEL)));
+  }
+  owned.level += 1;
+
+  let cost = level_up_cost(assist, owned.level).context("assist level is not in master")?;
+  let mut remote_data = match spend_items(&transaction, session.user_id, &cost).await? {
+    Ok(spent) => spent
+      .into_iter()
+      .flat_map(IntoRemoteData::into_remote_data)
+      .collect::<Vec<_>>(),
+    Err(error) => {
+      warn!(?params, %error, "not enough items to level up assist");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+  SetUserAssistLevel::new(&transaction)
+    .await?
+    .run(session.user_id, &owned)
+    .await?;
+  remote_data.extend(owned.to_remote().into_remote_data());
+
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?owned, "leveled up assist");
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
+}
@@ -2,20 +2,36 @@ use crate::api::battle_multi::{BattleCharacterLove, BattleClearReward, BattleMem
 use crate::api::master_all::get_master_manager;
 use crate::api::party_info::{Party, PartyForm, PartyPassiveSkillInfo, SpecialSkillInfo};
 use crate::api::quest::quest_hunting::BattleReward;
-use crate::api::quest::{parse_reward_items, QuestRewardItem};
+use crate::api::quest::drop::{roll_stage_rewards, roll_table};
+use crate::api::quest::QuestRewardItem;
 use crate::api::surprise::BasicBattlePartyForm;
 use crate::api::{ApiRequest, MemberFameStats, NotificationData, RemoteData, RemoteDataItemType};
 use crate::blob::IntoRemoteData;
-use crate::call::{CallCustom, CallResponse};
+use crate::call::{
+  CallCustom, CallResponse, STATUS_EMERGENCY_BOSS_NOT_OPEN, STATUS_EVENT_NOT_BOSS_TICKET, STATUS_EVENT_NOT_OPEN,
+  STATUS_EVENT_NOT_QUEST,
+};
+use crate::equipment::is_equipment;
+use crate::event::{
+  get_emergency_bosses, get_event_boss_rewards, get_event_single_boss, parse_log_damage, AddUserEventBossDamage,
+  AddUserEventPoints, DefeatUserEmergencyBoss, EventConfig, FetchUserEmergencyBoss, FetchUserEventBosses,
+  FinishUserEventBoss, StartUserEventBoss,
+};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
-use crate::item::UpdateItemCountBy;
+use crate::inventory::grant_equipment;
+use crate::item::{FetchUserItemCount, UpdateItemCountBy};
 use crate::member::{
-  FetchUserMemberSkillsIn, FetchUserMembersIn, FetchUserParty, Member, MemberActiveSkill, MemberPrototype,
-  MemberStrength,
+  apply_character_bonuses, FetchUserMemberSkillsIn, FetchUserMembersIn, FetchUserParty, Member, MemberActiveSkill,
+  MemberPrototype, MemberStrength,
 };
+use crate::mission::{record_mission_events, stage_difficulty, MissionEvent};
 use crate::notification::{IntoNotificationData, MissionDone};
+use crate::settings::InventorySettings;
 use crate::user::overrides::FetchUserOverrides;
+use crate::user::quest_progress::{
+  FetchUserEventQuestProgress, FetchUserQuestTaskCount, QuestKind, RecordUserQuestClear, tasks_from_clear_missions,
+};
 use crate::user::session::Session;
 use crate::AppState;
 use anyhow::Context;
@@ -101,7 +117,11 @@ pub struct AutoProgressionInfo {
   pub incomplete_setting: i32,
 }
 
-pub async fn make_battle_start(state: &AppState, session: &Session, party_id: i32) -> impl IntoHandlerResponse + use<> {
+pub async fn make_battle_start(
+  state: &AppState,
+  session: &Session,
+  party_id: i32,
+) -> anyhow::Result<Unsigned<CallResponse<dyn CallCustom>>> {
   let client = state
     .get_database_client()
     .await
@@ -121,8 +141,9 @@ pub async fn make_battle_start(state: &AppState, session: &Session, party_id: i3
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
-  let mut response = CallResponse::new_success(Box::new(BattleStartResponse {
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(BattleStartResponse {
     chest: "10101111,10101120,10101131".to_owned(),
     party: party.to_battle_party(),
     members: members
@@ -284,18 +305,32 @@ pub struct BattleResultRequest {
 
 pub async fn grant_rewards(
   transaction: &deadpool_postgres::Transaction<'_>,
+  settings: &InventorySettings,
   session: &Session,
   rewards: &[QuestRewardItem],
 ) -> anyhow::Result<Vec<RemoteData>> {
   let update = UpdateItemCountBy::new(transaction).await?;
   let mut update_items = Vec::new();
   for item in rewards {
-    let item = update
-      .run(
+    let item_type = RemoteDataItemType::from(item.item_type);
+    if is_equipment(item_type) {
+      let granted = grant_equipment(
+        transaction,
+        settings,
         session.user_id,
-        (RemoteDataItemType::from(item.item_type), item.item_id),
+        item_type,
+        item.item_id,
         item.item_num,
       )
+      .await?;
+      debug!(?item, overflow = granted.overflow, "granted equipment reward");
+
+      update_items.push(granted.remote_data);
+      continue;
+    }
+
+    let item = update
+      .run(session.user_id, (item_type, item.item_id), item.item_num)
       .await
       .context("failed to execute query")?;
     debug!(?item, "granted battle reward");
@@ -419,9 +454,48 @@ pub async fn battle_result(
     .iter()
     .map(|reward| (reward["id"].as_str().unwrap().parse::<i32>().unwrap(), reward))
     .collect::<HashMap<_, _>>();
-  let mut rewards = parse_reward_items(rewards[&params.quest_id]);
+  let reward = rewards[&params.quest_id];
+  let mut rewards = roll_stage_rewards(&state.settings.drops, Some(QuestKind::Main), reward);
   apply_reward_multiplier(&transaction, &session, &mut rewards).await?;
-  let update_items = grant_rewards(&transaction, &session, &rewards).await?;
+  let update_items = grant_rewards(&transaction, &state.settings.inventory, &session, &rewards).await?;
+  let firstclear = if params.win == 1 {
+    let progress = RecordUserQuestClear::new(&transaction)
+      .await?
+      .run(
+        session.user_id,
+        QuestKind::Main,
+        0,
+        params.quest_id as i64,
+        tasks_from_clear_missions(&params.clearquestmission),
+        1,
+      )
+      .await?;
+    progress.clear_count == 1
+  } else {
+    false
+  };
+  let missions = if params.win == 1 {
+    let tasks = FetchUserQuestTaskCount::new(&transaction)
+      .await?
+      .run(session.user_id, QuestKind::Main)
+      .await?;
+    let events = [
+      MissionEvent::BattleClear {
+        kind: QuestKind::Main,
+        event_id: 0,
+        quest_id: params.quest_id as i64,
+        difficulty: stage_difficulty(reward),
+        count: 1,
+      },
+      MissionEvent::QuestTasks {
+        kind: QuestKind::Main,
+        total: tasks,
+      },
+    ];
+    record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &events).await?
+  } else {
+    Vec::new()
+  };
   transaction.commit().await.context("failed to commit transaction")?;
 
   let party = FetchUserParty::new(&client)
@@ -457,7 +531,7 @@ pub async fn battle_result(
       reward_all: vec![],
       clearreward_all: vec![],
     },
-    firstclear: true,
+    firstclear,
   }));
   response.remote.extend(update_items);
   // TODO: Send remote data to actually update characters stats
@@ -468,7 +542,6 @@ pub async fn battle_result(
     NotificationData::new(1, 7, 14, 1, "".to_string(), "".to_string()),
     NotificationData::new(1, 7, 14, 1, "".to_string(), "".to_string()),
     NotificationData::new(1, 7, 20, 1, "".to_string(), "".to_string()),
-    MissionDone::new(11210001).into_notification_data(),
     NotificationData::new(1, 7, 3, 2, "".to_string(), "".to_string()),
     NotificationData::new(1, 7, 13, 7, "".to_string(), "".to_string()),
     NotificationData::new(1, 7, 34, 1, "show_button".to_string(), "".to_string()),
@@ -476,6 +549,7 @@ pub async fn battle_result(
     NotificationData::new(1, 10, 230731, 52307325, "".to_string(), "".to_string()),
     NotificationData::new(1, 10, 230831, 52308305, "".to_string(), "".to_string()),
   ]);
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
 
   Ok(Unsigned(response))
 }
@@ -512,9 +586,59 @@ pub async fn marathon_single_start(
   session: Arc<Session>,
   Params(params): Params<MarathonSingleStartRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "stub encountered: marathon_single_start");
+  let now = chrono::Utc::now().naive_utc();
+  let Some(config) = EventConfig::get(params.event_id as i64).filter(|config| config.is_open(now)) else {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_OPEN)));
+  };
+  let Some(boss) = get_event_single_boss(config.event_id, params.quest_id as i64) else {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_QUEST)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
 
-  let client = state.pool.get().await.context("failed to get database connection")?;
+  let progress = FetchUserEventQuestProgress::new(&transaction)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  if !boss.is_unlocked(&progress) {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_QUEST)));
+  }
+  if boss.is_emergency() {
+    let emergency = FetchUserEmergencyBoss::new(&transaction)
+      .await?
+      .run(session.user_id, config.event_id)
+      .await?;
+    if !boss.is_available(&progress, emergency.as_ref()) {
+      return Ok(Unsigned(CallResponse::new_error(STATUS_EMERGENCY_BOSS_NOT_OPEN)));
+    }
+  }
+
+  let ticket_ratio = params.ticket_ratio;
+  if ticket_ratio < 1 || ticket_ratio > config.boss_ticket_use_ratio.max(1) {
+    warn!(?ticket_ratio, max = ?config.boss_ticket_use_ratio, "invalid boss ticket ratio");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_BOSS_TICKET)));
+  }
+
+  let ticket_cost = boss.ticket_cost * ticket_ratio;
+  let tickets = FetchUserItemCount::new(&transaction)
+    .await?
+    .run(session.user_id, config.ticket_for(&boss))
+    .await?;
+  if tickets.quantity < ticket_cost {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_BOSS_TICKET)));
+  }
+  let tickets = UpdateItemCountBy::new(&transaction)
+    .await?
+    .run(session.user_id, tickets.item, -ticket_cost)
+    .await
+    .context("failed to consume boss tickets")?;
+  let boss_state = StartUserEventBoss::new(&transaction)
+    .await?
+    .run(session.user_id, config.event_id, boss.quest_id, ticket_ratio)
+    .await?;
+  debug!(?tickets, ?boss_state, "started event boss battle");
+  transaction.commit().await.context("failed to commit transaction")?;
 
   let party = FetchUserParty::new(&client)
     .await?
@@ -531,8 +655,9 @@ pub async fn marathon_single_start(
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
-  Ok(Unsigned(MarathonSingleStartResponse {
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(MarathonSingleStartResponse {
     chest: "10101111,10101120,10101131".to_string(),
     party: party.to_battle_party(),
     // We must send only members that are used in the party, otherwise hardlock occurs?
@@ -544,7 +669,9 @@ pub async fn marathon_single_start(
       })
       .collect(),
     get_log: true,
-  }))
+  }));
+  response.add_remote_data(tickets.into_remote_data());
+  Ok(Unsigned(response))
 }
 
 // See [Wonder_Api_MarathonSingleLogRequest_Fields]
@@ -561,10 +688,58 @@ pub async fn marathon_single_log(
   session: Arc<Session>,
   Params(params): Params<MarathonSingleLogRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: marathon_single_log");
+  let now = chrono::Utc::now().naive_utc();
+  if !EventConfig::get(params.event_id as i64).is_some_and(|config| config.is_open(now)) {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_OPEN)));
+  }
+  let damage = parse_log_damage(&params.log);
+  // Damage beyond the remaining boss HP is not counted, score challenges have no HP
+  let max_damage = get_event_single_boss(params.event_id as i64, params.quest_id as i64)
+    .and_then(|boss| boss.max_hp())
+    .map_or(i64::MAX, |hp| hp.iter().sum());
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let before = FetchUserEventBosses::new(&transaction)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?
+    .remove(&(params.quest_id as i64))
+    .unwrap_or_default();
+  let Some(boss) = AddUserEventBossDamage::new(&transaction)
+    .await?
+    .run(
+      session.user_id,
+      params.event_id as i64,
+      params.quest_id as i64,
+      damage,
+      max_damage,
+    )
+    .await?
+  else {
+    warn!(?params.event_id, ?params.quest_id, "battle log without a started battle");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_QUEST)));
+  };
+  let dealt = (boss.damage - before.damage).max(0);
+  if dealt < damage {
+    warn!(?damage, ?dealt, "battle log damage exceeds remaining boss HP");
+  }
+
+  // Higher Boss Ticket multipliers give proportionally more points
+  let points = AddUserEventPoints::new(&transaction)
+    .await?
+    .run(
+      session.user_id,
+      params.event_id as i64,
+      dealt.saturating_mul(boss.ticket_ratio as i64),
+    )
+    .await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+  debug!(?damage, ?boss, ?points, "recorded event boss damage");
 
   // See [Wonder_Api_MarathonSingleLogResponseDto_Fields]
-  Ok(Unsigned(()))
+  let response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  Ok(Unsigned(response))
 }
 
 // See [Wonder_Api_MarathonSingleResultRequest_Fields]
@@ -598,16 +773,71 @@ pub async fn marathon_single_result(
   session: Arc<Session>,
   Params(params): Params<MarathonSingleResultRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: marathon_single_result");
+  let event_id = params.event_id as i64;
+  let now = chrono::Utc::now().naive_utc();
+  let Some(config) = EventConfig::get(event_id).filter(|config| config.is_open(now)) else {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_OPEN)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let Some(boss) = FinishUserEventBoss::new(&transaction)
+    .await?
+    .run(session.user_id, event_id, params.quest_id as i64, params.win == 1)
+    .await?
+  else {
+    warn!(?event_id, ?params.quest_id, "battle result without a started battle");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_EVENT_NOT_QUEST)));
+  };
+
+  let mut rewards = if params.win == 1 {
+    let table = get_event_boss_rewards(params.quest_id as i64);
+    let mut rewards = roll_table(&state.settings.drops, &table, boss.ticket_ratio as f32);
+
+    // Emergency stages have their own drops, the emergency boss adds its reward packs and disappears
+    if let Some(emergency) = DefeatUserEmergencyBoss::new(&transaction)
+      .await?
+      .run(session.user_id, event_id, params.quest_id as i64)
+      .await?
+    {
+      info!(?emergency, "emergency boss defeated");
+      if let Some(prototype) = get_emergency_bosses(config.event_emergency_group_id)
+        .into_iter()
+        .find(|boss| boss.id == emergency.emergency_boss_id)
+      {
+        rewards.extend(prototype.rewards());
+      }
+    }
+
+    rewards
+  } else {
+    Vec::new()
+  };
+  let missions = if params.win == 1 {
+    let is_score_challenge =
+      get_event_single_boss(event_id, params.quest_id as i64).is_some_and(|boss| boss.is_score_challenge);
+    let event = MissionEvent::BossDefeat {
+      event_id,
+      quest_id: params.quest_id as i64,
+      is_score_challenge,
+    };
+    record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &[event]).await?
+  } else {
+    Vec::new()
+  };
+  apply_reward_multiplier(&transaction, &session, &mut rewards).await?;
+  // Event point milestone rewards are not granted, `event_quest_reward` master is not dumped
+  let update_items = grant_rewards(&transaction, &state.settings.inventory, &session, &rewards).await?;
+  transaction.commit().await.context("failed to commit transaction")?;
 
-  let client = state.pool.get().await.context("failed to get database connection")?;
   let party = FetchUserParty::new(&client)
     .await?
     .run(session.user_id, params.party_id as i64)
     .await?;
 
   let (member_exp, love) = make_battle_member_exp_and_character_love(&party, &client, &session).await?;
-  Ok(Unsigned(BattleResultResponse {
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(BattleResultResponse {
     limit: 0,
     exp: 5,
     lvup: 0,
@@ -616,7 +846,15 @@ pub async fn marathon_single_result(
     love,
     member_exp,
     mission: params.clearquestmission,
-    reward: vec![],
+    reward: rewards
+      .iter()
+      .map(|item| BattleReward {
+        itemtype: item.item_type,
+        itemid: item.item_id,
+        itemnum: item.item_num,
+        is_rare: item.item_rare,
+      })
+      .collect(),
     clearreward: vec![],
     auto_progression_result: AutoProgressionResultResponse {
       auto_count: 0,
@@ -626,6 +864,9 @@ pub async fn marathon_single_result(
       reward_all: vec![],
       clearreward_all: vec![],
     },
-    firstclear: true,
-  }))
+    firstclear: false,
+  }));
+  response.remote.extend(update_items);
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
+  Ok(Unsigned(response))
 }
@@ -7,7 +7,10 @@ use crate::api::{MemberFameStats, NotificationData, RemoteDataItemType};
 use crate::call::{CallCustom, CallResponse};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
-use crate::member::{FetchUserMemberSkillsIn, FetchUserMembers, FetchUserMembersIn, FetchUserParty, Member, MemberActiveSkill, MemberPrototype, MemberStrength};
+use crate::member::{
+  apply_character_bonuses, FetchUserMemberSkillsIn, FetchUserMembers, FetchUserMembersIn, FetchUserParty, Member,
+  MemberActiveSkill, MemberPrototype, MemberStrength,
+};
 use crate::user::session::Session;
 use crate::AppState;
 use anyhow::Context;
@@ -268,6 +271,9 @@ pub async fn score_challenge_start(
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await
     .unwrap();
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
+    .await
+    .unwrap();
 
   Ok(Unsigned(ScoreChallengeStartResponse {
     party: party.to_battle_party(),
@@ -11,8 +11,8 @@ use crate::call::CallCustom;
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
 use crate::member::{
-  FetchUserMemberSkillsIn, FetchUserMembersIn, FetchUserParty, Member, MemberActiveSkill, MemberPrototype,
-  MemberStrength,
+  apply_character_bonuses, FetchUserMemberSkillsIn, FetchUserMembersIn, FetchUserParty, Member, MemberActiveSkill,
+  MemberPrototype, MemberStrength,
 };
 use crate::user::session::Session;
 use crate::AppState;
@@ -313,6 +313,7 @@ pub async fn marathon_multi_start(
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
   Ok(Unsigned(MarathonMultiStartResponse {
     user_host: vec![/*MarathonMultiStartUser {
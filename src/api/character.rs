@@ -1,11 +1,35 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tracing::warn;
+use tracing::{info, warn};
 
-use crate::api::master_all::get_masters;
-use crate::call::{CallCustom, CallResponse};
+use crate::api::battle::{grant_rewards, make_battle_start};
+use crate::api::{RemoteDataItemType, SpSkill};
+use crate::blob::{fetch_character_parameters, IntoRemoteData, UpdateCharacter, UpdateMember};
+use crate::call::{CallCustom, CallResponse, STATUS_ERROR, STATUS_MAX_LEVEL, STATUS_USE_ITEM_NUM_ZERO};
+use crate::character_enhance::{
+  get_character_enhance_stages, get_current_trial_end, get_enhance_stage, EnhanceStagePrototype,
+  FetchUserEnhanceStages, FinishUserEnhanceBattle, ReleaseUserEnhanceStage, StartUserEnhanceBattle,
+};
+use crate::database::QueryExecutor;
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
+use crate::item::{spend_items, FetchUserItemCount, IntoItemReference, ItemCost};
+use crate::level::get_intimacy_level_calculator;
+use crate::love_item::get_love_item;
+use crate::member::{apply_character_bonuses, FetchUserMemberSkillsIn, FetchUserMembers};
+use crate::piece_board::{
+  get_current_stages, get_piece_board_rewards, get_piece_board_stage, get_piece_boards, FetchUserPieceBoards,
+  ReachUserPieceBoardReward, ReleaseUserPieceBoardStage, UserPieceBoards,
+};
+use crate::shop::get_pack_items;
+use crate::special_skill::{get_sp_level_cap, get_sp_skill, SetUserSpSkillGroupLevel};
+use crate::story::{AddUserCharacterIntimacy, FetchUserCharacterIntimacy};
+use crate::user::id::UserId;
+use crate::user::session::Session;
+use crate::AppState;
 
 // See [Wonder_Api_CharacterPieceBoardInfoResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -26,41 +50,167 @@ pub struct PieceBoardInfo {
 
 #[derive(Debug, Deserialize)]
 pub struct CharacterPieceBoardInfoRequest {
-  pub character_id: i32,
+  pub character_id: i64,
+}
+
+/// Current stage of each board of a character, and reached rewards.
+fn piece_board_info(boards: &UserPieceBoards, character_id: i64) -> CharacterPieceBoardInfo {
+  let current = get_current_stages(&boards.released(character_id));
+  let mut reward_ids = get_piece_board_rewards()
+    .iter()
+    .filter(|reward| reward.character_id == character_id && boards.rewards.contains(&reward.reward_id))
+    .map(|reward| reward.reward_id as i32)
+    .collect::<Vec<_>>();
+  reward_ids.sort();
+
+  CharacterPieceBoardInfo {
+    board_info: get_piece_boards(character_id)
+      .into_iter()
+      .map(|board_id| PieceBoardInfo {
+        board_id: board_id as i32,
+        stage_id: current.get(&board_id).map_or(0, |stage| stage.stage_id as i32),
+      })
+      .collect(),
+    reward_ids,
+  }
 }
 
 // Thanks to https://youtu.be/2NG0ZLuhNNg.
 // There are multiple blessing paths called 'boards' internally (Fire & Light, Water & Wind, etc.),
 // each of which contains 8+4 stages (Elemental ATK boost, Stats up, etc.).
 pub async fn character_piece_board_info(
+  state: Arc<AppState>,
+  session: Arc<Session>,
   Params(params): Params<CharacterPieceBoardInfoRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: character_piece_board_info");
+  let client = state.get_database_client().await?;
+  let boards = FetchUserPieceBoards::new(&client).await?.run(session.user_id).await?;
+
+  Ok(Unsigned(piece_board_info(&boards, params.character_id)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterPieceBoardReleaseRequest {
+  pub character_id: i64,
+  pub stage_id: i64,
+}
 
-  let masters = get_masters().await;
-  let piece_boards: Vec<Value> = serde_json::from_str(&masters["character_piece_board"].master_decompressed).unwrap();
-  let piece_boards = piece_boards
+pub async fn character_piece_board_release(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<CharacterPieceBoardReleaseRequest>,
+) -> impl IntoHandlerResponse {
+  let Some(stage) = get_piece_board_stage(params.stage_id).filter(|stage| stage.character_id == params.character_id)
+  else {
+    warn!(?params, "unknown piece board stage");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let mut boards = FetchUserPieceBoards::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?;
+  let released = boards.released(stage.character_id);
+  if !stage.is_releasable(
+    &released,
+    &boards.reached_rewards(stage.character_id),
+    Utc::now().naive_utc(),
+  ) {
+    warn!(?params, "piece board stage can not be released");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  }
+
+  // Own pieces are spent first, whatever is left is taken from the last usable piece
+  let fetch_count = FetchUserItemCount::new(&transaction).await?;
+  let mut pieces = Vec::new();
+  let mut remaining = stage.piece_num;
+  for (index, piece_id) in stage.usable_piece_ids.iter().enumerate() {
+    let owned = fetch_count
+      .run(session.user_id, (RemoteDataItemType::CharacterPiece, *piece_id))
+      .await?;
+    let amount = if index == stage.usable_piece_ids.len() - 1 {
+      remaining
+    } else {
+      remaining.min(owned.quantity)
+    };
+    pieces.push(owned.item.into_counted(amount));
+    remaining -= amount;
+  }
+  let mut remote_data = match spend_items(&transaction, session.user_id, &ItemCost::items(pieces)).await? {
+    Ok(spent) => spent
+      .into_iter()
+      .flat_map(IntoRemoteData::into_remote_data)
+      .collect::<Vec<_>>(),
+    Err(error) => {
+      warn!(?params, %error, "not enough character pieces");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+
+  // Concurrent release of the same stage, pieces are not spent
+  if !ReleaseUserPieceBoardStage::new(&transaction)
+    .await?
+    .run(session.user_id, stage)
+    .await?
+  {
+    warn!(?params, "piece board stage is already released");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  }
+  boards
+    .released
+    .entry(stage.character_id)
+    .or_default()
+    .insert(stage.stage_id);
+  let release_count = released.len() as i32 + 1;
+
+  let reach_reward = ReachUserPieceBoardReward::new(&transaction).await?;
+  for reward in get_piece_board_rewards()
     .iter()
-    .filter(|board| board["chara_id"].as_str().unwrap().parse::<i32>().unwrap() == params.character_id)
+    .filter(|reward| reward.character_id == stage.character_id && reward.require_release_count <= release_count)
+  {
+    // Left unreached, so that it is received once the pack master is dumped
+    let items = get_pack_items(reward.pack_id);
+    if items.is_empty() {
+      warn!(?reward, "piece board reward has no known pack contents");
+      continue;
+    }
+
+    if !reach_reward.run(session.user_id, reward).await? {
+      continue;
+    }
+    boards.rewards.insert(reward.reward_id);
+    remote_data.extend(grant_rewards(&transaction, &state.settings.inventory, &session, items).await?);
+  }
+
+  // Stats of all members of the character change
+  let mut members = FetchUserMembers::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .into_iter()
+    .filter(|member| member.prototype.character_id == stage.character_id)
     .collect::<Vec<_>>();
-  let piece_board_stages: Vec<Value> =
-    serde_json::from_str(&masters["character_piece_board_stage"].master_decompressed).unwrap();
-
-  Ok(Unsigned(CallResponse::new_success(Box::new(CharacterPieceBoardInfo {
-    board_info: piece_boards
-      .iter()
-      .map(|board| {
-        let board_id: i32 = board["board_id"].as_str().unwrap().parse().unwrap();
-        let stage = piece_board_stages
-          .iter()
-          .find(|stage| stage["board_id"].as_str().unwrap().parse::<i32>().unwrap() == board_id)
-          .unwrap();
-        let stage_id: i32 = stage["stage_id"].as_str().unwrap().parse().unwrap();
-        PieceBoardInfo { board_id, stage_id: 0 }
-      })
-      .collect(),
-    reward_ids: vec![],
-  }))))
+  let mut members = members.iter_mut().collect::<Vec<_>>();
+  FetchUserMemberSkillsIn::new(&transaction)
+    .await?
+    .run(session.user_id, &mut members)
+    .await?;
+  apply_character_bonuses(&transaction, session.user_id, &mut members).await?;
+  for member in &members {
+    remote_data.extend(UpdateMember::new(member.to_member_parameter_wire()).into_remote_data());
+  }
+
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?params, ?release_count, "released piece board stage");
+
+  let mut response: CallResponse<dyn CallCustom> =
+    CallResponse::new_success(Box::new(piece_board_info(&boards, stage.character_id)));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
 }
 
 // See [Wonder_Api_CharacterEnhanceInfoResponseDto_Fields]
@@ -124,47 +274,380 @@ pub struct CharacterEnhanceMaterial {
 
 #[derive(Debug, Deserialize)]
 pub struct CharacterEnhanceInfoRequest {
-  pub character_id: i32,
+  pub character_id: i64,
 }
 
-// Thanks to https://youtu.be/o5UUz2kHhto for unbricking this endpoint
-pub async fn character_enhance_info(Params(params): Params<CharacterEnhanceInfoRequest>) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: character_enhance_info");
-
-  let masters = get_masters().await;
-  let trials: Vec<Value> = serde_json::from_str(&masters["character_enhance"].master_decompressed).unwrap();
-  let trials = trials
-    .iter()
-    .filter(|trial| trial["character_id"].as_str().unwrap().parse::<i32>().unwrap() == params.character_id)
-    .collect::<Vec<_>>();
+/// All stages of a character in the order they are laid out.
+fn character_enhance_info_for(character_id: i64) -> CharacterEnhanceInfo {
+  let now = Utc::now().naive_utc();
 
-  Ok(Unsigned(CharacterEnhanceInfo {
-    progress: trials
-      .iter()
-      .map(|trial| CharacterEnhanceInfoProgress {
-        root_id: trial["root_id"].as_str().unwrap().parse().unwrap(),
-        root_stage_id: trial["root_stage_id"].as_str().unwrap().parse().unwrap(),
-        stage_id: trial["stage_id"].as_str().unwrap().parse().unwrap(),
+  CharacterEnhanceInfo {
+    progress: get_character_enhance_stages(character_id)
+      .into_iter()
+      .map(|stage| CharacterEnhanceInfoProgress {
+        root_id: stage.root_id,
+        root_stage_id: stage.root_stage_id,
+        stage_id: stage.stage_id as i32,
         parameter: EnhanceParameter {
-          hp: 100,
-          attack: 50,
-          magicattack: 50,
-          defense: 30,
-          magicdefence: 30,
-          agility: 20,
-          dexterity: 20,
-          luck: 10,
+          hp: stage.stats.hp,
+          attack: stage.stats.attack,
+          magicattack: stage.stats.magicattack,
+          defense: stage.stats.defense,
+          magicdefence: stage.stats.magicdefence,
+          agility: stage.stats.agility,
+          dexterity: stage.stats.dexterity,
+          luck: stage.stats.luck,
+        },
+        unique_weapon_id: stage.unique_weapon_id,
+        specialskill: if stage.sp_id != 0 {
+          vec![EnhanceSpecialSkill {
+            sp_id: stage.sp_id,
+            sp_group_id: stage.sp_group_id,
+            sp_lv: stage.sp_lv,
+          }]
+        } else {
+          vec![]
         },
-        unique_weapon_id: 0,
-        specialskill: vec![],
         unique_stone: EnhanceUniqueStone {
-          unique_stone_id: 0,
+          unique_stone_id: stage.unique_stone_id,
           unique_stone_lv: 0,
         },
-        material_items: vec![],
-        money: 1000,
+        material_items: stage
+          .materials
+          .iter()
+          .map(|material| CharacterEnhanceMaterial {
+            item_type: material.item.item_type.into(),
+            item_id: material.item.item_id,
+            item_num: material.quantity,
+          })
+          .collect(),
+        money: stage.money,
       })
       .collect(),
-    trial_timestamp: chrono::Utc::now().timestamp(),
-  }))
+    trial_timestamp: get_current_trial_end(now).unwrap_or(now).and_utc().timestamp(),
+  }
+}
+
+// Thanks to https://youtu.be/o5UUz2kHhto for unbricking this endpoint
+pub async fn character_enhance_info(Params(params): Params<CharacterEnhanceInfoRequest>) -> impl IntoHandlerResponse {
+  Ok(Unsigned(character_enhance_info_for(params.character_id)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterEnhanceBattleStartRequest {
+  pub character_id: i64,
+  pub stage_id: i64,
+  #[serde(rename = "party_no")]
+  pub party_id: i32,
+}
+
+/// Returns the stage if its trial can be fought by the user now.
+async fn find_releasable_enhance_stage<'a>(
+  executor: impl Into<QueryExecutor<'a>> + Copy,
+  user_id: UserId,
+  character_id: i64,
+  stage_id: i64,
+) -> anyhow::Result<Option<&'static EnhanceStagePrototype>> {
+  let Some(stage) = get_enhance_stage(stage_id).filter(|stage| stage.character_id == character_id) else {
+    return Ok(None);
+  };
+
+  let now = Utc::now().naive_utc();
+  if get_current_trial_end(now).is_none() {
+    return Ok(None);
+  }
+
+  let released = FetchUserEnhanceStages::new(executor)
+    .await?
+    .run(user_id)
+    .await?
+    .released(character_id);
+  let intimacy = FetchUserCharacterIntimacy::new(executor)
+    .await?
+    .run(user_id)
+    .await?
+    .get(&character_id)
+    .copied()
+    .unwrap_or_default();
+  let rank = get_intimacy_level_calculator().get_level(intimacy);
+
+  Ok(Some(stage).filter(|stage| stage.is_releasable(&released, rank, now)))
+}
+
+pub async fn character_enhance_battle_start(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<CharacterEnhanceBattleStartRequest>,
+) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let stage = find_releasable_enhance_stage(&client, session.user_id, params.character_id, params.stage_id).await?;
+  if stage.is_none() {
+    warn!(?params, "trial of the ancients stage can not be fought");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  }
+  StartUserEnhanceBattle::new(&client)
+    .await?
+    .run(session.user_id, params.stage_id)
+    .await?;
+
+  make_battle_start(&state, &session, params.party_id).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterEnhanceBattleResultRequest {
+  pub character_id: i64,
+  pub stage_id: i64,
+  pub win: i32,
+}
+
+/// Releases the stage when its trial is won.
+pub async fn character_enhance_battle_result(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<CharacterEnhanceBattleResultRequest>,
+) -> impl IntoHandlerResponse {
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let started = FinishUserEnhanceBattle::new(&transaction)
+    .await?
+    .run(session.user_id, params.stage_id)
+    .await?;
+  let mut remote_data = Vec::new();
+  if params.win == 1 {
+    if !started {
+      warn!(?params, "trial of the ancients battle was not started");
+      return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+    }
+
+    let stage =
+      find_releasable_enhance_stage(&transaction, session.user_id, params.character_id, params.stage_id).await?;
+    let Some(stage) = stage else {
+      warn!(?params, "trial of the ancients stage can not be released");
+      return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+    };
+
+    let cost = ItemCost {
+      items: stage.materials.clone(),
+      money: stage.money,
+      ..Default::default()
+    };
+    match spend_items(&transaction, session.user_id, &cost).await? {
+      Ok(spent) => remote_data.extend(spent.into_iter().flat_map(IntoRemoteData::into_remote_data)),
+      Err(error) => {
+        warn!(?params, %error, "not enough materials for trial of the ancients stage");
+        return Ok(Unsigned(CallResponse::new_error(error.status())));
+      }
+    }
+
+    // Concurrent release of the same stage, materials are not spent
+    if !ReleaseUserEnhanceStage::new(&transaction)
+      .await?
+      .run(session.user_id, stage)
+      .await?
+    {
+      warn!(?params, "trial of the ancients stage is already released");
+      return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+    }
+
+    // Stats of all members of the character change
+    let mut members = FetchUserMembers::new(&transaction)
+      .await?
+      .run(session.user_id)
+      .await?
+      .into_iter()
+      .filter(|member| member.prototype.character_id == stage.character_id)
+      .collect::<Vec<_>>();
+    let mut members = members.iter_mut().collect::<Vec<_>>();
+    FetchUserMemberSkillsIn::new(&transaction)
+      .await?
+      .run(session.user_id, &mut members)
+      .await?;
+    apply_character_bonuses(&transaction, session.user_id, &mut members).await?;
+    for member in &members {
+      remote_data.extend(UpdateMember::new(member.to_member_parameter_wire()).into_remote_data());
+    }
+    info!(?params, "released trial of the ancients stage");
+  }
+
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let mut response: CallResponse<dyn CallCustom> =
+    CallResponse::new_success(Box::new(character_enhance_info_for(params.character_id)));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterPresentRequest {
+  pub character_id: i64,
+  pub item_id: i64,
+  pub item_num: i32,
+}
+
+/// Gives affinity gifts from `CharaPresentConfirmDialog` in the client.
+pub async fn character_present(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<CharacterPresentRequest>,
+) -> impl IntoHandlerResponse {
+  if params.item_num <= 0 {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_USE_ITEM_NUM_ZERO)));
+  }
+  let Some(exp) = get_love_item(params.item_id)
+    .and_then(|item| item.exp_for(params.character_id))
+    .filter(|exp| *exp > 0)
+  else {
+    warn!(?params, "unknown gift or gift is dedicated to another character");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let calculator = get_intimacy_level_calculator();
+  let Some(intimacy) = FetchUserCharacterIntimacy::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .get(&params.character_id)
+    .copied()
+  else {
+    warn!(?params, "character is not owned");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+  if intimacy >= calculator.get_max_xp() {
+    warn!(?params, ?intimacy, "character is already at max affinity rank");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_MAX_LEVEL)));
+  }
+  // Only consume the gifts needed to reach max rank
+  let needed = (calculator.get_max_xp() - intimacy + exp - 1) / exp;
+  let item_num = params.item_num.min(needed);
+
+  let gifts = (RemoteDataItemType::MaterialLove, params.item_id)
+    .into_item_reference()
+    .into_counted(item_num);
+  let mut remote_data = match spend_items(&transaction, session.user_id, &ItemCost::items(vec![gifts])).await? {
+    Ok(spent) => spent
+      .into_iter()
+      .flat_map(IntoRemoteData::into_remote_data)
+      .collect::<Vec<_>>(),
+    Err(error) => {
+      warn!(?params, %error, "not enough gifts");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+
+  let new_intimacy = AddUserCharacterIntimacy::new(&transaction)
+    .await?
+    .run(
+      session.user_id,
+      params.character_id,
+      exp.saturating_mul(item_num),
+      calculator.get_max_xp(),
+    )
+    .await?
+    .context("character disappeared")?;
+  info!(?params, ?item_num, ?intimacy, ?new_intimacy, "gave affinity gifts");
+
+  let character = fetch_character_parameters(&transaction, session.user_id)
+    .await?
+    .remove(&params.character_id)
+    .context("character disappeared")?;
+  remote_data.extend(UpdateCharacter::new(character).into_remote_data());
+
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterSpSkillLevelUpRequest {
+  pub character_id: i64,
+  pub skill_id: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CharacterSpSkillLevelUpResponse {
+  pub sp_skill: Vec<SpSkill>,
+}
+
+impl CallCustom for CharacterSpSkillLevelUpResponse {}
+
+/// Levels up a special skill group of a character by one.
+pub async fn character_sp_skill_level_up(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<CharacterSpSkillLevelUpRequest>,
+) -> impl IntoHandlerResponse {
+  let Some(skill) = get_sp_skill(params.skill_id).filter(|skill| skill.character_id == params.character_id) else {
+    warn!(?params, "unknown special skill");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let Some(mut character) = fetch_character_parameters(&transaction, session.user_id)
+    .await?
+    .remove(&params.character_id)
+  else {
+    warn!(?params, "character is not owned");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+  let Some(current) = character.sp_skill.iter().find(|sp_skill| sp_skill.id == skill.skill_id) else {
+    warn!(?params, "special skill is not owned");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+
+  let level = current.lv + 1;
+  if level > skill.max_level() {
+    warn!(?params, ?level, "special skill is already at max level");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_MAX_LEVEL)));
+  }
+  let trial_level = FetchUserEnhanceStages::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .sp_level(params.character_id, skill.group_id as i64);
+  let cap = get_sp_level_cap(skill, character.rank, trial_level);
+  if !skill.can_reach(level, cap, character.rank) {
+    warn!(?params, ?level, ?cap, rank = ?character.rank, "special skill level is not unlocked");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  }
+
+  SetUserSpSkillGroupLevel::new(&transaction)
+    .await?
+    .run(session.user_id, skill, level)
+    .await?;
+  for sp_skill in character
+    .sp_skill
+    .iter_mut()
+    .filter(|sp_skill| sp_skill.group_id == skill.group_id)
+  {
+    sp_skill.lv = level;
+  }
+
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?params, ?level, "leveled up special skill");
+
+  let sp_skill = character
+    .sp_skill
+    .iter()
+    .map(|sp_skill| SpSkill {
+      group_id: sp_skill.group_id,
+      id: sp_skill.id,
+      lv: sp_skill.lv,
+      is_trial: sp_skill.is_trial,
+    })
+    .collect();
+  let mut response: CallResponse<dyn CallCustom> =
+    CallResponse::new_success(Box::new(CharacterSpSkillLevelUpResponse { sp_skill }));
+  response.add_remote_data(UpdateCharacter::new(character).into_remote_data());
+
+  Ok(Unsigned(response))
 }
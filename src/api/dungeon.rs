@@ -12,8 +12,8 @@ use crate::call::{CallCustom, CallResponse};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
 use crate::member::{
-  FetchUserMemberSkillsIn, FetchUserMembers, FetchUserMembersIn, Member, MemberActiveSkill, MemberPrototype,
-  MemberStrength,
+  apply_character_bonuses, FetchUserMemberSkillsIn, FetchUserMembers, FetchUserMembersIn, Member, MemberActiveSkill,
+  MemberPrototype, MemberStrength,
 };
 use crate::user::session::Session;
 use crate::AppState;
@@ -398,6 +398,7 @@ async fn get_team_set(state: &AppState, session: &Session, member_num: usize) ->
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
   Ok(DungeonTeamSet {
     party: (1..=member_num)
@@ -443,6 +444,7 @@ async fn get_party_set(state: &AppState, session: &Session, member_num: usize) -
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
   Ok(DungeonPartySet {
     stage_party_set: DungeonStagePartySet {
@@ -549,6 +551,7 @@ pub async fn dungeon_area_challenge(
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
   Ok(Unsigned(DungeonAreaChallengeResponse {
     stage_state: DungeonStageState {
@@ -640,6 +643,7 @@ pub async fn dungeon_stage_party_info(
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
   Ok(Unsigned(DungeonStagePartyInfoResponse {
     party_set: DungeonPartySet {
@@ -790,6 +794,7 @@ pub async fn dungeon_battle_start(
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
   Ok(Unsigned(DungeonBattleStartResponse {
     chest: "10101111,10101120,10101131".to_string(),
@@ -1,18 +1,23 @@
 use anyhow::Context;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{debug, info, trace, warn};
+use tracing::{info, trace, warn};
 
 use crate::AppState;
 use crate::api::RemoteDataItemType;
-use crate::api::master_all::{get_master_manager, get_masters};
+use crate::api::battle::grant_rewards;
+use crate::api::master_all::get_master_manager;
+use crate::api::quest::QuestRewardItem;
 use crate::blob::{DeleteMember, IntoRemoteData};
-use crate::call::{CallCustom, CallResponse};
+use crate::call::{CallCustom, CallResponse, STATUS_ITEM_NOT_ENOUGH, STATUS_UNKNOWN_EXCHANGE};
+use crate::exchange::{
+  FetchUserExchanges, MAX_EXCHANGE_COUNT, UpdateUserExchange, get_exchange, get_exchange_item, get_exchange_items,
+};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed};
-use crate::item::UpdateItemCountBy;
+use crate::item::{FetchUserItemCount, UpdateItemCountBy};
 use crate::member::MemberPrototype;
 use crate::user::session::Session;
 
@@ -39,30 +44,27 @@ pub struct ExchangeListRequest {
 }
 
 pub async fn exchange_list(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<ExchangeListRequest>,
 ) -> impl IntoHandlerResponse {
-  let masters = get_masters().await;
-  let items: Vec<Value> = serde_json::from_str(&masters["exchange_item"].master_decompressed).unwrap();
-  let items = items
+  let now = Utc::now();
+  let client = state.get_database_client().await?;
+  let exchanges = FetchUserExchanges::new(&client).await?.run(session.user_id).await?;
+
+  let items = get_exchange_items()
     .iter()
-    .filter_map(|item| {
-      if item
-        .get("exchange_id")
-        .unwrap()
-        .as_str()
-        .unwrap()
-        .parse::<i32>()
-        .unwrap()
-        == params.exchange_master_id
-      {
-        Some(ExchangeItem {
-          exchange_reward_master_id: item.get("id").unwrap().as_str().unwrap().parse::<i32>().unwrap(),
-          limit: 1000,
-          exchange_num: 500,
-        })
-      } else {
-        None
+    .filter(|item| item.exchange_id == params.exchange_master_id as i64 && item.is_open(now.naive_utc()))
+    .map(|item| {
+      // Zero limit means unlimited, [exchange_num] is the remaining stock otherwise
+      let limit = item.limit().unwrap_or(0);
+      let exchanged = exchanges.get(&item.id).map_or(0, |exchange| {
+        exchange.current_count(item, now, &state.settings.daily_reset)
+      });
+      ExchangeItem {
+        exchange_reward_master_id: item.id as i32,
+        limit,
+        exchange_num: (limit - exchanged).max(0),
       }
     })
     .collect::<Vec<_>>();
@@ -161,9 +163,79 @@ pub struct ExchangeRequest {
 
 // exchange_reward_master_id=10001
 // num=1
-pub async fn exchange(session: Arc<Session>, Params(params): Params<ExchangeRequest>) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: exchange");
+/// Inert until `exchange_item` master is dumped: its columns are guessed and no exchange items are known,
+/// see [crate::exchange].
+pub async fn exchange(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<ExchangeRequest>,
+) -> impl IntoHandlerResponse {
+  let now = Utc::now();
+  let Some(prototype) = get_exchange_item(params.exchange_reward_master_id as i64).filter(|prototype| {
+    prototype.is_open(now.naive_utc())
+      && get_exchange(prototype.exchange_id).is_some_and(|exchange| exchange.is_open(now.naive_utc()))
+  }) else {
+    warn!(?params, "unknown or closed exchange item");
+    return Ok(Signed(CallResponse::new_error(STATUS_UNKNOWN_EXCHANGE), session));
+  };
+  if !(1..=MAX_EXCHANGE_COUNT).contains(&params.num) {
+    warn!(?params, "invalid exchange count");
+    return Ok(Signed(CallResponse::new_error(STATUS_UNKNOWN_EXCHANGE), session));
+  }
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let exchanged = FetchUserExchanges::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .get(&prototype.id)
+    .map_or(0, |exchange| {
+      exchange.current_count(prototype, now, &state.settings.daily_reset)
+    });
+  let (Some(total_count), Some(cost), Some(reward_num)) = (
+    exchanged.checked_add(params.num),
+    prototype.price.quantity.checked_mul(params.num),
+    prototype.reward.item_num.checked_mul(params.num),
+  ) else {
+    warn!(?params, "exchange amounts overflow");
+    return Ok(Signed(CallResponse::new_error(STATUS_UNKNOWN_EXCHANGE), session));
+  };
+  if prototype.limit().is_some_and(|limit| total_count > limit) {
+    let status = prototype.limit_kind.limit_status();
+    return Ok(Signed(CallResponse::new_error(status), session));
+  }
+
+  let medals = FetchUserItemCount::new(&transaction)
+    .await?
+    .run(session.user_id, prototype.price.item)
+    .await?;
+  if medals.quantity < cost {
+    return Ok(Signed(CallResponse::new_error(STATUS_ITEM_NOT_ENOUGH), session));
+  }
 
   // See [Wonder_Api_ExchangeResponseDto_Fields]
-  Ok(Signed((), session))
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  let medals = UpdateItemCountBy::new(&transaction)
+    .await?
+    .run(session.user_id, prototype.price.item, -cost)
+    .await
+    .context("failed to spend medals")?;
+  response.add_remote_data(medals.into_remote_data());
+
+  UpdateUserExchange::new(&transaction)
+    .await?
+    .run(session.user_id, prototype.id, total_count)
+    .await?;
+
+  let reward = QuestRewardItem {
+    item_num: reward_num,
+    ..prototype.reward.clone()
+  };
+  response.add_remote_data(grant_rewards(&transaction, &state.settings.inventory, &session, &[reward]).await?);
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?prototype.id, num = params.num, ?cost, "exchanged medals");
+
+  Ok(Signed(response, session))
 }
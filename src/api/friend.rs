@@ -7,9 +7,11 @@ use jwt_simple::prelude::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use tracing::{debug, info, trace, warn};
 
-use crate::call::CallCustom;
+use crate::call::{CallCustom, CallResponse};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed};
+use crate::mission::{record_mission_events, MissionEvent};
+use crate::notification::{IntoNotificationData, MissionDone};
 use crate::user::session::Session;
 use crate::AppState;
 
@@ -296,19 +298,30 @@ pub async fn greeting_send(
   session: Arc<Session>,
   Params(params): Params<GreetingSendRequest>,
 ) -> impl IntoHandlerResponse {
-  Ok(Signed(
-    GreetingSend {
-      item_count: 0,
-      send_data: vec![GreetingSendData {
-        user_no: "-1".to_owned(),
-        user_icon: 1083110,
-        user_name: "Megumin".to_owned(),
-        profile_comment: "Nah.".to_owned(),
-        first: true,
-      }],
-    },
-    session,
-  ))
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let missions = record_mission_events(
+    &transaction,
+    &state.settings.daily_reset,
+    session.user_id,
+    &[MissionEvent::Greeting],
+  )
+  .await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let mut response = CallResponse::new_success(Box::new(GreetingSend {
+    item_count: 0,
+    send_data: vec![GreetingSendData {
+      user_no: "-1".to_owned(),
+      user_icon: 1083110,
+      user_name: "Megumin".to_owned(),
+      profile_comment: "Nah.".to_owned(),
+      first: true,
+    }],
+  }));
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
+
+  Ok(Signed(response, session))
 }
 
 // See [Wonder_Api_FriendInfoResponseDto_Fields]
@@ -1,22 +1,30 @@
 use anyhow::Context;
 use jwt_simple::prelude::Serialize;
-use rand::seq::IteratorRandom;
+use chrono::Utc;
+use rand::seq::{IndexedRandom, IteratorRandom};
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
 use serde::Deserialize;
 use serde_json::{json, Value};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tracing::{info, warn};
 
 use crate::api::master_all::get_master_manager;
 use crate::api::{NotificationData, RemoteData, RemoteDataCommand, RemoteDataItemType};
+use crate::assist::{
+  get_assist_gacha_pool, get_assist_gacha_ticket, get_duplicate_medals, get_max_assist_level, AddUserAssist,
+  AddUserAssistGachaLog, FetchUserAssistGachaLog, FetchUserAssists, SetUserAssistLevel, ASSIST_MEDAL_ID,
+};
 use crate::blob::{AddMember, IntoRemoteData, UpdateMember};
-use crate::call::{CallCustom, CallResponse};
+use crate::call::{CallCustom, CallResponse, STATUS_ERROR, STATUS_MAX_HAVE_ITEM};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
-use crate::item::UpdateItemCountBy;
+use crate::inventory::{FetchUserInventory, InventoryKind};
+use crate::item::{spend_items, IntoItemReference, ItemCost, UpdateItemCountBy};
 use crate::member::MemberPrototype;
+use crate::mission::{record_mission_events, MissionEvent};
+use crate::notification::{IntoNotificationData, MissionDone};
 use crate::user::session::Session;
 use crate::{master, AppState};
 
@@ -1295,7 +1303,7 @@ async fn gacha_impl(
   gacha_id: i32,
   money_type: i32,
   amount: usize,
-) -> impl IntoHandlerResponse {
+) -> Unsigned<CallResponse<dyn CallCustom>> {
   let members = get_master_manager().get_master("member");
   let members = members
     .iter()
@@ -1314,6 +1322,17 @@ async fn gacha_impl(
   let mut client = state.get_database_client().await.unwrap();
   let transaction = client.transaction().await.unwrap();
 
+  // Any pulled member can be a duplicate that goes to the reserve
+  let inventory = FetchUserInventory::new(&transaction)
+    .await
+    .unwrap()
+    .run(session.user_id)
+    .await
+    .unwrap();
+  if inventory.free_space(InventoryKind::ReserveMember, &state.settings.inventory) < amount as i32 {
+    return Unsigned(CallResponse::new_error(STATUS_MAX_HAVE_ITEM));
+  }
+
   #[rustfmt::skip]
   let statement = transaction
     .prepare(/* language=postgresql */ r#"
@@ -1392,6 +1411,12 @@ async fn gacha_impl(
     }
   }
 
+  let event = MissionEvent::GachaPull { count: amount as i32 };
+  let missions = record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &[event])
+    .await
+    .unwrap();
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
+
   transaction.commit().await.unwrap();
 
   // Fixed very old bug back from commit [baa1fae0],
@@ -1413,6 +1438,100 @@ async fn gacha_impl(
   Unsigned(response)
 }
 
+/// Pulls [amount] assists for one [ticket_id] each, see [crate::assist].
+async fn assist_gacha_impl(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  gacha_id: i32,
+  ticket_id: i64,
+  amount: usize,
+) -> anyhow::Result<Unsigned<CallResponse<dyn CallCustom>>> {
+  let pool = get_assist_gacha_pool(Utc::now().naive_utc());
+  if pool.is_empty() {
+    warn!(?gacha_id, "assist gacha has no assists to pull");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  }
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let ticket = (RemoteDataItemType::AssistTicket, ticket_id)
+    .into_item_reference()
+    .into_counted(amount as i32);
+  let mut remote_data = match spend_items(&transaction, session.user_id, &ItemCost::items(vec![ticket])).await? {
+    Ok(spent) => spent
+      .into_iter()
+      .flat_map(IntoRemoteData::into_remote_data)
+      .collect::<Vec<_>>(),
+    Err(error) => {
+      warn!(?gacha_id, %error, "not enough assist tickets");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+
+  let mut owned = FetchUserAssists::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .into_iter()
+    .map(|assist| (assist.assist_id, assist))
+    .collect::<HashMap<_, _>>();
+  let add_assist = AddUserAssist::new(&transaction).await?;
+  let set_level = SetUserAssistLevel::new(&transaction).await?;
+  let update = UpdateItemCountBy::new(&transaction).await?;
+  let add_log = AddUserAssistGachaLog::new(&transaction).await?;
+
+  let mut goods = Vec::new();
+  for _ in 0..amount {
+    let assist = *pool.choose(&mut rand::rng()).unwrap();
+    let (good, is_new) = match owned.get_mut(&assist.assist_id) {
+      None => {
+        let added = add_assist
+          .run(session.user_id, assist.assist_id)
+          .await?
+          .context("assist was added concurrently")?;
+        remote_data.extend(added.to_remote().into_remote_data());
+        owned.insert(added.assist_id, added);
+        ((RemoteDataItemType::Assist, added.detail_id()).into_item_reference().into_counted(1), true)
+      }
+      // Duplicates level up the assist
+      Some(existing) if existing.level < get_max_assist_level(existing.assist_id) => {
+        existing.level += 1;
+        set_level.run(session.user_id, existing).await?;
+        remote_data.extend(existing.to_remote().into_remote_data());
+        let item = (RemoteDataItemType::Assist, existing.detail_id()).into_item_reference();
+        (item.into_counted(1), false)
+      }
+      // Duplicates of max level assists are exchanged for medals
+      Some(_) => {
+        let medal = (RemoteDataItemType::ExchangeMedal, ASSIST_MEDAL_ID).into_item_reference();
+        let medals = get_duplicate_medals(assist.rarity);
+        remote_data.extend(update.run(session.user_id, medal, medals).await?.into_remote_data());
+        (medal.into_counted(medals), false)
+      }
+    };
+    add_log.run(session.user_id, gacha_id as i64, &good).await?;
+    info!(?good, ?assist.assist_id, "pulled assist");
+    goods.push(GachaGood::new(good.item.item_type.into(), good.item.item_id, good.quantity, is_new));
+  }
+
+  let event = MissionEvent::GachaPull { count: amount as i32 };
+  let missions = record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &[event]).await?;
+
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(GachaResult {
+    gacha_id,
+    goods,
+    bonus_info: None,
+    bonus_step: None,
+  }));
+  response.add_remote_data(remote_data);
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
+
+  Ok(Unsigned(response))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GachaChainRequest {
   pub gacha_id: i32,
@@ -1425,7 +1544,11 @@ pub async fn gacha_chain(
   session: Arc<Session>,
   Params(params): Params<GachaChainRequest>,
 ) -> impl IntoHandlerResponse {
-  gacha_impl(state, session, params.gacha_id, params.money_type, 10).await
+  if let Some(ticket_id) = get_assist_gacha_ticket(params.gacha_id) {
+    return assist_gacha_impl(state, session, params.gacha_id, ticket_id, 10).await;
+  }
+
+  Ok(gacha_impl(state, session, params.gacha_id, params.money_type, 10).await)
 }
 
 #[derive(Debug, Deserialize)]
@@ -1440,7 +1563,11 @@ pub async fn gacha_normal(
   session: Arc<Session>,
   Params(params): Params<GachaNormalRequest>,
 ) -> impl IntoHandlerResponse {
-  gacha_impl(state, session, params.gacha_id, params.money_type, 1).await
+  if let Some(ticket_id) = get_assist_gacha_ticket(params.gacha_id) {
+    return assist_gacha_impl(state, session, params.gacha_id, ticket_id, 1).await;
+  }
+
+  Ok(gacha_impl(state, session, params.gacha_id, params.money_type, 1).await)
 }
 
 // See [Wonder_Api_GachadailyRequest_Fields]
@@ -1482,14 +1609,44 @@ pub struct GachaRateAssist {
 
 impl CallCustom for GachaRateAssist {}
 
+/// Every assist of the pool is equally likely, see [assist_gacha_impl].
 pub async fn gacha_rate_assist(Params(params): Params<GachaRateAssistRequest>) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: gacha_rate_assist");
+  let pool = get_assist_gacha_pool(Utc::now().naive_utc());
+  let probability = if pool.is_empty() {
+    Decimal::ZERO
+  } else {
+    Decimal::from(100) / Decimal::from(pool.len())
+  };
+
+  let rate = pool
+    .iter()
+    .map(|assist| GachaRateRate {
+      rarity: assist.rarity,
+      member_id: assist.unique_id(),
+      rate: (probability * Decimal::from(1000)).round().to_i32().unwrap(),
+      is_rate_up: false,
+      is_details_visible: false,
+      details_priority: 0,
+    })
+    .collect::<Vec<_>>();
+  let rare_rate = pool
+    .iter()
+    .fold(BTreeMap::new(), |mut acc, assist| {
+      *acc.entry(assist.rarity).or_insert(Decimal::ZERO) += probability;
+      acc
+    })
+    .into_iter()
+    .map(|(rare, rate)| GachaRateRare {
+      rare,
+      rate: (rate * Decimal::from(100)).round().to_i32().unwrap(),
+    })
+    .collect::<Vec<_>>();
 
   Ok(Unsigned(GachaRateAssist {
     gacha_id: params.gacha_id,
-    rate: vec![],
+    rate,
     limit_rate: vec![],
-    rare_rate: vec![],
+    rare_rate,
     limit_rare_rate: vec![],
   }))
 }
@@ -1516,10 +1673,28 @@ pub struct GachaLogItem {
   pub gacha_id: i32,
 }
 
-pub async fn gacha_assist_log() -> impl IntoHandlerResponse {
-  warn!("encountered stub: gacha_assist_log");
+/// Number of latest assist gacha pulls sent in [GachaAssistLog].
+const ASSIST_LOG_LIMIT: i64 = 50;
 
-  Ok(Unsigned(GachaAssistLog { goods: vec![] }))
+pub async fn gacha_assist_log(state: Arc<AppState>, session: Arc<Session>) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let log = FetchUserAssistGachaLog::new(&client)
+    .await?
+    .run(session.user_id, ASSIST_LOG_LIMIT)
+    .await?;
+
+  Ok(Unsigned(GachaAssistLog {
+    goods: log
+      .into_iter()
+      .map(|entry| GachaLogItem {
+        item_type: entry.item.item.item_type.into(),
+        item_id: entry.item.item.item_id,
+        item_num: entry.item.quantity,
+        time: entry.pulled_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        gacha_id: entry.gacha_id as i32,
+      })
+      .collect(),
+  }))
 }
 
 #[derive(Debug)]
@@ -1,3 +1,5 @@
+use crate::ad_reward::AdRewardStatus;
+use crate::api::ad_reward::{get_advertisement_data, AdvertisementData};
 use crate::call::CallCustom;
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed, Unsigned};
@@ -47,15 +49,22 @@ pub struct MemberInfo {
   pub member_ids: [i64; 5],
 }
 
-// See [Wonder_Api_AdvertisementDataResponseDto_Fields]
-#[derive(Debug, Serialize)]
-pub struct AdvertisementData {
-  pub id: i32,
-  pub reward_type: i32,
-  pub status: i32,
-}
-
 pub async fn home(state: Arc<AppState>, session: Arc<Session>) -> impl IntoHandlerResponse {
+  // Banner shows the first claimable slot, and disappears once all are used
+  let advertisement_data = get_advertisement_data(&state, &session, None).await?;
+  let advertisement_data = match advertisement_data
+    .iter()
+    .position(|data| data.status == AdRewardStatus::Available as i32)
+  {
+    Some(index) => advertisement_data.into_iter().nth(index),
+    None => advertisement_data.into_iter().next(),
+  }
+  .unwrap_or(AdvertisementData {
+    id: 0,
+    reward_type: 0,
+    status: AdRewardStatus::LimitReached as i32,
+  });
+
   let client = state.get_database_client().await?;
 
   #[rustfmt::skip]
@@ -113,11 +122,7 @@ pub async fn home(state: Arc<AppState>, session: Arc<Session>) -> impl IntoHandl
         current_member_id: home_current_illustration_id,
         member_ids: members,
       },
-      advertisement_data: AdvertisementData {
-        id: 10006,
-        reward_type: 1,
-        status: 0,
-      },
+      advertisement_data,
       display_plan_map: false,
     },
     session,
@@ -1,15 +1,18 @@
-use crate::api::master_all::get_masters;
-use crate::call::CallCustom;
+use crate::api::master_all::{get_master_manager, get_masters};
+use crate::call::{CallCustom, CallResponse, STATUS_ERROR};
+use crate::character_enhance::{get_character_enhance_stages, FetchUserEnhanceStages};
+use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed};
 use crate::level::get_intimacy_level_calculator;
 use crate::user::session::Session;
 use crate::AppState;
 use anyhow::Context;
+use chrono::Utc;
 use jwt_simple::prelude::Serialize;
-use serde::Serializer;
+use serde::{Deserialize, Serializer};
 use serde_json::Value;
 use std::sync::Arc;
-use tracing::info;
+use tracing::{info, warn};
 
 // See [Wonder_Api_InteractionResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -25,10 +28,14 @@ pub struct Character {
   /// "Affinity points". Does not wrap to zero when reaching next level.
   /// See 'intimacy_exp' master data.
   pub rank_progress: i32,
+  /// Selected home voice ID from `voice` master, empty if default
   pub voice: String,
+  /// Last released Trial of the Ancients stage of the first root, 0 if none
   pub character_enhance_stage_id: i32,
   pub character_enhance_badge: i32,
+  /// Released Trial of the Ancients stages of each root
   pub character_enhance_released_count: [i32; 4],
+  /// Selected home background ID from `background` master, empty if default
   pub bg: String,
 }
 
@@ -116,7 +123,9 @@ pub async fn interaction(state: Arc<AppState>, session: Arc<Session>) -> impl In
     .prepare(/* language=postgresql */ r#"
       select
         character_id,
-        intimacy
+        intimacy,
+        voice,
+        bg
       from user_characters
       where user_id = $1
     "#)
@@ -126,20 +135,33 @@ pub async fn interaction(state: Arc<AppState>, session: Arc<Session>) -> impl In
     .query(&statement, &[&session.user_id])
     .await
     .context("failed to execute query")?;
+  let enhance_stages = FetchUserEnhanceStages::new(&client).await?.run(session.user_id).await?;
+  let now = Utc::now().naive_utc();
 
   Ok(Signed(
     InteractionResponse {
       characters: rows
         .iter()
         .map(|row| {
+          let character_id: i64 = row.get(0);
+          let intimacy: i32 = row.get(1);
+          let voice: String = row.get(2);
+          let bg: String = row.get(3);
+          let released = enhance_stages.released(character_id);
+          // Badge is shown while there is a trial that can be fought
+          let rank = get_intimacy_level_calculator().get_level(intimacy);
+          let has_trial = get_character_enhance_stages(character_id)
+            .iter()
+            .any(|stage| stage.is_releasable(&released, rank, now));
+
           Character::new(
-            row.get(0),
-            row.get(1),
-            "".to_string(),
-            0,
-            0,
-            [0, 0, 0, 0],
-            "".to_string(),
+            character_id,
+            intimacy,
+            voice,
+            enhance_stages.current_stage_ids(character_id)[0],
+            has_trial as i32,
+            enhance_stages.released_count(character_id),
+            bg,
           )
         })
         .collect(),
@@ -147,3 +169,139 @@ pub async fn interaction(state: Arc<AppState>, session: Arc<Session>) -> impl In
     session,
   ))
 }
+
+/// Returns the member that unlocks [voice_id] for selection on the home screen of [character_id],
+/// `Some(0)` if it is always unlocked, or [None] if the voice can not be selected.
+fn get_voice_unlock_member(character_id: i64, voice_id: i64) -> Option<i64> {
+  get_master_manager()
+    .get_master("voice")
+    .iter()
+    .find(|voice| voice["id"].as_str().unwrap().parse::<i64>().unwrap() == voice_id)
+    .filter(|voice| voice["chara_id"].as_str().unwrap().parse::<i64>().unwrap() == character_id)
+    .filter(|voice| {
+      voice["playplace"]
+        .as_str()
+        .unwrap()
+        .split(',')
+        .any(|place| place == "voice_select")
+    })
+    .map(|voice| voice["unlock_member"].as_str().unwrap().parse::<i64>().unwrap())
+}
+
+fn is_known_background(bg_id: i64) -> bool {
+  get_master_manager()
+    .get_master("background")
+    .iter()
+    .any(|background| background["id"].as_str().unwrap().parse::<i64>().unwrap() == bg_id)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterVoiceSetRequest {
+  pub character_id: i64,
+  /// Empty to reset to the default voice
+  pub voice: String,
+}
+
+pub async fn character_voice_set(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<CharacterVoiceSetRequest>,
+) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+
+  if !params.voice.is_empty() {
+    let Some(unlock_member) = params
+      .voice
+      .parse::<i64>()
+      .ok()
+      .and_then(|voice_id| get_voice_unlock_member(params.character_id, voice_id))
+    else {
+      warn!(?params, "voice can not be selected");
+      return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+    };
+
+    #[rustfmt::skip]
+    let statement = client
+      .prepare(/* language=postgresql */ r#"
+        select 1
+        from user_members
+        where user_id = $1 and member_id = $2
+      "#)
+      .await
+      .context("failed to prepare statement")?;
+    if unlock_member != 0
+      && client
+        .query_opt(&statement, &[&session.user_id, &unlock_member])
+        .await
+        .context("failed to execute query")?
+        .is_none()
+    {
+      warn!(?params, ?unlock_member, "voice is not unlocked");
+      return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+    }
+  }
+
+  #[rustfmt::skip]
+  let statement = client
+    .prepare(/* language=postgresql */ r#"
+      update user_characters
+      set voice = $3
+      where user_id = $1 and character_id = $2
+    "#)
+    .await
+    .context("failed to prepare statement")?;
+  let rows_modified = client
+    .execute(&statement, &[&session.user_id, &params.character_id, &params.voice])
+    .await
+    .context("failed to execute query")?;
+  if rows_modified == 0 {
+    warn!(?params, "character is not owned");
+    return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+  }
+  info!(?params, "updated character voice");
+
+  let response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  Ok(Signed(response, session))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterBgSetRequest {
+  pub character_id: i64,
+  /// Empty to reset to the default background
+  pub bg: String,
+}
+
+pub async fn character_bg_set(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<CharacterBgSetRequest>,
+) -> impl IntoHandlerResponse {
+  // All backgrounds are granted at login, see [crate::blob]
+  if !params.bg.is_empty() && !params.bg.parse::<i64>().is_ok_and(is_known_background) {
+    warn!(?params, "unknown background");
+    return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+  }
+
+  let client = state.get_database_client().await?;
+  #[rustfmt::skip]
+  let statement = client
+    .prepare(/* language=postgresql */ r#"
+      update user_characters
+      set bg = $3
+      where user_id = $1 and character_id = $2
+    "#)
+    .await
+    .context("failed to prepare statement")?;
+  let rows_modified = client
+    .execute(&statement, &[&session.user_id, &params.character_id, &params.bg])
+    .await
+    .context("failed to execute query")?;
+  if rows_modified == 0 {
+    warn!(?params, "character is not owned");
+    return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+  }
+  info!(?params, "updated character background");
+
+  let response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  Ok(Signed(response, session))
+}
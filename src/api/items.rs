@@ -1,9 +1,23 @@
-use serde::Serialize;
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 
 use crate::api::master_all::get_master_manager;
-use crate::api::ApiRequest;
-use crate::call::CallCustom;
+use crate::api::{ApiRequest, RemoteDataItemType};
+use crate::blob::IntoRemoteData;
+use crate::call::{
+  CallCustom, CallResponse, STATUS_ERROR, STATUS_ITEM_NOT_HAVE, STATUS_MAX_LEVEL, STATUS_NOHAVE_ACCESSORY,
+  STATUS_NOHAVE_WEAPON,
+};
+use crate::equipment::UpdateUserEquipmentLocked;
+use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
+use crate::inventory::{ExpandUserInventory, FetchUserInventory, InventoryKind};
+use crate::item::{spend_items, ItemCost};
+use crate::user::session::Session;
+use crate::AppState;
 
 // See [Wonder_Api_WeaponlistResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -68,3 +82,96 @@ pub async fn accessory_list(_request: ApiRequest) -> impl IntoHandlerResponse {
     }).collect(),
   }))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ItemLockRequest {
+  pub item_type: i32,
+  pub target_item_id: i64,
+  #[serde(with = "crate::bool_as_int")]
+  pub is_lock: bool,
+}
+
+pub async fn item_lock(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<ItemLockRequest>,
+) -> impl IntoHandlerResponse {
+  let item_type = RemoteDataItemType::from(params.item_type);
+  let status = match item_type {
+    RemoteDataItemType::Weapon => STATUS_NOHAVE_WEAPON,
+    RemoteDataItemType::Accessory => STATUS_NOHAVE_ACCESSORY,
+    _ => return Ok(Unsigned(CallResponse::new_error(STATUS_ITEM_NOT_HAVE))),
+  };
+
+  let client = state.get_database_client().await?;
+  let Some(equipment) = UpdateUserEquipmentLocked::new(&client)
+    .await?
+    .run(session.user_id, item_type, params.target_item_id, params.is_lock)
+    .await?
+  else {
+    warn!(?params, "equipment not found");
+    return Ok(Unsigned(CallResponse::new_error(status)));
+  };
+  info!(?equipment, "updated equipment lock");
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_remote_data(equipment.to_remote().into_remote_data());
+
+  Ok(Unsigned(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BoxExpansionRequest {
+  /// See [InventoryKind]
+  #[serde(rename = "type")]
+  pub kind: i32,
+}
+
+pub async fn box_expansion(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<BoxExpansionRequest>,
+) -> impl IntoHandlerResponse {
+  let Ok(kind) = InventoryKind::try_from(params.kind) else {
+    warn!(?params, "unknown inventory kind");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let inventory = FetchUserInventory::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?;
+  if inventory.expansions(kind) >= state.settings.inventory.max_expansions {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_MAX_LEVEL)));
+  }
+
+  let cost = ItemCost {
+    quartz: state.settings.inventory.expansion_cost,
+    ..Default::default()
+  };
+  let remote_data = match spend_items(&transaction, session.user_id, &cost).await? {
+    Ok(spent) => spent
+      .into_iter()
+      .flat_map(IntoRemoteData::into_remote_data)
+      .collect::<Vec<_>>(),
+    Err(error) => {
+      warn!(?params, %error, "not enough quartz");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+
+  let expansions = ExpandUserInventory::new(&transaction)
+    .await?
+    .run(session.user_id, kind)
+    .await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?kind, ?expansions, "expanded inventory");
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
+}
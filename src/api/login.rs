@@ -12,9 +12,11 @@ use tracing::{debug, info, trace};
 use crate::api::NotificationData;
 use crate::build_info::BUILD_INFO;
 use crate::call::{CallCustom, CallResponse};
+use crate::comeback::claim_comeback;
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed};
-use crate::notification::{FriendGreetingNotify, IntoNotificationData};
+use crate::mission::{record_mission_events, MissionEvent};
+use crate::notification::{FriendGreetingNotify, IntoNotificationData, MissionDone};
 use crate::user::id::UserId;
 use crate::user::session::Session;
 use crate::user::uuid::UserUuid;
@@ -78,7 +80,7 @@ pub async fn login(state: Arc<AppState>, Params(params): Params<LoginRequestRequ
     .context("failed to execute query")?;
   trace!(?rows, "login query executed");
 
-  let (id, username, created_at, tutorial_progress) = if rows.is_empty() {
+  let (id, username, created_at, tutorial_progress, last_used) = if rows.is_empty() {
     #[rustfmt::skip]
     let statement = client
       .prepare(/* language=postgresql */ r#"
@@ -124,7 +126,7 @@ pub async fn login(state: Arc<AppState>, Params(params): Params<LoginRequestRequ
       .context("failed to execute device insert query")?;
 
     info!("created new user {}", id);
-    (id, username, created_at, tutorial_progress)
+    (id, username, created_at, tutorial_progress, None)
   } else {
     if rows.len() > 1 {
       todo!("multiple rows returned from login query (unique token constraint violated?)");
@@ -136,6 +138,22 @@ pub async fn login(state: Arc<AppState>, Params(params): Params<LoginRequestRequ
     let created_at: DateTime<Utc> = row.get(2);
     let tutorial_progress: i32 = row.get(3);
 
+    // Read before updating user_devices, needed to detect comebacks
+    #[rustfmt::skip]
+    let statement = client
+      .prepare(/* language=postgresql */ r#"
+        select max(last_used)
+        from user_devices
+        where user_id = $1
+      "#)
+      .await
+      .context("failed to prepare last used statement")?;
+    let last_used: Option<DateTime<Utc>> = client
+      .query_one(&statement, &[&id])
+      .await
+      .context("failed to execute last used query")?
+      .get(0);
+
     // update user_devices
     #[rustfmt::skip]
     let statement = client
@@ -168,8 +186,8 @@ pub async fn login(state: Arc<AppState>, Params(params): Params<LoginRequestRequ
       .context("failed to execute device update query")?;
     debug!("updated device info for user {}, token {}", id, uuid);
 
-    info!(?username, "user {} logged in", id);
-    (id, username, created_at, tutorial_progress)
+    info!(?username, ?last_used, "user {} logged in", id);
+    (id, username, created_at, tutorial_progress, last_used)
   };
 
   let session = Arc::new(Session::new(id, Some(uuid.to_string())));
@@ -189,6 +207,18 @@ pub async fn login(state: Arc<AppState>, Params(params): Params<LoginRequestRequ
 
   migrations::run_migrations(&session, &mut client).await;
 
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let missions = record_mission_events(
+    &transaction,
+    &state.settings.daily_reset,
+    session.user_id,
+    &[MissionEvent::Login],
+  )
+  .await?;
+  claim_comeback(&transaction, session.user_id, last_used, Utc::now()).await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
+
   response.add_remote_data(blob::get_login_remote_data(&state, &session).await);
   response.add_notifications(vec![
     // Jobs
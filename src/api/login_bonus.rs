@@ -1,10 +1,14 @@
 use std::sync::Arc;
 
+use anyhow::Context;
+use chrono::Utc;
 use jwt_simple::prelude::Serialize;
 
 use crate::call::CallCustom;
 use crate::handler::{IntoHandlerResponse, Signed};
+use crate::login_bonus::claim_login_bonuses;
 use crate::user::session::Session;
+use crate::AppState;
 
 // See [Wonder_Api_LoginbonusResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -89,33 +93,79 @@ pub struct RouletteLoginBonusSnsShareResult {
   pub result_pattern_id: i32,
 }
 
-pub async fn login_bonus(session: Arc<Session>) -> impl IntoHandlerResponse {
+pub async fn login_bonus(state: Arc<AppState>, session: Arc<Session>) -> impl IntoHandlerResponse {
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let claimed = claim_login_bonuses(&transaction, &state.settings.daily_reset, session.user_id, Utc::now()).await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let (omikuji_id, fortune_id) = claimed.omikuji.unwrap_or_default();
   Ok(Signed(
     LoginBonus {
-      goods: vec![
-        // LoginBonusGood::new(20001, 1, 3, 1, 1000),
-        // LoginBonusGood::new(40266, 1, 21, 17, 1),
-        // LoginBonusGood::new(40293, 1, 21, 17, 1),
-        // LoginBonusGood::new(40294, 1, 21, 17, 1),
-        // LoginBonusGood::new(80029, 1, 8, 1, 800),
-      ],
+      goods: claimed
+        .calendars
+        .iter()
+        .flat_map(|calendar| {
+          calendar.items.iter().map(|item| {
+            LoginBonusGood::new(
+              calendar.loginbonus_id as i32,
+              calendar.day,
+              item.item_type,
+              item.item_id as i32,
+              item.item_num,
+            )
+          })
+        })
+        .collect(),
       omikuji: Omikuji {
-        omikuji_id: 0,
-        fortune_id: 0,
+        omikuji_id: omikuji_id as i32,
+        fortune_id: fortune_id as i32,
       },
-      random_login_bonus: RandomLoginBonus {
-        random_loginbonus_id: 0,
-        lot_id: 0,
-        story_id: 0,
-        user_story_id: 0,
-        days: vec![],
+      random_login_bonus: match claimed.random {
+        Some(random) => RandomLoginBonus {
+          random_loginbonus_id: random.prototype.random_loginbonus_id as i32,
+          lot_id: random.prototype.lot_id as i32,
+          story_id: random.prototype.story_id as i32,
+          user_story_id: 0,
+          days: random
+            .days
+            .iter()
+            .map(|result| RandomLoginBonusDay {
+              day: result.day,
+              pattern_id: result.result_id as i32,
+            })
+            .collect(),
+        },
+        None => RandomLoginBonus {
+          random_loginbonus_id: 0,
+          lot_id: 0,
+          story_id: 0,
+          user_story_id: 0,
+          days: vec![],
+        },
       },
-      roulette_login_bonus: RouletteLoginBonus {
-        roulette_loginbonus_id: 0,
-        result_pattern_id: 0,
-        roulette_view_id: 0,
-        days: vec![],
-        sns_share_results: vec![],
+      roulette_login_bonus: match claimed.roulette {
+        Some(roulette) => RouletteLoginBonus {
+          roulette_loginbonus_id: roulette.prototype.roulette_loginbonus_id as i32,
+          result_pattern_id: roulette.result_id as i32,
+          roulette_view_id: roulette.prototype.view_id(roulette.days.len() as i32) as i32,
+          days: roulette
+            .days
+            .iter()
+            .map(|result| RouletteLoginBonusDay {
+              day: result.day,
+              result_pattern_id: result.result_id as i32,
+            })
+            .collect(),
+          sns_share_results: vec![],
+        },
+        None => RouletteLoginBonus {
+          roulette_loginbonus_id: 0,
+          result_pattern_id: 0,
+          roulette_view_id: 0,
+          days: vec![],
+          sns_share_results: vec![],
+        },
       },
     },
     session,
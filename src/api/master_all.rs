@@ -485,8 +485,9 @@ pub struct MasterManager {
 
 impl MasterManager {
   pub fn new(opaque_masters: &HashMap<String, MasterAllItem>) -> Self {
-    // Large and not needed on server side
-    let blacklist = ["pack", "assetname", "text", "voice", "navi"];
+    // Large and not needed on server side.
+    // `pack` is parsed as well: shop items and rewards are granted from it when it is dumped.
+    let blacklist = ["assetname", "text", "voice", "navi"];
 
     let start = Instant::now();
     let mut masters = HashMap::new();
@@ -506,6 +507,33 @@ impl MasterManager {
   pub fn get_master(&self, key: &str) -> &Vec<Value> {
     self.masters.get(key).expect(&format!("master {:?} not found", key))
   }
+
+  /// Same as [get_master], for masters that are not always present in the masters directory.
+  pub fn try_get_master(&self, key: &str) -> Option<&Vec<Value>> {
+    self.masters.get(key)
+  }
+
+  /// Value of a `system` master entry, e.g. `skip_ticket_max_per_request`.
+  pub fn get_system_value(&self, key: &str) -> Option<&str> {
+    self
+      .get_master("system")
+      .iter()
+      .find(|entry| entry["key"].as_str() == Some(key))
+      .and_then(|entry| entry["value"].as_str())
+  }
+}
+
+/// Parses a numeric master column, panics if it is missing.
+pub fn parse_i64(value: &Value) -> i64 {
+  value.as_str().unwrap().parse::<i64>().unwrap()
+}
+
+/// Same as [parse_i64], reads missing columns as zero.
+pub fn parse_i64_or_zero(value: &Value) -> i64 {
+  value
+    .as_str()
+    .and_then(|value| value.parse::<i64>().ok())
+    .unwrap_or_default()
 }
 
 pub static MASTER_MANAGER: OnceLock<MasterManager> = OnceLock::new();
@@ -1,23 +1,35 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use tracing::warn;
+use tracing::{debug, warn};
 
-use crate::api::master_all::{get_master_manager, get_masters};
+use crate::api::master_all::get_master_manager;
 use crate::api::{battle, ApiRequest, NotificationData};
 use crate::api::battle::{apply_reward_multiplier, grant_rewards, make_battle_member_exp_and_character_love, AutoProgressionResultResponse, BattleResultResponse};
-use crate::api::quest::parse_reward_items;
+use crate::api::quest::drop::roll_stage_rewards;
 use crate::api::quest::quest_hunting::BattleReward;
-use crate::call::{CallCustom, CallResponse};
+use crate::call::{CallCustom, CallResponse, STATUS_EVENT_NOT_OPEN};
+use crate::event::{
+  get_boss_count_milestones, get_event_bosses, get_event_stages, roll_emergency_boss, EventBossPrototype, EventConfig,
+  FetchEmergencyBossTotalKills, FetchEventTotals, FetchUserBossCountRewards, FetchUserEmergencyBoss,
+  FetchUserEventBosses, FetchUserEventPoints, ReceiveUserBossCountReward, UserEmergencyBoss, UserEventBoss,
+};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed, Unsigned};
+use crate::user::quest_progress::{
+  tasks_from_clear_missions, FetchUserEventQuestProgress, QuestKind, QuestProgress, RecordUserQuestClear,
+};
 use crate::user::session::Session;
 use crate::AppState;
 use crate::member::FetchUserParty;
-use crate::notification::MissionDone;
+use crate::mission::{fetch_user_missions, get_missions, record_mission_events, MissionEvent};
+use crate::notification::{IntoNotificationData, MissionDone};
+use crate::database::QueryExecutor;
+use crate::api::RemoteData;
+use crate::shop::get_pack_items;
 
 #[derive(Debug, Serialize)]
 pub struct MissionList {
@@ -40,7 +52,7 @@ pub struct Mission {
   pub is_challenge: i32,
 }
 
-#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 pub enum MissionKind {
   Beginner = 1,
@@ -57,37 +69,33 @@ pub struct MissionListRequest {
 }
 
 pub async fn mission_list(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<MissionListRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: mission_list");
+  debug!(?params, "mission list");
 
-  let masters = get_masters().await;
-  let missions: Vec<Value> = serde_json::from_str(&masters["mission"].master_decompressed).unwrap();
-  let event_missions: Vec<Value> = serde_json::from_str(&masters["event_mission"].master_decompressed).unwrap();
+  let client = state.get_database_client().await?;
+  let now = chrono::Utc::now();
+  let progress = fetch_user_missions(&client, &state.settings.daily_reset, session.user_id, now).await?;
 
-  let missions = missions
-    .into_iter()
-    .chain(event_missions.into_iter().take(100))
+  let mut open_events = HashMap::new();
+  let missions = get_missions()
+    .iter()
+    .filter(|mission| {
+      mission.is_active(now.naive_utc())
+        && (mission.event_id == 0
+          || *open_events.entry(mission.event_id).or_insert_with(|| {
+            EventConfig::get(mission.event_id).is_some_and(|config| config.is_open(now.naive_utc()))
+          }))
+    })
     .map(|mission| {
-      let kind = mission.get("mission_type").unwrap().as_str().unwrap();
+      let progress = progress.get(&mission.mission_id).cloned().unwrap_or_default();
       Mission {
-        mission_id: mission
-          .get("mission_id")
-          .unwrap()
-          .as_str()
-          .unwrap()
-          .parse::<i32>()
-          .unwrap(),
-        kind: match kind {
-          "NORMAL" => MissionKind::Normal as i32,
-          "DAILY" => MissionKind::Daily as i32,
-          "BEGINNER" => MissionKind::Beginner as i32,
-          "EVENT" => MissionKind::Event as i32,
-          _ => todo!("unknown mission type: {}", kind),
-        },
-        progress: 0,
-        received: 0,
+        mission_id: mission.mission_id as i32,
+        kind: mission.kind as i32,
+        progress: progress.progress,
+        received: progress.received as i32,
         newmisson: 0,
         is_challenge: 1,
       }
@@ -171,30 +179,145 @@ pub struct BattleMarathonInfoRequest {
   pub event_id: i32,
 }
 
+/// 0 - locked, 1 - unlocked, 2 - defeated
+fn event_boss_status(
+  boss: &EventBossPrototype,
+  progress: &HashMap<i64, QuestProgress>,
+  emergency: Option<&UserEmergencyBoss>,
+  state: Option<&UserEventBoss>,
+) -> i32 {
+  if !boss.is_available(progress, emergency) {
+    0
+  } else if state.is_some_and(|state| state.kill_count > 0) {
+    2
+  } else {
+    1
+  }
+}
+
+/// Boss counters are reached when all users together collect enough event points.
+async fn make_total_boss_info(
+  executor: impl Into<QueryExecutor<'_>>,
+  config: &EventConfig,
+  bosses: &HashMap<i64, UserEventBoss>,
+  received: &HashSet<i64>,
+) -> anyhow::Result<TotalBossInfo> {
+  let totals = FetchEventTotals::new(executor).await?.run(config.event_id).await?;
+
+  Ok(TotalBossInfo {
+    total_defeat_count: totals.kill_count as i32,
+    my_defeat_count: bosses.values().map(|boss| boss.kill_count).sum(),
+    boss_count_rewards: get_boss_count_milestones(config.boss_count_group_id)
+      .into_iter()
+      .map(|milestone| BossCountRewards {
+        count_id: milestone.id as i32,
+        is_received: received.contains(&milestone.id),
+      })
+      .collect(),
+    ranking: 0,
+    in_ranking_period: false,
+  })
+}
+
+/// Grants rewards of reached boss counters to a user that collected points in the event.
+/// Counters with unknown pack contents are left unreceived, so that they are granted once `pack` master is dumped.
+async fn grant_boss_count_rewards(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  state: &AppState,
+  session: &Session,
+  config: &EventConfig,
+  received: &mut HashSet<i64>,
+) -> anyhow::Result<Vec<RemoteData>> {
+  let points = FetchUserEventPoints::new(transaction)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  if points == 0 {
+    return Ok(Vec::new());
+  }
+  let totals = FetchEventTotals::new(transaction).await?.run(config.event_id).await?;
+
+  let reached = get_boss_count_milestones(config.boss_count_group_id)
+    .into_iter()
+    .filter(|milestone| milestone.count_point <= totals.points && !received.contains(&milestone.id))
+    .collect::<Vec<_>>();
+
+  let receive = ReceiveUserBossCountReward::new(transaction).await?;
+  let mut remote_data = Vec::new();
+  for milestone in reached {
+    let items = get_pack_items(milestone.pack_id);
+    if items.is_empty() {
+      warn!(?milestone, "boss counter reward has no known pack contents");
+      continue;
+    }
+
+    if !receive.run(session.user_id, config.event_id, milestone.id).await? {
+      continue;
+    }
+    received.insert(milestone.id);
+    remote_data.extend(grant_rewards(transaction, &state.settings.inventory, session, items).await?);
+  }
+
+  Ok(remote_data)
+}
+
 pub async fn battle_marathon_info(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<BattleMarathonInfoRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: battle_marathon_info");
+  let Some(config) = EventConfig::get(params.event_id as i64) else {
+    return Ok(Signed(CallResponse::new_error(STATUS_EVENT_NOT_OPEN), session));
+  };
 
-  Ok(Signed(
-    BattleMarathonInfo {
-      opflag: 0,
-      boss: Boss {
-        quest_id: 0,
-        status: 0,
-        kill: 0,
-      },
-      total_boss_info: TotalBossInfo {
-        total_defeat_count: 0,
-        my_defeat_count: 0,
-        boss_count_rewards: vec![],
-        ranking: 0,
-        in_ranking_period: false,
-      },
-    },
-    session,
-  ))
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let mut received = FetchUserBossCountRewards::new(&transaction)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  let remote_data = grant_boss_count_rewards(&transaction, &state, &session, &config, &mut received).await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let progress = FetchUserEventQuestProgress::new(&client)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  let bosses = FetchUserEventBosses::new(&client)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  let emergency = FetchUserEmergencyBoss::new(&client)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+
+  // Show the strongest boss that can be fought
+  let boss = get_event_bosses(config.event_id, false)
+    .into_iter()
+    .rev()
+    .find(|boss| boss.is_available(&progress, emergency.as_ref()))
+    .map(|boss| {
+      let state = bosses.get(&boss.quest_id);
+      Boss {
+        quest_id: boss.quest_id as i32,
+        status: event_boss_status(&boss, &progress, emergency.as_ref(), state),
+        kill: state.map_or(0, |state| state.kill_count),
+      }
+    })
+    .unwrap_or(Boss {
+      quest_id: 0,
+      status: 0,
+      kill: 0,
+    });
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(BattleMarathonInfo {
+    opflag: 0,
+    boss,
+    total_boss_info: make_total_boss_info(&client, &config, &bosses, &received).await?,
+  }));
+  response.add_remote_data(remote_data);
+  Ok(Signed(response, session))
 }
 
 // See [Wonder_Api_MarathonInfoResponseDto_Fields]
@@ -223,6 +346,7 @@ pub struct MultiBattleInvitationRoom {
 #[derive(Debug, Serialize)]
 pub struct EmergencyBossInfo {
   pub emergency_boss_id: i32,
+  /// 0 - none, 1 - appeared, 2 - defeated or expired
   pub status: i32,
   pub total_defeat_count: i32,
   pub my_defeat_count: i32,
@@ -239,33 +363,64 @@ pub struct MarathonInfoRequest {
 }
 
 pub async fn marathon_info(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<MarathonInfoRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: marathon_info");
-  Ok(Signed(
-    MarathonInfo {
-      opflag: 0,
-      boss: 1,
-      open_scorechallenge: true,
-      multi_battle_invitation: None,
-      total_boss_info: TotalBossInfo {
-        total_defeat_count: 0,
-        my_defeat_count: 0,
-        boss_count_rewards: vec![],
-        ranking: 0,
-        in_ranking_period: false,
-      },
-      emergency_boss_info: EmergencyBossInfo {
-        emergency_boss_id: 0,
-        status: 0,
-        total_defeat_count: 0,
-        my_defeat_count: 0,
-        ranking: 0,
-      },
+  let Some(config) = EventConfig::get(params.event_id as i64) else {
+    return Ok(Signed(CallResponse::new_error(STATUS_EVENT_NOT_OPEN), session));
+  };
+
+  let client = state.get_database_client().await?;
+  let progress = FetchUserEventQuestProgress::new(&client)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  let bosses = FetchUserEventBosses::new(&client)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  let emergency = FetchUserEmergencyBoss::new(&client)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  let received = FetchUserBossCountRewards::new(&client)
+    .await?
+    .run(session.user_id, config.event_id)
+    .await?;
+  let boss_available = get_event_bosses(config.event_id, false)
+    .iter()
+    .any(|boss| boss.is_available(&progress, emergency.as_ref()));
+
+  let emergency_boss_info = match emergency {
+    Some(emergency) => EmergencyBossInfo {
+      emergency_boss_id: emergency.emergency_boss_id as i32,
+      status: if emergency.is_active { 1 } else { 2 },
+      total_defeat_count: FetchEmergencyBossTotalKills::new(&client)
+        .await?
+        .run(config.event_id, emergency.emergency_boss_id)
+        .await? as i32,
+      my_defeat_count: emergency.kill_count,
+      ranking: 0,
     },
-    session,
-  ))
+    None => EmergencyBossInfo {
+      emergency_boss_id: 0,
+      status: 0,
+      total_defeat_count: 0,
+      my_defeat_count: 0,
+      ranking: 0,
+    },
+  };
+
+  let response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(MarathonInfo {
+    opflag: 0,
+    boss: boss_available as i32,
+    open_scorechallenge: config.has_score_challenge(),
+    multi_battle_invitation: None,
+    total_boss_info: make_total_boss_info(&client, &config, &bosses, &received).await?,
+    emergency_boss_info,
+  }));
+  Ok(Signed(response, session))
 }
 
 // See [Wonder_Api_MarathonStageListResponseDto_Fields]
@@ -295,23 +450,42 @@ pub struct MarathonStageListRequest {
 }
 
 pub async fn marathon_stage_list(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<MarathonStageListRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: marathon_stage_list");
+  let client = state.get_database_client().await?;
+  let progress = FetchUserEventQuestProgress::new(&client)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?;
 
-  let masters = get_masters().await;
-  let quests: Vec<Value> = serde_json::from_str(&masters["event_marathon_quest_stage"].master_decompressed).unwrap();
-  let quests = quests
+  let quests = get_event_stages(params.event_id as i64)
     .into_iter()
-    .filter(|quest| quest.get("event_id").unwrap().as_str().unwrap().parse::<i32>().unwrap() == params.event_id)
-    .map(|quest| MarathonStageQuest {
-      quest_id: quest.get("id").unwrap().as_str().unwrap().parse::<i32>().unwrap(),
-      status: 3,
-      task1: 0,
-      task2: 0,
-      task3: 0,
-      hardnum: 0,
+    .map(|stage| {
+      let unlocked = stage
+        .unlock_stages
+        .iter()
+        .all(|stage| progress.get(stage).is_some_and(|progress| progress.clear_count > 0));
+      let stage_progress = progress.get(&stage.quest_id).cloned().unwrap_or_default();
+      let status = if !unlocked {
+        0
+      } else if stage_progress.is_all_clear() {
+        3
+      } else if stage_progress.clear_count > 0 {
+        2
+      } else {
+        1
+      };
+
+      MarathonStageQuest {
+        quest_id: stage.quest_id as i32,
+        status,
+        task1: stage_progress.tasks[0] as i32,
+        task2: stage_progress.tasks[1] as i32,
+        task3: stage_progress.tasks[2] as i32,
+        hardnum: 0,
+      }
     })
     .collect::<Vec<_>>();
 
@@ -362,9 +536,40 @@ pub async fn marathon_quest_result(
     .iter()
     .map(|reward| (reward["id"].as_str().unwrap().parse::<i32>().unwrap(), reward))
     .collect::<HashMap<_, _>>();
-  let mut rewards = parse_reward_items(rewards[&params.quest_id]);
+  let mut rewards = roll_stage_rewards(&state.settings.drops, Some(QuestKind::Event), rewards[&params.quest_id]);
   apply_reward_multiplier(&transaction, &session, &mut rewards).await?;
-  let update_items = grant_rewards(&transaction, &session, &rewards).await?;
+  let update_items = grant_rewards(&transaction, &state.settings.inventory, &session, &rewards).await?;
+  let firstclear = if params.win == 1 {
+    let progress = RecordUserQuestClear::new(&transaction)
+      .await?
+      .run(
+        session.user_id,
+        QuestKind::Event,
+        params.event_id as i64,
+        params.quest_id as i64,
+        tasks_from_clear_missions(&params.clearquestmission),
+        1,
+      )
+      .await?;
+    if let Some(config) = EventConfig::get(params.event_id as i64) {
+      roll_emergency_boss(&transaction, &state.settings.drops, session.user_id, &config).await?;
+    }
+    progress.clear_count == 1
+  } else {
+    false
+  };
+  let missions = if params.win == 1 {
+    let event = MissionEvent::BattleClear {
+      kind: QuestKind::Event,
+      event_id: params.event_id as i64,
+      quest_id: params.quest_id as i64,
+      difficulty: 0,
+      count: 1,
+    };
+    record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &[event]).await?
+  } else {
+    Vec::new()
+  };
   transaction.commit().await.context("failed to commit transaction")?;
 
   let party = FetchUserParty::new(&client)
@@ -400,7 +605,7 @@ pub async fn marathon_quest_result(
       reward_all: vec![],
       clearreward_all: vec![],
     },
-    firstclear: true,
+    firstclear,
   }));
   response.remote.extend(update_items);
   // TODO: Send remote data to actually update characters stats
@@ -418,6 +623,7 @@ pub async fn marathon_quest_result(
     NotificationData::new(1, 10, 230731, 52307325, "".to_string(), "".to_string()),
     NotificationData::new(1, 10, 230831, 52308305, "".to_string(), "".to_string()),
   ]);
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
 
   Ok(Unsigned(response))
 }
@@ -455,6 +661,14 @@ pub struct BattleMarathonBoss {
 
 impl CallCustom for MarathonBossList {}
 
+#[derive(Debug, Serialize)]
+pub struct BattleMarathonBossList {
+  #[serde(rename = "boss")]
+  pub bosses: Vec<BattleMarathonBoss>,
+}
+
+impl CallCustom for BattleMarathonBossList {}
+
 #[derive(Debug, Deserialize)]
 pub struct MarathonBossListRequest {
   pub event_id: i32,
@@ -463,25 +677,84 @@ pub struct MarathonBossListRequest {
 }
 
 pub async fn marathon_boss_list(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<MarathonBossListRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: marathon_boss_list");
+  let client = state.get_database_client().await?;
+  let progress = FetchUserEventQuestProgress::new(&client)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?;
+  // Multi battles are not tracked yet, their bosses are only unlocked by event stages
+  let states = FetchUserEventBosses::new(&client)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?;
+  let emergency = FetchUserEmergencyBoss::new(&client)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?;
 
-  let bosses = if params.is_multi {
-    get_master_manager().get_master("event_marathon_quest_stage_boss_multi")
-  } else {
-    get_master_manager().get_master("event_marathon_quest_stage_boss_single")
-  };
-  let bosses = bosses
+  let bosses = get_event_bosses(params.event_id as i64, params.is_multi)
     .into_iter()
-    .filter(|boss| boss.get("event_id").unwrap().as_str().unwrap().parse::<i32>().unwrap() == params.event_id)
-    .map(|boss| MarathonBoss {
-      quest_id: boss.get("id").unwrap().as_str().unwrap().parse::<i32>().unwrap(),
-      status: 1,
-      kill: 1,
+    .map(|boss| {
+      let state = states.get(&boss.quest_id);
+      MarathonBoss {
+        quest_id: boss.quest_id as i32,
+        status: event_boss_status(&boss, &progress, emergency.as_ref(), state),
+        kill: state.map_or(0, |state| state.kill_count),
+      }
     })
     .collect::<Vec<_>>();
 
   Ok(Signed(MarathonBossList { bosses }, session))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct BattleMarathonBossListRequest {
+  pub event_id: i32,
+}
+
+/// Single bosses with the HP left from earlier attempts, see [UserEventBoss::remaining_hp].
+pub async fn battle_marathon_boss_list(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<BattleMarathonBossListRequest>,
+) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let progress = FetchUserEventQuestProgress::new(&client)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?;
+  let states = FetchUserEventBosses::new(&client)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?;
+  let emergency = FetchUserEmergencyBoss::new(&client)
+    .await?
+    .run(session.user_id, params.event_id as i64)
+    .await?;
+
+  let bosses = get_event_bosses(params.event_id as i64, false)
+    .into_iter()
+    .map(|boss| {
+      let state = states.get(&boss.quest_id).cloned().unwrap_or_default();
+      let hp = boss.max_hp().map(|hp| state.remaining_hp(&hp)).unwrap_or_default();
+      let hp = |index: usize| hp.get(index).copied().unwrap_or(0) as i32;
+      BattleMarathonBoss {
+        quest_id: boss.quest_id as i32,
+        hp1: hp(0),
+        hp2: hp(1),
+        hp3: hp(2),
+        status: event_boss_status(&boss, &progress, emergency.as_ref(), Some(&state)),
+        kill: state.kill_count,
+        limit_num: 0,
+        display: 1,
+        ticket_ratio: state.ticket_ratio,
+      }
+    })
+    .collect::<Vec<_>>();
+
+  Ok(Signed(BattleMarathonBossList { bosses }, session))
+}
@@ -60,7 +60,7 @@ pub enum RemoteDataCommand {
 // TODO: See for more types:
 //  [Wonder.UI.ItemInfoAdapterFactory$$Create_82029124]
 //  [Wonder.Util.UserParam$$GetList]
-#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum RemoteDataItemType {
   /* IDA static analysis */
   /// "Eris"
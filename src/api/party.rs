@@ -1,25 +1,35 @@
-use std::collections::{BTreeMap, HashMap};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
 use anyhow::Context;
+use itertools::Itertools;
 use rand::seq::IndexedMutRandom;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 
 use crate::api::dungeon::{PartyAccessory, PartyMember, PartyWeapon};
-use crate::api::master_all::get_master_manager;
-use crate::api::party_info::{party_info, Party};
+use crate::api::party_info::{party_info, Party, PartyForm, PartyStrengthCalculator};
 use crate::api::{ApiRequest, MemberFameStats, NotificationData, RemoteDataItemType};
-use crate::blob::{IntoRemoteData, UpdateMember};
-use crate::call::{CallCustom, CallResponse};
+use crate::assist::{get_assist, FetchUserAssists};
+use crate::blob::{DeleteMember, IntoRemoteData, UpdateMember};
+use crate::call::{
+  CallCustom, CallResponse, STATUS_ERROR, STATUS_MAX_LEVEL, STATUS_UNKNOWN_LIMITBREAK, STATUS_USE_ITEM_NUM_ZERO,
+};
+use crate::equipment::{get_equipment_recipe, FetchUserEquipment, UserEquipment};
 use crate::extractor::Params;
-use crate::handler::{IntoHandlerResponse, Unsigned};
-use crate::item::UpdateItemCountBy;
+use crate::handler::{IntoHandlerResponse, Signed, Unsigned};
+use crate::item::{spend_items, IntoItemReference, ItemCost, NotEnoughItems};
 use crate::level::get_member_level_calculator;
+use crate::limit_break::{limit_break_cost, total_limit_break_cost};
 use crate::member::{
-  materialize_member_row, materialize_member_row_impl, FetchUserMemberSkillsIn, FetchUserMembersIn, Member, MemberActiveSkill,
-  MemberPrototype, MemberStrength, OptionallyFetched,
+  apply_character_bonuses, materialize_member_row, materialize_member_row_impl, ConsumeUserReserveMembers,
+  FetchUserMemberSkillsIn, FetchUserMembers, FetchUserMembersIn, FetchUserParty, Member, MemberActiveSkill,
+  MemberPrototype, MemberStats, MemberStrength, OptionallyFetched, PartyFormSlots, UpdateUserPartyForms,
 };
+use crate::mission::{record_mission_events, MissionEvent};
+use crate::notification::{IntoNotificationData, MissionDone};
+use crate::special_skill::{get_default_sp_skill_id, get_sp_skill};
 use crate::user::session::Session;
 use crate::AppState;
 
@@ -79,6 +89,9 @@ pub async fn grade_up(
     (3, 7500)
   ]);
 
+  if potions_to_use.values().any(|count| *count < 0) {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+  }
   let total_xp: i32 = potions_to_use
     .iter()
     .map(|(potion_type, count)| potion_to_xp.get(potion_type).unwrap() * count)
@@ -87,6 +100,25 @@ pub async fn grade_up(
   let mut client = state.get_database_client().await?;
   let transaction = client.transaction().await.context("failed to start transaction")?;
 
+  let cost = ItemCost::items(
+    potions_to_use
+      .iter()
+      .map(|(item_id, count)| {
+        (RemoteDataItemType::PowerPotion, *item_id)
+          .into_item_reference()
+          .into_counted(*count)
+      })
+      .collect(),
+  );
+  let spent = match spend_items(&transaction, session.user_id, &cost).await? {
+    Ok(spent) => spent,
+    Err(error) => {
+      warn!(?params, %error, "not enough power potions");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+  debug!(?spent, "consumed power potions");
+
   #[rustfmt::skip]
   let statement = transaction
     .prepare(/* language=postgresql */ r#"
@@ -150,31 +182,25 @@ pub async fn grade_up(
     .await?
     .run(session.user_id, &mut [&mut member])
     .await?;
+  apply_character_bonuses(&transaction, session.user_id, &mut [&mut member]).await?;
 
-  // Update used items
-  // TODO: This does not check if the user has enough potions
-  let update = UpdateItemCountBy::new(&transaction).await?;
-  let mut item_updates = Vec::new();
-  for (item_id, count) in potions_to_use {
-    if count > 0 {
-      let item = update
-        .run(session.user_id, (RemoteDataItemType::PowerPotion, item_id), -count)
-        .await
-        .context("failed to update item count")?;
-      debug!(?item_id, ?count, new_quantity = ?item.quantity, "consumed power potions");
-
-      item_updates.push(item.into_remote_data());
-    }
-  }
+  let event = MissionEvent::GradeUp {
+    from: current_level,
+    to: new_level,
+  };
+  let missions = record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &[event]).await?;
 
   transaction.commit().await.context("failed to commit transaction")?;
 
   // See [Wonder_Api_GradeupResponseDto_Fields]
-  let mut response = CallResponse::new_success_empty();
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
   response
     .remote
     .extend(UpdateMember::new(member.to_member_parameter_wire()).into_remote_data());
-  response.remote.extend(item_updates.into_iter().flatten());
+  response
+    .remote
+    .extend(spent.into_iter().flat_map(IntoRemoteData::into_remote_data));
   Ok(Unsigned(response))
 }
 
@@ -206,6 +232,20 @@ pub struct PartyFormInfoRequestDto {
   pub skill_pa_fame: i64,
 }
 
+impl PartyFormInfoRequestDto {
+  pub fn slots(&self) -> PartyFormSlots {
+    PartyFormSlots {
+      form_id: self.form_no as i64,
+      main: self.main,
+      sub1: self.sub1,
+      sub2: self.sub2,
+      weapon: self.weapon,
+      accessory: self.acc,
+      special_skill_id: self.special_skill.special_skill_id,
+    }
+  }
+}
+
 // See [Wonder_Api_SpecialSkillInfoRequestDto_Fields]
 #[derive(Debug, Deserialize)]
 pub struct SpecialSkillInfoRequestDto {
@@ -231,60 +271,23 @@ pub async fn update_party_form(
   Params(params): Params<UpdatePartyFormRequest>,
   session: Arc<Session>,
 ) -> impl IntoHandlerResponse {
-  // TODO: I wonder if this is a good way to batch update multiple rows
   let client = state.get_database_client().await?;
-  #[rustfmt::skip]
-  let statement = client
-    .prepare(/* language=postgresql */ r#"
-      update user_party_forms
-      set main_member_id = form_data.main_member_id,
-          sub1_member_id = form_data.sub1_member_id,
-          sub2_member_id = form_data.sub2_member_id,
-          weapon_id = form_data.weapon_id,
-          accessory_id = form_data.accessory_id,
-          special_skill_id = form_data.special_skill_id
-      from (
-        select unnest($3::int8[]) as form_id,
-               unnest($4::int8[]) as main_member_id,
-               unnest($5::int8[]) as sub1_member_id,
-               unnest($6::int8[]) as sub2_member_id,
-               unnest($7::int8[]) as weapon_id,
-               unnest($8::int8[]) as accessory_id,
-               unnest($9::int8[]) as special_skill_id
-      ) as form_data
-      where user_party_forms.user_id = $1
-        and user_party_forms.party_id = $2
-        and user_party_forms.form_id = form_data.form_id
-    "#)
-    .await
-    .context("failed to prepare statement")?;
-  let rows_modified = client
-    .execute(
-      &statement,
-      &[
-        &session.user_id,
-        &(params.party_id as i64),
-        &params.form_info.iter().map(|f| f.form_no as i64).collect::<Vec<_>>(),
-        &params.form_info.iter().map(|f| f.main).collect::<Vec<_>>(),
-        &params.form_info.iter().map(|f| f.sub1).collect::<Vec<_>>(),
-        &params.form_info.iter().map(|f| f.sub2).collect::<Vec<_>>(),
-        &params.form_info.iter().map(|f| f.weapon).collect::<Vec<_>>(),
-        &params.form_info.iter().map(|f| f.acc).collect::<Vec<_>>(),
-        &params
-          .form_info
-          .iter()
-          .map(|f| f.special_skill.special_skill_id)
-          .collect::<Vec<_>>(),
-      ],
-    )
-    .await
-    .context("failed to execute query")?;
+  let forms = params
+    .form_info
+    .iter()
+    .map(PartyFormInfoRequestDto::slots)
+    .collect::<Vec<_>>();
+  let rows_modified = UpdateUserPartyForms::new(&client)
+    .await?
+    .run(session.user_id, params.party_id as i64, &forms)
+    .await?;
   info!(?params.party_id, ?rows_modified, "updated party forms");
 
   // Response is identical to party_info
   Ok(party_info(state, session).await)
 }
 
+// See [Wonder_Api_PartyofferRequest_Fields]
 // trial=1
 // weapon_priority_status=attack
 // assist=1
@@ -297,27 +300,321 @@ pub async fn update_party_form(
 // accessory_priority_resistances=["none","none"]
 // elemental=["none","none"]
 // is_fame_quest=0
+#[derive(Debug, Deserialize)]
+pub struct PartyOfferRequest {
+  #[serde(rename = "party_no")]
+  pub party_id: i32,
+  /// Fill main members
+  pub main: bool,
+  /// Fill sub-members
+  pub sub: bool,
+  /// Fill weapons and accessories
+  pub equip: bool,
+  pub assist: bool,
+  /// `strength`, other values are assumed to be stat names, e.g. `attack`
+  pub priority_status: String,
+  pub weapon_priority_status: String,
+  /// Only members with an active skill of these elements, `none` for any
+  pub elemental: Vec<String>,
+  pub is_fame_quest: bool,
+}
+
+/// Value to rank members and equipment by, [strength] is used unless a stat is requested.
+fn offer_priority(stats: &MemberStats, priority_status: &str, strength: i32) -> i32 {
+  match priority_status {
+    "hp" => stats.hp,
+    "attack" => stats.attack,
+    "magicattack" => stats.magicattack,
+    "defense" => stats.defense,
+    "magicdefence" => stats.magicdefence,
+    "agility" => stats.agility,
+    "dexterity" => stats.dexterity,
+    "luck" => stats.luck,
+    _ => strength,
+  }
+}
+
+/// Fills main members and sub-members requested by [params], keeping other slots.
+/// Main members must be of different characters.
+fn offer_members(forms: &mut [PartyFormSlots], members: &[Member], params: &PartyOfferRequest) {
+  let elements = params
+    .elemental
+    .iter()
+    .filter(|element| *element != "none")
+    .collect::<Vec<_>>();
+  let candidates = members
+    .iter()
+    .filter(|member| {
+      elements.is_empty()
+        || member
+          .prototype
+          .active_skills
+          .iter()
+          .flatten()
+          .any(|skill| elements.contains(&&skill.attribute))
+    })
+    .map(|member| {
+      let wire = member.to_member_parameter_wire();
+      let stats = MemberStats {
+        hp: wire.hp,
+        attack: wire.attack,
+        magicattack: wire.magicattack,
+        defense: wire.defense,
+        magicdefence: wire.magicdefence,
+        agility: wire.agility,
+        dexterity: wire.dexterity,
+        luck: wire.luck,
+      };
+      (member, wire, stats)
+    })
+    .collect::<Vec<_>>();
+
+  let mut used = HashSet::new();
+  for form in forms.iter_mut() {
+    if params.main {
+      form.main = 0;
+    }
+    if params.sub {
+      form.sub1 = 0;
+      form.sub2 = 0;
+    }
+    used.extend([form.main, form.sub1, form.sub2].into_iter().filter(|id| *id != 0));
+  }
+
+  if params.main {
+    let mut characters = HashSet::new();
+    for form in forms.iter_mut() {
+      let Some((member, _, _)) = candidates
+        .iter()
+        .filter(|(member, _, _)| !used.contains(&(member.id as i64)))
+        .filter(|(member, _, _)| !characters.contains(&member.prototype.character_id))
+        .max_by_key(|(_, wire, stats)| {
+          let strength = if params.is_fame_quest {
+            wire.main_strength_for_fame_quest
+          } else {
+            wire.main_strength
+          };
+          offer_priority(stats, &params.priority_status, strength)
+        })
+      else {
+        break;
+      };
+
+      let character_id = member.prototype.character_id;
+      form.main = member.id as i64;
+      if get_sp_skill(form.special_skill_id).is_none_or(|skill| skill.character_id != character_id) {
+        form.special_skill_id = get_default_sp_skill_id(character_id).unwrap_or(0);
+      }
+      used.insert(form.main);
+      characters.insert(character_id);
+    }
+  }
+
+  if params.sub {
+    for form in forms.iter_mut() {
+      let Some(main) = members.iter().find(|member| member.id as i64 == form.main) else {
+        continue;
+      };
+      for slot in [&mut form.sub1, &mut form.sub2] {
+        let Some((member, _, _)) = candidates
+          .iter()
+          .filter(|(member, _, _)| !used.contains(&(member.id as i64)))
+          .max_by_key(|(member, _, stats)| {
+            let strength = if member.prototype.character_id == main.prototype.character_id {
+              member.sub_strength_bonus()
+            } else {
+              member.sub_strength()
+            };
+            offer_priority(stats, &params.priority_status, strength.get(params.is_fame_quest))
+          })
+        else {
+          break;
+        };
+
+        *slot = member.id as i64;
+        used.insert(*slot);
+      }
+    }
+  }
+}
+
+/// Fills weapons and accessories of forms with a main member. Accessories are ranked by strength only.
+fn offer_equipment(
+  forms: &mut [PartyFormSlots],
+  members: &[Member],
+  equipment: &[UserEquipment],
+  params: &PartyOfferRequest,
+) {
+  let items = equipment
+    .iter()
+    .filter_map(|item| {
+      let recipe = get_equipment_recipe(item.item_type, item.item_id)?;
+      let stats = item.level_data()?.stats;
+      let priority = match item.item_type {
+        RemoteDataItemType::Weapon => offer_priority(&stats, &params.weapon_priority_status, stats.strength()),
+        _ => stats.strength(),
+      };
+      Some((item, recipe, priority))
+    })
+    .sorted_by_key(|(_, _, priority)| Reverse(*priority))
+    .collect::<Vec<_>>();
+
+  let mut used = HashSet::new();
+  for form in forms.iter_mut() {
+    let main = members.iter().find(|member| member.id as i64 == form.main);
+    for (slot, item_type) in [
+      (&mut form.weapon, RemoteDataItemType::Weapon),
+      (&mut form.accessory, RemoteDataItemType::Accessory),
+    ] {
+      *slot = main
+        .and_then(|main| {
+          items.iter().find(|(item, recipe, _)| {
+            item.item_type == item_type && !used.contains(&item.id) && recipe.can_equip(main.prototype.character_id)
+          })
+        })
+        .map_or(0, |(item, _, _)| item.id);
+      used.insert(*slot);
+    }
+  }
+}
+
 /// "Suggest party" button
-pub async fn party_offer(state: Arc<AppState>, request: ApiRequest, session: Arc<Session>) -> impl IntoHandlerResponse {
-  let party_no: i32 = request.body["party_no"].parse().unwrap();
+///
+/// Trial members, Fame Traits and accessory resistances are not supported.
+pub async fn party_offer(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<PartyOfferRequest>,
+) -> anyhow::Result<Signed<CallResponse<dyn CallCustom>>> {
+  debug!(?params, "suggesting party");
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
 
-  warn!(?party_no, "encountered stub: party_offer");
+  let mut members = FetchUserMembers::new(&transaction).await?.run(session.user_id).await?;
+  FetchUserMemberSkillsIn::new(&transaction)
+    .await?
+    .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
+    .await?;
+  apply_character_bonuses(
+    &transaction,
+    session.user_id,
+    &mut members.iter_mut().collect::<Vec<_>>(),
+  )
+  .await?;
+  let party = FetchUserParty::new(&transaction)
+    .await?
+    .run(session.user_id, params.party_id as i64)
+    .await?;
+
+  let mut forms = party.party_forms.iter().map(PartyForm::slots).collect::<Vec<_>>();
+  offer_members(&mut forms, &members, &params);
+  if params.equip {
+    let equipment = FetchUserEquipment::new(&transaction)
+      .await?
+      .run(session.user_id)
+      .await?;
+    offer_equipment(&mut forms, &members, &equipment, &params);
+  }
+  UpdateUserPartyForms::new(&transaction)
+    .await?
+    .run(session.user_id, params.party_id as i64, &forms)
+    .await?;
+
+  let assists = FetchUserAssists::new(&transaction).await?.run(session.user_id).await?;
+  let assist = assists.iter().max_by_key(|assist| {
+    (
+      assist.level,
+      get_assist(assist.assist_id).map_or(0, |assist| assist.rarity),
+    )
+  });
+  if let Some(assist) = assist.filter(|_| params.assist) {
+    let sub_assists = party
+      .sub_assists
+      .iter()
+      .copied()
+      .filter(|id| *id != assist.unique_id())
+      .collect::<Vec<_>>();
+
+    #[rustfmt::skip]
+    let statement = transaction
+      .prepare(/* language=postgresql */ r#"
+        update user_parties
+        set assist_id = $3, sub_assist_ids = $4
+        where user_id = $1 and party_id = $2
+      "#)
+      .await
+      .context("failed to prepare statement")?;
+    transaction
+      .execute(
+        &statement,
+        &[
+          &session.user_id,
+          &(params.party_id as i64),
+          &assist.unique_id(),
+          &sub_assists,
+        ],
+      )
+      .await
+      .context("failed to execute query")?;
+  }
+
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?params.party_id, ?forms, "suggested party");
 
   // Response is identical to party_info
-  Ok(party_info(state, session).await)
+  party_info(state, session).await
 }
 
+// See [Wonder_Api_PartyresetRequest_Fields]
 // party_no=1
 // is_allow_trial=1
 // is_fame_quest=0
-/// "Tool" -> "Reset" button
-pub async fn party_reset(state: Arc<AppState>, request: ApiRequest, session: Arc<Session>) -> impl IntoHandlerResponse {
-  let party_no: i32 = request.body["party_no"].parse().unwrap();
+#[derive(Debug, Deserialize)]
+pub struct PartyResetRequest {
+  #[serde(rename = "party_no")]
+  pub party_id: i32,
+}
+
+/// "Tool" -> "Reset" button, empties all forms, assists and the trait of a party.
+pub async fn party_reset(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<PartyResetRequest>,
+) -> anyhow::Result<Signed<CallResponse<dyn CallCustom>>> {
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let forms = (1..=5)
+    .map(|form_id| PartyFormSlots {
+      form_id,
+      ..Default::default()
+    })
+    .collect::<Vec<_>>();
+  UpdateUserPartyForms::new(&transaction)
+    .await?
+    .run(session.user_id, params.party_id as i64, &forms)
+    .await?;
+
+  #[rustfmt::skip]
+  let statement = transaction
+    .prepare(/* language=postgresql */ r#"
+      update user_parties
+      set assist_id = 0, sub_assist_ids = '{}', trait_id = 0
+      where user_id = $1 and party_id = $2
+    "#)
+    .await
+    .context("failed to prepare statement")?;
+  transaction
+    .execute(&statement, &[&session.user_id, &(params.party_id as i64)])
+    .await
+    .context("failed to execute query")?;
 
-  warn!(?party_no, "encountered stub: party_reset");
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?params.party_id, "reset party");
 
   // Response is identical to party_info
-  Ok(party_info(state, session).await)
+  party_info(state, session).await
 }
 
 // See [Wonder_Api_PartychangelistResponseDto_Fields]
@@ -359,23 +656,51 @@ pub struct PartyChangeListRequest {
 }
 
 pub async fn party_change_list(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<PartyChangeListRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: party_change_list");
+  debug!(?params, "party change list");
 
-  let assists = get_master_manager().get_master("assist_details");
+  let client = state.get_database_client().await?;
+  let members = FetchUserMembers::new(&client).await?.run(session.user_id).await?;
+  let equipment = FetchUserEquipment::new(&client).await?.run(session.user_id).await?;
+  let assists = FetchUserAssists::new(&client).await?.run(session.user_id).await?;
 
   Ok(Unsigned(PartychangelistResponseDto {
-    members: vec![],
-    weapons: vec![],
-    accessories: vec![],
-    assists: assists
+    members: members
+      .iter()
+      .map(|member| ChangeListPartyMember {
+        id: member.id as i64,
+        lv: member.level(),
+        member_id: member.prototype.id,
+        character_id: member.prototype.character_id,
+      })
+      .collect(),
+    weapons: equipment
       .iter()
-      .map(|assist| ChangeListPartyAssist {
-        id: assist["id"].as_str().unwrap().parse::<i64>().unwrap(),
+      .filter(|item| item.item_type == RemoteDataItemType::Weapon)
+      .filter_map(|item| {
+        Some(ChangeListPartyWeapon {
+          id: item.id,
+          weapon_id: item.level_data()?.item_id_details,
+        })
       })
       .collect(),
+    accessories: equipment
+      .iter()
+      .filter(|item| item.item_type == RemoteDataItemType::Accessory)
+      .filter_map(|item| {
+        Some(PartyAccessory {
+          id: item.id,
+          accessory_id: item.level_data()?.item_id_details,
+        })
+      })
+      .collect(),
+    assists: assists
+      .iter()
+      .map(|assist| ChangeListPartyAssist { id: assist.unique_id() })
+      .collect(),
   }))
 }
 
@@ -408,33 +733,48 @@ pub async fn party_change_assist(
   state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<PartyChangeAssistRequest>,
-) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: party_change_assist");
-
+) -> anyhow::Result<Signed<CallResponse<dyn CallCustom>>> {
   let client = state.get_database_client().await?;
+  let owned = FetchUserAssists::new(&client)
+    .await?
+    .run(session.user_id)
+    .await?
+    .iter()
+    .map(|assist| assist.unique_id())
+    .collect::<HashSet<_>>();
+
+  // Main assist of 0 clears it
+  if (params.main_assist_unique_id != 0 && !owned.contains(&params.main_assist_unique_id))
+    || params.sub_assist_unique_ids.iter().any(|id| !owned.contains(id))
+  {
+    warn!(?params, "assist is not owned");
+    return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+  }
+
   #[rustfmt::skip]
   let statement = client
     .prepare(/* language=postgresql */ r#"
       update user_parties
-      set assist_id = $3
+      set assist_id = $3, sub_assist_ids = $4
       where user_id = $1 and party_id = $2
     "#)
     .await
     .context("failed to prepare statement")?;
   client
-    .query(
+    .execute(
       &statement,
       &[
         &session.user_id,
         &(params.party_id as i64),
         &params.main_assist_unique_id,
+        &params.sub_assist_unique_ids,
       ],
     )
     .await
     .context("failed to execute query")?;
 
   // Response is identical to party_info
-  Ok(party_info(state, session).await)
+  party_info(state, session).await
 }
 
 #[derive(Debug, Deserialize)]
@@ -559,16 +899,52 @@ pub struct PartyStrengthResponseDto {
 
 impl CallCustom for PartyStrengthResponseDto {}
 
+// See [Wonder_Api_PartystrengthRequest_Fields]
 // is_fame_quest=0
 // party_no=1
 // form_info=[{"form_no":1,"main":11,"sub1":0,"sub2":0,"weapon":0,"acc":0,"special_skill":{"special_skill_id":100001,"trial":false},"skill_pa_fame":0},{"form_no":2,"main":12,"sub1":0,"sub2":0,"weapon":0,"acc":0,"special_skill":{"special_skill_id":101001,"trial":false},"skill_pa_fame":0},{"form_no":3,"main":13,"sub1":0,"sub2":0,"weapon":0,"acc":0,"special_skill":{"special_skill_id":102001,"trial":false},"skill_pa_fame":0},{"form_no":4,"main":0,"sub1":0,"sub2":0,"weapon":0,"acc":0,"special_skill":{"special_skill_id":0,"trial":false},"skill_pa_fame":0},{"form_no":5,"main":0,"sub1":0,"sub2":0,"weapon":0,"acc":0,"special_skill":{"special_skill_id":0,"trial":false},"skill_pa_fame":0}]
 // is_allow_trial=1
-pub async fn party_strength(request: ApiRequest) -> impl IntoHandlerResponse {
-  let party_no: i32 = request.body["party_no"].parse().unwrap();
+#[derive(Debug, Deserialize)]
+pub struct PartyStrengthRequest {
+  #[serde(rename = "party_no")]
+  pub party_id: i32,
+  pub form_info: Vec<PartyFormInfoRequestDto>,
+  pub is_fame_quest: bool,
+}
 
-  warn!(?party_no, "encountered stub: party_strength");
+/// Strength of a party with forms that are being edited, before they are saved with [update_party_form].
+pub async fn party_strength(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<PartyStrengthRequest>,
+) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let mut members = FetchUserMembers::new(&client).await?.run(session.user_id).await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
+  let equipment = FetchUserEquipment::new(&client).await?.run(session.user_id).await?;
+  let assists = FetchUserAssists::new(&client).await?.run(session.user_id).await?;
+  let mut party = FetchUserParty::new(&client)
+    .await?
+    .run(session.user_id, params.party_id as i64)
+    .await?;
+
+  for info in &params.form_info {
+    if let Some(form) = party.party_forms.iter_mut().find(|form| form.form_no == info.form_no) {
+      form.set_slots(&info.slots());
+    }
+  }
+  PartyStrengthCalculator::new(&members, &equipment, params.is_fame_quest).apply(&mut party);
+
+  let assist_level = assists
+    .iter()
+    .find(|assist| assist.unique_id() == party.assist)
+    .map_or(0, |assist| assist.level);
+  let strength = party.power(assist_level as u32);
+  debug!(?params.party_id, ?strength, "calculated party strength");
 
-  Ok(Unsigned(PartyStrengthResponseDto { strength: 1000 }))
+  Ok(Unsigned(PartyStrengthResponseDto {
+    strength: strength as i32,
+  }))
 }
 
 // See [Wonder_Api_LimitbreakResponseDto_Fields]
@@ -593,43 +969,100 @@ pub async fn limit_break(
   session: Arc<Session>,
   Params(params): Params<LimitBreakRequest>,
 ) -> impl IntoHandlerResponse {
-  debug!(?params, ?params, "promote member");
+  debug!(?params, "promote member");
+  if params.levels < 1 {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_UNKNOWN_LIMITBREAK)));
+  }
 
   let mut client = state.get_database_client().await?;
   let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let Some(member) = FetchUserMembersIn::new(&transaction)
+    .await?
+    .run(session.user_id, &[params.member_id])
+    .await?
+    .into_iter()
+    .next()
+  else {
+    warn!(?params, "member to limit break not found");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_UNKNOWN_LIMITBREAK)));
+  };
+
+  let level = member.promotion_level + params.levels;
+  let Some(costs) = limit_break_cost(params.member_id, member.promotion_level, level) else {
+    warn!(?params, promotion_level = ?member.promotion_level, "member is already at max promotion level");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_MAX_LEVEL)));
+  };
+
+  // Items are spent first, reserve duplicates only replace the items of levels that can not be paid for.
+  // Nothing is deleted if the transaction is not committed.
+  let consume_duplicate = ConsumeUserReserveMembers::new(&transaction).await?;
+  let mut duplicates = Vec::new();
+  let (spent, money) = loop {
+    let (materials, money) = total_limit_break_cost(&costs, duplicates.len());
+    let cost = ItemCost {
+      items: materials,
+      money,
+      ..Default::default()
+    };
+    let error = match spend_items(&transaction, session.user_id, &cost).await? {
+      Ok(spent) => break (spent, money),
+      Err(error) => error,
+    };
+
+    let duplicate = if duplicates.len() < costs.len() {
+      consume_duplicate.run(session.user_id, params.member_id, 1).await?
+    } else {
+      Vec::new()
+    };
+    if duplicate.is_empty() {
+      warn!(?params, %error, duplicates = duplicates.len(), "not enough materials");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+    duplicates.extend(duplicate);
+  };
+
+  let mut remote_data = duplicates
+    .iter()
+    .flat_map(|id| DeleteMember::new(*id, params.member_id).into_remote_data())
+    .collect::<Vec<_>>();
+  remote_data.extend(spent.into_iter().flat_map(IntoRemoteData::into_remote_data));
+
   #[rustfmt::skip]
   let statement = transaction
     .prepare(/* language=postgresql */ r#"
       update user_members
-      set promotion_level = promotion_level + $3
+      set promotion_level = $3
       where user_id = $1 and member_id = $2
       returning member_id, xp, promotion_level
     "#)
     .await
     .context("failed to prepare statement")?;
   let row = transaction
-    .query(&statement, &[&session.user_id, &params.member_id, &params.levels])
+    .query_one(&statement, &[&session.user_id, &params.member_id, &level])
     .await
-    .context("failed to update user member promotion level")?
-    .into_iter()
-    .next()
-    .context("no such member")?;
-
-  // TODO: Consume items
+    .context("failed to update user member promotion level")?;
   transaction.commit().await.context("failed to commit transaction")?;
+  info!(
+    ?params,
+    ?level,
+    duplicates = duplicates.len(),
+    ?money,
+    "promoted member"
+  );
 
   let mut member = materialize_member_row(row);
   FetchUserMemberSkillsIn::new(&client)
     .await?
     .run(session.user_id, &mut [&mut member])
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut [&mut member]).await?;
 
-  let mut response = CallResponse::new_success(Box::new(LimitBreakResponse {
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(LimitBreakResponse {
     newlv: member.promotion_level,
   }));
-  response
-    .remote
-    .extend(UpdateMember::new(member.to_member_parameter_wire()).into_remote_data());
+  remote_data.extend(UpdateMember::new(member.to_member_parameter_wire()).into_remote_data());
+  response.add_remote_data(remote_data);
   Ok(Unsigned(response))
 }
 
@@ -672,8 +1105,14 @@ pub async fn member_skill_up(
     * match params.kind {
       0 => 3, // reserve members
       1 => 1, // skill potions
-      _ => todo!("unknown member skill up kind {}", params.kind),
+      _ => {
+        warn!(?params, "unknown member skill up kind");
+        return Ok(Unsigned(CallResponse::new_error(STATUS_ERROR)));
+      }
     };
+  if params.amount < 1 {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_USE_ITEM_NUM_ZERO)));
+  }
 
   let mut client = state.get_database_client().await?;
   let fetch_members = FetchUserMembersIn::new(&client).await?;
@@ -687,13 +1126,54 @@ pub async fn member_skill_up(
     .await?
     .run(session.user_id, &mut [&mut member])
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut [&mut member]).await?;
 
   let skills = match &mut member.active_skills {
     OptionallyFetched::Fetched(skills) => skills,
     OptionallyFetched::Unfetched => panic!("active skills not fetched for member {}", member.id),
   };
+  if skills.iter().flatten().all(|skill| skill.level >= 5) {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_MAX_LEVEL)));
+  }
+
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let mut remote_data = Vec::new();
+  if params.kind == 0 {
+    let duplicates = ConsumeUserReserveMembers::new(&transaction)
+      .await?
+      .run(session.user_id, member.prototype.id, params.amount as i64)
+      .await?;
+    if duplicates.len() < params.amount as usize {
+      let error = NotEnoughItems {
+        item: (RemoteDataItemType::Member, member.prototype.id).into_item_reference(),
+        required: params.amount,
+        owned: duplicates.len() as i32,
+      };
+      warn!(?params, %error, "not enough reserve members");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+    remote_data.extend(
+      duplicates
+        .iter()
+        .flat_map(|id| DeleteMember::new(*id, member.prototype.id).into_remote_data()),
+    );
+  } else {
+    // Skill potions are specific to a member and share its ID
+    let cost = ItemCost::items(vec![
+      (RemoteDataItemType::SkillPotion, member.prototype.id)
+        .into_item_reference()
+        .into_counted(params.amount),
+    ]);
+    match spend_items(&transaction, session.user_id, &cost).await? {
+      Ok(spent) => remote_data.extend(spent.into_iter().flat_map(IntoRemoteData::into_remote_data)),
+      Err(error) => {
+        warn!(?params, %error, "not enough skill potions");
+        return Ok(Unsigned(CallResponse::new_error(error.status())));
+      }
+    }
+  }
 
-  let mut transaction = client.transaction().await.context("failed to start transaction")?;
   #[rustfmt::skip]
   let statement = transaction
     .prepare(/* language=postgresql */ r#"
@@ -758,8 +1238,10 @@ pub async fn member_skill_up(
     .filter(|s| s.lvup > 0)
     .collect();
 
-  let mut response = CallResponse::new_success(Box::new(MemberSkillUpResponse { skill_levels }));
-  response.add_remote_data(UpdateMember::new(member.to_member_parameter_wire()).into_remote_data());
+  let mut response: CallResponse<dyn CallCustom> =
+    CallResponse::new_success(Box::new(MemberSkillUpResponse { skill_levels }));
+  remote_data.extend(UpdateMember::new(member.to_member_parameter_wire()).into_remote_data());
+  response.add_remote_data(remote_data);
 
   Ok(Unsigned(response))
 }
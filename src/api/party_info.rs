@@ -2,12 +2,16 @@ use crate::api::battle::BattleParty;
 use crate::api::dungeon::PartyMember;
 use crate::api::party::PartyWire;
 use crate::api::surprise::BasicBattlePartyForm;
-use crate::call::CallCustom;
-use crate::handler::{IntoHandlerResponse, Signed};
-use crate::member::{FetchUserMemberSkillsIn, FetchUserMembers, FetchUserParties};
+use crate::call::{CallCustom, CallResponse};
+use crate::equipment::{FetchUserEquipment, UserEquipment};
+use crate::handler::Signed;
+use crate::member::{
+  apply_character_bonuses, FetchUserMemberSkillsIn, FetchUserMembers, FetchUserParties, Member, PartyFormSlots,
+};
 use crate::user::session::Session;
 use crate::AppState;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -113,6 +117,27 @@ impl PartyForm {
     }
   }
 
+  pub fn slots(&self) -> PartyFormSlots {
+    PartyFormSlots {
+      form_id: self.id as i64,
+      main: self.main as i64,
+      sub1: self.sub1 as i64,
+      sub2: self.sub2 as i64,
+      weapon: self.weapon,
+      accessory: self.acc,
+      special_skill_id: self.specialskill.special_skill_id as i64,
+    }
+  }
+
+  pub fn set_slots(&mut self, slots: &PartyFormSlots) {
+    self.main = slots.main as i32;
+    self.sub1 = slots.sub1 as i32;
+    self.sub2 = slots.sub2 as i32;
+    self.weapon = slots.weapon;
+    self.acc = slots.accessory;
+    self.specialskill.special_skill_id = slots.special_skill_id as i32;
+  }
+
   pub fn to_basic_battle_party_form(&self) -> BasicBattlePartyForm {
     BasicBattlePartyForm {
       id: self.id,
@@ -173,7 +198,61 @@ impl Party {
   }
 }
 
-pub async fn party_info(state: Arc<AppState>, session: Arc<Session>) -> impl IntoHandlerResponse {
+/// Calculates strength of party forms from members and equipment of a user.
+pub struct PartyStrengthCalculator<'a> {
+  members: HashMap<i64, &'a Member>,
+  equipment: HashMap<i64, &'a UserEquipment>,
+  is_fame_quest: bool,
+}
+
+impl<'a> PartyStrengthCalculator<'a> {
+  pub fn new(members: &'a [Member], equipment: &'a [UserEquipment], is_fame_quest: bool) -> Self {
+    Self {
+      members: members.iter().map(|member| (member.id as i64, member)).collect(),
+      equipment: equipment.iter().map(|item| (item.id, item)).collect(),
+      is_fame_quest,
+    }
+  }
+
+  /// Strength of the main member with sub-members and equipment, forms without a main member have none.
+  pub fn form_strength(&self, form: &PartyFormSlots) -> i32 {
+    let Some(main) = self.members.get(&form.main) else {
+      return 0;
+    };
+
+    let subs: i32 = [form.sub1, form.sub2]
+      .iter()
+      .filter_map(|id| self.members.get(id))
+      .map(|sub| {
+        if sub.prototype.character_id == main.prototype.character_id {
+          sub.sub_strength_bonus()
+        } else {
+          sub.sub_strength()
+        }
+      })
+      .map(|strength| strength.get(self.is_fame_quest))
+      .sum();
+    let equipment: i32 = [form.weapon, form.accessory]
+      .iter()
+      .filter_map(|id| self.equipment.get(id))
+      .filter_map(|item| item.level_data())
+      .map(|level| level.stats.strength())
+      .sum();
+
+    main.main_strength().get(self.is_fame_quest) + subs + equipment
+  }
+
+  pub fn apply(&self, party: &mut Party) {
+    for form in &mut party.party_forms {
+      form.strength = self.form_strength(&form.slots());
+    }
+  }
+}
+
+pub async fn party_info(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+) -> anyhow::Result<Signed<CallResponse<dyn CallCustom>>> {
   let client = state.get_database_client().await?;
 
   let mut members = FetchUserMembers::new(&client).await?.run(session.user_id).await?;
@@ -181,16 +260,23 @@ pub async fn party_info(state: Arc<AppState>, session: Arc<Session>) -> impl Int
     .await?
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await?;
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>()).await?;
 
-  let parties = FetchUserParties::new(&client).await?.run(session.user_id).await?;
+  let equipment = FetchUserEquipment::new(&client).await?.run(session.user_id).await?;
+
+  let mut parties = FetchUserParties::new(&client).await?.run(session.user_id).await?;
+  let calculator = PartyStrengthCalculator::new(&members, &equipment, false);
+  for party in &mut parties {
+    calculator.apply(party);
+  }
 
   Ok(Signed(
-    PartyWire {
+    CallResponse::new_success(Box::new(PartyWire {
       party: parties,
       members: members.iter().map(|member| member.to_party_member()).collect(),
       weapons: vec![],
       accessories: vec![],
-    },
+    })),
     session,
   ))
 }
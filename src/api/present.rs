@@ -1,14 +1,20 @@
 use std::sync::Arc;
 
-use chrono::Utc;
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use tracing::warn;
+use tracing::{debug, info};
 
+use crate::api::battle::grant_rewards;
+use crate::api::quest::QuestRewardItem;
 use crate::api::{NotificationData, RemoteDataItemType};
-use crate::call::{CallCustom, CallResponse};
+use crate::call::{CallCustom, CallResponse, STATUS_MAX_HAVE_ITEM};
+use crate::equipment::is_equipment;
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed};
+use crate::inventory::{FetchUserInventory, InventoryKind};
+use crate::present::{FetchUserPresentLog, FetchUserPresents, ReceiveUserPresents};
 use crate::user::session::Session;
+use crate::AppState;
 
 // See [Wonder_Api_PresentlistResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -39,28 +45,42 @@ pub struct PresentListRequest {
 }
 
 pub async fn present_list(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<PresentListRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params.start, ?params.end, "encountered stub: present_list");
+  debug!(?params.start, ?params.end, "present list");
 
+  let client = state.get_database_client().await?;
+  let presents = FetchUserPresents::new(&client).await?.run(session.user_id).await?;
+
+  let count = presents.len() as i32;
   let mut response = CallResponse::new_success(Box::new(PresentList {
-    presents: vec![Present {
-      id: 28231780,
-      present_id: 48,
-      senddate: (Utc::now() - chrono::Duration::days(1)).timestamp(),
-      expireddate: (Utc::now() + chrono::Duration::days(3)).timestamp(),
-      item_type: RemoteDataItemType::RealMoney.into(),
-      item_id: 1,
-      item_num: 10000,
-      msg: "skebob".to_owned(),
-    }],
+    presents: presents
+      .into_iter()
+      .map(|present| Present {
+        id: present.id as i32,
+        present_id: present.id as i32,
+        senddate: present.sent_at.timestamp(),
+        expireddate: present.expires_at.map_or(0, |expires_at| expires_at.timestamp()),
+        item_type: present.item_type,
+        item_id: present.item_id,
+        item_num: present.item_num,
+        msg: present.message,
+      })
+      .collect(),
   }));
-  response.add_notifications(vec![NotificationData::new(1, 7, 2, 6, "".to_owned(), "".to_owned())]);
+  response.add_notifications(vec![make_present_count_notification(count)]);
 
   Ok(Signed(response, session))
 }
 
+/// Updates the present box badge on the home screen.
+// See [Wonder.UI.Mypage.MyPageScreen$$UpdateBadgeAll], [_presentButton], counted
+pub fn make_present_count_notification(count: i32) -> NotificationData {
+  NotificationData::new(1, 7, 2, count, "".to_owned(), "".to_owned())
+}
+
 // See [Wonder_Api_PresentloglistResponseDto_Fields]
 #[derive(Debug, Serialize)]
 pub struct PresentLogList {
@@ -90,22 +110,29 @@ pub struct PresentLogListRequest {
 }
 
 pub async fn present_log_list(
+  state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<PresentLogListRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params.start, ?params.end, "encountered stub: present_log_list");
+  debug!(?params.start, ?params.end, "present log list");
+
+  let client = state.get_database_client().await?;
+  let presents = FetchUserPresentLog::new(&client).await?.run(session.user_id).await?;
 
   let response = CallResponse::new_success(Box::new(PresentLogList {
-    presents: vec![PresentLog {
-      id: 28231780,
-      present_id: 48,
-      senddate: (Utc::now() - chrono::Duration::days(1)).timestamp(),
-      recveddate: (Utc::now() - chrono::Duration::minutes(5)).timestamp(),
-      item_type: RemoteDataItemType::RealMoney.into(),
-      item_id: 1,
-      item_num: 10000,
-      msg: "skebob".to_owned(),
-    }],
+    presents: presents
+      .into_iter()
+      .map(|present| PresentLog {
+        id: present.id as i32,
+        present_id: present.id as i32,
+        senddate: present.sent_at.timestamp(),
+        recveddate: present.received_at.map_or(0, |received_at| received_at.timestamp()),
+        item_type: present.item_type,
+        item_id: present.item_id,
+        item_num: present.item_num,
+        msg: present.message,
+      })
+      .collect(),
   }));
 
   Ok(Signed(response, session))
@@ -152,37 +179,70 @@ pub struct PresentGetRequest {
   pub ids: Vec<i32>,
 }
 
-pub async fn present_get(session: Arc<Session>, Params(params): Params<PresentGetRequest>) -> impl IntoHandlerResponse {
-  warn!(?params.ids, "encountered stub: present_get");
-
-  let mut response = CallResponse::new_success(Box::new(PresentGet {
-    presents: vec![PresentGetReceived {
-      id: 28231780,
-      present_id: 48,
-      senddate: (Utc::now() - chrono::Duration::days(1)).timestamp(),
-      expireddate: (Utc::now() + chrono::Duration::days(7)).timestamp(),
-      item_type: RemoteDataItemType::RealMoney.into(),
-      item_id: 1,
-      item_num: 10000,
-    }],
-    unrecvpresents: vec![PresentGetUnreceived {
-      id: 28231781,
-      present_id: 3,
-      senddate: (Utc::now() - chrono::Duration::days(1)).timestamp(),
-      expireddate: (Utc::now() + chrono::Duration::days(7)).timestamp(),
-      item_type: RemoteDataItemType::Stamina.into(),
-      item_id: 1,
-      item_num: 500,
-    }],
+pub async fn present_get(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<PresentGetRequest>,
+) -> impl IntoHandlerResponse {
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let ids = params.ids.iter().map(|id| *id as i64).collect::<Vec<_>>();
+  let presents = ReceiveUserPresents::new(&transaction)
+    .await?
+    .run(session.user_id, &ids)
+    .await?;
+
+  // Receiving must not overflow the inventory again, the presents stay in the box instead
+  let equipment_count: i32 = presents
+    .iter()
+    .filter(|present| is_equipment(RemoteDataItemType::from(present.item_type)))
+    .map(|present| present.item_num)
+    .sum();
+  let inventory = FetchUserInventory::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?;
+  if equipment_count > inventory.free_space(InventoryKind::Equipment, &state.settings.inventory) {
+    return Ok(Signed(CallResponse::new_error(STATUS_MAX_HAVE_ITEM), session));
+  }
+
+  let items = presents
+    .iter()
+    .map(|present| QuestRewardItem {
+      item_type: present.item_type,
+      item_id: present.item_id,
+      item_num: present.item_num,
+      item_rare: false,
+      probability: None,
+    })
+    .collect::<Vec<_>>();
+  let update_items = grant_rewards(&transaction, &state.settings.inventory, &session, &items).await?;
+  let remaining = FetchUserPresents::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .len();
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(received = ?presents.iter().map(|present| present.id).collect::<Vec<_>>(), "received presents");
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(PresentGet {
+    presents: presents
+      .into_iter()
+      .map(|present| PresentGetReceived {
+        id: present.id as i32,
+        present_id: present.id as i32,
+        senddate: present.sent_at.timestamp(),
+        expireddate: present.expires_at.map_or(0, |expires_at| expires_at.timestamp()),
+        item_type: present.item_type,
+        item_id: present.item_id,
+        item_num: present.item_num,
+      })
+      .collect(),
+    unrecvpresents: vec![],
   }));
-  response.add_notifications(vec![
-    NotificationData::new(1, 7, 3, 4, "".to_owned(), "".to_owned()),
-    NotificationData::new(1, 7, 13, 7, "".to_owned(), "".to_owned()),
-    NotificationData::new(1, 7, 34, 1, "show_button_new".to_owned(), "".to_owned()),
-    NotificationData::new(1, 6, 1, 30030001, "".to_owned(), "".to_owned()),
-    NotificationData::new(1, 10, 230731, 52307325, "".to_owned(), "".to_owned()),
-    NotificationData::new(1, 10, 230831, 52308305, "".to_owned(), "".to_owned()),
-  ]);
+  response.add_remote_data(update_items);
+  response.add_notifications(vec![make_present_count_notification(remaining as i32)]);
 
   Ok(Signed(response, session))
 }
@@ -0,0 +1,269 @@
+//! Reward masters only list possible drops of a stage, every slot is rolled separately on clear.
+//! Regular slots always drop, only slots marked with `item_rare{n}` are left to chance.
+
+use std::sync::{Mutex, OnceLock};
+
+use chrono::NaiveDateTime;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::quest::{parse_reward_items, QuestRewardItem};
+use crate::settings::DropSettings;
+use crate::user::quest_progress::QuestKind;
+
+/// Stage a reward master entry belongs to, used to match campaigns.
+/// Stage reward masters have the same columns as `campaign` master uses for filtering.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CampaignScope {
+  pub kind: QuestKind,
+  pub event_id: i32,
+  pub area_id: i32,
+  pub stage_id: i32,
+  /// `mode` column
+  pub difficulty: i32,
+}
+
+impl CampaignScope {
+  pub fn from_reward(kind: QuestKind, reward: &Value) -> Self {
+    let field = |name: &str| {
+      reward
+        .get(name)
+        .map(|value| value.as_str().unwrap().parse::<i32>().unwrap())
+        .unwrap_or(0)
+    };
+
+    Self {
+      kind,
+      event_id: field("event_id"),
+      area_id: field("area_id"),
+      stage_id: field("stage_id"),
+      difficulty: field("mode"),
+    }
+  }
+}
+
+/// Rolls rewards of a single stage clear from its reward master entry.
+/// [kind] is `None` for quests that are not affected by campaigns.
+pub fn roll_stage_rewards(settings: &DropSettings, kind: Option<QuestKind>, reward: &Value) -> Vec<QuestRewardItem> {
+  let table = parse_reward_items(reward);
+  let multiplier = kind
+    .map(|kind| campaign_multiplier(&CampaignScope::from_reward(kind, reward), now()))
+    .unwrap_or(1.0);
+
+  roll_table(settings, &table, multiplier)
+}
+
+/// Same as [roll_drops], using the configured random number generator.
+pub fn roll_table(settings: &DropSettings, table: &[QuestRewardItem], multiplier: f32) -> Vec<QuestRewardItem> {
+  with_rng(settings, |rng| roll_drops(settings, table, multiplier, rng))
+}
+
+/// Runs [roll] with the configured random number generator, seeded if [DropSettings::seed] is set.
+pub fn with_rng<T>(settings: &DropSettings, roll: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+  match settings.seed {
+    Some(seed) => roll(&mut *seeded_rng(seed).lock().unwrap()),
+    None => roll(&mut rand::rng()),
+  }
+}
+
+/// Seeded once per process, so consecutive rolls continue the same sequence instead of repeating it.
+fn seeded_rng(seed: u64) -> &'static Mutex<StdRng> {
+  static RNG: OnceLock<Mutex<StdRng>> = OnceLock::new();
+
+  RNG.get_or_init(|| Mutex::new(StdRng::seed_from_u64(seed)))
+}
+
+/// Rolls each slot of the drop table independently, and multiplies amounts of dropped items.
+/// Rare slots that dropped keep [QuestRewardItem::item_rare] set, which is shown as a rare drop.
+pub fn roll_drops(
+  settings: &DropSettings,
+  table: &[QuestRewardItem],
+  multiplier: f32,
+  rng: &mut (impl Rng + ?Sized),
+) -> Vec<QuestRewardItem> {
+  table
+    .iter()
+    .filter(|item| {
+      let chance = item
+        .probability
+        .unwrap_or(if item.item_rare { settings.rare_chance } else { 1.0 });
+      rng.random_bool(chance.clamp(0.0, 1.0) as f64)
+    })
+    .map(|item| {
+      let mut item = item.clone();
+      item.item_num = ((item.item_num as f32) * multiplier).ceil() as i32;
+      item
+    })
+    .collect()
+}
+
+/// Reward amount multiplier of a `campaign` master `type`.
+/// The master only schedules campaigns, their effect is defined by the type: `10` doubles stage drops.
+/// Other types (experience, stamina and event point campaigns) do not change drops.
+pub fn campaign_type_multiplier(campaign_type: i64) -> Option<f32> {
+  match campaign_type {
+    10 => Some(2.0),
+    _ => None,
+  }
+}
+
+/// Returns the highest reward multiplier of campaigns that are active at [now] and cover [scope].
+pub fn campaign_multiplier(scope: &CampaignScope, now: NaiveDateTime) -> f32 {
+  let multiplier = get_master_manager()
+    .get_master("campaign")
+    .iter()
+    .filter(|campaign| is_campaign_applicable(campaign, scope, now))
+    .filter_map(|campaign| campaign_type_multiplier(parse_i64(&campaign["type"])))
+    .fold(1.0, f32::max);
+  if multiplier != 1.0 {
+    debug!(?scope, ?multiplier, "applying campaign reward multiplier");
+  }
+
+  multiplier
+}
+
+/// Zero in `campaign` master filter columns means "any".
+pub fn is_campaign_applicable(campaign: &Value, scope: &CampaignScope, now: NaiveDateTime) -> bool {
+  let field = |name: &str| campaign[name].as_str().unwrap();
+  let matches = |name: &str, value: i32| {
+    let filter = field(name).parse::<i32>().unwrap();
+    filter == 0 || filter == value
+  };
+
+  if parse_date(field("start")).is_some_and(|start| now < start)
+    || parse_date(field("end")).is_some_and(|end| now >= end)
+  {
+    return false;
+  }
+
+  let areas = field("quest_area");
+  let area_matches = areas == "0"
+    || areas
+      .split('_')
+      .any(|area| area.parse::<i32>().unwrap() == scope.area_id);
+
+  matches("quest_type", scope.kind as i32)
+    && matches("event_id", scope.event_id)
+    && matches("quest_difficulty", scope.difficulty)
+    && matches("quest_stage", scope.stage_id)
+    && area_matches
+}
+
+fn now() -> NaiveDateTime {
+  chrono::Utc::now().naive_utc()
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  fn settings(rare_chance: f32) -> DropSettings {
+    DropSettings {
+      rare_chance,
+      seed: None,
+    }
+  }
+
+  fn item(item_id: i64, item_rare: bool, probability: Option<f32>) -> QuestRewardItem {
+    QuestRewardItem {
+      item_type: 15,
+      item_id,
+      item_num: 3,
+      item_rare,
+      probability,
+    }
+  }
+
+  fn date(value: &str) -> NaiveDateTime {
+    parse_date(value).unwrap()
+  }
+
+  #[test]
+  fn test_regular_slots_always_drop() {
+    let table = vec![item(1, false, None), item(2, true, None)];
+    let mut rng = StdRng::seed_from_u64(1);
+
+    assert_eq!(roll_drops(&settings(1.0), &table, 1.0, &mut rng), table);
+    assert_eq!(
+      roll_drops(&settings(0.0), &table, 1.0, &mut rng),
+      vec![item(1, false, None)]
+    );
+  }
+
+  #[test]
+  fn test_master_probability_overrides_settings() {
+    let table = vec![item(1, false, Some(0.0)), item(2, true, Some(1.0))];
+    let rewards = roll_drops(&settings(0.0), &table, 1.0, &mut StdRng::seed_from_u64(1));
+
+    assert_eq!(rewards, vec![item(2, true, Some(1.0))]);
+  }
+
+  #[test]
+  fn test_same_seed_same_rolls() {
+    let table = (1..=20).map(|id| item(id, true, None)).collect::<Vec<_>>();
+    let settings = settings(0.5);
+
+    let first = roll_drops(&settings, &table, 1.0, &mut StdRng::seed_from_u64(42));
+    let second = roll_drops(&settings, &table, 1.0, &mut StdRng::seed_from_u64(42));
+    assert_eq!(first, second);
+    assert!(!first.is_empty() && first.len() < table.len());
+  }
+
+  #[test]
+  fn test_multiplier_rounds_up() {
+    let table = vec![item(1, false, None)];
+    let rewards = roll_drops(&settings(1.0), &table, 1.5, &mut StdRng::seed_from_u64(1));
+
+    assert_eq!(rewards[0].item_num, 5);
+  }
+
+  #[test]
+  fn test_only_drop_campaigns_multiply() {
+    assert_eq!(campaign_type_multiplier(10), Some(2.0));
+    assert_eq!(campaign_type_multiplier(60), None);
+  }
+
+  #[test]
+  fn test_campaign_applicable() {
+    let campaign = json!({
+      "type": "10",
+      "quest_type": "2",
+      "event_id": "0",
+      "quest_difficulty": "0",
+      "quest_area": "1_2_16",
+      "quest_stage": "0",
+      "start": "2020/11/23 0:00",
+      "end": "2020/11/30 0:00",
+    });
+    let scope = CampaignScope {
+      kind: QuestKind::Hunting,
+      event_id: 0,
+      area_id: 16,
+      stage_id: 1,
+      difficulty: 1,
+    };
+
+    assert!(is_campaign_applicable(&campaign, &scope, date("2020/11/25 12:00")));
+    assert!(!is_campaign_applicable(&campaign, &scope, date("2020/11/30 0:00")));
+    assert!(!is_campaign_applicable(&campaign, &scope, date("2020/11/22 23:59")));
+    assert!(!is_campaign_applicable(
+      &campaign,
+      &CampaignScope { area_id: 3, ..scope },
+      date("2020/11/25 12:00")
+    ));
+    assert!(!is_campaign_applicable(
+      &campaign,
+      &CampaignScope {
+        kind: QuestKind::Main,
+        ..scope
+      },
+      date("2020/11/25 12:00")
+    ));
+  }
+}
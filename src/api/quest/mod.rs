@@ -1,24 +1,52 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::api::battle::{apply_reward_multiplier, grant_rewards};
+use crate::api::dungeon::BattleSkipReward;
+use crate::api::master_all::get_master_manager;
+use crate::api::quest::drop::roll_stage_rewards;
+use crate::api::quest::quest_main::{BattleSkipResponse, SkipInfoRequestDto};
+use crate::api::RemoteDataItemType;
+use crate::blob::IntoRemoteData;
+use crate::call::{
+  CallCustom, CallResponse, STATUS_ERROR, STATUS_SKIPTICKET_NOT_ALLCLEAR, STATUS_SKIPTICKET_NOT_STAMINA,
+  STATUS_SKIPTICKET_NOT_TICKET, STATUS_UNKNOWN_STAGE,
+};
+use crate::item::{FetchUserItemCount, UpdateItemCountBy};
+use crate::mission::{record_mission_events, stage_difficulty, MissionEvent};
+use crate::notification::{IntoNotificationData, MissionDone};
+use crate::user::quest_progress::{FetchUserQuestProgress, QuestKind};
+use crate::user::session::Session;
+use crate::AppState;
 
+pub mod drop;
 pub mod quest_fame;
 pub mod quest_hunting;
 pub mod quest_main;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct QuestRewardItem {
   pub item_type: i32,
   pub item_id: i64,
   pub item_num: i32,
   pub item_rare: bool,
+  /// Drop chance in `0.0..=1.0`, if the master specifies it (`item_probability{n}`, e.g. `"28.09%"`).
+  /// See [drop::roll_drops] for defaults.
+  pub probability: Option<f32>,
 }
 
-/// Parses quest reward items from:
+/// Parses quest drop table (all possible rewards) from:
 /// - fame_quest_stage_itemreward
 /// - huntingquest_stage_itemreward
 /// - mainquest_stage_itemreward
 /// - dungeon_stage_item_reward
 /// - event_quest_stage_itemreward
+///
+/// Use [drop::roll_stage_rewards] to get the rewards of a single clear.
 // TODO: event_quest_boss_stage_itemreward support?
 pub fn parse_reward_items(value: &Value) -> Vec<QuestRewardItem> {
   let object = value.as_object().unwrap();
@@ -61,6 +89,11 @@ pub fn parse_reward_items(value: &Value) -> Vec<QuestRewardItem> {
         .ok()?
         != 0;
 
+      let probability = object
+        .get(format!("item_probability{i}").as_str())
+        .and_then(|v| v.as_str().unwrap().strip_suffix('%'))
+        .map(|v| v.parse::<f32>().unwrap() / 100.0);
+
       // Skip empty item slots
       if item_type == 0 || item_id == 0 || item_num == 0 {
         return None;
@@ -71,7 +104,165 @@ pub fn parse_reward_items(value: &Value) -> Vec<QuestRewardItem> {
         item_id,
         item_num,
         item_rare,
+        probability,
       })
     })
     .collect()
 }
+
+/// Clears fully completed stages without playing them, see `battle_skip` and `battle_hunting_skip`.
+///
+/// Each run consumes Skip Tickets and stamina, and rolls the stage rewards same as a normal clear.
+pub async fn make_battle_skip(
+  state: &AppState,
+  session: &Session,
+  kind: QuestKind,
+  skips: &[SkipInfoRequestDto],
+) -> anyhow::Result<CallResponse<dyn CallCustom>> {
+  let (stages, rewards) = match kind {
+    QuestKind::Main => ("mainquest_stage", "mainquest_stage_itemreward"),
+    QuestKind::Hunting => ("huntingquest_stage", "huntingquest_stage_itemreward"),
+    QuestKind::Event => ("event_quest_stage", "event_quest_stage_itemreward"),
+  };
+  let stages = get_master_manager().get_master(stages);
+  let rewards = get_master_manager().get_master(rewards);
+  // Client never sends more runs than this in a single request
+  let max_runs = get_master_manager()
+    .get_system_value("skip_ticket_max_per_request")
+    .and_then(|value| value.parse::<i32>().ok())
+    .unwrap_or(100);
+  let total_runs = skips
+    .iter()
+    .try_fold(0i32, |total, skip| total.checked_add(skip.skip_count.max(0)));
+  if total_runs.is_none_or(|total_runs| total_runs > max_runs) {
+    warn!(?skips, ?max_runs, "too many battle skips in a single request");
+    return Ok(CallResponse::new_error(STATUS_ERROR));
+  }
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let fetch_progress = FetchUserQuestProgress::new(&transaction).await?;
+
+  let mut ticket_cost = 0;
+  let mut stamina_cost = 0;
+  let mut runs = Vec::new();
+  let mut events = Vec::new();
+  for skip in skips.iter().filter(|skip| skip.skip_count > 0) {
+    let Some(stage) = stages
+      .iter()
+      .find(|stage| stage["id"].as_str().unwrap().parse::<i32>().unwrap() == skip.quest_id)
+    else {
+      return Ok(CallResponse::new_error(STATUS_UNKNOWN_STAGE));
+    };
+
+    let progress = fetch_progress.run(session.user_id, kind, skip.quest_id as i64).await?;
+    if !progress.is_all_clear() {
+      return Ok(CallResponse::new_error(STATUS_SKIPTICKET_NOT_ALLCLEAR));
+    }
+
+    // Main quest stages have no "skipticket" column, they always use a single ticket
+    let tickets = stage
+      .get("skipticket")
+      .map(|value| value.as_str().unwrap().parse::<i32>().unwrap())
+      .unwrap_or(1);
+    let stamina = stage["stamina"].as_str().unwrap().parse::<i32>().unwrap();
+    let (Some(tickets), Some(stamina)) = (
+      tickets.checked_mul(skip.skip_count).and_then(|tickets| tickets.checked_add(ticket_cost)),
+      stamina.checked_mul(skip.skip_count).and_then(|stamina| stamina.checked_add(stamina_cost)),
+    ) else {
+      warn!(?skip, "battle skip cost overflowed");
+      return Ok(CallResponse::new_error(STATUS_ERROR));
+    };
+    ticket_cost = tickets;
+    stamina_cost = stamina;
+    runs.extend(std::iter::repeat_n(stage, skip.skip_count as usize));
+    events.push(MissionEvent::BattleClear {
+      kind,
+      event_id: 0,
+      quest_id: skip.quest_id as i64,
+      difficulty: stage_difficulty(stage),
+      count: skip.skip_count,
+    });
+  }
+
+  let fetch_count = FetchUserItemCount::new(&transaction).await?;
+  let tickets = fetch_count
+    .run(session.user_id, (RemoteDataItemType::SkipTicket, 1))
+    .await?;
+  if tickets.quantity < ticket_cost {
+    return Ok(CallResponse::new_error(STATUS_SKIPTICKET_NOT_TICKET));
+  }
+  // TODO: Stamina does not regenerate yet
+  let stamina = fetch_count
+    .run(session.user_id, (RemoteDataItemType::Stamina, 0))
+    .await?;
+  if stamina.quantity < stamina_cost {
+    return Ok(CallResponse::new_error(STATUS_SKIPTICKET_NOT_STAMINA));
+  }
+
+  let update = UpdateItemCountBy::new(&transaction).await?;
+  let tickets = update
+    .run(session.user_id, tickets.item, -ticket_cost)
+    .await
+    .context("failed to consume skip tickets")?;
+  let stamina = update
+    .run(session.user_id, stamina.item, -stamina_cost)
+    .await
+    .context("failed to consume stamina")?;
+  debug!(?tickets, ?stamina, runs = runs.len(), "consumed battle skip cost");
+
+  let mut total: BTreeMap<(i32, i64), QuestRewardItem> = BTreeMap::new();
+  let mut reward = Vec::new();
+  for (index, stage) in runs.into_iter().enumerate() {
+    let id = stage["id"].as_str().unwrap();
+    let exp = stage["player_exp"].as_str().unwrap().parse::<i32>().unwrap();
+    let money = stage["money"].as_str().unwrap().parse::<i32>().unwrap();
+    let dropnum = index as i32 + 1;
+
+    let mut items = rewards
+      .iter()
+      .find(|reward| reward["id"].as_str().unwrap() == id)
+      .map(|reward| roll_stage_rewards(&state.settings.drops, Some(kind), reward))
+      .unwrap_or_default();
+    apply_reward_multiplier(&transaction, session, &mut items).await?;
+
+    // Client expects at least one entry per run to display experience and money
+    if items.is_empty() {
+      reward.push(BattleSkipReward {
+        dropnum,
+        exp,
+        money,
+        itemtype: 0,
+        itemid: 0,
+        itemnum: 0,
+      });
+    }
+    for item in items {
+      reward.push(BattleSkipReward {
+        dropnum,
+        exp,
+        money,
+        itemtype: item.item_type,
+        itemid: item.item_id as i32,
+        itemnum: item.item_num,
+      });
+      total
+        .entry((item.item_type, item.item_id))
+        .and_modify(|total| total.item_num += item.item_num)
+        .or_insert(item);
+    }
+  }
+
+  let total = total.into_values().collect::<Vec<_>>();
+  let update_items = grant_rewards(&transaction, &state.settings.inventory, session, &total).await?;
+  let missions = record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &events).await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let mut response: CallResponse<dyn CallCustom> =
+    CallResponse::new_success(Box::new(BattleSkipResponse { lvup: 0, reward }));
+  response.add_remote_data(tickets.into_remote_data());
+  response.add_remote_data(stamina.into_remote_data());
+  response.add_remote_data(update_items);
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
+  Ok(response)
+}
@@ -12,7 +12,7 @@ use crate::api::master_all::{get_master_manager, get_masters};
 use crate::api::quest::quest_hunting::{BattleReward};
 use crate::api::{battle, ApiRequest, SkillPaFameAddStatus};
 use crate::api::battle::{apply_reward_multiplier, grant_rewards, make_battle_member_exp_and_character_love};
-use crate::api::quest::parse_reward_items;
+use crate::api::quest::drop::roll_stage_rewards;
 use crate::call::{CallCustom, CallResponse};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
@@ -282,9 +282,9 @@ pub async fn fame_quest_result(
     .iter()
     .map(|reward| (reward["fame_quest_id"].as_str().unwrap().parse::<i32>().unwrap(), reward))
     .collect::<HashMap<_, _>>();
-  let mut rewards = parse_reward_items(rewards[&params.stage_id]);
+  let mut rewards = roll_stage_rewards(&state.settings.drops, None, rewards[&params.stage_id]);
   apply_reward_multiplier(&transaction, &session, &mut rewards).await?;
-  let update_items = grant_rewards(&transaction, &session, &rewards).await?;
+  let update_items = grant_rewards(&transaction, &state.settings.inventory, &session, &rewards).await?;
   transaction.commit().await.context("failed to commit transaction")?;
 
   let party = FetchUserParty::new(&client)
@@ -3,18 +3,22 @@
 
 use crate::api::battle::{apply_reward_multiplier, grant_rewards, make_battle_member_exp_and_character_love};
 use crate::api::battle_multi::{BattleCharacterLove, BattleClearReward, BattleMemberExp};
-use crate::api::dungeon::BattleSkipReward;
 use crate::api::master_all::{get_master_manager, get_masters};
 use crate::api::party_info::{Party, PartyForm, SpecialSkillInfo};
-use crate::api::quest::parse_reward_items;
+use crate::api::quest::drop::roll_stage_rewards;
+use crate::api::quest::quest_main::SkipInfoRequestDto;
+use crate::api::quest::{make_battle_skip, parse_reward_items};
 use crate::api::smith_upgrade::{DungeonAreaMaterialInfoResponseDto, FameQuestMaterialInfoResponseDto};
-use crate::api::{battle, MemberFameStats, RemoteDataItemType};
+use crate::api::{battle, MemberFameStats};
 use crate::blob::IntoRemoteData;
 use crate::call::{CallCustom, CallResponse};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
 use crate::item::UpdateItemCountBy;
 use crate::member::{FetchUserMembers, FetchUserParty, Member, MemberActiveSkill, MemberPrototype, MemberStrength};
+use crate::mission::{record_mission_events, MissionEvent};
+use crate::notification::{IntoNotificationData, MissionDone};
+use crate::user::quest_progress::{tasks_from_clear_missions, QuestKind, RecordUserQuestClear};
 use crate::user::session::Session;
 use crate::AppState;
 use anyhow::Context;
@@ -23,7 +27,6 @@ use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tracing::{debug, warn};
-use crate::api::quest::quest_main::BattleSkipResponse;
 
 // See [Wonder_Api_QuesthuntinglistResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -253,9 +256,34 @@ pub async fn battle_hunting_result(
     .iter()
     .map(|reward| (reward["id"].as_str().unwrap().parse::<i32>().unwrap(), reward))
     .collect::<HashMap<_, _>>();
-  let mut rewards = parse_reward_items(rewards[&params.quest_id]);
+  let mut rewards = roll_stage_rewards(&state.settings.drops, Some(QuestKind::Hunting), rewards[&params.quest_id]);
   apply_reward_multiplier(&transaction, &session, &mut rewards).await?;
-  let update_items = grant_rewards(&transaction, &session, &rewards).await?;
+  let update_items = grant_rewards(&transaction, &state.settings.inventory, &session, &rewards).await?;
+  if params.win == 1 {
+    RecordUserQuestClear::new(&transaction)
+      .await?
+      .run(
+        session.user_id,
+        QuestKind::Hunting,
+        0,
+        params.quest_id as i64,
+        tasks_from_clear_missions(&params.clearquestmission),
+        1,
+      )
+      .await?;
+  }
+  let missions = if params.win == 1 {
+    let event = MissionEvent::BattleClear {
+      kind: QuestKind::Hunting,
+      event_id: 0,
+      quest_id: params.quest_id as i64,
+      difficulty: 0,
+      count: 1,
+    };
+    record_mission_events(&transaction, &state.settings.daily_reset, session.user_id, &[event]).await?
+  } else {
+    Vec::new()
+  };
   transaction.commit().await.context("failed to commit transaction")?;
 
   let party = FetchUserParty::new(&client)
@@ -286,6 +314,7 @@ pub async fn battle_hunting_result(
     clearreward: vec![],
   }));
   response.remote.extend(update_items);
+  response.add_notifications(missions.into_iter().map(MissionDone::into_notification_data).collect());
   Ok(Unsigned(response))
 }
 
@@ -380,29 +409,13 @@ pub struct BattleHuntingSkipRequest {
   pub max_splitcount: i32,
 }
 
-// See [Wonder_Api_SkipInfoRequestDto_Fields]
-#[derive(Debug, Deserialize)]
-pub struct SkipInfoRequestDto {
-  pub quest_id: i32,
-  pub skip_count: i32,
-}
-
 pub async fn battle_hunting_skip(
   state: Arc<AppState>,
   session: Arc<Session>,
   Params(params): Params<BattleHuntingSkipRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: battle_hunting_skip");
+  debug!(?params, "battle hunting skip");
 
-  Ok(Unsigned(BattleSkipResponse {
-    lvup: 0,
-    reward: vec![BattleSkipReward {
-      dropnum: 1,
-      exp: 500,
-      money: 1000,
-      itemtype: RemoteDataItemType::RealMoney.into(),
-      itemid: 1,
-      itemnum: 5000,
-    }],
-  }))
+  let response = make_battle_skip(&state, &session, QuestKind::Hunting, &params.skip).await?;
+  Ok(Unsigned(response))
 }
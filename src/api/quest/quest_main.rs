@@ -2,16 +2,17 @@
 
 use crate::api::dungeon::BattleSkipReward;
 use crate::api::master_all::get_master_manager;
-use crate::api::quest::quest_hunting::BattleHuntingSkipRequest;
-use crate::api::{NotificationData, RemoteDataItemType};
+use crate::api::quest::make_battle_skip;
+use crate::api::NotificationData;
 use crate::call::{CallCustom, CallResponse};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
+use crate::user::quest_progress::QuestKind;
 use crate::user::session::Session;
 use crate::AppState;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tracing::warn;
+use tracing::debug;
 
 // See [Wonder_Api_QuestMainPartListResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -166,19 +167,10 @@ impl CallCustom for BattleSkipResponse {}
 pub async fn battle_skip(
   state: Arc<AppState>,
   session: Arc<Session>,
-  Params(params): Params<BattleHuntingSkipRequest>,
+  Params(params): Params<BattleSkipRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: battle_skip");
-
-  Ok(Unsigned(BattleSkipResponse {
-    lvup: 0,
-    reward: vec![BattleSkipReward {
-      dropnum: 1,
-      exp: 500,
-      money: 1000,
-      itemtype: RemoteDataItemType::RealMoney.into(),
-      itemid: 1,
-      itemnum: 5000,
-    }],
-  }))
+  debug!(?params, "battle skip");
+
+  let response = make_battle_skip(&state, &session, QuestKind::Main, &params.skip).await?;
+  Ok(Unsigned(response))
 }
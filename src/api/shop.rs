@@ -1,8 +1,20 @@
-use crate::call::CallCustom;
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::call::{CallCustom, CallResponse, STATUS_PURCHASE_ERROR, STATUS_PURCHASE_MAINTENANCE};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
-use serde::{Serialize, Deserialize};
-use tracing::warn;
+use crate::purchase::{
+  credit_approved_purchases, credit_purchase, purchase_items, AddUserPurchase, FetchUserChargeTotal, PurchaseReceipt,
+  PurchaseVerdict,
+};
+use crate::shop::{get_real_money_item, get_shop_items, FetchUserShopPurchases};
+use crate::user::session::Session;
+use crate::AppState;
 
 // See [Wonder_Api_PurchaseGoogleLimitedProductsStatusResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -20,10 +32,48 @@ pub struct GoogleLimitedProductStatus {
   pub end_at: String,
 }
 
-pub async fn purchase_google_limited_products_status() -> impl IntoHandlerResponse {
-  warn!("encountered stub: purchase_google_limited_products_status");
+/// Also credits purchases approved since the last time, the client requests it when opening the shop.
+pub async fn purchase_google_limited_products_status(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+) -> impl IntoHandlerResponse {
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let credited = credit_approved_purchases(&transaction, &state.settings, &session).await?;
+  let purchases = FetchUserShopPurchases::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+
+  let now = Utc::now();
+  let product_list = get_shop_items()
+    .iter()
+    .filter(|item| item.is_real_money_sale() && item.is_open(now.naive_utc()))
+    .filter_map(|item| {
+      let limit = item.limit()?;
+      let bought = purchases.get(&item.id).map_or(0, |purchase| {
+        purchase.current_count(item, now, &state.settings.daily_reset)
+      });
+      // Whichever comes first: the sale ends or the buy count resets
+      let end_at = item
+        .end_at
+        .map(|end_at| end_at.and_utc())
+        .into_iter()
+        .chain(item.limit_kind.next_reset(now, &state.settings.daily_reset))
+        .min();
+      Some(GoogleLimitedProductStatus {
+        external_id: item.external_id.clone(),
+        purchasable_amount: (limit - bought).max(0),
+        end_at: end_at.map_or_else(String::new, |end_at| end_at.format("%Y-%m-%d %H:%M:%S").to_string()),
+      })
+    })
+    .collect();
+
+  let mut response = CallResponse::new_success(Box::new(PurchaseGoogleLimitedProductsStatus { product_list }));
+  response.add_remote_data(credited);
 
-  Unsigned(PurchaseGoogleLimitedProductsStatus { product_list: vec![] })
+  Ok(Unsigned(response))
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,9 +90,87 @@ pub struct PurchaseGoogleChargeStatus {
 impl CallCustom for PurchaseGoogleChargeStatus {}
 
 pub async fn purchase_google_charge_status(
+  state: Arc<AppState>,
+  session: Arc<Session>,
   Params(params): Params<PurchaseGoogleChargeStatusRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: purchase_google_charge_status");
+  let client = state.get_database_client().await?;
+  let charge_total = FetchUserChargeTotal::new(&client).await?.run(session.user_id).await?;
+  info!(?params.product_id, ?charge_total, "fetched charge total");
+
+  Ok(Unsigned(PurchaseGoogleChargeStatus { charge_total }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurchaseGoogleRequest {
+  pub product_id: String,
+  pub receipt: Option<String>,
+  pub signature: Option<String>,
+}
+
+pub async fn purchase_google(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<PurchaseGoogleRequest>,
+) -> impl IntoHandlerResponse {
+  let now = Utc::now();
+  let Some(prototype) = get_real_money_item(&params.product_id, now.naive_utc()) else {
+    warn!(?params.product_id, "unknown or closed store product");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_PURCHASE_ERROR)));
+  };
+  if purchase_items(prototype, &state.settings.purchase).is_empty() {
+    warn!(?params.product_id, "store product has no known contents");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_PURCHASE_ERROR)));
+  }
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+  let bought = FetchUserShopPurchases::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .get(&prototype.id)
+    .map_or(0, |purchase| {
+      purchase.current_count(prototype, now, &state.settings.daily_reset)
+    });
+  if prototype.limit().is_some_and(|limit| bought >= limit) {
+    return Ok(Unsigned(CallResponse::new_error(prototype.limit_kind.limit_status())));
+  }
+
+  let receipt = PurchaseReceipt {
+    product_id: params.product_id,
+    receipt: params.receipt,
+    signature: params.signature,
+  };
+  let verdict = state
+    .purchase_provider
+    .verify(session.user_id, prototype, &receipt)
+    .await?;
+  if verdict == PurchaseVerdict::Unavailable {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_PURCHASE_MAINTENANCE)));
+  }
+
+  let purchase = AddUserPurchase::new(&transaction)
+    .await?
+    .run(session.user_id, prototype, &receipt, verdict.approved())
+    .await?;
+  info!(?purchase, ?verdict, "recorded purchase");
+
+  let response: CallResponse<dyn CallCustom> = match verdict {
+    PurchaseVerdict::Approved => {
+      match credit_purchase(&transaction, &state.settings, &session, purchase.id, prototype).await? {
+        Some(remote_data) => {
+          let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+          response.add_remote_data(remote_data);
+          response
+        }
+        None => CallResponse::new_error(STATUS_PURCHASE_ERROR),
+      }
+    }
+    PurchaseVerdict::Pending => CallResponse::new_success(Box::new(())),
+    PurchaseVerdict::Rejected | PurchaseVerdict::Unavailable => CallResponse::new_error(STATUS_PURCHASE_ERROR),
+  };
+  transaction.commit().await.context("failed to commit transaction")?;
 
-  Unsigned(PurchaseGoogleChargeStatus { charge_total: 2112 })
+  Ok(Unsigned(response))
 }
@@ -1,17 +1,24 @@
 use crate::api::master_all::get_master_manager;
 use crate::api::RemoteDataItemType;
-use crate::blob::{AddEquipment, IntoRemoteData};
-use crate::call::{CallCustom, CallResponse};
+use crate::blob::IntoRemoteData;
+use crate::call::{
+  CallCustom, CallResponse, STATUS_MAX_HAVE_ITEM, STATUS_NOHAVE_ACCESSORY, STATUS_NOHAVE_WEAPON,
+  STATUS_UNKNOWN_BLACKSMITH,
+};
+use crate::equipment::{consume_material_equipment, get_equipment_recipe, AddUserEquipment};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed, Unsigned};
+use crate::inventory::{FetchUserInventory, InventoryKind};
+use crate::item::{spend_items, CountedItem, ItemCost};
 use crate::user::session::Session;
 use crate::AppState;
 use anyhow::Context;
+use chrono::Utc;
 use jwt_simple::prelude::Serialize;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 
 // See [Wonder_Api_BlacksmithlistResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -113,6 +120,28 @@ pub struct BlacksmithReturnedItem {
   pub item_num: i32,
 }
 
+impl From<CountedItem> for BlacksmithReturnedItem {
+  fn from(item: CountedItem) -> Self {
+    Self {
+      item_type: item.item.item_type.into(),
+      item_id: item.item.item_id,
+      item_num: item.quantity,
+    }
+  }
+}
+
+/// Error status for material equipment that does not match [required].
+pub fn material_equipment_status(required: &[CountedItem]) -> i32 {
+  if required
+    .iter()
+    .any(|required| required.item.item_type == RemoteDataItemType::Accessory)
+  {
+    STATUS_NOHAVE_ACCESSORY
+  } else {
+    STATUS_NOHAVE_WEAPON
+  }
+}
+
 // body={"num": "1", "item_id": "12211", "material_equipments": "[]", "item_type": "5"}
 #[derive(Debug, Deserialize)]
 pub struct BlacksmithRequest {
@@ -127,77 +156,98 @@ pub async fn blacksmith(
   session: Arc<Session>,
   Params(params): Params<BlacksmithRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: blacksmith");
-
-  let equip_weapon_details = get_master_manager().get_master("equip_weapon_details");
-
-  // (item_id, level) -> item_id_details
-  let item_to_item_details: HashMap<(i64, i32), i64> = equip_weapon_details
-    .iter()
-    .map(|data| {
-      let item_id: i64 = data["item_id"].as_str().unwrap().parse::<i64>().unwrap();
-      let level: i32 = data["lv"].as_str().unwrap().parse::<i32>().unwrap();
-      let item_id_details: i64 = data["item_id_details"].as_str().unwrap().parse::<i64>().unwrap();
-      ((item_id, level), item_id_details)
-    })
-    .collect::<HashMap<_, _>>();
+  let item_type = RemoteDataItemType::from(params.item_type);
+  let Some(recipe) = get_equipment_recipe(item_type, params.item_id).filter(|recipe| {
+    recipe.is_open(Utc::now().naive_utc()) && (1..=state.settings.inventory.equipment_capacity).contains(&params.num)
+  }) else {
+    warn!(?params, "equipment can not be crafted");
+    return Ok(Signed(CallResponse::new_error(STATUS_UNKNOWN_BLACKSMITH), session));
+  };
+  let items = recipe
+    .item_materials()
+    .map(|required| Some(required.item.into_counted(required.quantity.checked_mul(params.num)?)))
+    .collect::<Option<Vec<_>>>();
+  let (Some(items), Some(money)) = (items, recipe.money.checked_mul(params.num)) else {
+    warn!(?params, "crafting cost overflows");
+    return Ok(Signed(CallResponse::new_error(STATUS_UNKNOWN_BLACKSMITH), session));
+  };
+  let cost = ItemCost {
+    items,
+    money,
+    ..Default::default()
+  };
 
-  // TODO: Does not consume items, and does not validate anything.
   let mut client = state.get_database_client().await?;
   let transaction = client.transaction().await.context("failed to start transaction")?;
-  #[rustfmt::skip]
-  let statement = transaction
-    .prepare(/* language=postgresql */ r#"
-      insert into user_items_equipment (user_id, item_type, item_id, level)
-      values ($1, $2, $3, 0)
-      returning id, item_type, item_id, level, is_locked
-    "#)
-    .await
-    .context("failed to prepare statement")?;
-  let row = transaction
-    .query_one(
-      &statement,
-      &[&session.user_id, &(params.item_type as i64), &params.item_id],
-    )
-    .await
-    .context("failed to execute query")?;
-  debug!(?row, "created equipment item");
-
-  let id = row.get::<_, i64>("id");
-  let item_type = row.get::<_, i64>("item_type") as i32;
-  let item_id = row.get::<_, i64>("item_id");
-  let level = row.get::<_, i32>("level");
-  let is_locked = row.get::<_, bool>("is_locked");
-  let item_details_id = *item_to_item_details
-    .get(&(item_id, level))
-    .expect(&format!("missing item details for item_id={} level={}", item_id, level));
-
-  let mut response = CallResponse::new_success(Box::new(BlacksmithResponse {
-    items: vec![BlacksmithItem {
-      unique_id: id,
-      item_type,
-      item_id: item_details_id,
+
+  let spent = match spend_items(&transaction, session.user_id, &cost).await? {
+    Ok(spent) => spent,
+    Err(error) => {
+      warn!(?params, %error, "not enough materials");
+      return Ok(Signed(CallResponse::new_error(error.status()), session));
+    }
+  };
+
+  let required_equipment = recipe
+    .equipment_materials()
+    .map(|required| required.item.into_counted(required.quantity * params.num))
+    .collect::<Vec<_>>();
+  let Some(consumed) = consume_material_equipment(
+    &transaction,
+    session.user_id,
+    &required_equipment,
+    &params.material_equipments,
+  )
+  .await?
+  else {
+    warn!(?params, "invalid material equipment");
+    let status = material_equipment_status(&required_equipment);
+    return Ok(Signed(CallResponse::new_error(status), session));
+  };
+  let mut remote_data = consumed.remote_data;
+  remote_data.extend(spent.into_iter().flat_map(IntoRemoteData::into_remote_data));
+
+  // Consumed materials free up space first
+  let inventory = FetchUserInventory::new(&transaction)
+    .await?
+    .run(session.user_id)
+    .await?;
+  if inventory.free_space(InventoryKind::Equipment, &state.settings.inventory) < params.num {
+    return Ok(Signed(CallResponse::new_error(STATUS_MAX_HAVE_ITEM), session));
+  }
+
+  let add_equipment = AddUserEquipment::new(&transaction).await?;
+  let mut items = Vec::new();
+  for _ in 0..params.num {
+    let equipment = add_equipment
+      .run(session.user_id, item_type, params.item_id, 0)
+      .await
+      .context("failed to create equipment")?;
+    debug!(?equipment, "created equipment item");
+
+    let remote = equipment.to_remote();
+    items.push(BlacksmithItem {
+      unique_id: equipment.id,
+      item_type: params.item_type,
+      item_id: remote.item_id,
       item_num: 1,
-    }],
-    returned_items: vec![
-      // BlacksmithReturnedItem {
-      //   item_type: 5,
-      //   item_id: 111101,
-      //   item_num: 2,
-      // }
-    ],
-  }));
-  response.remote.extend(
-    AddEquipment::new(
-      RemoteDataItemType::from(item_type),
-      item_details_id,
-      id as i32,
-      is_locked,
-    )
-      .into_remote_data(),
-  );
+    });
+    remote_data.extend(remote.into_remote_data());
+  }
 
   transaction.commit().await.context("failed to commit transaction")?;
+  info!(
+    ?params.item_id,
+    num = params.num,
+    consumed = ?params.material_equipments,
+    "crafted equipment"
+  );
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(BlacksmithResponse {
+    items,
+    returned_items: consumed.returned_items.into_iter().map(Into::into).collect(),
+  }));
+  response.add_remote_data(remote_data);
 
   Ok(Signed(response, session))
 }
@@ -1,14 +1,19 @@
 use anyhow::Context;
 use jwt_simple::prelude::Serialize;
 use serde::Deserialize;
-use std::collections::HashMap;
 use std::sync::Arc;
-
-use crate::api::master_all::get_master_manager;
-use crate::api::ApiRequest;
-use crate::call::CallCustom;
+use tracing::{info, warn};
+
+use crate::api::RemoteDataItemType;
+use crate::blob::{DeleteEquipment, IntoRemoteData};
+use crate::call::{
+  CallCustom, CallResponse, STATUS_EQUIPMENT_UNAVAILABLE, STATUS_ITEM_NOT_ENOUGH, STATUS_ITEM_NOT_HAVE,
+  STATUS_NOHAVE_WEAPON, STATUS_USE_ITEM_NUM_ZERO,
+};
+use crate::equipment::{DeleteUserEquipmentIn, FetchEquippedEquipmentIds, FetchUserEquipmentIn};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
+use crate::item::{get_item_sell_price, FetchUserItemCount, IntoItemReference, UpdateItemCountBy};
 use crate::user::session::Session;
 use crate::AppState;
 
@@ -34,7 +39,7 @@ pub struct SaleItem {
   pub trial: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, Deserialize)]
 pub enum SaleListKind {
   /// "Equipment"
   #[serde(rename = "equip")]
@@ -56,62 +61,48 @@ pub async fn sale_list(
   session: Arc<Session>,
   Params(params): Params<SaleListRequest>,
 ) -> impl IntoHandlerResponse {
-  let items = get_master_manager().get_master("item");
-  let equip_weapons = get_master_manager().get_master("equip_weapon");
-  let equip_weapon_details = get_master_manager().get_master("equip_weapon_details");
-
   let client = state.get_database_client().await?;
 
   let items = match params.kind {
     SaleListKind::Equipment => {
-      // (item_id, level) -> item_id_details
-      let item_to_item_details: HashMap<(i64, i32), i64> = equip_weapon_details
-        .iter()
-        .map(|data| {
-          let item_id: i64 = data["item_id"].as_str().unwrap().parse::<i64>().unwrap();
-          let level: i32 = data["lv"].as_str().unwrap().parse::<i32>().unwrap();
-          let item_id_details: i64 = data["item_id_details"].as_str().unwrap().parse::<i64>().unwrap();
-          ((item_id, level), item_id_details)
-        })
-        .collect::<HashMap<_, _>>();
-
       #[rustfmt::skip]
       let statement = client
         .prepare(/* language=postgresql */ r#"
-          select
-            id,
-            item_type,
-            item_id,
-            level,
-            is_locked
+          select id
           from user_items_equipment
           where user_id = $1
         "#)
         .await
         .context("failed to prepare statement")?;
-      let rows = client
+      let ids = client
         .query(&statement, &[&session.user_id])
         .await
-        .context("failed to execute query")?;
-
-      rows
+        .context("failed to execute query")?
         .iter()
-        .map(|item| {
-          let item_id = item.get::<_, i64>("item_id");
-          let level = item.get::<_, i32>("level");
-          let item_details_id = *item_to_item_details
-            .get(&(item_id, level))
-            .expect(&format!("missing item details for item_id={} level={}", item_id, level));
-
-          SaleItem {
-            item_type: item.get::<_, i64>("item_type") as i32,
-            item_id: item_details_id,
-            target_item_id: item.get::<_, i64>("id"),
+        .map(|row| row.get::<_, i64>("id"))
+        .collect::<Vec<_>>();
+      let equipment = FetchUserEquipmentIn::new(&client)
+        .await?
+        .run(session.user_id, &ids)
+        .await?;
+      let equipped = FetchEquippedEquipmentIds::new(&client)
+        .await?
+        .run(session.user_id)
+        .await?;
+
+      equipment
+        .iter()
+        .filter_map(|equipment| {
+          let level = equipment.level_data()?;
+          Some(SaleItem {
+            item_type: equipment.item_type.into(),
+            item_id: level.item_id_details,
+            target_item_id: equipment.id,
             item_num: 1,
-            islock: item.get::<_, bool>("is_locked"),
-            isuse: false,
+            islock: equipment.is_locked,
+            isuse: equipped.contains(&equipment.id),
             trial: false,
-          }
+          })
         })
         .collect()
     }
@@ -135,6 +126,10 @@ pub async fn sale_list(
 
       rows
         .iter()
+        .filter(|item| {
+          let item_type = RemoteDataItemType::from(item.get::<_, i64>("item_type") as i32);
+          get_item_sell_price((item_type, item.get::<_, i64>("item_id"))).is_some()
+        })
         .map(|item| SaleItem {
           item_type: item.get::<_, i64>("item_type") as i32,
           item_id: item.get::<_, i64>("item_id"),
@@ -151,9 +146,108 @@ pub async fn sale_list(
   Ok(Unsigned(SaleList { items }))
 }
 
+// See [Wonder_Api_SaleRequestItemsRequestDto_Fields]
+#[derive(Debug, Deserialize)]
+pub struct SaleRequestItem {
+  pub item_type: i32,
+  /// Unique ID for equipment, item ID for materials
+  pub target_item_id: i64,
+  pub use_num: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaleRequest {
+  #[serde(rename = "type")]
+  pub kind: SaleListKind,
+  pub items: Vec<SaleRequestItem>,
+}
+
 // type=material
 // items=[{"item_type":15,"target_item_id":0,"use_num":1},{"item_type":15,"target_item_id":0,"use_num":1}]
-pub async fn sale(_request: ApiRequest) -> impl IntoHandlerResponse {
+pub async fn sale(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<SaleRequest>,
+) -> impl IntoHandlerResponse {
+  if params.items.is_empty() || params.items.iter().any(|item| item.use_num < 1) {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_USE_ITEM_NUM_ZERO)));
+  }
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let mut remote_data = Vec::new();
+  let mut money = 0;
+  match params.kind {
+    SaleListKind::Equipment => {
+      let ids = params.items.iter().map(|item| item.target_item_id).collect::<Vec<_>>();
+      let equipment = FetchUserEquipmentIn::new(&transaction)
+        .await?
+        .run(session.user_id, &ids)
+        .await?;
+      if equipment.len() != ids.len() {
+        warn!(?params, "equipment to sell not found");
+        return Ok(Unsigned(CallResponse::new_error(STATUS_NOHAVE_WEAPON)));
+      }
+
+      let equipped = FetchEquippedEquipmentIds::new(&transaction)
+        .await?
+        .run(session.user_id)
+        .await?;
+      for equipment in &equipment {
+        if equipment.is_locked || equipped.contains(&equipment.id) {
+          warn!(?equipment, "locked or equipped equipment can not be sold");
+          return Ok(Unsigned(CallResponse::new_error(STATUS_EQUIPMENT_UNAVAILABLE)));
+        }
+
+        let level = equipment.level_data().context("missing item details")?;
+        money += level.sell;
+        remote_data.extend(
+          DeleteEquipment::new(equipment.item_type, level.item_id_details, equipment.id as i32).into_remote_data(),
+        );
+      }
+      DeleteUserEquipmentIn::new(&transaction)
+        .await?
+        .run(session.user_id, &ids)
+        .await?;
+    }
+    SaleListKind::Material => {
+      let fetch_count = FetchUserItemCount::new(&transaction).await?;
+      let update = UpdateItemCountBy::new(&transaction).await?;
+      for item in &params.items {
+        let reference = (RemoteDataItemType::from(item.item_type), item.target_item_id).into_item_reference();
+        let Some(sell) = get_item_sell_price(reference) else {
+          warn!(?item, "item can not be sold");
+          return Ok(Unsigned(CallResponse::new_error(STATUS_ITEM_NOT_HAVE)));
+        };
+        let owned = fetch_count.run(session.user_id, reference).await?;
+        if owned.quantity < item.use_num {
+          warn!(?item, ?owned, "not enough items to sell");
+          return Ok(Unsigned(CallResponse::new_error(STATUS_ITEM_NOT_ENOUGH)));
+        }
+
+        money += sell * item.use_num;
+        let item = update
+          .run(session.user_id, reference, -item.use_num)
+          .await
+          .context("failed to sell item")?;
+        remote_data.extend(item.into_remote_data());
+      }
+    }
+  }
+
+  let item = UpdateItemCountBy::new(&transaction)
+    .await?
+    .run(session.user_id, (RemoteDataItemType::Money, 0), money)
+    .await
+    .context("failed to grant money")?;
+  remote_data.extend(item.into_remote_data());
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?params.kind, ?money, "sold items");
+
   // See [Wonder_Api_SaleResponseDto_Fields]
-  Ok(Unsigned(()))
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_remote_data(remote_data);
+
+  Ok(Unsigned(response))
 }
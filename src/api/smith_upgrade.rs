@@ -2,19 +2,25 @@ use crate::api::master_all::get_master_manager;
 use crate::api::quest::parse_reward_items;
 use crate::api::quest::quest_fame::FameQuestReleaseConditionInfo;
 use crate::api::quest::quest_hunting::HuntingQuest;
-use crate::api::smith_craft::{BlacksmithEquippedItemResponseDto, BlacksmithItem, BlacksmithReturnedItem};
+use crate::api::smith_craft::{material_equipment_status, BlacksmithEquippedItemResponseDto, BlacksmithReturnedItem};
 use crate::api::RemoteDataItemType;
-use crate::blob::{AddEquipment, IntoRemoteData};
-use crate::call::{CallCustom, CallResponse};
+use crate::blob::IntoRemoteData;
+use crate::call::{
+  CallCustom, CallResponse, STATUS_MAX_LEVEL, STATUS_NOHAVE_ACCESSORY, STATUS_NOHAVE_WEAPON, STATUS_UNKNOWN_ITEMPOWERUP,
+};
+use crate::equipment::{
+  consume_material_equipment, is_equipment, upgrade_cost, FetchUserEquipmentIn, UpdateUserEquipmentLevel,
+};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Unsigned};
+use crate::item::{spend_items, ItemCost};
 use crate::user::session::Session;
 use crate::AppState;
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{debug, info, warn};
+use tracing::{info, warn};
 
 // See [Wonder_Api_ItempoweruplistResponseDto_Fields]
 #[derive(Debug, Serialize)]
@@ -114,72 +120,91 @@ pub async fn item_power_up(
   session: Arc<Session>,
   Params(params): Params<ItemPowerUpRequest>,
 ) -> impl IntoHandlerResponse {
-  warn!(?params, "encountered stub: item_power_up");
+  let item_type = RemoteDataItemType::from(params.item_type);
+  if !is_equipment(item_type) || params.powerup_count < 1 {
+    return Ok(Unsigned(CallResponse::new_error(STATUS_UNKNOWN_ITEMPOWERUP)));
+  }
 
-  let equip_weapon_details = get_master_manager().get_master("equip_weapon_details");
-
-  // (item_id, level) -> item_id_details
-  let item_to_item_details: HashMap<(i64, i32), i64> = equip_weapon_details
-    .iter()
-    .map(|data| {
-      let item_id: i64 = data["item_id"].as_str().unwrap().parse::<i64>().unwrap();
-      let level: i32 = data["lv"].as_str().unwrap().parse::<i32>().unwrap();
-      let item_id_details: i64 = data["item_id_details"].as_str().unwrap().parse::<i64>().unwrap();
-      ((item_id, level), item_id_details)
-    })
-    .collect::<HashMap<_, _>>();
-
-  // TODO: Does not consume items, and does not validate anything.
   let mut client = state.get_database_client().await?;
   let transaction = client.transaction().await.context("failed to start transaction")?;
-  #[rustfmt::skip]
-  let statement = transaction
-    .prepare(/* language=postgresql */ r#"
-      update user_items_equipment
-      set level = level + $4
-      where id = $1 and user_id = $2 and item_type = $3
-      returning id, item_type, item_id, level, is_locked
-    "#)
-    .await
-    .context("failed to prepare statement")?;
-  let row = transaction
-    .query_one(
-      &statement,
-      &[&params.target_item_id, &session.user_id, &(params.item_type as i64), &params.powerup_count],
-    )
-    .await
-    .context("failed to execute query")?;
-  debug!(?row, "upgraded equipment item");
-
-  let id = row.get::<_, i64>("id");
-  let item_type = row.get::<_, i64>("item_type") as i32;
-  let item_id = row.get::<_, i64>("item_id");
-  let level = row.get::<_, i32>("level");
-  let is_locked = row.get::<_, bool>("is_locked");
-  let item_details_id = *item_to_item_details
-    .get(&(item_id, level))
-    .expect(&format!("missing item details for item_id={} level={}", item_id, level));
-  info!(?id, ?item_type, ?item_id, ?level, ?is_locked, "upgraded item");
-
-  let mut response = CallResponse::new_success(Box::new(ItemPowerUpResponse {
+
+  let Some(target) = FetchUserEquipmentIn::new(&transaction)
+    .await?
+    .run(session.user_id, &[params.target_item_id])
+    .await?
+    .into_iter()
+    .find(|target| target.item_type == item_type)
+  else {
+    warn!(?params, "equipment to upgrade not found");
+    let status = if item_type == RemoteDataItemType::Accessory {
+      STATUS_NOHAVE_ACCESSORY
+    } else {
+      STATUS_NOHAVE_WEAPON
+    };
+    return Ok(Unsigned(CallResponse::new_error(status)));
+  };
+
+  // Saturated levels are above the max level, see [upgrade_cost]
+  let level = target.level.saturating_add(params.powerup_count);
+  let Some((materials, money)) = upgrade_cost(target.item_type, target.item_id, target.level, level) else {
+    warn!(?params, ?target, "equipment is already at max level");
+    return Ok(Unsigned(CallResponse::new_error(STATUS_MAX_LEVEL)));
+  };
+  let (required_equipment, materials): (Vec<_>, Vec<_>) = materials
+    .into_iter()
+    .partition(|material| is_equipment(material.item.item_type));
+
+  let cost = ItemCost {
+    items: materials,
+    money,
+    ..Default::default()
+  };
+  let spent = match spend_items(&transaction, session.user_id, &cost).await? {
+    Ok(spent) => spent,
+    Err(error) => {
+      warn!(?params, %error, "not enough materials");
+      return Ok(Unsigned(CallResponse::new_error(error.status())));
+    }
+  };
+
+  // Target can not be consumed as its own material
+  let material_ids = params
+    .material_equipments
+    .iter()
+    .map(|material| material.unique_id as i64)
+    .collect::<Vec<_>>();
+  let consumed = if material_ids.contains(&target.id) {
+    None
+  } else {
+    consume_material_equipment(&transaction, session.user_id, &required_equipment, &material_ids).await?
+  };
+  let Some(consumed) = consumed else {
+    warn!(?params, "invalid material equipment");
+    let status = material_equipment_status(&required_equipment);
+    return Ok(Unsigned(CallResponse::new_error(status)));
+  };
+  let mut remote_data = consumed.remote_data;
+
+  remote_data.extend(spent.into_iter().flat_map(IntoRemoteData::into_remote_data));
+
+  let target = UpdateUserEquipmentLevel::new(&transaction)
+    .await?
+    .run(session.user_id, target.id, level)
+    .await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?target, ?money, "upgraded item");
+
+  let remote = target.to_remote();
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(ItemPowerUpResponse {
     items: vec![ItemPowerUpItem {
-      itemtype: item_type,
-      itemid: item_details_id as i32,
+      itemtype: params.item_type,
+      itemid: remote.item_id as i32,
       itemnum: 1,
     }],
-    returned_items: vec![],
+    returned_items: consumed.returned_items.into_iter().map(Into::into).collect(),
   }));
-  response.remote.extend(
-    AddEquipment::new(
-      RemoteDataItemType::from(item_type),
-      item_details_id,
-      id as i32,
-      is_locked,
-    )
-    .into_remote_data(),
-  );
-
-  transaction.commit().await.context("failed to commit transaction")?;
+  remote_data.extend(remote.into_remote_data());
+  response.add_remote_data(remote_data);
 
   Ok(Unsigned(response))
 }
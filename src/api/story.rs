@@ -1,16 +1,19 @@
-use std::iter;
 use std::sync::Arc;
 
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use serde_repr::{Deserialize_repr, Serialize_repr};
+use tracing::{info, warn};
 
-use crate::api::master_all::get_masters;
-use crate::api::ApiRequest;
-use crate::call::CallCustom;
+use crate::api::battle::grant_rewards;
+use crate::api::quest::QuestRewardItem;
+use crate::api::RemoteData;
+use crate::call::{CallCustom, CallResponse, STATUS_ERROR};
 use crate::extractor::Params;
 use crate::handler::{IntoHandlerResponse, Signed, Unsigned};
+use crate::story::{get_stories, get_story, parse_selections, ReadUserStory, UserStoryState};
 use crate::user::session::Session;
+use crate::AppState;
 
 // See [Wonder_Api_StorylistResponseDto_Fields]
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,7 +46,7 @@ pub enum StoryStatus {
 }
 
 // See [Wonder.UI.Data.StoryDataManager$$CreateList]
-#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 pub enum StoryType {
   /// "Main Story"
@@ -60,6 +63,22 @@ pub enum StoryType {
   Other = 6,
 }
 
+impl TryFrom<i32> for StoryType {
+  type Error = i32;
+
+  fn try_from(value: i32) -> Result<Self, Self::Error> {
+    match value {
+      1 => Ok(StoryType::Main),
+      2 => Ok(StoryType::Member),
+      3 => Ok(StoryType::Reminiscence),
+      4 => Ok(StoryType::Event),
+      5 => Ok(StoryType::Gacha),
+      6 => Ok(StoryType::Other),
+      value => Err(value),
+    }
+  }
+}
+
 // See [Wonder_Api_StorylistSelectionsResponseDto_Fields]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StorySelection {
@@ -73,31 +92,31 @@ pub struct StoryListRequest {
   pub kind: i32,
 }
 
-pub async fn story_list(Params(params): Params<StoryListRequest>) -> impl IntoHandlerResponse {
-  let index = 0;
+pub async fn story_list(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(_params): Params<StoryListRequest>,
+) -> impl IntoHandlerResponse {
+  let client = state.get_database_client().await?;
+  let story_state = UserStoryState::fetch(&client, session.user_id).await?;
 
-  let masters = get_masters().await;
-  let parse = |master_name: &str| {
-    serde_json::from_str::<Vec<Value>>(&masters[&format!("story_{master_name}")].master_decompressed).unwrap()
-  };
-
-  let stories = parse("main")
+  let stories = get_stories()
     .iter()
-    .zip(iter::repeat(StoryType::Main))
-    .chain(parse("reminiscence").iter().zip(iter::repeat(StoryType::Reminiscence)))
-    .chain(parse("etc").iter().zip(iter::repeat(StoryType::Other)))
-    .chain(parse("event").iter().zip(iter::repeat(StoryType::Event)))
-    .chain(parse("gacha").iter().zip(iter::repeat(StoryType::Gacha)))
-    .chain(parse("member").iter().zip(iter::repeat(StoryType::Member)))
-    .chain(parse("unique").iter().zip(iter::repeat(StoryType::Reminiscence)))
-    .enumerate()
-    .map(|(index, (story, kind))| StoryData {
-      user_story_id: index as i32,
-      story_type: kind,
-      story_id: story.get("id").unwrap().as_str().unwrap().parse::<i32>().unwrap(),
+    .map(|story| StoryData {
+      user_story_id: story.user_story_id,
+      story_type: story.story_type,
+      story_id: story.story_id as i32,
       force_release: false,
-      selections: vec![StorySelection { selection: vec![] }],
-      status: StoryStatus::Done,
+      selections: match story_state.read.get(&(story.story_type, story.story_id)) {
+        Some(selections) if !selections.is_empty() => selections
+          .iter()
+          .map(|selection| StorySelection {
+            selection: selection.clone(),
+          })
+          .collect(),
+        _ => vec![StorySelection { selection: vec![] }],
+      },
+      status: story.status(&story_state),
     })
     .collect::<Vec<_>>();
 
@@ -399,24 +418,102 @@ pub struct StoryRewardItem {
   pub num: i32,
 }
 
+/// Marks a story as read, granting its rewards on the first read.
+/// Returns `None` if the story is unknown or still locked.
+async fn read_story(
+  state: &AppState,
+  session: &Session,
+  user_story_id: i32,
+  selections: &str,
+) -> anyhow::Result<Option<(Vec<QuestRewardItem>, Vec<RemoteData>)>> {
+  let Some(story) = get_story(user_story_id) else {
+    warn!(?user_story_id, "unknown story");
+    return Ok(None);
+  };
+
+  let mut client = state.get_database_client().await?;
+  let transaction = client.transaction().await.context("failed to start transaction")?;
+
+  let story_state = UserStoryState::fetch(&transaction, session.user_id).await?;
+  if matches!(story.status(&story_state), StoryStatus::Locked) {
+    warn!(?story, "story is locked");
+    return Ok(None);
+  }
+
+  let selections = parse_selections(selections);
+  let first_read = ReadUserStory::new(&transaction)
+    .await?
+    .run(session.user_id, story, &selections)
+    .await?;
+  let rewards = if first_read {
+    story.first_read_rewards(&state.settings.story)
+  } else {
+    Vec::new()
+  };
+  let remote_data = grant_rewards(&transaction, &state.settings.inventory, session, &rewards).await?;
+  transaction.commit().await.context("failed to commit transaction")?;
+  info!(?story.story_type, ?story.story_id, ?first_read, ?selections, "read story");
+
+  Ok(Some((rewards, remote_data)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StoryRewardRequest {
+  pub user_story_id: i32,
+  pub selections: String,
+}
+
 // type=3
 // route=direct
 // is_skip=0
 // user_story_id=255
 // selections=[]
 // story_id=300102
-pub async fn story_reward(_request: ApiRequest, session: Arc<Session>) -> impl IntoHandlerResponse {
-  Signed(StoryReward { reward: vec![] }, session)
+pub async fn story_reward(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<StoryRewardRequest>,
+) -> impl IntoHandlerResponse {
+  let Some((rewards, remote_data)) = read_story(&state, &session, params.user_story_id, &params.selections).await?
+  else {
+    return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+  };
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(StoryReward {
+    reward: rewards
+      .into_iter()
+      .map(|item| StoryRewardItem {
+        item_type: item.item_type,
+        item_id: item.item_id,
+        num: item.item_num,
+      })
+      .collect(),
+  }));
+  response.add_remote_data(remote_data);
+
+  Ok(Signed(response, session))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StoryReadRequest {
+  pub user_story_id: i32,
+  pub selections: String,
 }
 
 // user_story_id=32
 // selections=[]
-// is_skip=1
-pub async fn story_read(request: ApiRequest, session: Arc<Session>) -> impl IntoHandlerResponse {
-  let user_story_id: i32 = request.body["user_story_id"].parse().unwrap();
-  let selections = &request.body["selections"];
-  // Probably always set to 1 when StoryStatus::Done
-  let is_skip: i32 = request.body["is_skip"].parse().unwrap();
+// is_skip=1 (probably always set to 1 when [StoryStatus::Done])
+pub async fn story_read(
+  state: Arc<AppState>,
+  session: Arc<Session>,
+  Params(params): Params<StoryReadRequest>,
+) -> impl IntoHandlerResponse {
+  let Some((_, remote_data)) = read_story(&state, &session, params.user_story_id, &params.selections).await? else {
+    return Ok(Signed(CallResponse::new_error(STATUS_ERROR), session));
+  };
+
+  let mut response: CallResponse<dyn CallCustom> = CallResponse::new_success(Box::new(()));
+  response.add_remote_data(remote_data);
 
-  Signed((), session)
+  Ok(Signed(response, session))
 }
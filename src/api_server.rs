@@ -143,6 +143,8 @@ async fn api_call(
     .handle("honor_set", profile::honor_set)
     .handle("seticon", profile::set_icon)
     .handle("interaction", interaction::interaction)
+    .handle("character_voice_set", interaction::character_voice_set)
+    .handle("character_bg_set", interaction::character_bg_set)
     .handle("partyinfo", party_info::party_info)
     .handle("storylist", story::story_list)
     .handle("battleskip", quest::quest_main::battle_skip)
@@ -180,6 +182,8 @@ async fn api_call(
     .handle("dungeon_benefit_re_lottery", dungeon::dungeon_benefit_re_lottery)
     .handle("weaponlist", items::weapon_list)
     .handle("accessorylist", items::accessory_list)
+    .handle("itemlock", items::item_lock)
+    .handle("boxexpansion", items::box_expansion)
     .handle("battlestart", battle::battle_start)
     .handle("battleretire", battle::battle_retire)
     .handle("battlewaveresult", battle::battle_wave_result)
@@ -195,11 +199,18 @@ async fn api_call(
     .handle("greeting_send", friend::greeting_send)
     .handle("assist_make_notice", assist::assist_make_notice)
     .handle("assist_make_list", assist::assist_make_list)
+    .handle("assist_make", assist::assist_make)
+    .handle("assist_level_up", assist::assist_level_up)
     .handle("exchangelist", exchange::exchange_list)
     .handle("leavemenbers", exchange::leave_members)
     .handle("exchange", exchange::exchange)
     .handle("character_piece_board_info", character::character_piece_board_info)
+    .handle("character_piece_board_release", character::character_piece_board_release)
     .handle("character_enhance_info", character::character_enhance_info)
+    .handle("character_enhance_battle_start", character::character_enhance_battle_start)
+    .handle("character_enhance_battle_result", character::character_enhance_battle_result)
+    .handle("character_present", character::character_present)
+    .handle("character_sp_skill_level_up", character::character_sp_skill_level_up)
     .handle("idconfirm", transfer::id_confirm)
     .handle("prepare_set_migration", transfer::prepare_set_migration)
     .handle("newidcheck", transfer::new_id_check)
@@ -216,6 +227,7 @@ async fn api_call(
     .handle("marathon_quest_start", mission::marathon_quest_start)
     .handle("marathon_quest_result", mission::marathon_quest_result)
     .handle("marathon_boss_list", mission::marathon_boss_list)
+    .handle("battlemarathonbosslist", mission::battle_marathon_boss_list)
     .handle("panel_mission_list", panel_mission::panel_mission_list)
     .handle("panel_mission", panel_mission::panel_mission)
     .handle("sale_list", smith_sell::sale_list)
@@ -241,9 +253,11 @@ async fn api_call(
     .handle("expeditioncharacter", expedition::expedition_character)
     .handle("expeditionset", expedition::expedition_set)
     .handle("advertisement_reward_status", ad_reward::advertisement_reward_status)
+    .handle("advertisement_reward", ad_reward::advertisement_reward)
     .handle("shopitemlist", ad_reward::shop_item_list)
     .handle("purchase_google_limited_products_status", shop::purchase_google_limited_products_status)
     .handle("purchase_google_charge_status", shop::purchase_google_charge_status)
+    .handle("purchase_google", shop::purchase_google)
     .handle("buy", ad_reward::buy)
     .handle("surprise_mini_event_select", surprise::surprise_mini_event_select)
     .handle("surprise_mini_event_top", surprise::surprise_mini_event_top)
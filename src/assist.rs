@@ -0,0 +1,442 @@
+//! Assists (support cards) from `assist` and `assist_details` masters.
+//!
+//! A user owns at most one copy of each assist. Its unique ID is the `assist_details` ID of its first level,
+//! that is what the client sends when assigning assists to a party, while the item ID is the `assist_details`
+//! ID of the current level. Assists are pulled from assist gachas for `AssistTicket`, duplicates level up
+//! the pulled assist and turn into [ASSIST_MEDAL_ID] medals once it is at max level. Assists with a
+//! cost in `assist` are crafted instead. Levels are bought with the materials of `assist_details`, levels
+//! without any are bought with a single `AssistMaterial` instead.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::Value;
+use tokio_postgres::Statement;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::RemoteDataItemType;
+use crate::blob::AddAssist;
+use crate::database::QueryExecutor;
+use crate::item::{CountedItem, ItemCost, ItemReference};
+use crate::member::MemberStats;
+use crate::user::id::UserId;
+
+/// Exchange medal granted for pulling a duplicate of a max level assist.
+pub const ASSIST_MEDAL_ID: i64 = 500811;
+/// `AssistMaterial` spent on levels of assists of rarity below 4, see [level_up_cost].
+pub const ASSIST_MATERIAL_ID: i64 = 3;
+/// `AssistMaterial` spent on levels of assists of rarity 4, see [level_up_cost].
+pub const ASSIST_MATERIAL_RARE_ID: i64 = 4;
+
+/// Parses `item_type{N}`/`material{N}`/`num{N}` and `money` columns.
+fn parse_cost(data: &Value) -> ItemCost {
+  ItemCost {
+    items: (1..=5)
+      .filter_map(|index| {
+        let item_type = parse_i64(&data[format!("item_type{}", index)]) as i32;
+        let quantity = parse_i64(&data[format!("num{}", index)]) as i32;
+        (item_type != 0 && quantity > 0).then(|| CountedItem {
+          item: ItemReference {
+            item_type: RemoteDataItemType::from(item_type),
+            item_id: parse_i64(&data[format!("material{}", index)]),
+          },
+          quantity,
+        })
+      })
+      .collect(),
+    money: parse_i64(&data["money"]) as i32,
+    quartz: 0,
+  }
+}
+
+fn is_free(cost: &ItemCost) -> bool {
+  cost.items.is_empty() && cost.money == 0
+}
+
+#[derive(Debug, Clone)]
+pub struct AssistPrototype {
+  pub assist_id: i64,
+  pub rarity: i32,
+  pub character_id: i64,
+  pub display_start: Option<NaiveDateTime>,
+  pub display_end: Option<NaiveDateTime>,
+  /// Crafting cost, free for gacha assists
+  pub make_cost: ItemCost,
+}
+
+impl AssistPrototype {
+  /// Assists with a crafting cost are crafted, all others are pulled from assist gachas.
+  pub fn is_craftable(&self) -> bool {
+    !is_free(&self.make_cost)
+  }
+
+  pub fn is_displayed(&self, now: NaiveDateTime) -> bool {
+    self.display_start.is_none_or(|start| now >= start) && self.display_end.is_none_or(|end| now < end)
+  }
+
+  /// Unique ID of the assist, the `assist_details` ID of its first level.
+  pub fn unique_id(&self) -> i64 {
+    self.assist_id * 100 + 1
+  }
+}
+
+/// Returns all assists, parsed once.
+pub fn get_assists() -> &'static [AssistPrototype] {
+  static ASSISTS: OnceLock<Vec<AssistPrototype>> = OnceLock::new();
+
+  ASSISTS.get_or_init(|| {
+    get_master_manager()
+      .get_master("assist")
+      .iter()
+      .map(|assist| AssistPrototype {
+        assist_id: parse_i64(&assist["id"]),
+        rarity: parse_i64(&assist["rare"]) as i32,
+        character_id: parse_i64(&assist["character_id"]),
+        display_start: parse_date(assist["display_start"].as_str().unwrap()),
+        display_end: parse_date(assist["display_end"].as_str().unwrap()),
+        make_cost: parse_cost(assist),
+      })
+      .collect()
+  })
+}
+
+pub fn get_assist(assist_id: i64) -> Option<&'static AssistPrototype> {
+  get_assists().iter().find(|assist| assist.assist_id == assist_id)
+}
+
+pub fn get_assist_by_unique_id(unique_id: i64) -> Option<&'static AssistPrototype> {
+  get_assists().iter().find(|assist| assist.unique_id() == unique_id)
+}
+
+/// Assists that can be pulled from assist gachas at [now].
+pub fn get_assist_gacha_pool(now: NaiveDateTime) -> Vec<&'static AssistPrototype> {
+  get_assists()
+    .iter()
+    .filter(|assist| !assist.is_craftable() && assist.is_displayed(now))
+    .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct AssistLevelPrototype {
+  /// `assist_details` ID, sent as item ID
+  pub detail_id: i64,
+  pub assist_id: i64,
+  pub level: i32,
+  pub stats: MemberStats,
+  /// Cost of reaching this level from the previous one
+  pub cost: ItemCost,
+}
+
+/// Returns levels by assist and level, parsed once.
+fn get_assist_levels() -> &'static HashMap<i64, BTreeMap<i32, AssistLevelPrototype>> {
+  static LEVELS: OnceLock<HashMap<i64, BTreeMap<i32, AssistLevelPrototype>>> = OnceLock::new();
+
+  LEVELS.get_or_init(|| {
+    let mut levels: HashMap<i64, BTreeMap<i32, AssistLevelPrototype>> = HashMap::new();
+    for data in get_master_manager().get_master("assist_details") {
+      let level = AssistLevelPrototype {
+        detail_id: parse_i64(&data["id"]),
+        assist_id: parse_i64(&data["assist_id"]),
+        level: parse_i64(&data["lv"]) as i32,
+        stats: MemberStats {
+          hp: parse_i64(&data["hp"]) as i32,
+          attack: parse_i64(&data["attack"]) as i32,
+          magicattack: parse_i64(&data["magicattack"]) as i32,
+          defense: parse_i64(&data["defense"]) as i32,
+          magicdefence: parse_i64(&data["magicdefence"]) as i32,
+          agility: parse_i64(&data["agility"]) as i32,
+          dexterity: parse_i64(&data["dexterity"]) as i32,
+          luck: parse_i64(&data["luck"]) as i32,
+        },
+        cost: parse_cost(data),
+      };
+      levels.entry(level.assist_id).or_default().insert(level.level, level);
+    }
+    levels
+  })
+}
+
+pub fn get_assist_level(assist_id: i64, level: i32) -> Option<&'static AssistLevelPrototype> {
+  get_assist_levels().get(&assist_id)?.get(&level)
+}
+
+pub fn get_max_assist_level(assist_id: i64) -> i32 {
+  get_assist_levels()
+    .get(&assist_id)
+    .and_then(|levels| levels.keys().last().copied())
+    .unwrap_or(1)
+}
+
+/// Cost of reaching [level] from the previous level, or [None] if there is no such level.
+pub fn level_up_cost(assist: &AssistPrototype, level: i32) -> Option<ItemCost> {
+  let cost = &get_assist_level(assist.assist_id, level)?.cost;
+  if !is_free(cost) {
+    return Some(cost.clone());
+  }
+
+  let material_id = if assist.rarity >= 4 {
+    ASSIST_MATERIAL_RARE_ID
+  } else {
+    ASSIST_MATERIAL_ID
+  };
+  Some(ItemCost::items(vec![CountedItem {
+    item: ItemReference {
+      item_type: RemoteDataItemType::AssistMaterial,
+      item_id: material_id,
+    },
+    quantity: 1,
+  }]))
+}
+
+/// Medals granted for a duplicate of a max level assist, see `assist_medal_rate` master.
+pub fn get_duplicate_medals(rarity: i32) -> i32 {
+  get_master_manager()
+    .get_master("assist_medal_rate")
+    .iter()
+    .find(|rate| parse_i64(&rate["rare"]) as i32 == rarity)
+    .map_or(0, |rate| parse_i64(&rate["medal_rate"]) as i32)
+}
+
+/// Ticket spent on each pull of an assist gacha, or [None] if the gacha is not an assist gacha.
+pub fn get_assist_gacha_ticket(gacha_id: i32) -> Option<i64> {
+  get_master_manager()
+    .get_master("gacha_item")
+    .iter()
+    .find(|item| parse_i64(&item["gacha_id"]) == gacha_id as i64 && item["policy"].as_str() == Some("ASSIST"))
+    .map(|item| parse_i64(&item["ticket_gacha"]))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UserAssist {
+  pub assist_id: i64,
+  pub level: i32,
+}
+
+impl UserAssist {
+  pub fn unique_id(&self) -> i64 {
+    self.assist_id * 100 + 1
+  }
+
+  pub fn detail_id(&self) -> i64 {
+    get_assist_level(self.assist_id, self.level).map_or(self.unique_id(), |level| level.detail_id)
+  }
+
+  pub fn stats(&self) -> MemberStats {
+    get_assist_level(self.assist_id, self.level).map_or_else(MemberStats::default, |level| level.stats)
+  }
+
+  pub fn to_remote(&self) -> AddAssist {
+    AddAssist {
+      detail_id: self.detail_id(),
+      unique_id: self.unique_id(),
+    }
+  }
+}
+
+pub struct FetchUserAssists<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserAssists<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select assist_id, level
+        from user_assists
+        where user_id = $1
+        order by assist_id
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<Vec<UserAssist>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| UserAssist {
+          assist_id: row.get("assist_id"),
+          level: row.get("level"),
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Adds an assist at level 1, returns [None] if it is already owned.
+pub struct AddUserAssist<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserAssist<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_assists (user_id, assist_id, level)
+        values ($1, $2, 1)
+        on conflict (user_id, assist_id) do nothing
+        returning assist_id, level
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, assist_id: i64) -> anyhow::Result<Option<UserAssist>> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &assist_id])
+      .await?;
+    Ok(row.map(|row| UserAssist {
+      assist_id: row.get("assist_id"),
+      level: row.get("level"),
+    }))
+  }
+}
+
+pub struct SetUserAssistLevel<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> SetUserAssistLevel<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_assists
+        set level = $3
+        where user_id = $1 and assist_id = $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, assist: &UserAssist) -> anyhow::Result<()> {
+    self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &assist.assist_id, &assist.level])
+      .await?;
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct AssistGachaLogEntry {
+  pub gacha_id: i64,
+  pub item: CountedItem,
+  pub pulled_at: DateTime<Utc>,
+}
+
+/// Returns the latest [limit] items pulled from assist gachas, newest first.
+pub struct FetchUserAssistGachaLog<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserAssistGachaLog<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select gacha_id, item_type, item_id, item_num, pulled_at
+        from user_assist_gacha_log
+        where user_id = $1
+        order by pulled_at desc, id desc
+        limit $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, limit: i64) -> anyhow::Result<Vec<AssistGachaLogEntry>> {
+    let rows = self
+      .executor
+      .client()
+      .query(&self.statement, &[&user_id, &limit])
+      .await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| AssistGachaLogEntry {
+          gacha_id: row.get("gacha_id"),
+          item: CountedItem {
+            item: ItemReference {
+              item_type: RemoteDataItemType::from(row.get::<_, i32>("item_type")),
+              item_id: row.get("item_id"),
+            },
+            quantity: row.get("item_num"),
+          },
+          pulled_at: row.get("pulled_at"),
+        })
+        .collect(),
+    )
+  }
+}
+
+pub struct AddUserAssistGachaLog<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserAssistGachaLog<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_assist_gacha_log (user_id, gacha_id, item_type, item_id, item_num)
+        values ($1, $2, $3, $4, $5)
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, gacha_id: i64, item: &CountedItem) -> anyhow::Result<()> {
+    let item_type: i32 = item.item.item_type.into();
+    self
+      .executor
+      .client()
+      .execute(
+        &self.statement,
+        &[&user_id, &gacha_id, &item_type, &item.item.item_id, &item.quantity],
+      )
+      .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_displayed() {
+    let assist = AssistPrototype {
+      assist_id: 11664100,
+      rarity: 4,
+      character_id: 166,
+      display_start: parse_date("2023/03/04 0:00:00"),
+      display_end: parse_date("2023/03/28 0:00:00"),
+      make_cost: ItemCost::default(),
+    };
+
+    assert_eq!(assist.unique_id(), 1166410001);
+    assert!(!assist.is_craftable());
+    assert!(!assist.is_displayed(parse_date("2023/03/01 0:00:00").unwrap()));
+    assert!(assist.is_displayed(parse_date("2023/03/10 0:00:00").unwrap()));
+    assert!(!assist.is_displayed(parse_date("2023/03/28 0:00:00").unwrap()));
+  }
+}
@@ -1,7 +1,12 @@
 use crate::api::master_all::get_master_manager;
 use crate::api::{CharacterParameter, MemberParameterWire, RemoteData, RemoteDataCommand, RemoteDataItemType, SpSkill};
+use crate::character_enhance::FetchUserEnhanceStages;
+use crate::database::QueryExecutor;
+use crate::equipment::get_equipment_level;
 use crate::level::get_intimacy_level_calculator;
-use crate::member::{FetchUserMemberSkillsIn, FetchUserMembers, MemberPrototype};
+use crate::member::{apply_character_bonuses, FetchUserMemberSkillsIn, FetchUserMembers, MemberPrototype};
+use crate::piece_board::FetchUserPieceBoards;
+use crate::user::id::UserId;
 use crate::user::session::Session;
 use crate::AppState;
 use anyhow::Context;
@@ -45,82 +50,17 @@ pub async fn get_login_remote_data(state: &AppState, session: &Session) -> Vec<R
     .run(session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
     .await
     .unwrap();
+  apply_character_bonuses(&client, session.user_id, &mut members.iter_mut().collect::<Vec<_>>())
+    .await
+    .unwrap();
 
-  let characters = {
-    #[rustfmt::skip]
-    let statement = client
-      .prepare(/* language=postgresql */ r#"
-        select
-          c.user_id, c.character_id, c.intimacy,
-          s.skill_id, s.level as skill_level
-        from user_characters c
-          left join user_character_special_skills s
-            on s.user_id = c.user_id and s.character_id = c.character_id
-        where c.user_id = $1
-      "#)
-      .await
-      .context("failed to prepare statement").unwrap();
-    let rows = client
-      .query(&statement, &[&session.user_id])
-      .await
-      .context("failed to execute query")
-      .unwrap();
-
-    let skill_to_group = {
-      let mut map: HashMap<i64, i32> = HashMap::new();
-      for skill in masters.get_master("skill_sp").iter() {
-        let skill_id = skill["skill_id"].as_str().unwrap().parse::<i64>().unwrap();
-        let skill_group_id = skill["skill_group_id"].as_str().unwrap().parse::<i32>().unwrap();
-        map.insert(skill_id, skill_group_id);
-      }
-      map
-    };
-
-    let mut map: HashMap<i64, CharacterParameter> = HashMap::new();
-    for row in rows.iter() {
-      let character_id: i64 = row.get("character_id");
-      let intimacy: i32 = row.get("intimacy");
-
-      let character = map.entry(character_id).or_insert_with(|| {
-        trace!("adding character_id={} intimacy={}", character_id, intimacy);
-        CharacterParameter {
-          id: character_id,
-          character_id,
-          rank: intimacy,
-          rank_progress: get_intimacy_level_calculator().get_level(intimacy),
-          sp_skill: vec![],
-          character_enhance_stage_id_list: vec![0, 0, 0, 0],
-          character_piece_board_stage_id_list: vec![100001001, 100002002, 100003003, 100004004],
-          is_trial: false,
-        }
-      });
-
-      let skill_id: Option<i64> = row.get("skill_id");
-      let skill_level: Option<i32> = row.get("skill_level");
-
-      if let (Some(skill_id), Some(level)) = (skill_id, skill_level) {
-        let group_id = *skill_to_group
-          .get(&skill_id)
-          .expect(&format!("missing group_id for skill_id={}", skill_id));
-        trace!(
-          "adding group_id={} skill_id={} level={} to character_id={}",
-          group_id, skill_id, level, character_id
-        );
-        character.sp_skill.push(SpSkill {
-          group_id,
-          id: skill_id,
-          lv: level,
-          is_trial: false,
-        });
-      }
-    }
-
-    map
-      .into_values()
-      .map(|character| AddCharacter::new(character.character_id as i32, character).into_remote_data())
-      .flatten()
-      .collect::<Vec<_>>()
-  };
+  let characters = fetch_character_parameters(&client, session.user_id)
+    .await
+    .unwrap()
+    .into_values()
+    .map(|character| AddCharacter::new(character.character_id as i32, character).into_remote_data())
+    .flatten()
+    .collect::<Vec<_>>();
 
   let costumes = costumes
     .iter()
@@ -217,19 +157,6 @@ pub async fn get_login_remote_data(state: &AppState, session: &Session) -> Vec<R
   };
 
   let weapons = {
-    let weapon_details = masters.get_master("equip_weapon_details");
-
-    // (item_id, level) -> item_id_details
-    let item_to_item_details: HashMap<(i64, i32), i64> = weapon_details
-      .iter()
-      .map(|data| {
-        let item_id: i64 = data["item_id"].as_str().unwrap().parse::<i64>().unwrap();
-        let level: i32 = data["lv"].as_str().unwrap().parse::<i32>().unwrap();
-        let item_id_details: i64 = data["item_id_details"].as_str().unwrap().parse::<i64>().unwrap();
-        ((item_id, level), item_id_details)
-      })
-      .collect::<HashMap<_, _>>();
-
     #[rustfmt::skip]
     let statement = client
       .prepare(/* language=postgresql */ r#"
@@ -258,17 +185,12 @@ pub async fn get_login_remote_data(state: &AppState, session: &Session) -> Vec<R
         let item_id: i64 = row.get(2);
         let level: i32 = row.get(3);
         let is_locked: bool = row.get(4);
-        let item_id_details = *item_to_item_details
-          .get(&(item_id, level))
-          .expect(&format!("missing item details for item_id={} level={}", item_id, level));
-
-        AddEquipment::new(
-          RemoteDataItemType::from(item_type as i32),
-          item_id_details,
-          id as i32,
-          is_locked,
-        )
-        .into_remote_data()
+        let item_type = RemoteDataItemType::from(item_type as i32);
+        let item_id_details = get_equipment_level(item_type, item_id, level)
+          .unwrap_or_else(|| panic!("missing item details for item_id={} level={}", item_id, level))
+          .item_id_details;
+
+        AddEquipment::new(item_type, item_id_details, id as i32, is_locked).into_remote_data()
       })
       .flatten()
       .collect::<Vec<_>>()
@@ -277,9 +199,7 @@ pub async fn get_login_remote_data(state: &AppState, session: &Session) -> Vec<R
   #[cfg_attr(rustfmt, rustfmt::skip)]
   vec![
     ClearUserParams.into_remote_data(),
-    AddSingletonItem::new(RemoteDataItemType::Money, 85720).into_remote_data(),
-    AddSingletonItem::new(RemoteDataItemType::RealMoney, 100000).into_remote_data(),
-    AddSingletonItem::new(RemoteDataItemType::RealMoneyFree, 1000000).into_remote_data(),
+    // Eris and quartz are in user_items, see [crate::migrations]
     // AddMember::new(MemberPrototype::load_from_id(1001100).create_member_wire(), "front").into_remote_data(),
     // AddMember::new(MemberParameterWire { id: 11, lv: 4, exp: 150, member_id: 1001100, ac_skill_id_a: 21503639, ac_skill_lv_a: 1, ac_skill_val_a: 110, ac_skill_id_b: 0, ac_skill_lv_b: 1, ac_skill_val_b: 0, ac_skill_id_c: 0, ac_skill_lv_c: 1, ac_skill_val_c: 130, hp: 277, magicattack: 31, defense: 24, magicdefence: 22, agility: 72, dexterity: 78, luck: 88, limit_break: 0, character_id: 100, passiveskill: 0, specialattack: 0, resist_state: 0, resist_attr: 0, attack: 32, waiting_room: 0, main_strength: 444, main_strength_for_fame_quest: 444, sub_strength: 106, sub_strength_for_fame_quest: 106, sub_strength_bonus: 141, sub_strength_bonus_for_fame_quest: 141, fame_hp_rank: 0, fame_attack_rank: 0, fame_defense_rank: 0, fame_magicattack_rank: 0, fame_magicdefence_rank: 0, skill_pa_fame_list: vec![] }, "front").into_remote_data(),
     // AddMember::new(MemberParameterWire { id: 8, lv: 1, exp: 0, member_id: 1002102, ac_skill_id_a: 0, ac_skill_lv_a: 1, ac_skill_val_a: 110, ac_skill_id_b: 0, ac_skill_lv_b: 1, ac_skill_val_b: 20, ac_skill_id_c: 0, ac_skill_lv_c: 1, ac_skill_val_c: 130, hp: 257, magicattack: 28, defense: 21, magicdefence: 20, agility: 73, dexterity: 79, luck: 87, limit_break: 0, character_id: 100, passiveskill: 0, specialattack: 0, resist_state: 0, resist_attr: 0, attack: 28, waiting_room: 0, main_strength: 409, main_strength_for_fame_quest: 409, sub_strength: 95, sub_strength_for_fame_quest: 95, sub_strength_bonus: 127, sub_strength_bonus_for_fame_quest: 127, fame_hp_rank: 0, fame_attack_rank: 0, fame_defense_rank: 0, fame_magicattack_rank: 0, fame_magicdefence_rank: 0, skill_pa_fame_list: vec![] }, "front").into_remote_data(),
@@ -302,8 +222,6 @@ pub async fn get_login_remote_data(state: &AppState, session: &Session) -> Vec<R
     // AddMember::new(MemberParameterWire { id: 9, lv: 1, exp: 0, member_id: 1282100, ac_skill_id_a: 0, ac_skill_lv_a: 1, ac_skill_val_a: 93, ac_skill_id_b: 0, ac_skill_lv_b: 1, ac_skill_val_b: 128, ac_skill_id_c: 0, ac_skill_lv_c: 1, ac_skill_val_c: 122, hp: 239, magicattack: 32, defense: 24, magicdefence: 24, agility: 71, dexterity: 74, luck: 72, limit_break: 0, character_id: 128, passiveskill: 0, specialattack: 0, resist_state: 0, resist_attr: 0, attack: 25, waiting_room: 0, main_strength: 416, main_strength_for_fame_quest: 416, sub_strength: 97, sub_strength_for_fame_quest: 97, sub_strength_bonus: 130, sub_strength_bonus_for_fame_quest: 130, fame_hp_rank: 0, fame_attack_rank: 0, fame_defense_rank: 0, fame_magicattack_rank: 0, fame_magicdefence_rank: 0, skill_pa_fame_list: vec![] }, "front").into_remote_data(),
     // AddMember::new(MemberParameterWire { id: 1, lv: 0, exp: 0, member_id: 1192102, ac_skill_id_a: 0, ac_skill_lv_a: 0, ac_skill_val_a: 0, ac_skill_id_b: 0, ac_skill_lv_b: 0, ac_skill_val_b: 0, ac_skill_id_c: 0, ac_skill_lv_c: 0, ac_skill_val_c: 0, hp: 0, magicattack: 0, defense: 0, magicdefence: 0, agility: 0, dexterity: 0, luck: 0, limit_break: 0, character_id: 0, passiveskill: 0, specialattack: 0, resist_state: 0, resist_attr: 0, attack: 0, waiting_room: 0, main_strength: 0, main_strength_for_fame_quest: 0, sub_strength: 0, sub_strength_for_fame_quest: 0, sub_strength_bonus: 0, sub_strength_bonus_for_fame_quest: 0, fame_hp_rank: 0, fame_attack_rank: 0, fame_defense_rank: 0, fame_magicattack_rank: 0, fame_magicdefence_rank: 0, skill_pa_fame_list: vec![] }, "back").into_remote_data(),
     // AddMember::new(MemberParameterWire { id: 111, lv: 1, exp: 0, member_id: 1282100, ac_skill_id_a: 0, ac_skill_lv_a: 1, ac_skill_val_a: 93, ac_skill_id_b: 0, ac_skill_lv_b: 1, ac_skill_val_b: 128, ac_skill_id_c: 0, ac_skill_lv_c: 1, ac_skill_val_c: 122, hp: 239, magicattack: 32, defense: 24, magicdefence: 24, agility: 71, dexterity: 74, luck: 72, limit_break: 0, character_id: 128, passiveskill: 0, specialattack: 0, resist_state: 0, resist_attr: 0, attack: 25, waiting_room: 0, main_strength: 416, main_strength_for_fame_quest: 416, sub_strength: 97, sub_strength_for_fame_quest: 97, sub_strength_bonus: 130, sub_strength_bonus_for_fame_quest: 130, fame_hp_rank: 0, fame_attack_rank: 0, fame_defense_rank: 0, fame_magicattack_rank: 0, fame_magicdefence_rank: 0, skill_pa_fame_list: vec![] }, "front").into_remote_data(),
-    AddItem::new(RemoteDataItemType::SkipTicket, 0, 1, 800).into_remote_data(),
-    AddSingletonItem::new(RemoteDataItemType::Stamina, 419).into_remote_data(),
     AddSingletonItem::new(RemoteDataItemType::Exp, 10).into_remote_data(),
     // AddCharacter::new(8, CharacterParameter { id: 5335218194, character_id: 100, rank: 1, rank_progress: 4, sp_skill: vec![SpSkill { group_id: 10000, id: 100001, lv: 1, is_trial: false }], character_enhance_stage_id_list: vec![0, 0, 0, 0], character_piece_board_stage_id_list: vec![], is_trial: false }).into_remote_data(),
     // AddCharacter::new(10, CharacterParameter { id: 5335220194, character_id: 101, rank: 1, rank_progress: 4, sp_skill: vec![SpSkill { group_id: 10100, id: 101001, lv: 1, is_trial: false }, SpSkill { group_id: 10102, id: 101021, lv: 1, is_trial: false }], character_enhance_stage_id_list: vec![0, 0, 0, 0], character_piece_board_stage_id_list: vec![], is_trial: false }).into_remote_data(),
@@ -466,6 +384,87 @@ pub async fn get_login_remote_data(state: &AppState, session: &Session) -> Vec<R
     .collect::<Vec<_>>()
 }
 
+/// Builds character parameters of all characters of the user, by character ID.
+pub async fn fetch_character_parameters<'a>(
+  executor: impl Into<QueryExecutor<'a>> + Copy,
+  user_id: UserId,
+) -> anyhow::Result<HashMap<i64, CharacterParameter>> {
+  let masters = get_master_manager();
+  let client = executor.into();
+  #[rustfmt::skip]
+  let statement = client
+    .prepare(/* language=postgresql */ r#"
+      select
+        c.user_id, c.character_id, c.intimacy,
+        s.skill_id, s.level as skill_level
+      from user_characters c
+        left join user_character_special_skills s
+          on s.user_id = c.user_id and s.character_id = c.character_id
+      where c.user_id = $1
+    "#)
+    .await
+    .context("failed to prepare statement")?;
+  let rows = client
+    .client()
+    .query(&statement, &[&user_id])
+    .await
+    .context("failed to execute query")?;
+
+  let skill_to_group = {
+    let mut map: HashMap<i64, i32> = HashMap::new();
+    for skill in masters.get_master("skill_sp").iter() {
+      let skill_id = skill["skill_id"].as_str().unwrap().parse::<i64>().unwrap();
+      let skill_group_id = skill["skill_group_id"].as_str().unwrap().parse::<i32>().unwrap();
+      map.insert(skill_id, skill_group_id);
+    }
+    map
+  };
+
+  let piece_boards = FetchUserPieceBoards::new(executor).await?.run(user_id).await?;
+  let enhance_stages = FetchUserEnhanceStages::new(executor).await?.run(user_id).await?;
+
+  let mut map: HashMap<i64, CharacterParameter> = HashMap::new();
+  for row in rows.iter() {
+    let character_id: i64 = row.get("character_id");
+    let intimacy: i32 = row.get("intimacy");
+
+    let character = map.entry(character_id).or_insert_with(|| {
+      trace!("adding character_id={} intimacy={}", character_id, intimacy);
+      CharacterParameter {
+        id: character_id,
+        character_id,
+        rank: get_intimacy_level_calculator().get_level(intimacy),
+        rank_progress: intimacy,
+        sp_skill: vec![],
+        character_enhance_stage_id_list: enhance_stages.current_stage_ids(character_id).to_vec(),
+        character_piece_board_stage_id_list: piece_boards.current_stage_ids(character_id),
+        is_trial: false,
+      }
+    });
+
+    let skill_id: Option<i64> = row.get("skill_id");
+    let skill_level: Option<i32> = row.get("skill_level");
+
+    if let (Some(skill_id), Some(level)) = (skill_id, skill_level) {
+      let group_id = *skill_to_group
+        .get(&skill_id)
+        .expect(&format!("missing group_id for skill_id={}", skill_id));
+      trace!(
+        "adding group_id={} skill_id={} level={} to character_id={}",
+        group_id, skill_id, level, character_id
+      );
+      character.sp_skill.push(SpSkill {
+        group_id,
+        id: skill_id,
+        lv: level,
+        is_trial: false,
+      });
+    }
+  }
+
+  Ok(map)
+}
+
 pub trait IntoRemoteData {
   fn into_remote_data(self) -> Vec<RemoteData>;
 }
@@ -514,6 +513,30 @@ impl IntoRemoteData for AddEquipment {
   }
 }
 
+/// Adds an assist, or updates the level of an assist with the same unique ID.
+pub struct AddAssist {
+  pub detail_id: i64,
+  pub unique_id: i64,
+}
+
+impl IntoRemoteData for AddAssist {
+  fn into_remote_data(self) -> Vec<RemoteData> {
+    vec![RemoteData {
+      cmd: RemoteDataCommand::UserParamNew as i32,
+      uid: None,
+      item_type: RemoteDataItemType::Assist.into(),
+      item_id: self.detail_id,
+      item_num: 1,
+      uniqid: self.unique_id as i32,
+      lv: 0,
+      tag: String::from(""),
+      member_parameter: None,
+      character_parameter: None,
+      is_trial: None,
+    }]
+  }
+}
+
 pub struct AddMemberBackground {
   pub unique_id: i32,
   pub background_id: i64,
@@ -782,6 +805,40 @@ impl IntoRemoteData for DeleteMember {
   }
 }
 
+pub struct DeleteEquipment {
+  pub item_type: RemoteDataItemType,
+  pub item_id: i64,
+  pub unique_id: i32,
+}
+
+impl DeleteEquipment {
+  pub fn new(item_type: RemoteDataItemType, item_id: i64, unique_id: i32) -> Self {
+    Self {
+      item_type,
+      item_id,
+      unique_id,
+    }
+  }
+}
+
+impl IntoRemoteData for DeleteEquipment {
+  fn into_remote_data(self) -> Vec<RemoteData> {
+    vec![RemoteData {
+      cmd: RemoteDataCommand::UserParamDelete as i32,
+      uid: None,
+      item_type: self.item_type.into(),
+      item_id: self.item_id,
+      item_num: 1,
+      uniqid: self.unique_id,
+      lv: 0,
+      tag: String::from(""),
+      member_parameter: None,
+      character_parameter: None,
+      is_trial: None,
+    }]
+  }
+}
+
 /// Unlocks character stories, also needed for [AddMember].
 pub struct AddCharacter {
   pub unique_id: i32,
@@ -815,6 +872,35 @@ impl IntoRemoteData for AddCharacter {
   }
 }
 
+pub struct UpdateCharacter {
+  pub character_parameter: CharacterParameter,
+}
+
+impl UpdateCharacter {
+  pub fn new(character_parameter: CharacterParameter) -> Self {
+    Self { character_parameter }
+  }
+}
+
+impl IntoRemoteData for UpdateCharacter {
+  fn into_remote_data(self) -> Vec<RemoteData> {
+    vec![RemoteData {
+      cmd: RemoteDataCommand::UserParamUpdate as i32,
+      uid: None,
+      item_type: RemoteDataItemType::Character.into(),
+      item_id: self.character_parameter.character_id,
+      item_num: 1,
+      // Same as [AddCharacter] sent at login
+      uniqid: self.character_parameter.character_id as i32,
+      lv: 1,
+      tag: String::from(""),
+      member_parameter: None,
+      character_parameter: Some(self.character_parameter),
+      is_trial: None,
+    }]
+  }
+}
+
 pub struct ClearUserParams;
 
 impl IntoRemoteData for ClearUserParams {
@@ -45,7 +45,41 @@ pub struct CallResponse<T: CallCustom + ?Sized> {
 pub const STATUS_ERROR: i32 = -100;
 pub const STATUS_MAINTENANCE: i32 = -102;
 
+pub const STATUS_MONEY_NOT_ENOUGH: i32 = -109;
 pub const STATUS_QUARTZ_NOT_ENOUGH: i32 = -110;
+pub const STATUS_ITEM_NOT_ENOUGH: i32 = -111;
+
+// See [errortext] master
+pub const STATUS_UNKNOWN_STAGE: i32 = -118;
+pub const STATUS_UNKNOWN_BLACKSMITH: i32 = -121;
+pub const STATUS_UNKNOWN_LIMITBREAK: i32 = -122;
+pub const STATUS_ITEM_NOT_HAVE: i32 = -129;
+pub const STATUS_MAX_LEVEL: i32 = -131;
+pub const STATUS_UNKNOWN_EXCHANGE: i32 = -144;
+pub const STATUS_SHOP_NOT_FOR_SALE: i32 = -145;
+/// Locked or used in a party
+pub const STATUS_EQUIPMENT_UNAVAILABLE: i32 = -148;
+pub const STATUS_UNKNOWN_ITEMPOWERUP: i32 = -149;
+pub const STATUS_NOHAVE_WEAPON: i32 = -150;
+pub const STATUS_NOHAVE_ACCESSORY: i32 = -151;
+pub const STATUS_SHOP_BUY_LIMIT: i32 = -153;
+pub const STATUS_SHOP_DAILY_LIMIT: i32 = -154;
+pub const STATUS_SHOP_WEEKLY_LIMIT: i32 = -155;
+pub const STATUS_SHOP_MONTHLY_LIMIT: i32 = -156;
+pub const STATUS_SHOP_ONE_PURCHASE: i32 = -157;
+pub const STATUS_PURCHASE_MAINTENANCE: i32 = -158;
+pub const STATUS_SKIPTICKET_NOT_TICKET: i32 = -159;
+pub const STATUS_SKIPTICKET_NOT_STAMINA: i32 = -160;
+pub const STATUS_SKIPTICKET_NOT_ALLCLEAR: i32 = -170;
+pub const STATUS_USE_ITEM_NUM_ZERO: i32 = -175;
+pub const STATUS_MAX_HAVE_ITEM: i32 = -212;
+pub const STATUS_EVENT_NOT_OPEN: i32 = -1000;
+pub const STATUS_EVENT_NOT_BOSS_TICKET: i32 = -1002;
+pub const STATUS_EVENT_NOT_QUEST: i32 = -1010;
+pub const STATUS_AD_REWARD_LIMIT: i32 = -1019;
+pub const STATUS_AD_REWARD_RECASTING: i32 = -1020;
+pub const STATUS_AD_REWARD_NOT_AVAILABLE: i32 = -1021;
+pub const STATUS_EMERGENCY_BOSS_NOT_OPEN: i32 = -1027;
 
 pub const STATUS_LOGIN_TRANSFER_WRONG_KEY: i32 = -104;
 /// Logs the user out
@@ -55,6 +89,8 @@ pub const STATUS_LOGIN_TRANSFER_DONE: i32 = -1013;
 /// Cannot transfer account,
 pub const STATUS_LOGIN_TRANSFER_LOCAL_ACCOUNT_PRESENT: i32 = -178;
 
+pub const STATUS_PURCHASE_ERROR: i32 = -304;
+
 pub const STATUS_ACCOUNT_RESTRICTED: i32 = -903;
 
 pub const STATUS_NEW_DATA_AVAILABLE: i32 = -901;
@@ -92,6 +128,13 @@ impl<T: CallCustom + ?Sized> CallResponse<T> {
   }
 }
 
+impl CallResponse<dyn CallCustom> {
+  /// Client shows an error dialog with the text from `errortext` master corresponding to [status].
+  pub fn new_error(status: i32) -> Self {
+    Self::new_custom(status, Box::new(()))
+  }
+}
+
 impl CallResponse<()> {
   pub fn new_success_empty() -> Self {
     Self {
@@ -0,0 +1,405 @@
+//! Trial of the Ancients (character enhance) stages from `character_enhance*` masters.
+//!
+//! Each character has up to 4 roots of stages laid out by `character_enhance_stage_position`. A stage is
+//! released by winning its trial battle once the previous stage is released and the affinity rank is high
+//! enough, the materials and Eris of the stage are spent on clear. Released stages add flat stats to every
+//! member of the character and raise the level cap of special skills, unique weapons and stones of a stage are
+//! not granted.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use chrono::NaiveDateTime;
+use tokio_postgres::Statement;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::RemoteDataItemType;
+use crate::database::QueryExecutor;
+use crate::item::{CountedItem, ItemReference};
+use crate::member::MemberStats;
+use crate::user::id::UserId;
+
+/// Number of roots sent in `character_enhance_stage_id_list` and `character_enhance_released_count`.
+pub const ROOT_COUNT: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct EnhanceStagePrototype {
+  pub stage_id: i64,
+  pub character_id: i64,
+  /// Root (path) of the stage, starting from 1
+  pub root_id: i32,
+  /// Position of the stage in its root, starting from 1
+  pub root_stage_id: i32,
+  /// Stage that must be released first, zero if none
+  pub unlock_stage_id: i64,
+  pub unlock_at: Option<NaiveDateTime>,
+  pub unlock_until: Option<NaiveDateTime>,
+  /// Required affinity rank of the character
+  pub intimacy_rank: i32,
+  pub stats: MemberStats,
+  pub sp_id: i64,
+  pub sp_group_id: i64,
+  pub sp_lv: i32,
+  pub unique_weapon_id: i64,
+  pub unique_stone_id: i64,
+  pub materials: Vec<CountedItem>,
+  pub money: i32,
+}
+
+impl EnhanceStagePrototype {
+  /// Whether the trial of the stage can be fought after [released] stages, at affinity [intimacy_rank].
+  pub fn is_releasable(&self, released: &HashSet<i64>, intimacy_rank: i32, now: NaiveDateTime) -> bool {
+    !released.contains(&self.stage_id)
+      && (self.unlock_stage_id == 0 || released.contains(&self.unlock_stage_id))
+      && intimacy_rank >= self.intimacy_rank
+      && self.unlock_at.is_none_or(|unlock_at| now >= unlock_at)
+      && self.unlock_until.is_none_or(|unlock_until| now < unlock_until)
+  }
+}
+
+/// Returns all stages of all characters, parsed once.
+pub fn get_enhance_stages() -> &'static [EnhanceStagePrototype] {
+  static STAGES: OnceLock<Vec<EnhanceStagePrototype>> = OnceLock::new();
+
+  STAGES.get_or_init(|| {
+    get_master_manager()
+      .get_master("character_enhance")
+      .iter()
+      .map(|stage| EnhanceStagePrototype {
+        stage_id: parse_i64(&stage["stage_id"]),
+        character_id: parse_i64(&stage["character_id"]),
+        root_id: parse_i64(&stage["root_id"]) as i32,
+        root_stage_id: parse_i64(&stage["root_stage_id"]) as i32,
+        unlock_stage_id: parse_i64(&stage["unlock_clearstage"]),
+        unlock_at: parse_date(stage["unlock_date_start"].as_str().unwrap()),
+        unlock_until: parse_date(stage["unlock_date_end"].as_str().unwrap()),
+        intimacy_rank: parse_i64(&stage["intimacy_lv"]) as i32,
+        stats: MemberStats {
+          hp: parse_i64(&stage["hp"]) as i32,
+          attack: parse_i64(&stage["attack"]) as i32,
+          magicattack: parse_i64(&stage["magicattack"]) as i32,
+          defense: parse_i64(&stage["defense"]) as i32,
+          magicdefence: parse_i64(&stage["magicdefence"]) as i32,
+          agility: parse_i64(&stage["agility"]) as i32,
+          dexterity: parse_i64(&stage["dexterity"]) as i32,
+          luck: parse_i64(&stage["luck"]) as i32,
+        },
+        sp_id: parse_i64(&stage["sp_id"]),
+        sp_group_id: parse_i64(&stage["sp_group_id"]),
+        sp_lv: parse_i64(&stage["sp_lv"]) as i32,
+        unique_weapon_id: parse_i64(&stage["unique_weapon_id"]),
+        unique_stone_id: parse_i64(&stage["unique_stone_id"]),
+        materials: (1..=5)
+          .filter_map(|index| {
+            let item_type = parse_i64(&stage[format!("item_type{}", index)]) as i32;
+            let quantity = parse_i64(&stage[format!("item_num{}", index)]) as i32;
+            (item_type != 0 && quantity > 0).then(|| CountedItem {
+              item: ItemReference {
+                item_type: RemoteDataItemType::from(item_type),
+                item_id: parse_i64(&stage[format!("item_id{}", index)]),
+              },
+              quantity,
+            })
+          })
+          .collect(),
+        money: parse_i64(&stage["money"]) as i32,
+      })
+      .collect()
+  })
+}
+
+/// Returns a stage, or [None] if it is unknown or not laid out in the screen.
+pub fn get_enhance_stage(stage_id: i64) -> Option<&'static EnhanceStagePrototype> {
+  get_enhance_stages()
+    .iter()
+    .find(|stage| stage.stage_id == stage_id)
+    .filter(|stage| get_stage_position(stage.root_id, stage.root_stage_id).is_some())
+}
+
+/// Position of a stage in the Trial of the Ancients screen.
+#[derive(Debug, Clone)]
+pub struct EnhanceStagePosition {
+  /// Page of the screen, a stage may be shown on several pages
+  pub display_number: i32,
+  pub root_id: i32,
+  pub root_stage_id: i32,
+}
+
+/// Returns stage positions, parsed once, ordered by page.
+pub fn get_stage_positions() -> &'static [EnhanceStagePosition] {
+  static POSITIONS: OnceLock<Vec<EnhanceStagePosition>> = OnceLock::new();
+
+  POSITIONS.get_or_init(|| {
+    let mut positions = get_master_manager()
+      .get_master("character_enhance_stage_position")
+      .iter()
+      .map(|position| EnhanceStagePosition {
+        display_number: parse_i64(&position["display_number"]) as i32,
+        root_id: parse_i64(&position["root_id"]) as i32,
+        root_stage_id: parse_i64(&position["root_stage_id"]) as i32,
+      })
+      .collect::<Vec<_>>();
+    positions.sort_by_key(|position| position.display_number);
+    positions
+  })
+}
+
+/// Returns the first position of a stage, or [None] if the stage is not shown.
+pub fn get_stage_position(root_id: i32, root_stage_id: i32) -> Option<&'static EnhanceStagePosition> {
+  get_stage_positions()
+    .iter()
+    .find(|position| position.root_id == root_id && position.root_stage_id == root_stage_id)
+}
+
+/// Returns stages of a character in the order they are laid out.
+pub fn get_character_enhance_stages(character_id: i64) -> Vec<&'static EnhanceStagePrototype> {
+  let mut stages = get_enhance_stages()
+    .iter()
+    .filter(|stage| stage.character_id == character_id)
+    .filter_map(|stage| Some((get_stage_position(stage.root_id, stage.root_stage_id)?, stage)))
+    .collect::<Vec<_>>();
+  stages.sort_by_key(|(position, stage)| (position.display_number, stage.root_id, stage.root_stage_id));
+  stages.into_iter().map(|(_, stage)| stage).collect()
+}
+
+/// Returns the end of the current trial period, see `character_enhance_trial` master.
+pub fn get_current_trial_end(now: NaiveDateTime) -> Option<NaiveDateTime> {
+  get_master_manager()
+    .get_master("character_enhance_trial")
+    .iter()
+    .filter_map(|trial| {
+      let start_at = parse_date(trial["start_at"].as_str().unwrap())?;
+      let end_at = parse_date(trial["end_at"].as_str().unwrap())?;
+      (start_at <= now && now < end_at).then_some(end_at)
+    })
+    .next()
+}
+
+/// Stats added to every member of a character with [released] stages.
+pub fn get_stats_bonus(released: &HashSet<i64>) -> MemberStats {
+  get_enhance_stages()
+    .iter()
+    .filter(|stage| released.contains(&stage.stage_id))
+    .map(|stage| stage.stats)
+    .sum()
+}
+
+/// Released stages of all characters of a user.
+#[derive(Debug, Default)]
+pub struct UserEnhanceStages {
+  /// Released stage IDs by character
+  pub released: HashMap<i64, HashSet<i64>>,
+}
+
+impl UserEnhanceStages {
+  pub fn released(&self, character_id: i64) -> HashSet<i64> {
+    self.released.get(&character_id).cloned().unwrap_or_default()
+  }
+
+  /// Last released stage of each root, zero if none. Sent in `character_enhance_stage_id_list`.
+  pub fn current_stage_ids(&self, character_id: i64) -> [i32; ROOT_COUNT] {
+    let released = self.released(character_id);
+    let mut current = [(0, 0); ROOT_COUNT];
+    for stage in get_enhance_stages()
+      .iter()
+      .filter(|stage| released.contains(&stage.stage_id))
+    {
+      let Some(current) = current.get_mut(stage.root_id as usize - 1) else {
+        continue;
+      };
+      if stage.root_stage_id > current.0 {
+        *current = (stage.root_stage_id, stage.stage_id as i32);
+      }
+    }
+    current.map(|(_, stage_id)| stage_id)
+  }
+
+  /// Number of released stages of each root.
+  pub fn released_count(&self, character_id: i64) -> [i32; ROOT_COUNT] {
+    let released = self.released(character_id);
+    let mut count = [0; ROOT_COUNT];
+    for stage in get_enhance_stages()
+      .iter()
+      .filter(|stage| released.contains(&stage.stage_id))
+    {
+      if let Some(count) = count.get_mut(stage.root_id as usize - 1) {
+        *count += 1;
+      }
+    }
+    count
+  }
+
+  /// Highest special skill level of [sp_group_id] granted by released stages, zero if none.
+  pub fn sp_level(&self, character_id: i64, sp_group_id: i64) -> i32 {
+    let released = self.released(character_id);
+    get_enhance_stages()
+      .iter()
+      .filter(|stage| released.contains(&stage.stage_id) && stage.sp_group_id == sp_group_id)
+      .map(|stage| stage.sp_lv)
+      .max()
+      .unwrap_or(0)
+  }
+}
+
+pub struct FetchUserEnhanceStages<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserEnhanceStages<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select character_id, stage_id
+        from user_character_enhance_stages
+        where user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<UserEnhanceStages> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+
+    let mut stages = UserEnhanceStages::default();
+    for row in rows {
+      stages
+        .released
+        .entry(row.get("character_id"))
+        .or_default()
+        .insert(row.get("stage_id"));
+    }
+    Ok(stages)
+  }
+}
+
+/// Marks a stage as released, returns whether it was not released before.
+pub struct ReleaseUserEnhanceStage<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ReleaseUserEnhanceStage<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_character_enhance_stages (user_id, character_id, root_id, stage_id)
+        values ($1, $2, $3, $4)
+        on conflict (user_id, stage_id) do nothing
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, stage: &EnhanceStagePrototype) -> anyhow::Result<bool> {
+    let rows_affected = self
+      .executor
+      .client()
+      .execute(
+        &self.statement,
+        &[&user_id, &stage.character_id, &stage.root_id, &stage.stage_id],
+      )
+      .await?;
+    Ok(rows_affected != 0)
+  }
+}
+
+/// Remembers the stage of a started trial, replacing any trial that was not finished.
+pub struct StartUserEnhanceBattle<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> StartUserEnhanceBattle<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_character_enhance_battles (user_id, stage_id)
+        values ($1, $2)
+        on conflict (user_id)
+          do update
+          set stage_id = excluded.stage_id,
+              started_at = now()
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, stage_id: i64) -> anyhow::Result<()> {
+    self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &stage_id])
+      .await?;
+    Ok(())
+  }
+}
+
+/// Finishes the started trial, returns whether it was started for [stage_id].
+pub struct FinishUserEnhanceBattle<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FinishUserEnhanceBattle<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        delete from user_character_enhance_battles
+        where user_id = $1 and stage_id = $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, stage_id: i64) -> anyhow::Result<bool> {
+    let rows_affected = self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &stage_id])
+      .await?;
+    Ok(rows_affected != 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_releasable() {
+    let stage = EnhanceStagePrototype {
+      stage_id: 1000102,
+      character_id: 100,
+      root_id: 1,
+      root_stage_id: 2,
+      unlock_stage_id: 1000101,
+      unlock_at: None,
+      unlock_until: None,
+      intimacy_rank: 4,
+      stats: MemberStats::default(),
+      sp_id: 0,
+      sp_group_id: 0,
+      sp_lv: 0,
+      unique_weapon_id: 0,
+      unique_stone_id: 0,
+      materials: vec![],
+      money: 350000,
+    };
+    let now = NaiveDateTime::default();
+
+    assert!(!stage.is_releasable(&HashSet::new(), 4, now));
+    assert!(!stage.is_releasable(&HashSet::from([1000101]), 3, now));
+    assert!(stage.is_releasable(&HashSet::from([1000101]), 4, now));
+    assert!(!stage.is_releasable(&HashSet::from([1000101, 1000102]), 4, now));
+  }
+}
@@ -0,0 +1,192 @@
+//! Comeback rewards for players returning after a long absence, from `comeback_present` masters.
+//!
+//! Absence is measured from the most recent `user_devices.last_used` of all user's devices,
+//! each absence can be rewarded only once, see [ClaimComebackPresent].
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use tokio_postgres::Statement;
+use tracing::info;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::quest::QuestRewardItem;
+use crate::database::QueryExecutor;
+use crate::login_bonus::{get_login_bonuses, present_duration, LoginBonusKind, ResetUserLoginBonus};
+use crate::present::{NewPresent, SendUserPresent};
+use crate::user::id::UserId;
+
+/// Shown in the present box, there is no message in the master.
+const COMEBACK_PRESENT_MESSAGE: &str = "カムバックプレゼントで獲得したアイテムです";
+
+#[derive(Debug, Clone)]
+pub struct ComebackPresentPrototype {
+  pub comeback_present_id: i64,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+  /// Minimum absence to receive the present
+  pub comeback_days: i64,
+  pub items: Vec<QuestRewardItem>,
+}
+
+impl ComebackPresentPrototype {
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+
+  /// Whether a user last seen at [last_used] is considered returning at [now].
+  pub fn is_comeback(&self, last_used: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    now - last_used >= TimeDelta::days(self.comeback_days)
+  }
+}
+
+/// Returns all comeback presents, parsed once.
+pub fn get_comeback_presents() -> &'static [ComebackPresentPrototype] {
+  static COMEBACK_PRESENTS: OnceLock<Vec<ComebackPresentPrototype>> = OnceLock::new();
+
+  COMEBACK_PRESENTS.get_or_init(|| {
+    let mut items: HashMap<i64, Vec<QuestRewardItem>> = HashMap::new();
+    for item in get_master_manager().get_master("comeback_present_item") {
+      items
+        .entry(parse_i64(&item["comeback_present_id"]))
+        .or_default()
+        .push(QuestRewardItem {
+          item_type: parse_i64(&item["item_type"]) as i32,
+          item_id: parse_i64(&item["item_id"]),
+          item_num: parse_i64(&item["item_num"]) as i32,
+          item_rare: false,
+          probability: None,
+        });
+    }
+
+    get_master_manager()
+      .get_master("comeback_present")
+      .iter()
+      .map(|present| {
+        let comeback_present_id = parse_i64(&present["id"]);
+        ComebackPresentPrototype {
+          comeback_present_id,
+          start_at: parse_date(present["start_at"].as_str().unwrap()),
+          end_at: parse_date(present["end_at"].as_str().unwrap()),
+          comeback_days: parse_i64(&present["comeback_days"]),
+          items: items.remove(&comeback_present_id).unwrap_or_default(),
+        }
+      })
+      .collect()
+  })
+}
+
+/// Sends comeback presents to a user that was last seen at [last_used], and restarts comeback
+/// login bonus calendars. Returns received comeback present IDs, empty if the user was not absent
+/// long enough or the absence was already rewarded.
+pub async fn claim_comeback(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  user_id: UserId,
+  last_used: Option<DateTime<Utc>>,
+  now: DateTime<Utc>,
+) -> anyhow::Result<Vec<i64>> {
+  let Some(last_used) = last_used else {
+    return Ok(Vec::new());
+  };
+
+  let claim = ClaimComebackPresent::new(transaction).await?;
+  let send_present = SendUserPresent::new(transaction).await?;
+  let mut received = Vec::new();
+  for prototype in get_comeback_presents()
+    .iter()
+    .filter(|prototype| prototype.is_open(now.naive_utc()) && prototype.is_comeback(last_used, now))
+  {
+    if !claim.run(user_id, prototype.comeback_present_id, last_used).await? {
+      continue;
+    }
+
+    for item in &prototype.items {
+      send_present
+        .run(
+          user_id,
+          &NewPresent {
+            item_type: item.item_type,
+            item_id: item.item_id,
+            item_num: item.item_num,
+            message: COMEBACK_PRESENT_MESSAGE.to_owned(),
+            expires_at: Some(now + present_duration()),
+          },
+        )
+        .await?;
+    }
+    info!(?user_id, ?prototype.comeback_present_id, ?last_used, "received comeback present");
+    received.push(prototype.comeback_present_id);
+  }
+
+  if !received.is_empty() {
+    let reset = ResetUserLoginBonus::new(transaction).await?;
+    for bonus in get_login_bonuses()
+      .iter()
+      .filter(|bonus| bonus.kind == LoginBonusKind::Comeback && bonus.is_open(now.naive_utc()))
+    {
+      reset.run(user_id, bonus.loginbonus_id, last_used).await?;
+    }
+  }
+
+  Ok(received)
+}
+
+/// Records that the absence starting at `absent_since` was rewarded,
+/// returns `false` if it already was (e.g. by a concurrent login from another device).
+pub struct ClaimComebackPresent<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ClaimComebackPresent<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_comebacks (user_id, comeback_present_id, absent_since)
+        values ($1, $2, $3)
+        on conflict (user_id, comeback_present_id, absent_since) do nothing
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    comeback_present_id: i64,
+    absent_since: DateTime<Utc>,
+  ) -> anyhow::Result<bool> {
+    let inserted = self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &comeback_present_id, &absent_since])
+      .await?;
+    Ok(inserted > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  #[test]
+  fn test_is_comeback() {
+    let prototype = ComebackPresentPrototype {
+      comeback_present_id: 1,
+      start_at: None,
+      end_at: None,
+      comeback_days: 14,
+      items: Vec::new(),
+    };
+    let last_used = Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap();
+
+    assert!(!prototype.is_comeback(last_used, Utc.with_ymd_and_hms(2026, 1, 15, 11, 59, 59).unwrap()));
+    assert!(prototype.is_comeback(last_used, Utc.with_ymd_and_hms(2026, 1, 15, 12, 0, 0).unwrap()));
+  }
+}
@@ -0,0 +1,591 @@
+//! Weapons and accessories, from `equip_weapon`/`equip_accessory` and their `_details` masters.
+//!
+//! Recipe masters list materials and Eris needed to craft an item, `_details` masters list stats
+//! and the materials and Eris needed to reach each level.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use tokio_postgres::{Row, Statement};
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::{RemoteData, RemoteDataItemType};
+use crate::blob::{AddEquipment, DeleteEquipment, IntoRemoteData};
+use crate::database::QueryExecutor;
+use crate::item::{CountedItem, ItemReference, UpdateItemCountBy};
+use crate::member::MemberStats;
+use crate::user::id::UserId;
+
+/// Parses `item_type1`/`material1`/`num1` ... `item_type4`/`material4`/`num4` columns.
+fn parse_materials(data: &Value) -> Vec<CountedItem> {
+  (1..=4)
+    .filter_map(|index| {
+      let item_type = parse_i64(&data[format!("item_type{}", index)]) as i32;
+      let item_id = parse_i64(&data[format!("material{}", index)]);
+      let quantity = parse_i64(&data[format!("num{}", index)]) as i32;
+      (item_type != 0 && quantity > 0).then(|| CountedItem {
+        item: ItemReference {
+          item_type: RemoteDataItemType::from(item_type),
+          item_id,
+        },
+        quantity,
+      })
+    })
+    .collect()
+}
+
+pub fn is_equipment(item_type: RemoteDataItemType) -> bool {
+  matches!(item_type, RemoteDataItemType::Weapon | RemoteDataItemType::Accessory)
+}
+
+/// Crafting recipe of an equipment item.
+#[derive(Debug, Clone)]
+pub struct EquipmentRecipe {
+  pub item_type: RemoteDataItemType,
+  pub item_id: i64,
+  /// `None` for accessories
+  pub weapon_type: Option<String>,
+  /// Character that can equip the item, 0 if anyone can
+  pub character_id: i64,
+  pub enable: bool,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+  /// Items and equipment consumed by crafting one item
+  pub materials: Vec<CountedItem>,
+  pub money: i32,
+}
+
+impl EquipmentRecipe {
+  /// Weapons are limited to the weapon type of the character, see `character` master.
+  pub fn can_equip(&self, character_id: i64) -> bool {
+    (self.character_id == 0 || self.character_id == character_id)
+      && self
+        .weapon_type
+        .as_ref()
+        .is_none_or(|weapon_type| get_character_weapon_type(character_id) == Some(weapon_type.as_str()))
+  }
+
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.enable && self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+
+  /// Materials that are counted items, not equipment.
+  pub fn item_materials(&self) -> impl Iterator<Item = &CountedItem> {
+    self
+      .materials
+      .iter()
+      .filter(|material| !is_equipment(material.item.item_type))
+  }
+
+  /// Materials that are equipment, consumed by unique ID.
+  pub fn equipment_materials(&self) -> impl Iterator<Item = &CountedItem> {
+    self
+      .materials
+      .iter()
+      .filter(|material| is_equipment(material.item.item_type))
+  }
+}
+
+/// A single level of an equipment item.
+#[derive(Debug, Clone)]
+pub struct EquipmentLevel {
+  pub item_type: RemoteDataItemType,
+  pub item_id: i64,
+  pub level: i32,
+  /// Item ID sent to the client, differs for each level
+  pub item_id_details: i64,
+  pub sell: i32,
+  pub stats: MemberStats,
+  /// Items consumed to reach this level from the previous one
+  pub materials: Vec<CountedItem>,
+  pub money: i32,
+}
+
+/// Returns recipes of all weapons and accessories, parsed once.
+pub fn get_equipment_recipes() -> &'static HashMap<(RemoteDataItemType, i64), EquipmentRecipe> {
+  static RECIPES: OnceLock<HashMap<(RemoteDataItemType, i64), EquipmentRecipe>> = OnceLock::new();
+
+  RECIPES.get_or_init(|| {
+    [
+      (RemoteDataItemType::Weapon, "equip_weapon"),
+      (RemoteDataItemType::Accessory, "equip_accessory"),
+    ]
+    .into_iter()
+    .flat_map(|(item_type, master)| {
+      get_master_manager().get_master(master).iter().map(move |data| {
+        let recipe = EquipmentRecipe {
+          item_type,
+          item_id: parse_i64(&data["item_id"]),
+          weapon_type: data["weapon_type"].as_str().map(str::to_owned),
+          character_id: data["chara_id"].as_str().map_or(0, |id| id.parse().unwrap()),
+          enable: parse_i64(&data["enable"]) != 0,
+          start_at: parse_date(data["start_at"].as_str().unwrap()),
+          end_at: parse_date(data["end_at"].as_str().unwrap()),
+          materials: parse_materials(data),
+          money: parse_i64(&data["money"]) as i32,
+        };
+        ((item_type, recipe.item_id), recipe)
+      })
+    })
+    .collect()
+  })
+}
+
+pub fn get_equipment_recipe(item_type: RemoteDataItemType, item_id: i64) -> Option<&'static EquipmentRecipe> {
+  get_equipment_recipes().get(&(item_type, item_id))
+}
+
+/// Returns `weapon_type` of a character from `character` master.
+pub fn get_character_weapon_type(character_id: i64) -> Option<&'static str> {
+  static WEAPON_TYPES: OnceLock<HashMap<i64, String>> = OnceLock::new();
+
+  WEAPON_TYPES
+    .get_or_init(|| {
+      get_master_manager()
+        .get_master("character")
+        .iter()
+        .map(|character| {
+          (
+            parse_i64(&character["id"]),
+            character["weapon_type"].as_str().unwrap().to_owned(),
+          )
+        })
+        .collect()
+    })
+    .get(&character_id)
+    .map(String::as_str)
+}
+
+/// Returns levels of all weapons and accessories, ordered by level, parsed once.
+pub fn get_equipment_levels() -> &'static HashMap<(RemoteDataItemType, i64), Vec<EquipmentLevel>> {
+  static LEVELS: OnceLock<HashMap<(RemoteDataItemType, i64), Vec<EquipmentLevel>>> = OnceLock::new();
+
+  LEVELS.get_or_init(|| {
+    let mut levels: HashMap<(RemoteDataItemType, i64), Vec<EquipmentLevel>> = HashMap::new();
+    for (item_type, master) in [
+      (RemoteDataItemType::Weapon, "equip_weapon_details"),
+      (RemoteDataItemType::Accessory, "equip_accessory_details"),
+    ] {
+      for data in get_master_manager().get_master(master) {
+        let level = EquipmentLevel {
+          item_type,
+          item_id: parse_i64(&data["item_id"]),
+          level: parse_i64(&data["lv"]) as i32,
+          item_id_details: parse_i64(&data["item_id_details"]),
+          sell: parse_i64(&data["sell"]) as i32,
+          stats: MemberStats {
+            hp: parse_i64(&data["hp"]) as i32,
+            attack: parse_i64(&data["attack"]) as i32,
+            magicattack: parse_i64(&data["magicattack"]) as i32,
+            defense: parse_i64(&data["defense"]) as i32,
+            magicdefence: parse_i64(&data["magicdefence"]) as i32,
+            agility: parse_i64(&data["agility"]) as i32,
+            dexterity: parse_i64(&data["dexterity"]) as i32,
+            luck: parse_i64(&data["luck"]) as i32,
+          },
+          materials: parse_materials(data),
+          money: parse_i64(&data["money"]) as i32,
+        };
+        levels.entry((item_type, level.item_id)).or_default().push(level);
+      }
+    }
+
+    for levels in levels.values_mut() {
+      levels.sort_by_key(|level| level.level);
+    }
+    levels
+  })
+}
+
+pub fn get_equipment_level(item_type: RemoteDataItemType, item_id: i64, level: i32) -> Option<&'static EquipmentLevel> {
+  get_equipment_levels()
+    .get(&(item_type, item_id))?
+    .iter()
+    .find(|data| data.level == level)
+}
+
+pub fn get_max_equipment_level(item_type: RemoteDataItemType, item_id: i64) -> Option<i32> {
+  get_equipment_levels()
+    .get(&(item_type, item_id))?
+    .iter()
+    .map(|data| data.level)
+    .max()
+}
+
+/// Materials and Eris needed to upgrade an item from level [from] to level [to],
+/// `None` if [to] is above the max level.
+pub fn upgrade_cost(
+  item_type: RemoteDataItemType,
+  item_id: i64,
+  from: i32,
+  to: i32,
+) -> Option<(Vec<CountedItem>, i32)> {
+  if get_max_equipment_level(item_type, item_id).is_none_or(|max| to > max) {
+    return None;
+  }
+
+  let mut materials: BTreeMap<ItemReference, i32> = BTreeMap::new();
+  let mut money = 0;
+  for data in get_equipment_levels()[&(item_type, item_id)]
+    .iter()
+    .filter(|data| data.level > from && data.level <= to)
+  {
+    for material in &data.materials {
+      *materials.entry(material.item).or_default() += material.quantity;
+    }
+    money += data.money;
+  }
+
+  let materials = materials
+    .into_iter()
+    .map(|(item, quantity)| item.into_counted(quantity))
+    .collect();
+  Some((materials, money))
+}
+
+/// Materials spent to upgrade an item up to [level], returned when it is consumed.
+/// Eris and equipment are not returned.
+pub fn upgrade_refund(item_type: RemoteDataItemType, item_id: i64, level: i32) -> Vec<CountedItem> {
+  upgrade_cost(item_type, item_id, 0, level)
+    .map(|(materials, _)| materials)
+    .unwrap_or_default()
+    .into_iter()
+    .filter(|material| !is_equipment(material.item.item_type))
+    .collect()
+}
+
+/// Equipment consumed as materials by [consume_material_equipment].
+#[derive(Debug, Default)]
+pub struct ConsumedEquipment {
+  pub remote_data: Vec<RemoteData>,
+  /// Materials that were spent upgrading the consumed equipment, already granted
+  pub returned_items: Vec<CountedItem>,
+}
+
+/// Deletes equipment with unique IDs [ids], which must be exactly the [required] equipment,
+/// not locked and not used in a party. Returns `None` without changing anything otherwise.
+pub async fn consume_material_equipment(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  user_id: UserId,
+  required: &[CountedItem],
+  ids: &[i64],
+) -> anyhow::Result<Option<ConsumedEquipment>> {
+  let materials = FetchUserEquipmentIn::new(transaction).await?.run(user_id, ids).await?;
+  let equipped = FetchEquippedEquipmentIds::new(transaction).await?.run(user_id).await?;
+
+  let required_count: i32 = required.iter().map(|required| required.quantity).sum();
+  if materials.len() != ids.len() || materials.len() != required_count as usize {
+    return Ok(None);
+  }
+  for required in required {
+    let provided = materials
+      .iter()
+      .filter(|material| material.item_type == required.item.item_type && material.item_id == required.item.item_id)
+      .filter(|material| !material.is_locked && !equipped.contains(&material.id))
+      .count();
+    if provided != required.quantity as usize {
+      return Ok(None);
+    }
+  }
+
+  DeleteUserEquipmentIn::new(transaction).await?.run(user_id, ids).await?;
+  let update = UpdateItemCountBy::new(transaction).await?;
+  let mut consumed = ConsumedEquipment::default();
+  for material in &materials {
+    let level = material.level_data().context("missing item details")?;
+    consumed
+      .remote_data
+      .extend(DeleteEquipment::new(material.item_type, level.item_id_details, material.id as i32).into_remote_data());
+
+    for returned in upgrade_refund(material.item_type, material.item_id, material.level) {
+      let item = update
+        .run(user_id, returned.item, returned.quantity)
+        .await
+        .context("failed to return materials")?;
+      consumed.remote_data.extend(item.into_remote_data());
+      consumed.returned_items.push(returned);
+    }
+  }
+
+  Ok(Some(consumed))
+}
+
+/// Equipment item owned by a user, `user_items_equipment` table.
+#[derive(Debug, Clone)]
+pub struct UserEquipment {
+  pub id: i64,
+  pub item_type: RemoteDataItemType,
+  pub item_id: i64,
+  pub level: i32,
+  pub is_locked: bool,
+}
+
+impl UserEquipment {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      id: row.get("id"),
+      item_type: RemoteDataItemType::from(row.get::<_, i64>("item_type") as i32),
+      item_id: row.get("item_id"),
+      level: row.get("level"),
+      is_locked: row.get("is_locked"),
+    }
+  }
+
+  pub fn level_data(&self) -> Option<&'static EquipmentLevel> {
+    get_equipment_level(self.item_type, self.item_id, self.level)
+  }
+
+  pub fn to_remote(&self) -> AddEquipment {
+    let item_id_details = self
+      .level_data()
+      .unwrap_or_else(|| panic!("missing item details for item_id={} level={}", self.item_id, self.level))
+      .item_id_details;
+    AddEquipment::new(self.item_type, item_id_details, self.id as i32, self.is_locked)
+  }
+}
+
+pub struct AddUserEquipment<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserEquipment<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_items_equipment (user_id, item_type, item_id, level)
+        values ($1, $2, $3, $4)
+        returning id, item_type, item_id, level, is_locked
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    item_type: RemoteDataItemType,
+    item_id: i64,
+    level: i32,
+  ) -> anyhow::Result<UserEquipment> {
+    let item_type: i32 = item_type.into();
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&user_id, &(item_type as i64), &item_id, &level])
+      .await?;
+    Ok(UserEquipment::from_row(&row))
+  }
+}
+
+pub struct FetchUserEquipment<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserEquipment<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select id, item_type, item_id, level, is_locked
+        from user_items_equipment
+        where user_id = $1
+        order by id
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<Vec<UserEquipment>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(rows.iter().map(UserEquipment::from_row).collect())
+  }
+}
+
+/// Returns user's equipment with the given unique IDs, locking them.
+pub struct FetchUserEquipmentIn<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserEquipmentIn<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select id, item_type, item_id, level, is_locked
+        from user_items_equipment
+        where user_id = $1 and id = any($2)
+        for update
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, ids: &[i64]) -> anyhow::Result<Vec<UserEquipment>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id, &ids]).await?;
+    Ok(rows.iter().map(UserEquipment::from_row).collect())
+  }
+}
+
+pub struct UpdateUserEquipmentLevel<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> UpdateUserEquipmentLevel<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_items_equipment
+        set level = $3
+        where user_id = $1 and id = $2
+        returning id, item_type, item_id, level, is_locked
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, id: i64, level: i32) -> anyhow::Result<UserEquipment> {
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&user_id, &id, &level])
+      .await?;
+    Ok(UserEquipment::from_row(&row))
+  }
+}
+
+/// Returns `None` if the user has no such equipment.
+pub struct UpdateUserEquipmentLocked<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> UpdateUserEquipmentLocked<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_items_equipment
+        set is_locked = $4
+        where user_id = $1 and id = $2 and item_type = $3
+        returning id, item_type, item_id, level, is_locked
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    item_type: RemoteDataItemType,
+    id: i64,
+    is_locked: bool,
+  ) -> anyhow::Result<Option<UserEquipment>> {
+    let item_type: i32 = item_type.into();
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &id, &(item_type as i64), &is_locked])
+      .await?;
+    Ok(row.as_ref().map(UserEquipment::from_row))
+  }
+}
+
+pub struct DeleteUserEquipmentIn<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> DeleteUserEquipmentIn<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        delete from user_items_equipment
+        where user_id = $1 and id = any($2)
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, ids: &[i64]) -> anyhow::Result<u64> {
+    Ok(
+      self
+        .executor
+        .client()
+        .execute(&self.statement, &[&user_id, &ids])
+        .await?,
+    )
+  }
+}
+
+/// Returns unique IDs of equipment used in any of user's parties.
+pub struct FetchEquippedEquipmentIds<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchEquippedEquipmentIds<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select weapon_id as id from user_party_forms where user_id = $1 and weapon_id != 0
+        union
+        select accessory_id as id from user_party_forms where user_id = $1 and accessory_id != 0
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<Vec<i64>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(rows.iter().map(|row| row.get("id")).collect())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::item::IntoItemReference;
+
+  #[test]
+  fn test_parse_materials() {
+    let data = json!({
+      "item_type1": "15", "material1": "5001", "num1": "20",
+      "item_type2": "5", "material2": "12411", "num2": "1",
+      "item_type3": "0", "material3": "0", "num3": "0",
+      "item_type4": "0", "material4": "0", "num4": "0",
+    });
+
+    assert_eq!(
+      parse_materials(&data),
+      vec![
+        (RemoteDataItemType::MaterialWA, 5001)
+          .into_item_reference()
+          .into_counted(20),
+        (RemoteDataItemType::Weapon, 12411)
+          .into_item_reference()
+          .into_counted(1),
+      ]
+    );
+  }
+}
@@ -0,0 +1,961 @@
+//! Event marathons: event stages, single boss battles, emergency bosses and event points.
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::quest::drop::with_rng;
+use crate::api::quest::QuestRewardItem;
+use crate::api::RemoteDataItemType;
+use crate::database::QueryExecutor;
+use crate::item::ItemReference;
+use crate::settings::DropSettings;
+use crate::shop::get_pack_items;
+use crate::user::id::UserId;
+use crate::user::quest_progress::{FetchUserEventQuestProgress, QuestProgress};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use rand::seq::IndexedRandom;
+use rand::Rng;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::{Row, Statement};
+use tracing::{info, warn};
+
+/// Event information from `event_config` master.
+#[derive(Debug, Clone)]
+pub struct EventConfig {
+  pub event_id: i64,
+  pub boss_ticket_id: i64,
+  /// Maximum Boss Ticket multiplier of a single boss battle
+  pub boss_ticket_use_ratio: i32,
+  /// Score challenge ticket, zero if the event has no score challenge
+  pub special_boss_ticket_id: i64,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+  /// See `event_boss_count` master, zero if the event has no boss counter
+  pub boss_count_group_id: i64,
+  /// See `event_emergency_boss` master, zero if the event has no emergency bosses
+  pub event_emergency_group_id: i64,
+}
+
+impl EventConfig {
+  pub fn get(event_id: i64) -> Option<Self> {
+    get_master_manager()
+      .get_master("event_config")
+      .iter()
+      .find(|event| parse_i64(&event["event_id"]) == event_id)
+      .map(|event| Self {
+        event_id,
+        boss_ticket_id: parse_i64(&event["boss_ticket_id"]),
+        boss_ticket_use_ratio: parse_i64(&event["boss_ticket_use_ratio"]) as i32,
+        special_boss_ticket_id: parse_i64(&event["special_boss_ticket_id"]),
+        start_at: parse_date(event["start_at"].as_str().unwrap()),
+        end_at: parse_date(event["end_at"].as_str().unwrap()),
+        boss_count_group_id: parse_i64(&event["boss_count_group_id"]),
+        event_emergency_group_id: parse_i64(&event["event_emergency_group_id"]),
+      })
+  }
+
+  /// Whether stages and bosses of the event can be played at [now].
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+
+  pub fn has_score_challenge(&self) -> bool {
+    self.special_boss_ticket_id != 0
+  }
+
+  /// Ticket consumed to fight [boss]. Score challenge stages use the Event Ticket,
+  /// all other bosses use the Boss Ticket of the event.
+  pub fn ticket_for(&self, boss: &EventBossPrototype) -> ItemReference {
+    if boss.is_score_challenge {
+      ItemReference {
+        item_type: RemoteDataItemType::EventTicket,
+        item_id: self.special_boss_ticket_id,
+      }
+    } else {
+      ItemReference {
+        item_type: RemoteDataItemType::BossTicket,
+        item_id: self.boss_ticket_id,
+      }
+    }
+  }
+}
+
+/// Event boss stage from `event_marathon_quest_stage_boss_single`, `event_marathon_quest_stage_boss_multi`
+/// or `event_scorechallenge_stage` master.
+#[derive(Debug, Clone)]
+pub struct EventBossPrototype {
+  pub quest_id: i64,
+  pub event_id: i64,
+  /// Tickets consumed with multiplier of 1
+  pub ticket_cost: i32,
+  /// Event stages that must be cleared to unlock the boss
+  pub unlock_stages: Vec<i64>,
+  pub is_score_challenge: bool,
+  /// Regular boss this stage is an emergency version of, zero for regular bosses
+  pub emergency_source_stage_id: i64,
+  /// Boss wave, see [get_wave_enemy_hp]
+  pub wave_id: i64,
+}
+
+impl EventBossPrototype {
+  fn from_master(boss: &Value, is_score_challenge: bool) -> Self {
+    Self {
+      quest_id: parse_i64(&boss["id"]),
+      event_id: parse_i64(&boss["event_id"]),
+      ticket_cost: parse_i64(&boss["bossticket"]) as i32,
+      unlock_stages: [&boss["unlock_clearstage1"], &boss["unlock_clearstage2"]]
+        .into_iter()
+        .map(parse_i64)
+        .filter(|stage| *stage != 0)
+        .collect(),
+      is_score_challenge,
+      emergency_source_stage_id: boss.get("emergency_source_stage_id").map_or(0, parse_i64),
+      wave_id: parse_i64(&boss["wave_id1"]),
+    }
+  }
+
+  /// HP of each boss wave enemy, `None` for score challenges, which have no HP to deplete.
+  pub fn max_hp(&self) -> Option<Vec<i64>> {
+    if self.is_score_challenge {
+      return None;
+    }
+    get_wave_enemy_hp(self.wave_id)
+  }
+
+  /// Emergency stages can only be fought while an emergency boss is active, see [UserEmergencyBoss].
+  pub fn is_emergency(&self) -> bool {
+    self.emergency_source_stage_id != 0
+  }
+
+  /// Whether the boss can be fought, emergency stages also need an active emergency boss on them.
+  pub fn is_available(&self, progress: &HashMap<i64, QuestProgress>, emergency: Option<&UserEmergencyBoss>) -> bool {
+    self.is_unlocked(progress)
+      && (!self.is_emergency() || emergency.is_some_and(|boss| boss.is_active && boss.quest_id == self.quest_id))
+  }
+
+  pub fn is_unlocked(&self, progress: &HashMap<i64, QuestProgress>) -> bool {
+    self
+      .unlock_stages
+      .iter()
+      .all(|stage| progress.get(stage).is_some_and(|progress| progress.clear_count > 0))
+  }
+}
+
+pub fn get_event_bosses(event_id: i64, is_multi: bool) -> Vec<EventBossPrototype> {
+  let master = if is_multi {
+    "event_marathon_quest_stage_boss_multi"
+  } else {
+    "event_marathon_quest_stage_boss_single"
+  };
+  get_master_manager()
+    .get_master(master)
+    .iter()
+    .filter(|boss| parse_i64(&boss["event_id"]) == event_id)
+    .map(|boss| EventBossPrototype::from_master(boss, false))
+    .collect()
+}
+
+/// Returns a boss that can be fought with `marathon_single_start`.
+pub fn get_event_single_boss(event_id: i64, quest_id: i64) -> Option<EventBossPrototype> {
+  let single = get_master_manager()
+    .get_master("event_marathon_quest_stage_boss_single")
+    .iter()
+    .map(|boss| EventBossPrototype::from_master(boss, false));
+  let score_challenge = get_master_manager()
+    .get_master("event_scorechallenge_stage")
+    .iter()
+    .map(|boss| EventBossPrototype::from_master(boss, true));
+
+  single
+    .chain(score_challenge)
+    .find(|boss| boss.event_id == event_id && boss.quest_id == quest_id)
+}
+
+/// HP of enemies of a wave from `battle_wave` and `battle_enemy` masters, in wave order.
+/// Returns `None` if the wave or the masters are missing.
+pub fn get_wave_enemy_hp(wave_id: i64) -> Option<Vec<i64>> {
+  let wave = get_master_manager()
+    .try_get_master("battle_wave")?
+    .iter()
+    .find(|wave| parse_i64(&wave["wave_id"]) == wave_id)?;
+  let enemies = get_master_manager().try_get_master("battle_enemy")?;
+
+  Some(
+    (1..=5)
+      .filter_map(|index| wave.get(format!("enemy_id{index}").as_str()).map(parse_i64))
+      .filter(|enemy_id| *enemy_id != 0)
+      .filter_map(|enemy_id| enemies.iter().find(|enemy| parse_i64(&enemy["enemy_id"]) == enemy_id))
+      .map(|enemy| parse_i64(&enemy["hp"]))
+      .collect(),
+  )
+}
+
+/// Emergency boss from `event_emergency_boss` master.
+#[derive(Debug, Clone)]
+pub struct EmergencyBossPrototype {
+  pub id: i64,
+  /// Event points the user must have before the boss can appear
+  pub count_point: i64,
+  /// How long the boss stays after it appears, same as its scheduled window in the live game
+  pub duration: TimeDelta,
+  /// Reward for defeating the boss
+  pub pack_id: i64,
+  /// Reward for finding the boss, emergency bosses only appear to the user who fights them
+  pub add_pack_id: i64,
+}
+
+impl EmergencyBossPrototype {
+  /// Contents of both reward packs, packs with unknown contents are skipped.
+  pub fn rewards(&self) -> Vec<QuestRewardItem> {
+    [self.pack_id, self.add_pack_id]
+      .into_iter()
+      .filter(|pack_id| *pack_id != 0)
+      .flat_map(|pack_id| {
+        let items = get_pack_items(pack_id);
+        if items.is_empty() {
+          warn!(?pack_id, emergency_boss_id = ?self.id, "emergency boss reward pack contents are unknown");
+        }
+        items.iter().cloned()
+      })
+      .collect()
+  }
+}
+
+pub fn get_emergency_bosses(group_id: i64) -> Vec<EmergencyBossPrototype> {
+  if group_id == 0 {
+    return Vec::new();
+  }
+
+  get_master_manager()
+    .get_master("event_emergency_boss")
+    .iter()
+    .filter(|boss| parse_i64(&boss["group_id"]) == group_id)
+    .filter_map(|boss| {
+      let start_at = parse_date(boss["start_at"].as_str().unwrap())?;
+      let end_at = parse_date(boss["end_at"].as_str().unwrap())?;
+      Some(EmergencyBossPrototype {
+        id: parse_i64(&boss["id"]),
+        count_point: parse_i64(&boss["count_point"]),
+        duration: end_at - start_at,
+        pack_id: parse_i64(&boss["pack_id"]),
+        add_pack_id: parse_i64(&boss["add_pack_id"]),
+      })
+    })
+    .collect()
+}
+
+/// The live game opens emergency bosses in scheduled windows during the event.
+/// A clear spawns one with the same rate: the share of the event the windows of [bosses] cover.
+pub fn emergency_boss_chance(config: &EventConfig, bosses: &[EmergencyBossPrototype]) -> f64 {
+  let (Some(start_at), Some(end_at)) = (config.start_at, config.end_at) else {
+    return 0.0;
+  };
+  let event_duration = (end_at - start_at).num_seconds();
+  if event_duration <= 0 {
+    return 0.0;
+  }
+
+  let scheduled = bosses.iter().map(|boss| boss.duration.num_seconds()).sum::<i64>();
+  (scheduled as f64 / event_duration as f64).clamp(0.0, 1.0)
+}
+
+/// Rolls an emergency boss after an event stage clear, if the user has none active.
+/// Appeared boss is fought on one of the unlocked emergency stages of the event.
+pub async fn roll_emergency_boss(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  settings: &DropSettings,
+  user_id: UserId,
+  config: &EventConfig,
+) -> anyhow::Result<Option<UserEmergencyBoss>> {
+  let bosses = get_emergency_bosses(config.event_emergency_group_id);
+  let chance = emergency_boss_chance(config, &bosses);
+  if bosses.is_empty() || !with_rng(settings, |rng| rng.random_bool(chance)) {
+    return Ok(None);
+  }
+
+  let current = FetchUserEmergencyBoss::new(transaction)
+    .await?
+    .run(user_id, config.event_id)
+    .await?;
+  if current.is_some_and(|boss| boss.is_active) {
+    return Ok(None);
+  }
+
+  let points = FetchUserEventPoints::new(transaction)
+    .await?
+    .run(user_id, config.event_id)
+    .await?;
+  let progress = FetchUserEventQuestProgress::new(transaction)
+    .await?
+    .run(user_id, config.event_id)
+    .await?;
+  let stages = get_event_bosses(config.event_id, false)
+    .into_iter()
+    .filter(|stage| stage.is_emergency() && stage.is_unlocked(&progress))
+    .collect::<Vec<_>>();
+  let bosses = bosses
+    .into_iter()
+    .filter(|boss| boss.count_point <= points)
+    .collect::<Vec<_>>();
+
+  let (Some(boss), Some(stage)) = with_rng(settings, |rng| (bosses.choose(rng), stages.choose(rng))) else {
+    return Ok(None);
+  };
+
+  let spawned = SpawnUserEmergencyBoss::new(transaction)
+    .await?
+    .run(
+      user_id,
+      config.event_id,
+      boss.id,
+      stage.quest_id,
+      Utc::now() + boss.duration,
+    )
+    .await?;
+  info!(?user_id, ?spawned, "emergency boss appeared");
+  Ok(Some(spawned))
+}
+
+/// Possible drops of a boss from `event_quest_boss_stage_itemreward` master.
+/// The master has no amounts, a single item is dropped per Boss Ticket multiplier.
+pub fn get_event_boss_rewards(quest_id: i64) -> Vec<QuestRewardItem> {
+  get_master_manager()
+    .get_master("event_quest_boss_stage_itemreward")
+    .iter()
+    .filter(|reward| parse_i64(&reward["quest_stage_id"]) == quest_id)
+    .map(|reward| QuestRewardItem {
+      item_type: parse_i64(&reward["item_type"]) as i32,
+      item_id: parse_i64(&reward["item_id"]),
+      item_num: 1,
+      item_rare: reward["item_rare"].as_str().unwrap() == "1",
+      probability: None,
+    })
+    .collect()
+}
+
+/// Event stage from `event_marathon_quest_stage` master.
+#[derive(Debug, Clone)]
+pub struct EventStagePrototype {
+  pub quest_id: i64,
+  pub unlock_stages: Vec<i64>,
+}
+
+pub fn get_event_stages(event_id: i64) -> Vec<EventStagePrototype> {
+  get_master_manager()
+    .get_master("event_marathon_quest_stage")
+    .iter()
+    .filter(|stage| parse_i64(&stage["event_id"]) == event_id)
+    .map(|stage| EventStagePrototype {
+      quest_id: parse_i64(&stage["id"]),
+      unlock_stages: [&stage["unlock_clearstage1"], &stage["unlock_clearstage2"]]
+        .into_iter()
+        .map(parse_i64)
+        .filter(|stage| *stage != 0)
+        .collect(),
+    })
+    .collect()
+}
+
+/// Boss counter milestone from `event_boss_count` master, reached when all users
+/// together collect [count_point] event points.
+#[derive(Debug, Clone)]
+pub struct BossCountMilestone {
+  pub id: i64,
+  pub count_point: i64,
+  /// Reward for every user that collected points in the event
+  pub pack_id: i64,
+}
+
+pub fn get_boss_count_milestones(group_id: i64) -> Vec<BossCountMilestone> {
+  if group_id == 0 {
+    return Vec::new();
+  }
+
+  get_master_manager()
+    .get_master("event_boss_count")
+    .iter()
+    .filter(|milestone| parse_i64(&milestone["group_id"]) == group_id)
+    .map(|milestone| BossCountMilestone {
+      id: parse_i64(&milestone["id"]),
+      count_point: parse_i64(&milestone["count_point"]),
+      pack_id: parse_i64(&milestone["pack_id"]),
+    })
+    .collect()
+}
+
+/// Sums damage of all attack records in a battle log, negative damage is ignored.
+///
+/// The log is a flat comma-separated list, attack records are 13 fields long:
+/// `w1,attack,{member id},0,{skill id},0,0,{damage},0,0,0,0,0`.
+pub fn parse_log_damage(log: &[String]) -> i64 {
+  const ATTACK_RECORD_LENGTH: usize = 13;
+  const DAMAGE_OFFSET: usize = 7;
+
+  let is_wave = |field: &str| {
+    field
+      .strip_prefix('w')
+      .is_some_and(|wave| !wave.is_empty() && wave.chars().all(|c| c.is_ascii_digit()))
+  };
+
+  let mut damage = 0i64;
+  let mut index = 0;
+  while index + ATTACK_RECORD_LENGTH <= log.len() {
+    if is_wave(&log[index]) && log[index + 1] == "attack" {
+      let dealt = log[index + DAMAGE_OFFSET].parse::<i64>().unwrap_or(0).max(0);
+      damage = damage.saturating_add(dealt);
+      index += ATTACK_RECORD_LENGTH;
+    } else {
+      index += 1;
+    }
+  }
+  damage
+}
+
+/// Single boss battle progress of a user.
+#[derive(Debug, Clone, Default)]
+pub struct UserEventBoss {
+  /// Damage dealt since the boss was last defeated, lets the client continue the fight
+  pub damage: i64,
+  pub kill_count: i32,
+  pub ticket_ratio: i32,
+}
+
+impl UserEventBoss {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      damage: row.get("damage"),
+      kill_count: row.get("kill_count"),
+      ticket_ratio: row.get("ticket_ratio"),
+    }
+  }
+
+  /// HP left on each enemy of [max_hp], damage is dealt to the enemies in wave order.
+  pub fn remaining_hp(&self, max_hp: &[i64]) -> Vec<i64> {
+    let mut damage = self.damage;
+    max_hp
+      .iter()
+      .map(|hp| {
+        let dealt = damage.min(*hp);
+        damage -= dealt;
+        hp - dealt
+      })
+      .collect()
+  }
+}
+
+pub struct FetchUserEventPoints<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserEventPoints<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select points
+        from user_events
+        where user_id = $1 and event_id = $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64) -> anyhow::Result<i64> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &event_id])
+      .await?;
+    Ok(row.map(|row| row.get(0)).unwrap_or(0))
+  }
+}
+
+/// Returns the new point total.
+pub struct AddUserEventPoints<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserEventPoints<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_events (user_id, event_id, points)
+        values ($1, $2, $3)
+        on conflict (user_id, event_id)
+          do update
+          set points = user_events.points + excluded.points
+        returning points
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64, points: i64) -> anyhow::Result<i64> {
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&user_id, &event_id, &points])
+      .await?;
+    Ok(row.get(0))
+  }
+}
+
+/// Event progress of all users together, used for boss counters.
+#[derive(Debug, Clone, Default)]
+pub struct EventTotals {
+  pub points: i64,
+  pub kill_count: i64,
+}
+
+pub struct FetchEventTotals<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchEventTotals<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select
+          (select coalesce(sum(points), 0)::bigint from user_events where event_id = $1) as points,
+          (select coalesce(sum(kill_count), 0)::bigint from user_event_bosses where event_id = $1) as kill_count
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, event_id: i64) -> anyhow::Result<EventTotals> {
+    let row = self.executor.client().query_one(&self.statement, &[&event_id]).await?;
+    Ok(EventTotals {
+      points: row.get("points"),
+      kill_count: row.get("kill_count"),
+    })
+  }
+}
+
+/// Returns boss counter milestones of the event the user has received the rewards of.
+pub struct FetchUserBossCountRewards<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserBossCountRewards<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select count_id
+        from user_event_boss_count_rewards
+        where user_id = $1 and event_id = $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64) -> anyhow::Result<HashSet<i64>> {
+    let rows = self
+      .executor
+      .client()
+      .query(&self.statement, &[&user_id, &event_id])
+      .await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+  }
+}
+
+/// Returns `false` if the rewards of the milestone were already received.
+pub struct ReceiveUserBossCountReward<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ReceiveUserBossCountReward<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_event_boss_count_rewards (user_id, event_id, count_id)
+        values ($1, $2, $3)
+        on conflict do nothing
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64, count_id: i64) -> anyhow::Result<bool> {
+    let rows_affected = self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &event_id, &count_id])
+      .await?;
+    Ok(rows_affected != 0)
+  }
+}
+
+/// Returns boss progress of the event, by quest ID.
+pub struct FetchUserEventBosses<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserEventBosses<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select quest_id, damage, kill_count, ticket_ratio
+        from user_event_bosses
+        where user_id = $1 and event_id = $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64) -> anyhow::Result<HashMap<i64, UserEventBoss>> {
+    let rows = self
+      .executor
+      .client()
+      .query(&self.statement, &[&user_id, &event_id])
+      .await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| (row.get("quest_id"), UserEventBoss::from_row(row)))
+        .collect(),
+    )
+  }
+}
+
+/// Remembers Boss Ticket multiplier of the started battle, damage from earlier attempts is kept.
+/// The battle log and the battle result are each accepted once per started battle.
+pub struct StartUserEventBoss<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> StartUserEventBoss<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_event_bosses (user_id, event_id, quest_id, ticket_ratio, log_pending, result_pending)
+        values ($1, $2, $3, $4, true, true)
+        on conflict (user_id, event_id, quest_id)
+          do update
+          set ticket_ratio = excluded.ticket_ratio,
+              log_pending = true,
+              result_pending = true
+        returning damage, kill_count, ticket_ratio
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    event_id: i64,
+    quest_id: i64,
+    ticket_ratio: i32,
+  ) -> anyhow::Result<UserEventBoss> {
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&user_id, &event_id, &quest_id, &ticket_ratio])
+      .await?;
+    Ok(UserEventBoss::from_row(&row))
+  }
+}
+
+/// Damage is capped at [max_damage], the total HP of the boss.
+/// Returns `None` if the battle was never started or its log was already recorded.
+pub struct AddUserEventBossDamage<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserEventBossDamage<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_event_bosses
+        set damage = least(damage + $4, $5),
+            log_pending = false
+        where user_id = $1 and event_id = $2 and quest_id = $3 and log_pending
+        returning damage, kill_count, ticket_ratio
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    event_id: i64,
+    quest_id: i64,
+    damage: i64,
+    max_damage: i64,
+  ) -> anyhow::Result<Option<UserEventBoss>> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &event_id, &quest_id, &damage, &max_damage])
+      .await?;
+    Ok(row.map(|row| UserEventBoss::from_row(&row)))
+  }
+}
+
+/// Ends the started battle, a win is counted and resets the boss HP.
+/// Returns `None` if the battle was never started or its result was already recorded.
+pub struct FinishUserEventBoss<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FinishUserEventBoss<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_event_bosses
+        set kill_count = kill_count + case when $4 then 1 else 0 end,
+            damage = case when $4 then 0 else damage end,
+            result_pending = false
+        where user_id = $1 and event_id = $2 and quest_id = $3 and result_pending
+        returning damage, kill_count, ticket_ratio
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    event_id: i64,
+    quest_id: i64,
+    win: bool,
+  ) -> anyhow::Result<Option<UserEventBoss>> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &event_id, &quest_id, &win])
+      .await?;
+    Ok(row.map(|row| UserEventBoss::from_row(&row)))
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserEmergencyBoss {
+  pub emergency_boss_id: i64,
+  pub quest_id: i64,
+  pub kill_count: i32,
+  pub expires_at: DateTime<Utc>,
+  pub is_active: bool,
+}
+
+impl UserEmergencyBoss {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      emergency_boss_id: row.get("emergency_boss_id"),
+      quest_id: row.get("quest_id"),
+      kill_count: row.get("kill_count"),
+      expires_at: row.get("expires_at"),
+      is_active: row.get("is_active"),
+    }
+  }
+}
+
+/// Returns the most recently appeared emergency boss of the event, even if it has already expired.
+pub struct FetchUserEmergencyBoss<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserEmergencyBoss<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select emergency_boss_id, quest_id, kill_count, expires_at, expires_at > now() as is_active
+        from user_event_emergency_bosses
+        where user_id = $1 and event_id = $2
+        order by spawned_at desc
+        limit 1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64) -> anyhow::Result<Option<UserEmergencyBoss>> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &event_id])
+      .await?;
+    Ok(row.map(|row| UserEmergencyBoss::from_row(&row)))
+  }
+}
+
+/// Kill counts are kept when the same emergency boss appears again.
+pub struct SpawnUserEmergencyBoss<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> SpawnUserEmergencyBoss<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_event_emergency_bosses (user_id, event_id, emergency_boss_id, quest_id, expires_at)
+        values ($1, $2, $3, $4, $5)
+        on conflict (user_id, event_id, emergency_boss_id)
+          do update
+          set quest_id = excluded.quest_id,
+              spawned_at = now(),
+              expires_at = excluded.expires_at
+        returning emergency_boss_id, quest_id, kill_count, expires_at, expires_at > now() as is_active
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    event_id: i64,
+    emergency_boss_id: i64,
+    quest_id: i64,
+    expires_at: DateTime<Utc>,
+  ) -> anyhow::Result<UserEmergencyBoss> {
+    let row = self
+      .executor
+      .client()
+      .query_one(
+        &self.statement,
+        &[&user_id, &event_id, &emergency_boss_id, &quest_id, &expires_at],
+      )
+      .await?;
+    Ok(UserEmergencyBoss::from_row(&row))
+  }
+}
+
+/// Counts a win against the active emergency boss fought on [quest_id] and makes it disappear.
+/// Returns `None` if there is no such active boss.
+pub struct DefeatUserEmergencyBoss<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> DefeatUserEmergencyBoss<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_event_emergency_bosses
+        set kill_count = kill_count + 1,
+            expires_at = now()
+        where user_id = $1 and event_id = $2 and quest_id = $3 and expires_at > now()
+        returning emergency_boss_id, quest_id, kill_count, expires_at, false as is_active
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64, quest_id: i64) -> anyhow::Result<Option<UserEmergencyBoss>> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &event_id, &quest_id])
+      .await?;
+    Ok(row.map(|row| UserEmergencyBoss::from_row(&row)))
+  }
+}
+
+/// Returns how many times all users together defeated the emergency boss.
+pub struct FetchEmergencyBossTotalKills<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchEmergencyBossTotalKills<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select coalesce(sum(kill_count), 0)::bigint
+        from user_event_emergency_bosses
+        where event_id = $1 and emergency_boss_id = $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, event_id: i64, emergency_boss_id: i64) -> anyhow::Result<i64> {
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&event_id, &emergency_boss_id])
+      .await?;
+    Ok(row.get(0))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn log(value: &str) -> Vec<String> {
+    value.split(',').map(str::to_owned).collect()
+  }
+
+  #[test]
+  fn test_parse_log_damage() {
+    let log = log(concat!(
+      "party front,1014117,754,111,119,76,81,65,78,10,",
+      "party back,1004205,627,85,84,62,58,73,80,86,",
+      "w1,attack,1044110,0,72000172,0,0,142,0,0,0,0,0,",
+      "w1,attack,1024186,0,211042100000310134,0,0,172,0,0,0,0,0,",
+      "w2,attack,1014117,0,152540020001330154,0,0,113,0,0,0,0,0"
+    ));
+
+    assert_eq!(parse_log_damage(&log), 142 + 172 + 113);
+  }
+
+  #[test]
+  fn test_remaining_hp() {
+    let boss = UserEventBoss {
+      damage: 1500,
+      ..Default::default()
+    };
+
+    assert_eq!(boss.remaining_hp(&[1000, 2000, 500]), vec![0, 1500, 500]);
+    assert_eq!(UserEventBoss::default().remaining_hp(&[1000]), vec![1000]);
+  }
+
+  #[test]
+  fn test_parse_log_damage_without_attacks() {
+    assert_eq!(
+      parse_log_damage(&log("party front,1014117,754,111,119,76,81,65,78,10")),
+      0
+    );
+    assert_eq!(parse_log_damage(&[]), 0);
+  }
+}
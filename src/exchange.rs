@@ -0,0 +1,274 @@
+//! Medal exchange from `exchange` and `exchange_item` masters: medal prices and exchange limits.
+//!
+//! `exchange_item` master is not present in the masters directory, so its columns are assumed to follow
+//! `shop_item`: `item_type`/`item_id`/`item_num` reward, `need_item_type`/`need_item_id`/`need_item_num` price,
+//! and `limit_type`/`limit`. Missing columns are read as zero. Until the master is dumped and the columns are
+//! checked against it, exchanges list no items.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::Value;
+use tokio_postgres::Statement;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64_or_zero};
+use crate::api::quest::QuestRewardItem;
+use crate::api::RemoteDataItemType;
+use crate::database::QueryExecutor;
+use crate::item::{CountedItem, IntoItemReference};
+use crate::settings::DailyResetSettings;
+use crate::shop::ShopLimitKind;
+use crate::user::id::UserId;
+
+/// Most exchanges of one item in a single `exchange` request.
+pub const MAX_EXCHANGE_COUNT: i32 = 99;
+
+fn parse_optional_date(value: &Value) -> Option<NaiveDateTime> {
+  value.as_str().and_then(parse_date)
+}
+
+/// Whether [item_type] can be spent in an exchange.
+pub fn is_medal(item_type: RemoteDataItemType) -> bool {
+  matches!(
+    item_type,
+    RemoteDataItemType::ExchangeMedal | RemoteDataItemType::SlayerMedal | RemoteDataItemType::CollaborationMedal
+  )
+}
+
+#[derive(Debug, Clone)]
+pub struct ExchangePrototype {
+  pub id: i64,
+  pub enable: bool,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+}
+
+impl ExchangePrototype {
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.enable && self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+}
+
+/// Returns all exchanges, parsed once.
+pub fn get_exchanges() -> &'static [ExchangePrototype] {
+  static EXCHANGES: OnceLock<Vec<ExchangePrototype>> = OnceLock::new();
+
+  EXCHANGES.get_or_init(|| {
+    get_master_manager()
+      .get_master("exchange")
+      .iter()
+      .map(|exchange| ExchangePrototype {
+        id: parse_i64_or_zero(&exchange["exchange_id"]),
+        enable: exchange["enable"].as_str() == Some("1"),
+        start_at: parse_optional_date(&exchange["start_at"]),
+        end_at: parse_optional_date(&exchange["end_at"]),
+      })
+      .collect()
+  })
+}
+
+pub fn get_exchange(id: i64) -> Option<&'static ExchangePrototype> {
+  get_exchanges().iter().find(|exchange| exchange.id == id)
+}
+
+#[derive(Debug, Clone)]
+pub struct ExchangeItemPrototype {
+  pub id: i64,
+  pub exchange_id: i64,
+  /// Received for a single exchange
+  pub reward: QuestRewardItem,
+  /// Medals spent for a single exchange
+  pub price: CountedItem,
+  pub limit_kind: ShopLimitKind,
+  /// Zero if unlimited
+  pub limit: i32,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+}
+
+impl ExchangeItemPrototype {
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+
+  pub fn limit(&self) -> Option<i32> {
+    match self.limit_kind {
+      ShopLimitKind::OnePurchase => Some(1),
+      _ if self.limit > 0 => Some(self.limit),
+      _ => None,
+    }
+  }
+}
+
+/// Returns all exchange items, parsed once. Items not priced in medals are skipped.
+pub fn get_exchange_items() -> &'static [ExchangeItemPrototype] {
+  static EXCHANGE_ITEMS: OnceLock<Vec<ExchangeItemPrototype>> = OnceLock::new();
+
+  EXCHANGE_ITEMS.get_or_init(|| {
+    get_master_manager()
+      .try_get_master("exchange_item")
+      .into_iter()
+      .flatten()
+      .filter_map(|item| {
+        let price_type = RemoteDataItemType::from(parse_i64_or_zero(&item["need_item_type"]) as i32);
+        if !is_medal(price_type) {
+          return None;
+        }
+
+        Some(ExchangeItemPrototype {
+          id: parse_i64_or_zero(&item["id"]),
+          exchange_id: parse_i64_or_zero(&item["exchange_id"]),
+          reward: QuestRewardItem {
+            item_type: parse_i64_or_zero(&item["item_type"]) as i32,
+            item_id: parse_i64_or_zero(&item["item_id"]),
+            item_num: parse_i64_or_zero(&item["item_num"]) as i32,
+            item_rare: false,
+            probability: None,
+          },
+          price: (price_type, parse_i64_or_zero(&item["need_item_id"]))
+            .into_item_reference()
+            .into_counted(parse_i64_or_zero(&item["need_item_num"]) as i32),
+          limit_kind: ShopLimitKind::parse(item["limit_type"].as_str().unwrap_or_default()),
+          limit: parse_i64_or_zero(&item["limit"]) as i32,
+          start_at: parse_optional_date(&item["start_at"]),
+          end_at: parse_optional_date(&item["end_at"]),
+        })
+      })
+      .collect()
+  })
+}
+
+pub fn get_exchange_item(id: i64) -> Option<&'static ExchangeItemPrototype> {
+  get_exchange_items().iter().find(|item| item.id == id)
+}
+
+#[derive(Debug, Clone)]
+pub struct UserExchange {
+  pub exchange_count: i32,
+  pub last_exchanged_at: DateTime<Utc>,
+}
+
+impl UserExchange {
+  /// Exchanges counted towards the limit of [prototype] at [now].
+  pub fn current_count(
+    &self,
+    prototype: &ExchangeItemPrototype,
+    now: DateTime<Utc>,
+    settings: &DailyResetSettings,
+  ) -> i32 {
+    match prototype.limit_kind.period_start(now, settings) {
+      Some(start) if self.last_exchanged_at < start => 0,
+      _ => self.exchange_count,
+    }
+  }
+}
+
+pub struct FetchUserExchanges<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserExchanges<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select exchange_item_id, exchange_count, last_exchanged_at
+        from user_exchanges
+        where user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<HashMap<i64, UserExchange>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| {
+          let exchange = UserExchange {
+            exchange_count: row.get("exchange_count"),
+            last_exchanged_at: row.get("last_exchanged_at"),
+          };
+          (row.get("exchange_item_id"), exchange)
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Sets the exchange count of the current period, see [UserExchange::current_count].
+pub struct UpdateUserExchange<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> UpdateUserExchange<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_exchanges (user_id, exchange_item_id, exchange_count)
+        values ($1, $2, $3)
+        on conflict (user_id, exchange_item_id)
+          do update
+          set exchange_count = excluded.exchange_count,
+              last_exchanged_at = now()
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, exchange_item_id: i64, exchange_count: i32) -> anyhow::Result<()> {
+    self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &exchange_item_id, &exchange_count])
+      .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{TimeDelta, TimeZone};
+
+  use super::*;
+
+  #[test]
+  fn test_current_count() {
+    let mut prototype = ExchangeItemPrototype {
+      id: 10001,
+      exchange_id: 100,
+      reward: QuestRewardItem {
+        item_type: RemoteDataItemType::Money.into(),
+        item_id: 0,
+        item_num: 1000,
+        item_rare: false,
+        probability: None,
+      },
+      price: (RemoteDataItemType::ExchangeMedal, 1001)
+        .into_item_reference()
+        .into_counted(10),
+      limit_kind: ShopLimitKind::Daily,
+      limit: 5,
+      start_at: None,
+      end_at: None,
+    };
+    let now = Utc.with_ymd_and_hms(2026, 1, 14, 15, 30, 0).unwrap();
+    let exchange = UserExchange {
+      exchange_count: 3,
+      last_exchanged_at: now - TimeDelta::days(1),
+    };
+    let settings = DailyResetSettings::default();
+
+    assert_eq!(exchange.current_count(&prototype, now, &settings), 0);
+    prototype.limit_kind = ShopLimitKind::BuyLimit;
+    assert_eq!(exchange.current_count(&prototype, now, &settings), 3);
+  }
+}
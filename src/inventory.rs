@@ -0,0 +1,237 @@
+//! Inventory capacity: how many equipment items and reserve members a user can hold.
+//!
+//! Capacity starts at a base value and grows with every expansion bought for quartz, see [InventorySettings].
+//! Equipment rewards that do not fit are sent to the present box instead, see [grant_equipment].
+
+use tokio_postgres::Statement;
+use tracing::info;
+
+use crate::api::{RemoteData, RemoteDataItemType};
+use crate::blob::IntoRemoteData;
+use crate::database::QueryExecutor;
+use crate::equipment::AddUserEquipment;
+use crate::present::{NewPresent, SendUserPresent};
+use crate::settings::InventorySettings;
+use crate::user::id::UserId;
+
+const OVERFLOW_PRESENT_MESSAGE: &str = "所持上限を超えたため、プレゼントボックスに送られました";
+
+/// `type` request parameter of [crate::api::items::box_expansion].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InventoryKind {
+  Equipment,
+  ReserveMember,
+}
+
+impl TryFrom<i32> for InventoryKind {
+  type Error = i32;
+
+  fn try_from(value: i32) -> Result<Self, Self::Error> {
+    match value {
+      1 => Ok(InventoryKind::Equipment),
+      2 => Ok(InventoryKind::ReserveMember),
+      value => Err(value),
+    }
+  }
+}
+
+impl InventoryKind {
+  fn base_capacity(&self, settings: &InventorySettings) -> i32 {
+    match self {
+      InventoryKind::Equipment => settings.equipment_capacity,
+      InventoryKind::ReserveMember => settings.reserve_member_capacity,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserInventory {
+  pub equipment_count: i32,
+  pub reserve_member_count: i32,
+  pub equipment_expansions: i32,
+  pub reserve_member_expansions: i32,
+}
+
+impl UserInventory {
+  pub fn count(&self, kind: InventoryKind) -> i32 {
+    match kind {
+      InventoryKind::Equipment => self.equipment_count,
+      InventoryKind::ReserveMember => self.reserve_member_count,
+    }
+  }
+
+  pub fn expansions(&self, kind: InventoryKind) -> i32 {
+    match kind {
+      InventoryKind::Equipment => self.equipment_expansions,
+      InventoryKind::ReserveMember => self.reserve_member_expansions,
+    }
+  }
+
+  pub fn capacity(&self, kind: InventoryKind, settings: &InventorySettings) -> i32 {
+    kind.base_capacity(settings) + self.expansions(kind) * settings.expansion_step
+  }
+
+  /// Number of items that can still be added, zero if the inventory is over capacity.
+  pub fn free_space(&self, kind: InventoryKind, settings: &InventorySettings) -> i32 {
+    (self.capacity(kind, settings) - self.count(kind)).max(0)
+  }
+}
+
+pub struct FetchUserInventory<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserInventory<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select
+          (select count(*)::integer from user_items_equipment where user_id = $1) as equipment_count,
+          (select count(*)::integer from user_members_reserve where user_id = $1) as reserve_member_count,
+          coalesce(expansions.equipment_expansions, 0) as equipment_expansions,
+          coalesce(expansions.reserve_member_expansions, 0) as reserve_member_expansions
+        from (select 1) as dummy
+          left join user_inventory_expansions expansions on expansions.user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<UserInventory> {
+    let row = self.executor.client().query_one(&self.statement, &[&user_id]).await?;
+    Ok(UserInventory {
+      equipment_count: row.get("equipment_count"),
+      reserve_member_count: row.get("reserve_member_count"),
+      equipment_expansions: row.get("equipment_expansions"),
+      reserve_member_expansions: row.get("reserve_member_expansions"),
+    })
+  }
+}
+
+/// Adds a single expansion of [InventoryKind], returns the new expansion count.
+pub struct ExpandUserInventory<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ExpandUserInventory<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_inventory_expansions (user_id, equipment_expansions, reserve_member_expansions)
+        values ($1, $2, $3)
+        on conflict (user_id)
+          do update
+          set equipment_expansions = user_inventory_expansions.equipment_expansions + excluded.equipment_expansions,
+              reserve_member_expansions = user_inventory_expansions.reserve_member_expansions + excluded.reserve_member_expansions
+        returning equipment_expansions, reserve_member_expansions
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, kind: InventoryKind) -> anyhow::Result<i32> {
+    let (equipment, reserve_member) = match kind {
+      InventoryKind::Equipment => (1, 0),
+      InventoryKind::ReserveMember => (0, 1),
+    };
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&user_id, &equipment, &reserve_member])
+      .await?;
+    Ok(match kind {
+      InventoryKind::Equipment => row.get("equipment_expansions"),
+      InventoryKind::ReserveMember => row.get("reserve_member_expansions"),
+    })
+  }
+}
+
+/// Equipment granted by [grant_equipment].
+#[derive(Debug, Default)]
+pub struct GrantedEquipment {
+  pub remote_data: Vec<RemoteData>,
+  /// Copies sent to the present box because the inventory is full
+  pub overflow: i32,
+}
+
+/// Creates [count] copies of an equipment item, as many as fit into the inventory.
+/// The rest is sent to the present box.
+pub async fn grant_equipment(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  settings: &InventorySettings,
+  user_id: UserId,
+  item_type: RemoteDataItemType,
+  item_id: i64,
+  count: i32,
+) -> anyhow::Result<GrantedEquipment> {
+  let inventory = FetchUserInventory::new(transaction).await?.run(user_id).await?;
+  let fits = count.min(inventory.free_space(InventoryKind::Equipment, settings));
+
+  let mut granted = GrantedEquipment::default();
+  let add_equipment = AddUserEquipment::new(transaction).await?;
+  for _ in 0..fits {
+    let equipment = add_equipment.run(user_id, item_type, item_id, 0).await?;
+    granted.remote_data.extend(equipment.to_remote().into_remote_data());
+  }
+
+  granted.overflow = count - fits;
+  if granted.overflow > 0 {
+    SendUserPresent::new(transaction)
+      .await?
+      .run(
+        user_id,
+        &NewPresent {
+          item_type: item_type.into(),
+          item_id,
+          item_num: granted.overflow,
+          message: OVERFLOW_PRESENT_MESSAGE.to_owned(),
+          expires_at: None,
+        },
+      )
+      .await?;
+    info!(
+      ?user_id,
+      ?item_type,
+      ?item_id,
+      overflow = granted.overflow,
+      "sent equipment overflow to present box"
+    );
+  }
+
+  Ok(granted)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_free_space() {
+    let settings = InventorySettings::default();
+    let inventory = UserInventory {
+      equipment_count: settings.equipment_capacity + 5,
+      reserve_member_count: 10,
+      equipment_expansions: 1,
+      reserve_member_expansions: 0,
+    };
+
+    assert_eq!(
+      inventory.capacity(InventoryKind::Equipment, &settings),
+      settings.equipment_capacity + settings.expansion_step
+    );
+    assert_eq!(
+      inventory.free_space(InventoryKind::Equipment, &settings),
+      settings.expansion_step - 5
+    );
+    assert_eq!(
+      inventory.free_space(InventoryKind::ReserveMember, &settings),
+      settings.reserve_member_capacity - 10
+    );
+  }
+}
@@ -1,7 +1,11 @@
+use crate::api::master_all::get_master_manager;
 use crate::api::{RemoteData, RemoteDataCommand, RemoteDataItemType};
 use crate::blob::IntoRemoteData;
+use crate::call::{STATUS_ITEM_NOT_ENOUGH, STATUS_MONEY_NOT_ENOUGH, STATUS_QUARTZ_NOT_ENOUGH};
 use crate::database::QueryExecutor;
 use crate::user::id::UserId;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
 use tokio_postgres::Statement;
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -54,7 +58,7 @@ impl ItemReference {
   }
 }
 
-#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct ItemReference {
   pub item_type: RemoteDataItemType,
   pub item_id: i64,
@@ -64,6 +68,12 @@ pub trait IntoItemReference {
   fn into_item_reference(self) -> ItemReference;
 }
 
+impl IntoItemReference for ItemReference {
+  fn into_item_reference(self) -> ItemReference {
+    self
+  }
+}
+
 impl IntoItemReference for (RemoteDataItemType, i64) {
   fn into_item_reference(self) -> ItemReference {
     ItemReference {
@@ -73,6 +83,30 @@ impl IntoItemReference for (RemoteDataItemType, i64) {
   }
 }
 
+/// Returns the price in Eris an item is sold for, from `item` master.
+/// `None` if the item can not be sold.
+pub fn get_item_sell_price(item: impl IntoItemReference) -> Option<i32> {
+  static SELL_PRICES: OnceLock<HashMap<ItemReference, i32>> = OnceLock::new();
+
+  let prices = SELL_PRICES.get_or_init(|| {
+    get_master_manager()
+      .get_master("item")
+      .iter()
+      .map(|item| {
+        let item_type: i32 = item["type"].as_str().unwrap().parse().unwrap();
+        let item_id: i64 = item["id"].as_str().unwrap().parse().unwrap();
+        let sell: i32 = item["sell"].as_str().unwrap().parse().unwrap();
+        let reference = (RemoteDataItemType::from(item_type), item_id).into_item_reference();
+        (reference, sell)
+      })
+      .collect()
+  });
+  prices
+    .get(&item.into_item_reference())
+    .copied()
+    .filter(|sell| *sell > 0)
+}
+
 pub struct UpdateItemCountBy<'a> {
   executor: QueryExecutor<'a>,
   statement: Statement,
@@ -110,3 +144,171 @@ impl<'a> UpdateItemCountBy<'a> {
     Ok(item.into_counted(new_quantity))
   }
 }
+
+pub struct FetchUserItemCount<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserItemCount<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      // Lock the row, so concurrent requests cannot spend the same items twice
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select quantity
+        from user_items
+        where user_id = $1 and item_type = $2 and item_id = $3
+        for update
+      "#).await?,
+      executor,
+    })
+  }
+
+  /// Returns zero for items the user has never had.
+  pub async fn run(&self, user_id: UserId, item: impl IntoItemReference) -> anyhow::Result<CountedItem> {
+    let item = item.into_item_reference();
+    let item_type: i32 = item.item_type.into();
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &(item_type as i64), &item.item_id])
+      .await?;
+    let quantity: i32 = row.map(|row| row.get(0)).unwrap_or(0);
+    Ok(item.into_counted(quantity))
+  }
+}
+
+/// Bundle of resources paid at once with [spend_items].
+#[derive(Debug, Clone, Default)]
+pub struct ItemCost {
+  pub items: Vec<CountedItem>,
+  /// Eris
+  pub money: i32,
+  /// Free quartz is spent first, then paid quartz
+  pub quartz: i32,
+}
+
+impl ItemCost {
+  pub fn items(items: Vec<CountedItem>) -> Self {
+    Self {
+      items,
+      ..Default::default()
+    }
+  }
+
+  /// Items to spend, with duplicates merged and Eris included.
+  fn merged_items(&self) -> BTreeMap<ItemReference, i32> {
+    let mut items: BTreeMap<ItemReference, i32> = BTreeMap::new();
+    for item in self
+      .items
+      .iter()
+      .cloned()
+      .chain(std::iter::once(
+        (RemoteDataItemType::Money, 0)
+          .into_item_reference()
+          .into_counted(self.money),
+      ))
+      .filter(|item| item.quantity > 0)
+    {
+      *items.entry(item.item).or_default() += item.quantity;
+    }
+    items
+  }
+}
+
+/// The user does not have enough of a resource to pay an [ItemCost].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NotEnoughItems {
+  /// Paid quartz for quartz costs
+  pub item: ItemReference,
+  pub required: i32,
+  pub owned: i32,
+}
+
+impl NotEnoughItems {
+  /// Error status sent to the client.
+  pub fn status(&self) -> i32 {
+    match self.item.item_type {
+      RemoteDataItemType::Money => STATUS_MONEY_NOT_ENOUGH,
+      RemoteDataItemType::RealMoney | RemoteDataItemType::RealMoneyFree => STATUS_QUARTZ_NOT_ENOUGH,
+      _ => STATUS_ITEM_NOT_ENOUGH,
+    }
+  }
+}
+
+impl std::fmt::Display for NotEnoughItems {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "not enough {:?}:{}, required {}, owned {}",
+      self.item.item_type, self.item.item_id, self.required, self.owned
+    )
+  }
+}
+
+impl std::error::Error for NotEnoughItems {}
+
+/// Checks that the user has everything in [cost] and deducts it, returns new counts of spent items.
+/// Nothing is deducted if anything is missing. Counted rows stay locked until the transaction ends.
+pub async fn spend_items(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  user_id: UserId,
+  cost: &ItemCost,
+) -> anyhow::Result<Result<Vec<CountedItem>, NotEnoughItems>> {
+  let fetch_count = FetchUserItemCount::new(transaction).await?;
+
+  let mut spend = Vec::new();
+  for (item, required) in cost.merged_items() {
+    let owned = fetch_count.run(user_id, item).await?;
+    if owned.quantity < required {
+      return Ok(Err(NotEnoughItems {
+        item,
+        required,
+        owned: owned.quantity,
+      }));
+    }
+    spend.push((item, required));
+  }
+
+  if cost.quartz > 0 {
+    let realmoney = fetch_count.run(user_id, (RemoteDataItemType::RealMoney, 0)).await?;
+    let realmoneyfree = fetch_count.run(user_id, (RemoteDataItemType::RealMoneyFree, 0)).await?;
+    if realmoney.quantity + realmoneyfree.quantity < cost.quartz {
+      return Ok(Err(NotEnoughItems {
+        item: realmoney.item,
+        required: cost.quartz,
+        owned: realmoney.quantity + realmoneyfree.quantity,
+      }));
+    }
+    let free = cost.quartz.min(realmoneyfree.quantity);
+    spend.push((realmoneyfree.item, free));
+    spend.push((realmoney.item, cost.quartz - free));
+  }
+
+  let update = UpdateItemCountBy::new(transaction).await?;
+  let mut spent = Vec::new();
+  for (item, amount) in spend.into_iter().filter(|(_, amount)| *amount > 0) {
+    spent.push(update.run(user_id, item, -amount).await?);
+  }
+  Ok(Ok(spent))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_merged_items() {
+    let potion = (RemoteDataItemType::PowerPotion, 1).into_item_reference();
+    let cost = ItemCost {
+      items: vec![potion.into_counted(2), potion.into_counted(3), potion.into_counted(0)],
+      money: 100,
+      quartz: 0,
+    };
+
+    let money = (RemoteDataItemType::Money, 0).into_item_reference();
+    assert_eq!(cost.merged_items(), BTreeMap::from([(money, 100), (potion, 5)]));
+  }
+}
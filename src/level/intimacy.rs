@@ -46,6 +46,11 @@ impl IntimacyLevelCalculator {
   pub fn get_xp_for_level(&self, level: i32) -> Option<i32> {
     self.level_to_absolute_xp.get(&level).copied()
   }
+
+  /// Experience of the max level, experience above it is not counted.
+  pub fn get_max_xp(&self) -> i32 {
+    self.level_to_absolute_xp.values().next_back().copied().unwrap_or(0)
+  }
 }
 
 static INTIMACY_LEVEL_CALCULATOR: OnceLock<IntimacyLevelCalculator> = OnceLock::new();
@@ -141,6 +146,12 @@ mod tests {
     assert_eq!(calculator.get_xp_for_level(20), Some(8980));
   }
 
+  #[test]
+  fn test_get_max_xp() {
+    let calculator = create_test_calculator();
+    assert_eq!(calculator.get_max_xp(), 8980);
+  }
+
   #[test]
   fn test_cumulative_xp_calculation() {
     let calculator = create_test_calculator();
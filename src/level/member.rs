@@ -103,6 +103,11 @@ impl MemberLevelCalculator {
 
     base_limit + bonus_limits
   }
+
+  /// Returns the highest promotion level that raises the level cap.
+  pub fn get_max_promotion_level(&self) -> i32 {
+    self.promotion_levels.keys().next_back().copied().unwrap_or(0)
+  }
 }
 
 static MEMBER_LEVEL_CALCULATOR: OnceLock<MemberLevelCalculator> = OnceLock::new();
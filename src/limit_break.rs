@@ -0,0 +1,120 @@
+//! Member limit break (promotion) costs from `member_limitbreak_materiall` master.
+//!
+//! Each promotion level costs Eris and `MaterialLimit` items. When the items are not enough, a duplicate of
+//! the member from the reserve is consumed instead of the items of one level, Eris is still spent. Maximum
+//! promotion level is the last level that has both a cost row and a level cap bonus in `member_lv_limitbreak`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::RemoteDataItemType;
+use crate::item::{CountedItem, ItemReference};
+use crate::level::get_member_level_calculator;
+
+/// Cost of reaching a single promotion level.
+#[derive(Debug, Clone)]
+pub struct LimitBreakCost {
+  pub materials: Vec<CountedItem>,
+  pub money: i32,
+}
+
+/// Returns costs by member and promotion level, parsed once.
+fn get_limit_break_costs() -> &'static HashMap<i64, BTreeMap<i32, LimitBreakCost>> {
+  static COSTS: OnceLock<HashMap<i64, BTreeMap<i32, LimitBreakCost>>> = OnceLock::new();
+
+  COSTS.get_or_init(|| {
+    let mut costs: HashMap<i64, BTreeMap<i32, LimitBreakCost>> = HashMap::new();
+    for data in get_master_manager().get_master("member_limitbreak_materiall") {
+      let materials = (1..=4)
+        .filter_map(|index| {
+          let item_type = parse_i64(&data[format!("item_type{}", index)]) as i32;
+          let item_id = parse_i64(&data[format!("item_id{}", index)]);
+          let quantity = parse_i64(&data[format!("item_num{}", index)]) as i32;
+          (item_type != 0 && quantity > 0).then(|| CountedItem {
+            item: ItemReference {
+              item_type: RemoteDataItemType::from(item_type),
+              item_id,
+            },
+            quantity,
+          })
+        })
+        .collect();
+      costs.entry(parse_i64(&data["member_id"])).or_default().insert(
+        parse_i64(&data["limitbreak_lv"]) as i32,
+        LimitBreakCost {
+          materials,
+          money: parse_i64(&data["money"]) as i32,
+        },
+      );
+    }
+    costs
+  })
+}
+
+pub fn get_max_promotion_level(member_id: i64) -> i32 {
+  let max_level = get_member_level_calculator().get_max_promotion_level();
+  get_limit_break_costs().get(&member_id).map_or(0, |costs| {
+    costs.keys().take_while(|level| **level <= max_level).count() as i32
+  })
+}
+
+/// Costs of each level after [from] up to [to], or [None] if [to] is above [get_max_promotion_level].
+pub fn limit_break_cost(member_id: i64, from: i32, to: i32) -> Option<Vec<&'static LimitBreakCost>> {
+  if to > get_max_promotion_level(member_id) {
+    return None;
+  }
+
+  let costs = get_limit_break_costs().get(&member_id)?;
+  Some(costs.range(from + 1..=to).map(|(_, cost)| cost).collect())
+}
+
+/// Items spent on limit break, duplicates replace the items of the first [duplicates] levels.
+pub fn total_limit_break_cost(costs: &[&LimitBreakCost], duplicates: usize) -> (Vec<CountedItem>, i32) {
+  let mut materials: BTreeMap<ItemReference, i32> = BTreeMap::new();
+  for cost in costs.iter().skip(duplicates) {
+    for material in &cost.materials {
+      *materials.entry(material.item).or_default() += material.quantity;
+    }
+  }
+
+  let materials = materials
+    .into_iter()
+    .map(|(item, quantity)| item.into_counted(quantity))
+    .collect();
+  (materials, costs.iter().map(|cost| cost.money).sum())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_total_limit_break_cost() {
+    let material = |item_id, quantity| CountedItem {
+      item: ItemReference {
+        item_type: RemoteDataItemType::MaterialLimit,
+        item_id,
+      },
+      quantity,
+    };
+    let first = LimitBreakCost {
+      materials: vec![material(161, 20)],
+      money: 30000,
+    };
+    let second = LimitBreakCost {
+      materials: vec![material(161, 25), material(162, 5)],
+      money: 48000,
+    };
+
+    let (materials, money) = total_limit_break_cost(&[&first, &second], 0);
+    assert_eq!(materials, vec![material(161, 45), material(162, 5)]);
+    assert_eq!(money, 78000);
+
+    // Duplicate replaces the items of the first level only
+    let (materials, money) = total_limit_break_cost(&[&first, &second], 1);
+    assert_eq!(materials, vec![material(161, 25), material(162, 5)]);
+    assert_eq!(money, 78000);
+  }
+}
@@ -0,0 +1,844 @@
+//! Login bonuses: daily calendars from `loginbonus` masters, omikuji, random and roulette login bonuses.
+//!
+//! Everything is received at most once per login day, see [login_day]. Rewards go to the present box.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+use tokio_postgres::{Row, Statement};
+use tracing::{info, warn};
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::quest::QuestRewardItem;
+use crate::database::QueryExecutor;
+use crate::mission::daily_reset_at;
+use crate::present::{NewPresent, SendUserPresent};
+use crate::settings::DailyResetSettings;
+use crate::shop::get_pack_items;
+use crate::user::id::UserId;
+
+const OMIKUJI_PRESENT_MESSAGE: &str = "おみくじで獲得したアイテムです";
+const RANDOM_LOGIN_BONUS_PRESENT_MESSAGE: &str = "ログインボーナスで獲得したアイテムです";
+
+/// Presents sent by login bonuses can be received for this long.
+pub fn present_duration() -> TimeDelta {
+  TimeDelta::days(30)
+}
+
+/// Login day [now] belongs to, see [daily_reset_at].
+pub fn login_day(now: DateTime<Utc>, settings: &DailyResetSettings) -> NaiveDate {
+  daily_reset_at(now, settings).date_naive()
+}
+
+/// `loginbonus_type` column.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LoginBonusKind {
+  /// Repeats after the last day
+  Normal,
+  StartDash,
+  Event,
+  Comeback,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginBonusItem {
+  pub day: i32,
+  pub item: QuestRewardItem,
+  /// `description` column with the day filled in, shown in the present box
+  pub message: String,
+}
+
+/// Login bonus calendar from `loginbonus` and `loginbonus_item` masters.
+#[derive(Debug, Clone)]
+pub struct LoginBonusPrototype {
+  pub loginbonus_id: i64,
+  pub kind: LoginBonusKind,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+  /// Rewards can no longer be received after this, even if the calendar is still shown
+  pub accept_limit: Option<NaiveDateTime>,
+  /// Sorted by day
+  pub items: Vec<LoginBonusItem>,
+}
+
+impl LoginBonusPrototype {
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.start_at.is_none_or(|start| now >= start)
+      && self.end_at.is_none_or(|end| now < end)
+      && self.accept_limit.is_none_or(|limit| now < limit)
+  }
+
+  pub fn days(&self) -> i32 {
+    self.items.last().map_or(0, |item| item.day)
+  }
+
+  /// Day received after [last_day] was, `None` once the calendar is completed.
+  /// Normal calendar starts over after its last day.
+  pub fn next_day(&self, last_day: i32) -> Option<i32> {
+    let days = self.days();
+    if days == 0 {
+      return None;
+    }
+
+    match self.kind {
+      LoginBonusKind::Normal => Some(last_day % days + 1),
+      _ if last_day < days => Some(last_day + 1),
+      _ => None,
+    }
+  }
+
+  pub fn items_for_day(&self, day: i32) -> impl Iterator<Item = &LoginBonusItem> {
+    self.items.iter().filter(move |item| item.day == day)
+  }
+}
+
+/// Returns all calendars, parsed once.
+pub fn get_login_bonuses() -> &'static [LoginBonusPrototype] {
+  static LOGIN_BONUSES: OnceLock<Vec<LoginBonusPrototype>> = OnceLock::new();
+
+  LOGIN_BONUSES.get_or_init(|| {
+    let mut items: HashMap<i64, Vec<LoginBonusItem>> = HashMap::new();
+    for item in get_master_manager().get_master("loginbonus_item") {
+      let day = parse_i64(&item["days"]) as i32;
+      items
+        .entry(parse_i64(&item["loginbonus_id"]))
+        .or_default()
+        .push(LoginBonusItem {
+          day,
+          item: QuestRewardItem {
+            item_type: parse_i64(&item["item_type"]) as i32,
+            item_id: parse_i64(&item["item_id"]),
+            item_num: parse_i64(&item["item_num"]) as i32,
+            item_rare: false,
+            probability: None,
+          },
+          message: item["description"].as_str().unwrap().replace("<0>", &day.to_string()),
+        });
+    }
+
+    get_master_manager()
+      .get_master("loginbonus")
+      .iter()
+      .filter(|bonus| bonus["enable"].as_str().unwrap() == "1")
+      .filter_map(|bonus| {
+        let kind = match bonus["loginbonus_type"].as_str().unwrap() {
+          "NORMAL" => LoginBonusKind::Normal,
+          "STARTDASH" => LoginBonusKind::StartDash,
+          "EVENT" => LoginBonusKind::Event,
+          "COMEBACK" => LoginBonusKind::Comeback,
+          kind => {
+            warn!(?kind, "unknown login bonus type");
+            return None;
+          }
+        };
+        let loginbonus_id = parse_i64(&bonus["loginbonus_id"]);
+        let mut items = items.remove(&loginbonus_id).unwrap_or_default();
+        items.sort_by_key(|item| item.day);
+
+        Some(LoginBonusPrototype {
+          loginbonus_id,
+          kind,
+          start_at: parse_date(bonus["start_at"].as_str().unwrap()),
+          end_at: parse_date(bonus["end_at"].as_str().unwrap()),
+          accept_limit: parse_date(bonus["accept_limit"].as_str().unwrap()),
+          items,
+        })
+      })
+      .collect()
+  })
+}
+
+/// Result kinds stored in `user_login_bonus_results`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(i32)]
+pub enum LoginBonusResultKind {
+  Omikuji = 1,
+  Random = 2,
+  Roulette = 3,
+}
+
+/// Omikuji from `omikuji` master, the fortune is rolled from `omikuji_lot` by weight.
+#[derive(Debug, Clone)]
+pub struct OmikujiPrototype {
+  pub omikuji_id: i64,
+  pub lot_id: i64,
+}
+
+/// Fortune from `omikuji_lot` master.
+#[derive(Debug, Clone)]
+pub struct OmikujiFortune {
+  pub fortune_id: i64,
+  pub pack_id: i64,
+}
+
+pub fn get_active_omikuji(now: NaiveDateTime) -> Option<OmikujiPrototype> {
+  get_master_manager()
+    .get_master("omikuji")
+    .iter()
+    .find(|omikuji| is_within(omikuji, "start_at", "end_at", now))
+    .map(|omikuji| OmikujiPrototype {
+      omikuji_id: parse_i64(&omikuji["omikuji_id"]),
+      lot_id: parse_i64(&omikuji["omikuji_lot_id"]),
+    })
+}
+
+pub fn get_omikuji_fortunes(lot_id: i64) -> Vec<(OmikujiFortune, i64)> {
+  get_master_manager()
+    .get_master("omikuji_lot")
+    .iter()
+    .filter(|fortune| parse_i64(&fortune["omikuji_lot_id"]) == lot_id)
+    .map(|fortune| {
+      let prototype = OmikujiFortune {
+        fortune_id: parse_i64(&fortune["fortune_id"]),
+        pack_id: parse_i64(&fortune["pack_id"]),
+      };
+      (prototype, parse_i64(&fortune["weight"]))
+    })
+    .collect()
+}
+
+/// Random login bonus from `random_loginbonus` master, a result pattern is rolled every day.
+#[derive(Debug, Clone)]
+pub struct RandomLoginBonusPrototype {
+  pub random_loginbonus_id: i64,
+  pub lot_id: i64,
+  pub story_id: i64,
+  pub days: i32,
+  /// Days that always land on a winning pattern, `win_result_days` column
+  pub win_days: Vec<i32>,
+}
+
+/// Result pattern from `random_loginbonus_result` master.
+#[derive(Debug, Clone)]
+pub struct RandomLoginBonusPattern {
+  pub result_pattern_id: i64,
+  pub pack_id: i64,
+  pub is_win: bool,
+}
+
+impl RandomLoginBonusPrototype {
+  /// There is no lot master, result patterns are matched by the login bonus ID in their name
+  /// (e.g. `TEXT_RANDOM_LOGINBONUS_RESUT_2021081_80`).
+  /// Winning patterns are only rolled on [Self::win_days], any pattern can be rolled if there are none.
+  pub fn get_patterns(&self, day: i32) -> Vec<RandomLoginBonusPattern> {
+    let id = self.random_loginbonus_id.to_string();
+    let win = self.win_days.contains(&day);
+    get_master_manager()
+      .get_master("random_loginbonus_result")
+      .iter()
+      .filter(|result| result["name"].as_str().unwrap().contains(&id))
+      .map(|result| RandomLoginBonusPattern {
+        result_pattern_id: parse_i64(&result["result_pattern_id"]),
+        pack_id: parse_i64(&result["pack_id"]),
+        is_win: result["show_win_effect"].as_str().unwrap() == "1",
+      })
+      .filter(|pattern| self.win_days.is_empty() || pattern.is_win == win)
+      .collect()
+  }
+}
+
+pub fn get_active_random_login_bonus(now: NaiveDateTime) -> Option<RandomLoginBonusPrototype> {
+  get_master_manager()
+    .get_master("random_loginbonus")
+    .iter()
+    .find(|bonus| is_within(bonus, "start_at", "end_at", now))
+    .map(|bonus| RandomLoginBonusPrototype {
+      random_loginbonus_id: parse_i64(&bonus["random_loginbonus_id"]),
+      lot_id: parse_i64(&bonus["lot_id"]),
+      story_id: parse_i64(&bonus["story_id"]),
+      days: parse_i64(&bonus["day"]) as i32,
+      win_days: parse_win_days(bonus["win_result_days"].as_str().unwrap()),
+    })
+}
+
+/// `7_14` - days 7 and 14, `0` - none.
+fn parse_win_days(days: &str) -> Vec<i32> {
+  days
+    .split('_')
+    .filter_map(|day| day.parse().ok())
+    .filter(|day| *day > 0)
+    .collect()
+}
+
+/// Roulette login bonus from `roulette_loginbonus` master, the roulette is spun every day.
+/// Results are free pulls of [Self::gacha_id].
+#[derive(Debug, Clone)]
+pub struct RouletteLoginBonusPrototype {
+  pub roulette_loginbonus_id: i64,
+  pub gacha_id: i64,
+  pub lot_id: i64,
+  pub days: i32,
+}
+
+impl RouletteLoginBonusPrototype {
+  /// Only days with `win_flag` can land on a winning pattern.
+  pub fn is_win_day(&self, day: i32) -> bool {
+    get_master_manager()
+      .get_master("roulette_loginbonus_days")
+      .iter()
+      .any(|entry| {
+        parse_i64(&entry["roulette_loginbonus_id"]) == self.roulette_loginbonus_id
+          && parse_i64(&entry["day"]) as i32 == day
+          && entry["win_flag"].as_str().unwrap() == "1"
+      })
+  }
+
+  /// Win days show view 2, which has the large winning section.
+  pub fn view_id(&self, day: i32) -> i64 {
+    if self.is_win_day(day) { 2 } else { 1 }
+  }
+
+  /// Sections of the roulette from `roulette_loginbonus_view` master: gacha pulls and their size.
+  pub fn get_sections(&self, view_id: i64) -> Vec<(i32, i64)> {
+    get_master_manager()
+      .get_master("roulette_loginbonus_view")
+      .iter()
+      .filter(|section| {
+        parse_i64(&section["roulette_loginbonus_id"]) == self.roulette_loginbonus_id
+          && parse_i64(&section["roulette_loginbonus_view_id"]) == view_id
+      })
+      .map(|section| {
+        (
+          parse_i64(&section["present_gacha_times"]) as i32,
+          parse_i64(&section["range"]),
+        )
+      })
+      .collect()
+  }
+
+  /// There is no lot master, result patterns of lot N have IDs N01..N99 (lot 1 uses 1..99).
+  pub fn get_patterns(&self, gacha_times: i32) -> Vec<i64> {
+    get_master_manager()
+      .get_master("roulette_loginbonus_result_pattern")
+      .iter()
+      .filter(|pattern| (parse_i64(&pattern["result_pattern_id"]) / 100).max(1) == self.lot_id)
+      .filter(|pattern| parse_i64(&pattern["present_gacha_times"]) as i32 == gacha_times)
+      .map(|pattern| parse_i64(&pattern["result_pattern_id"]))
+      .collect()
+  }
+
+  /// Spins the roulette weighted by section sizes, returns gacha pulls and the result pattern showing them.
+  pub fn spin(&self, day: i32, rng: &mut impl Rng) -> Option<(i32, i64)> {
+    let sections = self.get_sections(self.view_id(day));
+    let (gacha_times, _) = sections.choose_weighted(rng, |(_, range)| *range).ok()?;
+    let pattern = *self.get_patterns(*gacha_times).choose(rng)?;
+    Some((*gacha_times, pattern))
+  }
+}
+
+pub fn get_active_roulette_login_bonus(now: NaiveDateTime) -> Option<RouletteLoginBonusPrototype> {
+  get_master_manager()
+    .get_master("roulette_loginbonus")
+    .iter()
+    .find(|bonus| is_within(bonus, "start_at", "end_at", now))
+    .map(|bonus| RouletteLoginBonusPrototype {
+      roulette_loginbonus_id: parse_i64(&bonus["roulette_loginbonus_id"]),
+      gacha_id: parse_i64(&bonus["gacha_id"]),
+      lot_id: parse_i64(&bonus["lot_id"]),
+      days: parse_i64(&bonus["day"]) as i32,
+    })
+}
+
+fn is_within(entry: &Value, start: &str, end: &str, now: NaiveDateTime) -> bool {
+  parse_date(entry[start].as_str().unwrap()).is_none_or(|start| now >= start)
+    && parse_date(entry[end].as_str().unwrap()).is_none_or(|end| now < end)
+}
+
+/// Calendar day received by [claim_login_bonuses].
+#[derive(Debug, Clone)]
+pub struct ClaimedLoginBonus {
+  pub loginbonus_id: i64,
+  pub day: i32,
+  pub items: Vec<QuestRewardItem>,
+}
+
+/// Random or roulette login bonus rolled by [claim_login_bonuses], with results of all previous days.
+#[derive(Debug, Clone)]
+pub struct ClaimedLoginBonusResults<T> {
+  pub prototype: T,
+  pub result_id: i64,
+  /// Including today, by day
+  pub days: Vec<LoginBonusResult>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClaimedLoginBonuses {
+  pub calendars: Vec<ClaimedLoginBonus>,
+  /// Omikuji ID and fortune ID
+  pub omikuji: Option<(i64, i64)>,
+  pub random: Option<ClaimedLoginBonusResults<RandomLoginBonusPrototype>>,
+  pub roulette: Option<ClaimedLoginBonusResults<RouletteLoginBonusPrototype>>,
+}
+
+/// Receives everything that was not received yet on the current login day.
+/// Comeback calendars are received only after being started by [crate::comeback::claim_comeback].
+pub async fn claim_login_bonuses(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  settings: &DailyResetSettings,
+  user_id: UserId,
+  now: DateTime<Utc>,
+) -> anyhow::Result<ClaimedLoginBonuses> {
+  let today = login_day(now, settings);
+  let received_today = |received_at: DateTime<Utc>| login_day(received_at, settings) == today;
+
+  let states = FetchUserLoginBonuses::new(transaction).await?.run(user_id).await?;
+  let update = UpdateUserLoginBonus::new(transaction).await?;
+  let send_present = SendUserPresent::new(transaction).await?;
+  let mut calendars = Vec::new();
+  for bonus in get_login_bonuses()
+    .iter()
+    .filter(|bonus| bonus.is_open(now.naive_utc()))
+  {
+    let state = states.get(&bonus.loginbonus_id);
+    if bonus.kind == LoginBonusKind::Comeback && state.is_none() {
+      continue;
+    }
+    if state.is_some_and(|state| received_today(state.received_at)) {
+      continue;
+    }
+    let last_day = state.map_or(0, |state| state.day_count);
+    let Some(day) = bonus.next_day(last_day) else {
+      continue;
+    };
+
+    if !update.run(user_id, bonus.loginbonus_id, last_day, day).await? {
+      warn!(?bonus.loginbonus_id, ?day, "login bonus was received concurrently");
+      continue;
+    }
+    for item in bonus.items_for_day(day) {
+      send_present
+        .run(
+          user_id,
+          &NewPresent {
+            item_type: item.item.item_type,
+            item_id: item.item.item_id,
+            item_num: item.item.item_num,
+            message: item.message.clone(),
+            expires_at: Some(now + present_duration()),
+          },
+        )
+        .await?;
+    }
+    info!(?bonus.loginbonus_id, ?day, "received login bonus");
+    calendars.push(ClaimedLoginBonus {
+      loginbonus_id: bonus.loginbonus_id,
+      day,
+      items: bonus.items_for_day(day).map(|item| item.item.clone()).collect(),
+    });
+  }
+
+  let fetch_results = FetchUserLoginBonusResults::new(transaction).await?;
+  let add_result = AddUserLoginBonusResult::new(transaction).await?;
+  // Thread RNG can not be held across awaits
+  let mut rng = StdRng::from_rng(&mut rand::rng());
+
+  let mut omikuji = None;
+  if let Some(prototype) = get_active_omikuji(now.naive_utc()) {
+    let results = fetch_results
+      .run(user_id, LoginBonusResultKind::Omikuji, prototype.omikuji_id)
+      .await?;
+    let fortunes = get_omikuji_fortunes(prototype.lot_id);
+    if !results.iter().any(|result| received_today(result.received_at))
+      && let Ok((fortune, _)) = fortunes.choose_weighted(&mut rng, |(_, weight)| *weight)
+      && add_result
+        .run(
+          user_id,
+          LoginBonusResultKind::Omikuji,
+          prototype.omikuji_id,
+          results.len() as i32 + 1,
+          fortune.fortune_id,
+        )
+        .await?
+        .is_some()
+    {
+      send_pack(&send_present, user_id, fortune.pack_id, OMIKUJI_PRESENT_MESSAGE, now).await?;
+      info!(?prototype.omikuji_id, ?fortune.fortune_id, "received omikuji");
+      omikuji = Some((prototype.omikuji_id, fortune.fortune_id));
+    }
+  }
+
+  let mut random = None;
+  if let Some(prototype) = get_active_random_login_bonus(now.naive_utc()) {
+    let mut results = fetch_results
+      .run(user_id, LoginBonusResultKind::Random, prototype.random_loginbonus_id)
+      .await?;
+    let day = results.len() as i32 + 1;
+    if day <= prototype.days
+      && !results.iter().any(|result| received_today(result.received_at))
+      && let Some(pattern) = prototype.get_patterns(day).choose(&mut rng)
+      && let Some(result) = add_result
+        .run(
+          user_id,
+          LoginBonusResultKind::Random,
+          prototype.random_loginbonus_id,
+          day,
+          pattern.result_pattern_id,
+        )
+        .await?
+    {
+      send_pack(
+        &send_present,
+        user_id,
+        pattern.pack_id,
+        RANDOM_LOGIN_BONUS_PRESENT_MESSAGE,
+        now,
+      )
+      .await?;
+      info!(?prototype.random_loginbonus_id, ?day, ?pattern.result_pattern_id, "received random login bonus");
+      results.push(result);
+      random = Some(ClaimedLoginBonusResults {
+        prototype,
+        result_id: pattern.result_pattern_id,
+        days: results,
+      });
+    }
+  }
+
+  let mut roulette = None;
+  if let Some(prototype) = get_active_roulette_login_bonus(now.naive_utc()) {
+    let mut results = fetch_results
+      .run(
+        user_id,
+        LoginBonusResultKind::Roulette,
+        prototype.roulette_loginbonus_id,
+      )
+      .await?;
+    let day = results.len() as i32 + 1;
+    if day <= prototype.days
+      && !results.iter().any(|result| received_today(result.received_at))
+      && let Some((gacha_times, result_id)) = prototype.spin(day, &mut rng)
+      && let Some(result) = add_result
+        .run(
+          user_id,
+          LoginBonusResultKind::Roulette,
+          prototype.roulette_loginbonus_id,
+          day,
+          result_id,
+        )
+        .await?
+    {
+      // Free gacha pulls are not tracked by the server, see [crate::api::gacha]
+      warn!(?prototype.gacha_id, ?gacha_times, "roulette login bonus gacha pulls are not granted");
+      results.push(result);
+      roulette = Some(ClaimedLoginBonusResults {
+        prototype,
+        result_id,
+        days: results,
+      });
+    }
+  }
+
+  Ok(ClaimedLoginBonuses {
+    calendars,
+    omikuji,
+    random,
+    roulette,
+  })
+}
+
+/// Sends contents of [pack_id] to the present box, packs with unknown contents are skipped.
+async fn send_pack(
+  send_present: &SendUserPresent<'_>,
+  user_id: UserId,
+  pack_id: i64,
+  message: &str,
+  now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+  let items = get_pack_items(pack_id);
+  if items.is_empty() {
+    warn!(?pack_id, "login bonus reward pack contents are unknown");
+  }
+  for item in items {
+    send_present
+      .run(
+        user_id,
+        &NewPresent {
+          item_type: item.item_type,
+          item_id: item.item_id,
+          item_num: item.item_num,
+          message: message.to_owned(),
+          expires_at: Some(now + present_duration()),
+        },
+      )
+      .await?;
+  }
+  Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct UserLoginBonus {
+  pub day_count: i32,
+  pub received_at: DateTime<Utc>,
+}
+
+pub struct FetchUserLoginBonuses<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserLoginBonuses<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select loginbonus_id, day_count, received_at
+        from user_login_bonuses
+        where user_id = $1
+        for update
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<HashMap<i64, UserLoginBonus>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| {
+          let state = UserLoginBonus {
+            day_count: row.get("day_count"),
+            received_at: row.get("received_at"),
+          };
+          (row.get("loginbonus_id"), state)
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Moves a calendar from [last_day] to [day], returns `false` if it is no longer at [last_day].
+pub struct UpdateUserLoginBonus<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> UpdateUserLoginBonus<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_login_bonuses (user_id, loginbonus_id, day_count)
+        values ($1, $2, $3)
+        on conflict (user_id, loginbonus_id)
+          do update
+          set day_count = excluded.day_count,
+              received_at = now()
+          where user_login_bonuses.day_count = $4
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, loginbonus_id: i64, last_day: i32, day: i32) -> anyhow::Result<bool> {
+    let rows = self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &loginbonus_id, &day, &last_day])
+      .await?;
+    Ok(rows > 0)
+  }
+}
+
+/// Starts a calendar over, as if no days were received since [received_at].
+pub struct ResetUserLoginBonus<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ResetUserLoginBonus<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_login_bonuses (user_id, loginbonus_id, day_count, received_at)
+        values ($1, $2, 0, $3)
+        on conflict (user_id, loginbonus_id)
+          do update
+          set day_count = 0,
+              received_at = excluded.received_at
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, loginbonus_id: i64, received_at: DateTime<Utc>) -> anyhow::Result<()> {
+    self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &loginbonus_id, &received_at])
+      .await?;
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginBonusResult {
+  pub day: i32,
+  pub result_id: i64,
+  pub received_at: DateTime<Utc>,
+}
+
+impl LoginBonusResult {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      day: row.get("day"),
+      result_id: row.get("result_id"),
+      received_at: row.get("received_at"),
+    }
+  }
+}
+
+/// Returns results of all days so far, by day.
+pub struct FetchUserLoginBonusResults<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserLoginBonusResults<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select day, result_id, received_at
+        from user_login_bonus_results
+        where user_id = $1 and kind = $2 and bonus_id = $3
+        order by day
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    kind: LoginBonusResultKind,
+    bonus_id: i64,
+  ) -> anyhow::Result<Vec<LoginBonusResult>> {
+    let rows = self
+      .executor
+      .client()
+      .query(&self.statement, &[&user_id, &(kind as i32), &bonus_id])
+      .await?;
+    Ok(rows.iter().map(LoginBonusResult::from_row).collect())
+  }
+}
+
+/// Stores the result of [day], returns `None` if it was already rolled concurrently.
+pub struct AddUserLoginBonusResult<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserLoginBonusResult<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_login_bonus_results (user_id, kind, bonus_id, day, result_id)
+        values ($1, $2, $3, $4, $5)
+        on conflict (user_id, kind, bonus_id, day) do nothing
+        returning day, result_id, received_at
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    kind: LoginBonusResultKind,
+    bonus_id: i64,
+    day: i32,
+    result_id: i64,
+  ) -> anyhow::Result<Option<LoginBonusResult>> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(
+        &self.statement,
+        &[&user_id, &(kind as i32), &bonus_id, &day, &result_id],
+      )
+      .await?;
+    Ok(row.as_ref().map(LoginBonusResult::from_row))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  fn calendar(kind: LoginBonusKind, days: i32) -> LoginBonusPrototype {
+    LoginBonusPrototype {
+      loginbonus_id: 1,
+      kind,
+      start_at: None,
+      end_at: None,
+      accept_limit: None,
+      items: (1..=days)
+        .map(|day| LoginBonusItem {
+          day,
+          item: QuestRewardItem {
+            item_type: 3,
+            item_id: 1,
+            item_num: 100,
+            item_rare: false,
+            probability: None,
+          },
+          message: String::new(),
+        })
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn test_next_day() {
+    let normal = calendar(LoginBonusKind::Normal, 7);
+    let event = calendar(LoginBonusKind::Event, 7);
+
+    assert_eq!(normal.next_day(0), Some(1));
+    assert_eq!(normal.next_day(7), Some(1));
+    assert_eq!(event.next_day(6), Some(7));
+    assert_eq!(event.next_day(7), None);
+  }
+
+  #[test]
+  fn test_parse_win_days() {
+    assert_eq!(parse_win_days("7_14"), vec![7, 14]);
+    assert_eq!(parse_win_days("1_14"), vec![1, 14]);
+    assert_eq!(parse_win_days("0"), Vec::<i32>::new());
+  }
+
+  #[test]
+  fn test_login_day_reset_hour() {
+    let before = Utc.with_ymd_and_hms(2024, 1, 2, 3, 59, 0).unwrap();
+    let after = Utc.with_ymd_and_hms(2024, 1, 2, 4, 0, 0).unwrap();
+    let settings = DailyResetSettings { hour: 4 };
+
+    assert_eq!(
+      login_day(before, &settings),
+      NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+    );
+    assert_eq!(
+      login_day(after, &settings),
+      NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()
+    );
+    assert_eq!(
+      login_day(before, &DailyResetSettings::default()),
+      NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()
+    );
+  }
+}
@@ -0,0 +1,96 @@
+//! Affinity gifts (`MaterialLove` items) from `love_item` master.
+//!
+//! A gift adds `exp` affinity, or `exp_special` to characters it is preferred by. Gifts dedicated to a
+//! character can not be given to anyone else.
+
+use std::sync::OnceLock;
+
+
+use crate::api::master_all::{get_master_manager, parse_i64};
+
+#[derive(Debug, Clone)]
+pub struct LoveItemPrototype {
+  pub item_id: i64,
+  pub exp: i32,
+  pub exp_special: i32,
+  /// Characters that prefer the gift and receive [exp_special] instead
+  pub special_character_ids: Vec<i64>,
+  /// Only character the gift can be given to, if any
+  pub dedicated_character_id: Option<i64>,
+}
+
+impl LoveItemPrototype {
+  /// Affinity added to [character_id] by a single gift, or [None] if it is dedicated to someone else.
+  pub fn exp_for(&self, character_id: i64) -> Option<i32> {
+    if self
+      .dedicated_character_id
+      .is_some_and(|dedicated| dedicated != character_id)
+    {
+      return None;
+    }
+
+    if self.special_character_ids.contains(&character_id) {
+      Some(self.exp_special)
+    } else {
+      Some(self.exp)
+    }
+  }
+}
+
+/// Returns all gifts, parsed once.
+pub fn get_love_items() -> &'static [LoveItemPrototype] {
+  static LOVE_ITEMS: OnceLock<Vec<LoveItemPrototype>> = OnceLock::new();
+
+  LOVE_ITEMS.get_or_init(|| {
+    get_master_manager()
+      .get_master("love_item")
+      .iter()
+      .map(|item| LoveItemPrototype {
+        item_id: parse_i64(&item["id"]),
+        exp: parse_i64(&item["exp"]) as i32,
+        exp_special: parse_i64(&item["exp_special"]) as i32,
+        // e.g. "147_150"
+        special_character_ids: item["special_chara_id"]
+          .as_str()
+          .unwrap()
+          .split('_')
+          .map(|id| id.parse::<i64>().unwrap())
+          .filter(|id| *id != 0)
+          .collect(),
+        dedicated_character_id: Some(parse_i64(&item["dedicated_chara_id"])).filter(|id| *id != 0),
+      })
+      .collect()
+  })
+}
+
+pub fn get_love_item(item_id: i64) -> Option<&'static LoveItemPrototype> {
+  get_love_items().iter().find(|item| item.item_id == item_id)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_exp_for() {
+    let item = LoveItemPrototype {
+      item_id: 20111,
+      exp: 20,
+      exp_special: 100,
+      special_character_ids: vec![147, 150],
+      dedicated_character_id: None,
+    };
+    assert_eq!(item.exp_for(100), Some(20));
+    assert_eq!(item.exp_for(150), Some(100));
+
+    let dedicated = LoveItemPrototype {
+      item_id: 1001,
+      exp: 100,
+      exp_special: 0,
+      special_character_ids: vec![],
+      dedicated_character_id: Some(100),
+    };
+    assert_eq!(dedicated.exp_for(100), Some(100));
+    assert_eq!(dedicated.exp_for(101), None);
+  }
+}
@@ -1,29 +1,47 @@
 #![allow(non_snake_case, unused_variables)]
 
+pub mod ad_reward;
 pub mod api;
 pub mod api_server;
+pub mod assist;
 pub mod blob;
 pub mod bool_as_int;
 pub mod build_info;
 pub mod call;
+pub mod character_enhance;
 pub mod client_ip;
+pub mod comeback;
 pub mod database;
+pub mod equipment;
+pub mod event;
+pub mod exchange;
 pub mod extractor;
 pub mod handler;
 pub mod impl_handler;
+pub mod inventory;
 pub mod item;
 pub mod level;
+pub mod limit_break;
+pub mod login_bonus;
+pub mod love_item;
 pub mod master;
 pub mod member;
 pub mod migrations;
+pub mod mission;
 pub mod normalize_path;
 pub mod notification;
 pub mod params_deserializer;
+pub mod piece_board;
+pub mod present;
+pub mod purchase;
 pub mod request_logging;
 pub mod router;
 pub mod serde_compat;
 pub mod settings;
+pub mod shop;
+pub mod special_skill;
 pub mod static_server;
+pub mod story;
 pub mod string_as_base64;
 pub mod user;
 
@@ -38,6 +56,7 @@ use std::sync::{Arc, Mutex};
 use crate::api::master_all::{MASTER_MANAGER, MasterManager, get_masters};
 use crate::api::{RemoteDataCommand, RemoteDataItemType};
 use crate::database::create_pool;
+use crate::purchase::{PurchaseProvider, create_purchase_provider};
 use crate::settings::Settings;
 use crate::user::id::UserId;
 use crate::user::session::Session;
@@ -60,6 +79,7 @@ pub struct AppState {
   pub settings: Settings,
   pub sessions: Mutex<HashMap<UserId, Arc<Session>>>,
   pub pool: deadpool_postgres::Pool,
+  pub purchase_provider: Box<dyn PurchaseProvider>,
 }
 
 pub struct AppPoolError(PoolError);
@@ -163,11 +183,13 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     result[0].get::<_, i64>(0)
   );
 
+  let purchase_provider = create_purchase_provider(&settings.purchase);
   let state = AppState {
     args,
     settings,
     sessions: Mutex::new(HashMap::new()),
     pool,
+    purchase_provider,
   };
   let state = Arc::new(state);
 
@@ -1,14 +1,18 @@
 use crate::api::battle::BattleMember;
 use crate::api::dungeon::{DungeonBattleMember, PartyMember};
+use crate::api::interaction::parse_date;
 use crate::api::master_all::get_master_manager;
 use crate::api::party_info::{Party, PartyForm, PartyPassiveSkillInfo, SpecialSkillInfo};
 use crate::api::{MemberFameStats, MemberParameterWire, SkillPaFame};
+use crate::character_enhance::{self, FetchUserEnhanceStages};
 use crate::database::QueryExecutor;
 use crate::level::get_member_level_calculator;
+use crate::piece_board::{get_stats_bonus, FetchUserPieceBoards};
 use crate::user::id::UserId;
+use chrono::{NaiveDateTime, Utc};
 use itertools::Itertools;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use tokio_postgres::{Row, Statement};
 use tracing::warn;
 
@@ -49,6 +53,7 @@ impl MemberPrototype {
         .unwrap();
       ActiveSkillPrototype {
         id: skill_id,
+        attribute: skill_detail["attribute"].as_str().unwrap().to_owned(),
         value: MinMaxRange {
           min: skill_detail["value_min"].as_str().unwrap().parse::<i32>().unwrap(),
           max: skill_detail["value_max"].as_str().unwrap().parse::<i32>().unwrap(),
@@ -152,11 +157,10 @@ impl MemberPrototype {
           .unwrap(),
       ),
       stats: self.stats.clone(),
-      main_strength: MemberStrength::default(),
-      sub_strength: MemberStrength::default(),
-      sub_strength_bonus: MemberStrength::default(),
       fame_stats: MemberFameStats::default(),
       skill_pa_fame_list: vec![],
+      bonus_stats: MemberStats::default(),
+      piece_board_stage_ids: vec![],
     }
   }
 
@@ -168,11 +172,10 @@ impl MemberPrototype {
       promotion_level: 0,
       active_skills: OptionallyFetched::Fetched([None, None, None]),
       stats: self.stats.clone(),
-      main_strength: MemberStrength::default(),
-      sub_strength: MemberStrength::default(),
-      sub_strength_bonus: MemberStrength::default(),
       fame_stats: MemberFameStats::default(),
       skill_pa_fame_list: vec![],
+      bonus_stats: MemberStats::default(),
+      piece_board_stage_ids: vec![],
     }
   }
 }
@@ -201,11 +204,12 @@ pub struct Member {
   pub promotion_level: i32,
   pub active_skills: OptionallyFetched<[Option<MemberActiveSkill>; 3]>,
   pub stats: MemberStatsPrototype,
-  pub main_strength: MemberStrength,
-  pub sub_strength: MemberStrength,
-  pub sub_strength_bonus: MemberStrength,
   pub fame_stats: MemberFameStats,
   pub skill_pa_fame_list: Vec<SkillPaFame>,
+  /// Bonuses shared by all members of the character, see [apply_character_bonuses]
+  pub bonus_stats: MemberStats,
+  /// Last released stage of each Potential board of the character
+  pub piece_board_stage_ids: Vec<i32>,
 }
 
 impl Member {
@@ -220,11 +224,65 @@ impl Member {
     get_member_level_calculator().get_level(self.xp, self.prototype.rarity, self.promotion_level)
   }
 
+  /// Stats at the current level, with bonuses.
+  pub fn current_stats(&self) -> MemberStats {
+    let level = self.level();
+    let stats = MemberStats {
+      hp: self.stats.hp.interpolate(level),
+      attack: self.stats.attack.interpolate(level),
+      magicattack: self.stats.attack_magic.interpolate(level),
+      defense: self.stats.defense.interpolate(level),
+      magicdefence: self.stats.defense_magic.interpolate(level),
+      agility: self.stats.agility.interpolate(level),
+      dexterity: self.stats.dexterity.interpolate(level),
+      luck: self.stats.luck.interpolate(level),
+    };
+    stats + self.bonus_stats
+  }
+
+  /// Strength as a main member of a party form.
+  pub fn main_strength(&self) -> MemberStrength {
+    self.strength(MemberStats::main_strength)
+  }
+
+  /// Strength added to the main member as a sub-member.
+  pub fn sub_strength(&self) -> MemberStrength {
+    self.strength(MemberStats::sub_strength)
+  }
+
+  /// Strength added to a main member of the same character as a sub-member.
+  pub fn sub_strength_bonus(&self) -> MemberStrength {
+    self.strength(MemberStats::sub_strength_bonus)
+  }
+
+  /// Fame ranks raise stats in Fame Quests, only a part of that is reflected outside of them.
+  fn strength(&self, strength: fn(&MemberStats) -> i32) -> MemberStrength {
+    let stats = self.current_stats();
+    let fame = MemberStats {
+      hp: self.fame_stats.fame_hp,
+      attack: self.fame_stats.fame_attack,
+      magicattack: self.fame_stats.fame_magicattack,
+      defense: self.fame_stats.fame_defense,
+      magicdefence: self.fame_stats.fame_magicdefence,
+      ..Default::default()
+    };
+    let reflected = get_fame_reflected_percent(Utc::now().naive_utc());
+
+    MemberStrength {
+      strength: strength(&(stats + fame.percent(reflected))),
+      for_fame_quest: strength(&(stats + fame)),
+    }
+  }
+
   pub fn to_member_parameter_wire(&self) -> MemberParameterWire {
     let skills = match &self.active_skills {
       OptionallyFetched::Fetched(skills) => skills,
       OptionallyFetched::Unfetched => panic!("active skills not fetched for member {}", self.id),
     };
+    let stats = self.current_stats();
+    let main_strength = self.main_strength();
+    let sub_strength = self.sub_strength();
+    let sub_strength_bonus = self.sub_strength_bonus();
 
     MemberParameterWire {
       id: self.id,
@@ -240,27 +298,27 @@ impl Member {
       ac_skill_id_c: skills[2].as_ref().map_or(0, |skill| skill.prototype.id),
       ac_skill_lv_c: skills[2].as_ref().map_or(0, |skill| skill.level),
       ac_skill_val_c: skills[2].as_ref().map_or(0, |skill| skill.value),
-      hp: self.stats.hp.interpolate(self.level()),
-      magicattack: self.stats.attack_magic.interpolate(self.level()),
-      defense: self.stats.defense.interpolate(self.level()),
-      magicdefence: self.stats.defense_magic.interpolate(self.level()),
-      agility: self.stats.agility.interpolate(self.level()),
-      dexterity: self.stats.dexterity.interpolate(self.level()),
-      luck: self.stats.luck.interpolate(self.level()),
+      hp: stats.hp,
+      magicattack: stats.magicattack,
+      defense: stats.defense,
+      magicdefence: stats.magicdefence,
+      agility: stats.agility,
+      dexterity: stats.dexterity,
+      luck: stats.luck,
       limit_break: self.promotion_level,
       character_id: self.prototype.character_id,
       passiveskill: self.prototype.passive_skill.as_ref().map_or(0, |skill| skill.id),
       specialattack: self.prototype.special_attack.as_ref().map_or(0, |skill| skill.id),
       resist_state: self.prototype.resistance_group.id,
       resist_attr: 0,
-      attack: self.stats.attack.interpolate(self.level()),
+      attack: stats.attack,
       waiting_room: 0,
-      main_strength: self.main_strength.strength,
-      main_strength_for_fame_quest: self.main_strength.for_fame_quest,
-      sub_strength: self.sub_strength.strength,
-      sub_strength_for_fame_quest: self.sub_strength.for_fame_quest,
-      sub_strength_bonus: self.sub_strength_bonus.strength,
-      sub_strength_bonus_for_fame_quest: self.sub_strength_bonus.for_fame_quest,
+      main_strength: main_strength.strength,
+      main_strength_for_fame_quest: main_strength.for_fame_quest,
+      sub_strength: sub_strength.strength,
+      sub_strength_for_fame_quest: sub_strength.for_fame_quest,
+      sub_strength_bonus: sub_strength_bonus.strength,
+      sub_strength_bonus_for_fame_quest: sub_strength_bonus.for_fame_quest,
       fame_hp_rank: self.fame_stats.fame_hp,
       fame_attack_rank: self.fame_stats.fame_attack,
       fame_defense_rank: self.fame_stats.fame_defense,
@@ -275,6 +333,7 @@ impl Member {
       OptionallyFetched::Fetched(skills) => skills,
       OptionallyFetched::Unfetched => panic!("active skills not fetched for member {}", self.id),
     };
+    let stats = self.current_stats();
 
     PartyMember {
       id: self.id,
@@ -287,14 +346,14 @@ impl Member {
       ac_skill_val_b: skills[1].as_ref().map_or(0, |skill| skill.value as i64),
       ac_skill_lv_c: skills[2].as_ref().map_or(0, |skill| skill.level),
       ac_skill_val_c: skills[2].as_ref().map_or(0, |skill| skill.value as i64),
-      hp: self.stats.hp.interpolate(self.level()),
-      attack: self.stats.attack.interpolate(self.level()),
-      magicattack: self.stats.attack_magic.interpolate(self.level()),
-      defense: self.stats.defense.interpolate(self.level()),
-      magicdefence: self.stats.defense_magic.interpolate(self.level()),
-      agility: self.stats.agility.interpolate(self.level()),
-      dexterity: self.stats.dexterity.interpolate(self.level()),
-      luck: self.stats.luck.interpolate(self.level()),
+      hp: stats.hp,
+      attack: stats.attack,
+      magicattack: stats.magicattack,
+      defense: stats.defense,
+      magicdefence: stats.magicdefence,
+      agility: stats.agility,
+      dexterity: stats.dexterity,
+      luck: stats.luck,
       limit_break: self.promotion_level,
       character_id: self.prototype.character_id,
       waiting_room: 0,
@@ -308,6 +367,7 @@ impl Member {
       OptionallyFetched::Fetched(skills) => skills,
       OptionallyFetched::Unfetched => panic!("active skills not fetched for member {}", self.id),
     };
+    let stats = self.current_stats();
 
     BattleMember {
       id: self.id,
@@ -323,20 +383,20 @@ impl Member {
       ac_skill_id_c: skills[2].as_ref().map_or(0, |skill| skill.prototype.id),
       ac_skill_lv_c: skills[2].as_ref().map_or(0, |skill| skill.level),
       ac_skill_val_c: skills[2].as_ref().map_or(0, |skill| skill.value),
-      hp: self.stats.hp.interpolate(self.level()),
-      magicattack: self.stats.attack_magic.interpolate(self.level()),
-      defense: self.stats.defense.interpolate(self.level()),
-      magicdefence: self.stats.defense_magic.interpolate(self.level()),
-      agility: self.stats.agility.interpolate(self.level()),
-      dexterity: self.stats.dexterity.interpolate(self.level()),
-      luck: self.stats.luck.interpolate(self.level()),
+      hp: stats.hp,
+      magicattack: stats.magicattack,
+      defense: stats.defense,
+      magicdefence: stats.magicdefence,
+      agility: stats.agility,
+      dexterity: stats.dexterity,
+      luck: stats.luck,
       limit_break: self.promotion_level,
       character_id: self.prototype.character_id,
       passiveskill: 210201, // self.prototype.passive_skill.as_ref().map_or(0, |skill| skill.id),
       specialattack: form.specialskill.special_skill_id as i64, // self.prototype.special_attack.as_ref().map_or(0, |skill| skill.id),
       resist_state: 210201,                                     // self.prototype.resistance_group.id,
       resist_attr: 150000000,
-      attack: self.stats.attack.interpolate(self.level()),
+      attack: stats.attack,
       ex_flg: 0,
       is_undead: 0,
       special_skill_lv: 1,
@@ -348,6 +408,7 @@ impl Member {
       OptionallyFetched::Fetched(skills) => skills,
       OptionallyFetched::Unfetched => panic!("active skills not fetched for member {}", self.id),
     };
+    let stats = self.current_stats();
 
     DungeonBattleMember {
       id: self.id,
@@ -363,24 +424,24 @@ impl Member {
       ac_skill_id_c: skills[2].as_ref().map_or(0, |skill| skill.prototype.id),
       ac_skill_lv_c: skills[2].as_ref().map_or(0, |skill| skill.level),
       ac_skill_val_c: skills[2].as_ref().map_or(0, |skill| skill.value),
-      hp: self.stats.hp.interpolate(self.level()),
-      magicattack: self.stats.attack_magic.interpolate(self.level()),
-      defense: self.stats.defense.interpolate(self.level()),
-      magicdefence: self.stats.defense_magic.interpolate(self.level()),
-      agility: self.stats.agility.interpolate(self.level()),
-      dexterity: self.stats.dexterity.interpolate(self.level()),
-      luck: self.stats.luck.interpolate(self.level()),
+      hp: stats.hp,
+      magicattack: stats.magicattack,
+      defense: stats.defense,
+      magicdefence: stats.magicdefence,
+      agility: stats.agility,
+      dexterity: stats.dexterity,
+      luck: stats.luck,
       limit_break: self.promotion_level,
       character_id: self.prototype.character_id,
       passiveskill: 210201,  // self.prototype.passive_skill.as_ref().map_or(0, |skill| skill.id),
       specialattack: 100001, // self.prototype.special_attack.as_ref().map_or(0, |skill| skill.id),
       resist_state: 210201,  // self.prototype.resistance_group.id,
       resist_attr: 150000000,
-      attack: self.stats.attack.interpolate(self.level()),
+      attack: stats.attack,
       ex_flg: 0,
       is_undead: 0,
       special_skill_lv: 1,
-      character_piece_board_stage_id_list: vec![100001001, 100002002, 100003003, 100004004],
+      character_piece_board_stage_id_list: self.piece_board_stage_ids.clone(),
     }
   }
 }
@@ -391,6 +452,121 @@ pub struct MemberStrength {
   pub for_fame_quest: i32,
 }
 
+impl MemberStrength {
+  pub fn get(&self, is_fame_quest: bool) -> i32 {
+    if is_fame_quest {
+      self.for_fame_quest
+    } else {
+      self.strength
+    }
+  }
+}
+
+/// Percentage of Fame stats that is applied outside of Fame Quests, from `fame_status_reflected_value` master.
+pub fn get_fame_reflected_percent(now: NaiveDateTime) -> i32 {
+  type Period = (Option<NaiveDateTime>, Option<NaiveDateTime>, i32);
+  static PERIODS: OnceLock<Vec<Period>> = OnceLock::new();
+
+  let periods = PERIODS.get_or_init(|| {
+    get_master_manager()
+      .get_master("fame_status_reflected_value")
+      .iter()
+      .map(|period| {
+        (
+          parse_date(period["start_at"].as_str().unwrap()),
+          parse_date(period["end_at"].as_str().unwrap()),
+          period["reflected_value"].as_str().unwrap().parse::<i32>().unwrap(),
+        )
+      })
+      .collect()
+  });
+  periods
+    .iter()
+    .find(|(start, end, _)| start.is_none_or(|start| now >= start) && end.is_none_or(|end| now < end))
+    .map_or(0, |(_, _, percent)| *percent)
+}
+
+/// Flat member parameters, or a bonus added to them.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct MemberStats {
+  pub hp: i32,
+  pub attack: i32,
+  pub magicattack: i32,
+  pub defense: i32,
+  pub magicdefence: i32,
+  pub agility: i32,
+  pub dexterity: i32,
+  pub luck: i32,
+}
+
+impl std::ops::Add for MemberStats {
+  type Output = Self;
+
+  fn add(self, other: Self) -> Self {
+    Self {
+      hp: self.hp + other.hp,
+      attack: self.attack + other.attack,
+      magicattack: self.magicattack + other.magicattack,
+      defense: self.defense + other.defense,
+      magicdefence: self.magicdefence + other.magicdefence,
+      agility: self.agility + other.agility,
+      dexterity: self.dexterity + other.dexterity,
+      luck: self.luck + other.luck,
+    }
+  }
+}
+
+// See [Wonder.Data.MemberParameter$$GetStrength]
+// Fitted to strengths sent by the official server: agility, dexterity and luck do not count,
+// main members get a flat 90 on top.
+impl MemberStats {
+  /// Strength of these stats, in hundredths.
+  fn strength_hundredths(&self) -> i64 {
+    35 * self.hp as i64
+      + 300 * (self.attack + self.magicattack) as i64
+      + 150 * (self.defense + self.magicdefence) as i64
+  }
+
+  /// Strength of equipment or any other stat bonus.
+  pub fn strength(&self) -> i32 {
+    (self.strength_hundredths() / 100) as i32
+  }
+
+  pub fn main_strength(&self) -> i32 {
+    self.strength() + 90
+  }
+
+  /// 30% of the sub-member strength, rounded down, is applied to the main member.
+  pub fn sub_strength(&self) -> i32 {
+    self.strength() * 30 / 100
+  }
+
+  /// 40% with the bonus for the same character, also taken from the rounded down strength.
+  pub fn sub_strength_bonus(&self) -> i32 {
+    self.strength() * 40 / 100
+  }
+
+  /// These stats scaled to [percent], rounded down.
+  pub fn percent(self, percent: i32) -> Self {
+    Self {
+      hp: self.hp * percent / 100,
+      attack: self.attack * percent / 100,
+      magicattack: self.magicattack * percent / 100,
+      defense: self.defense * percent / 100,
+      magicdefence: self.magicdefence * percent / 100,
+      agility: self.agility * percent / 100,
+      dexterity: self.dexterity * percent / 100,
+      luck: self.luck * percent / 100,
+    }
+  }
+}
+
+impl std::iter::Sum for MemberStats {
+  fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+    iter.fold(Self::default(), |sum, stats| sum + stats)
+  }
+}
+
 #[derive(Debug)]
 pub struct MemberActiveSkill {
   pub prototype: Arc<ActiveSkillPrototype>,
@@ -413,6 +589,8 @@ pub struct MemberStatsPrototype {
 #[derive(Debug, Clone)]
 pub struct ActiveSkillPrototype {
   pub id: i64,
+  /// Element, e.g. `fire`, `0` if none
+  pub attribute: String,
   pub value: MinMaxRange,
 }
 
@@ -513,6 +691,62 @@ impl<'a> FetchUserMembersIn<'a> {
   }
 }
 
+/// Deletes up to [count] reserve duplicates of a member, returns IDs of the deleted ones.
+pub struct ConsumeUserReserveMembers<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ConsumeUserReserveMembers<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        delete from user_members_reserve
+        where id in (
+          select id
+          from user_members_reserve
+          where user_id = $1 and member_id = $2
+          order by id
+          limit $3
+          for update
+        )
+        returning id
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, member_id: i64, count: i64) -> anyhow::Result<Vec<i64>> {
+    let rows = self
+      .executor
+      .client()
+      .query(&self.statement, &[&user_id, &member_id, &count])
+      .await?;
+    Ok(rows.iter().map(|row| row.get("id")).collect())
+  }
+}
+
+/// Fills bonuses shared by all members of a character: stats and stages of Potential boards
+/// and Trial of the Ancients.
+pub async fn apply_character_bonuses<'a>(
+  executor: impl Into<QueryExecutor<'a>> + Copy,
+  user_id: UserId,
+  members: &mut [&mut Member],
+) -> anyhow::Result<()> {
+  let boards = FetchUserPieceBoards::new(executor).await?.run(user_id).await?;
+  let enhance_stages = FetchUserEnhanceStages::new(executor).await?.run(user_id).await?;
+  for member in members.iter_mut() {
+    let released = boards.released(member.prototype.character_id);
+    let released_enhance = enhance_stages.released(member.prototype.character_id);
+    member.bonus_stats = get_stats_bonus(&released) + character_enhance::get_stats_bonus(&released_enhance);
+    member.piece_board_stage_ids = boards.current_stage_ids(member.prototype.character_id);
+  }
+
+  Ok(())
+}
+
 pub fn materialize_member_row(row: Row) -> Member {
   let member_id: i64 = row.get("member_id");
   let xp: i32 = row.get("xp");
@@ -535,11 +769,10 @@ pub fn materialize_member_row_impl(
     promotion_level,
     active_skills: OptionallyFetched::Unfetched,
     stats: prototype.stats.clone(),
-    main_strength: MemberStrength::default(),
-    sub_strength: MemberStrength::default(),
-    sub_strength_bonus: MemberStrength::default(),
     fame_stats: MemberFameStats::default(),
     skill_pa_fame_list: vec![],
+    bonus_stats: MemberStats::default(),
+    piece_board_stage_ids: vec![],
   }
 }
 
@@ -558,6 +791,7 @@ impl<'a> FetchUserParties<'a> {
           up.party_id,
           up.name,
           up.assist_id,
+          up.sub_assist_ids,
           up.trait_id,
           upf.form_id,
           upf.main_member_id,
@@ -597,6 +831,7 @@ impl<'a> FetchUserParty<'a> {
           up.party_id,
           up.name,
           up.assist_id,
+          up.sub_assist_ids,
           up.trait_id,
           upf.form_id,
           upf.main_member_id,
@@ -625,6 +860,78 @@ impl<'a> FetchUserParty<'a> {
   }
 }
 
+/// Members, equipment and special skill of a party form, as stored in `user_party_forms` table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PartyFormSlots {
+  pub form_id: i64,
+  pub main: i64,
+  pub sub1: i64,
+  pub sub2: i64,
+  pub weapon: i64,
+  pub accessory: i64,
+  pub special_skill_id: i64,
+}
+
+pub struct UpdateUserPartyForms<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> UpdateUserPartyForms<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      // TODO: I wonder if this is a good way to batch update multiple rows
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_party_forms
+        set main_member_id = form_data.main_member_id,
+            sub1_member_id = form_data.sub1_member_id,
+            sub2_member_id = form_data.sub2_member_id,
+            weapon_id = form_data.weapon_id,
+            accessory_id = form_data.accessory_id,
+            special_skill_id = form_data.special_skill_id
+        from (
+          select unnest($3::int8[]) as form_id,
+                 unnest($4::int8[]) as main_member_id,
+                 unnest($5::int8[]) as sub1_member_id,
+                 unnest($6::int8[]) as sub2_member_id,
+                 unnest($7::int8[]) as weapon_id,
+                 unnest($8::int8[]) as accessory_id,
+                 unnest($9::int8[]) as special_skill_id
+        ) as form_data
+        where user_party_forms.user_id = $1
+          and user_party_forms.party_id = $2
+          and user_party_forms.form_id = form_data.form_id
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, party_id: i64, forms: &[PartyFormSlots]) -> anyhow::Result<u64> {
+    Ok(
+      self
+        .executor
+        .client()
+        .execute(
+          &self.statement,
+          &[
+            &user_id,
+            &party_id,
+            &forms.iter().map(|form| form.form_id).collect::<Vec<_>>(),
+            &forms.iter().map(|form| form.main).collect::<Vec<_>>(),
+            &forms.iter().map(|form| form.sub1).collect::<Vec<_>>(),
+            &forms.iter().map(|form| form.sub2).collect::<Vec<_>>(),
+            &forms.iter().map(|form| form.weapon).collect::<Vec<_>>(),
+            &forms.iter().map(|form| form.accessory).collect::<Vec<_>>(),
+            &forms.iter().map(|form| form.special_skill_id).collect::<Vec<_>>(),
+          ],
+        )
+        .await?,
+    )
+  }
+}
+
 pub fn materialize_party_rows(rows: Vec<Row>) -> Vec<Party> {
   rows
     .into_iter()
@@ -638,6 +945,7 @@ pub fn materialize_party_rows(rows: Vec<Row>) -> Vec<Party> {
       // Hack because of JOIN, I guess
       let form_one = &forms[0];
       let assist_id: i64 = form_one.get("assist_id");
+      let sub_assist_ids: Vec<i64> = form_one.get("sub_assist_ids");
       let trait_id: i64 = form_one.get("trait_id");
 
       let forms = forms
@@ -662,7 +970,8 @@ pub fn materialize_party_rows(rows: Vec<Row>) -> Vec<Party> {
             weapon: weapon_id,
             acc: accessory_id,
             name: party_name,
-            strength: 12300,
+            // Filled by [PartyStrengthCalculator::apply]
+            strength: 0,
             specialskill: SpecialSkillInfo {
               special_skill_id: special_skill_id as i32,
               trial: false,
@@ -678,7 +987,7 @@ pub fn materialize_party_rows(rows: Vec<Row>) -> Vec<Party> {
         party_forms: forms,
         party_no: party_id,
         assist: assist_id,
-        sub_assists: vec![],
+        sub_assists: sub_assist_ids,
         party_passive_skill: PartyPassiveSkillInfo {
           skill_id: trait_id,
           user_member_id: 0,
@@ -744,3 +1053,60 @@ impl<'a> FetchUserMemberSkillsIn<'a> {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_strength() {
+    // Member 1001100 at level 4, as sent by the official server
+    let stats = MemberStats {
+      hp: 277,
+      attack: 32,
+      magicattack: 31,
+      defense: 24,
+      magicdefence: 22,
+      agility: 72,
+      dexterity: 78,
+      luck: 88,
+    };
+
+    assert_eq!(stats.main_strength(), 444);
+    assert_eq!(stats.sub_strength(), 106);
+    assert_eq!(stats.sub_strength_bonus(), 141);
+  }
+
+  #[test]
+  fn test_sub_strength_bonus_rounding() {
+    // Member 1011100 at level 4: 40% of the unrounded strength (387.75) would be 155
+    let stats = MemberStats {
+      hp: 285,
+      attack: 33,
+      magicattack: 37,
+      defense: 25,
+      magicdefence: 27,
+      agility: 66,
+      dexterity: 76,
+      luck: 10,
+    };
+
+    assert_eq!(stats.sub_strength(), 116);
+    assert_eq!(stats.sub_strength_bonus(), 154);
+
+    // Member 1093100 at level 1
+    let stats = MemberStats {
+      hp: 266,
+      attack: 30,
+      magicattack: 32,
+      defense: 22,
+      magicdefence: 24,
+      agility: 68,
+      dexterity: 67,
+      luck: 65,
+    };
+
+    assert_eq!(stats.sub_strength(), 104);
+    assert_eq!(stats.sub_strength_bonus(), 139);
+  }
+}
@@ -219,7 +219,12 @@ async fn add_user_items(session: &Session, client: &mut Client) -> u64 {
              v.item_id,
              v.quantity
       from (select item_type, item_id, sum(quantity) as quantity
-            from (values (18::bigint, 1::bigint, 30000::integer),
+            -- Eris and free quartz, paid quartz is only credited by purchases
+            from (values (1::bigint, 0::bigint, 85720::integer),
+                         (3::bigint, 0::bigint, 1000000::integer),
+                         (8::bigint, 1::bigint, 800::integer),
+                         (9::bigint, 0::bigint, 419::integer),
+                         (18::bigint, 1::bigint, 30000::integer),
                          (18::bigint, 2::bigint, 20000::integer),
                          (18::bigint, 3::bigint, 10000::integer)) as t(item_type, item_id, quantity)
             group by item_type, item_id) as v
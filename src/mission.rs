@@ -0,0 +1,621 @@
+//! Missions from `mission` and `event_mission` masters, progressed by game events.
+//!
+//! Handlers report what happened as [MissionEvent]s to [record_mission_events], every mission whose
+//! `rule` matches the event is progressed, and [MissionDone] notifications are returned for missions
+//! completed by it.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc};
+use serde_json::Value;
+use tokio_postgres::{Row, Statement};
+use tracing::{debug, info};
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::get_master_manager;
+use crate::api::mission::MissionKind;
+use crate::database::QueryExecutor;
+use crate::event::EventConfig;
+use crate::notification::MissionDone;
+use crate::settings::DailyResetSettings;
+use crate::user::id::UserId;
+use crate::user::quest_progress::QuestKind;
+
+/// Something that happened in the game that missions can count.
+#[derive(Debug, Clone)]
+pub enum MissionEvent {
+  /// Won battle or skipped runs of a stage, [event_id] is zero for non-event stages.
+  BattleClear {
+    kind: QuestKind,
+    event_id: i64,
+    quest_id: i64,
+    /// `mode` column of the stage master, zero if the stage has no difficulty
+    difficulty: i32,
+    count: i32,
+  },
+  /// Completed tasks ("crowns") over all stages of [kind], reported after they may have changed.
+  QuestTasks {
+    kind: QuestKind,
+    total: i32,
+  },
+  /// Won event single boss battle.
+  BossDefeat {
+    event_id: i64,
+    quest_id: i64,
+    is_score_challenge: bool,
+  },
+  GachaPull {
+    count: i32,
+  },
+  /// Member leveled up from [from] to [to].
+  GradeUp {
+    from: i32,
+    to: i32,
+  },
+  /// Current masters have no greeting missions, the event is still reported for future ones.
+  Greeting,
+  Login,
+}
+
+/// Condition parsed from `rule`, `rule_x`, `rule_y` and `rule_z` columns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MissionRule {
+  /// Clears of a single stage, e.g. `battle_count_normal_unique` or `event_battle_count_unique`
+  ClearStage {
+    kind: QuestKind,
+    quest_id: i64,
+  },
+  /// Clears of any stage, optionally only of a single difficulty
+  ClearStages {
+    kind: QuestKind,
+    difficulty: Option<i32>,
+  },
+  /// Completed tasks over all stages, `battle_star`
+  QuestTasks {
+    kind: QuestKind,
+  },
+  /// Event boss wins, optionally only of a single boss
+  DefeatBoss {
+    quest_id: Option<i64>,
+    is_score_challenge: bool,
+  },
+  GachaCount,
+  /// Members raised to at least [level]
+  MemberLevel {
+    level: i32,
+  },
+  /// Logins on different days
+  Login,
+  /// Login between [from] and [to] (inclusive, server time)
+  LoginTime {
+    from: NaiveTime,
+    to: NaiveTime,
+  },
+  /// Other completed missions of the same kind, `all_daily` and `all_beginner`
+  AllOfKind,
+  /// Rule that is not tracked by the server yet, never progresses
+  Unsupported,
+}
+
+impl MissionRule {
+  pub fn parse(rule: &str, x: &str, y: &str) -> Self {
+    let y_i64 = || y.parse::<i64>().unwrap_or(0);
+    match rule {
+      "battle_count_normal_unique" | "battle_count_hard_unique" | "battle_count_expert_unique" => Self::ClearStage {
+        kind: QuestKind::Main,
+        quest_id: y_i64(),
+      },
+      "event_battle_count_unique" => Self::ClearStage {
+        kind: QuestKind::Event,
+        quest_id: y_i64(),
+      },
+      "battle_count_main" => Self::ClearStages {
+        kind: QuestKind::Main,
+        difficulty: None,
+      },
+      "battle_count_main_hard" => Self::ClearStages {
+        kind: QuestKind::Main,
+        difficulty: Some(2),
+      },
+      "battle_count_hunt" => Self::ClearStages {
+        kind: QuestKind::Hunting,
+        difficulty: None,
+      },
+      "battle_star" => Self::QuestTasks { kind: QuestKind::Main },
+      "event_battle_boss_count" => Self::DefeatBoss {
+        quest_id: None,
+        is_score_challenge: false,
+      },
+      "event_battle_boss_count_unique" => Self::DefeatBoss {
+        quest_id: Some(y_i64()),
+        is_score_challenge: false,
+      },
+      "event_battle_scorechallenge_count" => Self::DefeatBoss {
+        quest_id: None,
+        is_score_challenge: true,
+      },
+      "gacha_count" => Self::GachaCount,
+      "member_lv" => Self::MemberLevel {
+        level: y.parse().unwrap_or(0),
+      },
+      "player_login" => Self::Login,
+      "time" => match (
+        NaiveTime::parse_from_str(x, "%H:%M"),
+        NaiveTime::parse_from_str(y, "%H:%M"),
+      ) {
+        (Ok(from), Ok(to)) => Self::LoginTime { from, to },
+        _ => Self::Unsupported,
+      },
+      "all_daily" | "all_beginner" => Self::AllOfKind,
+      // Player level is not tracked by the server (`player_lv`), battle results always report no level up
+      _ => Self::Unsupported,
+    }
+  }
+
+  /// Progress [event] sets on a mission with this rule, for rules that track a total instead of counting events.
+  pub fn total(&self, event: &MissionEvent) -> Option<i32> {
+    match (self, event) {
+      (
+        Self::QuestTasks { kind },
+        MissionEvent::QuestTasks {
+          kind: event_kind,
+          total,
+        },
+      ) if kind == event_kind => Some(*total),
+      _ => None,
+    }
+  }
+
+  /// Progress [event] adds to a mission of [event_id] with this rule.
+  pub fn progress(&self, event: &MissionEvent, mission_event_id: i64, now: NaiveDateTime) -> i32 {
+    match (self, event) {
+      (
+        Self::ClearStage { kind, quest_id },
+        MissionEvent::BattleClear {
+          kind: event_kind,
+          event_id,
+          quest_id: event_quest_id,
+          count,
+          ..
+        },
+      ) if kind == event_kind && quest_id == event_quest_id && *event_id == mission_event_id => *count,
+      (
+        Self::ClearStages { kind, difficulty },
+        MissionEvent::BattleClear {
+          kind: event_kind,
+          event_id,
+          difficulty: event_difficulty,
+          count,
+          ..
+        },
+      ) if kind == event_kind
+        && difficulty.is_none_or(|difficulty| difficulty == *event_difficulty)
+        && *event_id == mission_event_id =>
+      {
+        *count
+      }
+      (
+        Self::DefeatBoss {
+          quest_id,
+          is_score_challenge,
+        },
+        MissionEvent::BossDefeat {
+          event_id,
+          quest_id: event_quest_id,
+          is_score_challenge: event_is_score_challenge,
+        },
+      ) if quest_id.is_none_or(|quest_id| quest_id == *event_quest_id)
+        && is_score_challenge == event_is_score_challenge
+        && *event_id == mission_event_id =>
+      {
+        1
+      }
+      (Self::GachaCount, MissionEvent::GachaPull { count }) => *count,
+      (Self::MemberLevel { level }, MissionEvent::GradeUp { from, to }) if from < level && to >= level => 1,
+      (Self::Login, MissionEvent::Login) => 1,
+      (Self::LoginTime { from, to }, MissionEvent::Login) if (*from..=*to).contains(&now.time()) => 1,
+      _ => 0,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct MissionPrototype {
+  pub mission_id: i64,
+  pub kind: MissionKind,
+  /// Zero for missions from `mission` master
+  pub event_id: i64,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+  pub rule: MissionRule,
+  /// `rule_x`, zero for [MissionRule::AllOfKind] means all other missions of the kind
+  pub target: i32,
+}
+
+impl MissionPrototype {
+  fn from_master(mission: &Value) -> Option<Self> {
+    let field = |name: &str| mission.get(name).map_or("0", |value| value.as_str().unwrap());
+    if field("enable") != "1" {
+      return None;
+    }
+
+    let kind = match field("mission_type") {
+      "NORMAL" => MissionKind::Normal,
+      "DAILY" => MissionKind::Daily,
+      "BEGINNER" => MissionKind::Beginner,
+      "EVENT" => MissionKind::Event,
+      _ => return None,
+    };
+    let rule = MissionRule::parse(field("rule"), field("rule_x"), field("rule_y"));
+    Some(Self {
+      mission_id: field("mission_id").parse().unwrap(),
+      kind,
+      event_id: field("event_id").parse().unwrap(),
+      start_at: parse_date(field("start_at")),
+      end_at: parse_date(field("end_at")),
+      target: match rule {
+        // Time window, completed by a single login
+        MissionRule::LoginTime { .. } => 1,
+        _ => field("rule_x").parse().unwrap_or(0),
+      },
+      rule,
+    })
+  }
+
+  /// Whether [now] is within `start_at` and `end_at` of the mission, see [Self::is_open].
+  pub fn is_active(&self, now: NaiveDateTime) -> bool {
+    self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+
+  /// Event missions are additionally only available while their event is open.
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.is_active(now)
+      && (self.event_id == 0 || EventConfig::get(self.event_id).is_some_and(|config| config.is_open(now)))
+  }
+}
+
+/// Returns missions of both masters, parsed once.
+pub fn get_missions() -> &'static [MissionPrototype] {
+  static MISSIONS: OnceLock<Vec<MissionPrototype>> = OnceLock::new();
+
+  MISSIONS.get_or_init(|| {
+    let manager = get_master_manager();
+    let missions = manager
+      .get_master("mission")
+      .iter()
+      .chain(manager.get_master("event_mission"))
+      .filter_map(MissionPrototype::from_master)
+      .collect::<Vec<_>>();
+    info!(count = missions.len(), "loaded missions");
+    missions
+  })
+}
+
+/// Start of the game day containing [now], shared by everything that resets daily.
+pub fn daily_reset_at(now: DateTime<Utc>, settings: &DailyResetSettings) -> DateTime<Utc> {
+  let offset = TimeDelta::hours(settings.hour as i64);
+  Utc.from_utc_datetime(&(now - offset).date_naive().and_time(NaiveTime::MIN)) + offset
+}
+
+/// Returns `mode` column of a stage or stage reward master entry.
+pub fn stage_difficulty(stage: &Value) -> i32 {
+  stage
+    .get("mode")
+    .map_or(0, |value| value.as_str().unwrap().parse::<i32>().unwrap())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UserMission {
+  pub progress: i32,
+  pub completed_at: Option<DateTime<Utc>>,
+  pub received: bool,
+  pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl UserMission {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      progress: row.get("progress"),
+      completed_at: row.get("completed_at"),
+      received: row.get("received"),
+      updated_at: Some(row.get("updated_at")),
+    }
+  }
+
+  pub fn is_completed(&self) -> bool {
+    self.completed_at.is_some()
+  }
+
+  /// Whether the mission was updated after [reset_at], daily missions that were not start over.
+  fn is_current(&self, reset_at: DateTime<Utc>) -> bool {
+    self.updated_at.is_some_and(|updated_at| updated_at >= reset_at)
+  }
+}
+
+/// Returns progress of all missions the user has, with daily missions reset at [daily_reset_at].
+pub async fn fetch_user_missions(
+  executor: impl Into<QueryExecutor<'_>>,
+  settings: &DailyResetSettings,
+  user_id: UserId,
+  now: DateTime<Utc>,
+) -> anyhow::Result<HashMap<i64, UserMission>> {
+  let reset_at = daily_reset_at(now, settings);
+  let daily = get_missions()
+    .iter()
+    .filter(|mission| mission.kind == MissionKind::Daily)
+    .map(|mission| mission.mission_id)
+    .collect::<HashSet<_>>();
+
+  let mut missions = FetchUserMissions::new(executor).await?.run(user_id).await?;
+  missions.retain(|mission_id, mission| !daily.contains(mission_id) || mission.is_current(reset_at));
+  Ok(missions)
+}
+
+/// Progresses all open missions that subscribe to [events].
+/// Returns notifications of missions completed by them, including `all_daily` and `all_beginner`.
+pub async fn record_mission_events(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  settings: &DailyResetSettings,
+  user_id: UserId,
+  events: &[MissionEvent],
+) -> anyhow::Result<Vec<MissionDone>> {
+  let now = Utc::now();
+  let reset_at = daily_reset_at(now, settings);
+  let mut states = fetch_user_missions(transaction, settings, user_id, now).await?;
+  let update = UpdateUserMission::new(transaction).await?;
+
+  let mut done = Vec::new();
+  let mut updated = Vec::new();
+  for mission in get_missions() {
+    let state = states.get(&mission.mission_id).cloned().unwrap_or_default();
+    if state.is_completed() {
+      continue;
+    }
+
+    let mut delta = match events.iter().filter_map(|event| mission.rule.total(event)).max() {
+      Some(total) => (total - state.progress).max(0),
+      None => events
+        .iter()
+        .map(|event| mission.rule.progress(event, mission.event_id, now.naive_utc()))
+        .sum::<i32>(),
+    };
+    // Logins only count once per day
+    if mission.rule == MissionRule::Login && state.is_current(reset_at) {
+      delta = 0;
+    }
+    if delta == 0 || !mission.is_open(now.naive_utc()) {
+      continue;
+    }
+
+    let progress = (state.progress + delta).min(mission.target);
+    let completed = progress >= mission.target;
+    let state = update
+      .run(user_id, mission.mission_id, progress, completed, state.received)
+      .await
+      .context("failed to update mission progress")?;
+    debug!(mission_id = ?mission.mission_id, ?progress, completed, "progressed mission");
+    if completed {
+      done.push(MissionDone::new(mission.mission_id as i32));
+    }
+    states.insert(mission.mission_id, state);
+    updated.push(mission.mission_id);
+  }
+
+  if !updated.is_empty() {
+    for mission in get_missions()
+      .iter()
+      .filter(|mission| mission.rule == MissionRule::AllOfKind && mission.event_id == 0)
+    {
+      if states.get(&mission.mission_id).is_some_and(UserMission::is_completed) || !mission.is_open(now.naive_utc()) {
+        continue;
+      }
+
+      let (completed, total) = get_missions()
+        .iter()
+        .filter(|other| other.kind == mission.kind && other.event_id == 0)
+        .filter(|other| other.rule != MissionRule::AllOfKind && other.is_open(now.naive_utc()))
+        .fold((0, 0), |(completed, total), other| {
+          let is_completed = states.get(&other.mission_id).is_some_and(UserMission::is_completed);
+          (completed + is_completed as i32, total + 1)
+        });
+      let target = if mission.target == 0 { total } else { mission.target };
+      let progress = completed.min(target);
+      let state = states.get(&mission.mission_id).cloned().unwrap_or_default();
+      if progress == state.progress {
+        continue;
+      }
+
+      update
+        .run(
+          user_id,
+          mission.mission_id,
+          progress,
+          progress >= target,
+          state.received,
+        )
+        .await
+        .context("failed to update mission progress")?;
+      if progress >= target {
+        done.push(MissionDone::new(mission.mission_id as i32));
+      }
+    }
+  }
+
+  if !done.is_empty() {
+    info!(missions = ?done.iter().map(|mission| mission.mission_id).collect::<Vec<_>>(), "completed missions");
+  }
+  Ok(done)
+}
+
+pub struct FetchUserMissions<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserMissions<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select mission_id, progress, completed_at, received, updated_at
+        from user_missions
+        where user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  /// Returns stored rows as is, see [fetch_user_missions] for daily resets.
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<HashMap<i64, UserMission>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| (row.get("mission_id"), UserMission::from_row(row)))
+        .collect(),
+    )
+  }
+}
+
+/// Stores new mission progress. [received] is taken from the current state, so it is only cleared
+/// when a daily mission starts over after the reset.
+pub struct UpdateUserMission<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> UpdateUserMission<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_missions (user_id, mission_id, progress, completed_at, received)
+        values ($1, $2, $3, case when $4 then now() end, $5)
+        on conflict (user_id, mission_id)
+          do update
+          set progress = excluded.progress,
+              completed_at = excluded.completed_at,
+              received = excluded.received,
+              updated_at = now()
+        returning progress, completed_at, received, updated_at
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    mission_id: i64,
+    progress: i32,
+    completed: bool,
+    received: bool,
+  ) -> anyhow::Result<UserMission> {
+    let row = self
+      .executor
+      .client()
+      .query_one(
+        &self.statement,
+        &[&user_id, &mission_id, &progress, &completed, &received],
+      )
+      .await?;
+    Ok(UserMission::from_row(&row))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn date(value: &str) -> NaiveDateTime {
+    parse_date(value).unwrap()
+  }
+
+  fn clear(kind: QuestKind, event_id: i64, quest_id: i64, difficulty: i32) -> MissionEvent {
+    MissionEvent::BattleClear {
+      kind,
+      event_id,
+      quest_id,
+      difficulty,
+      count: 2,
+    }
+  }
+
+  #[test]
+  fn test_stage_rules() {
+    let now = date("2024/1/1 12:00");
+    let unique = MissionRule::parse("battle_count_normal_unique", "1", "101011");
+    let hard = MissionRule::parse("battle_count_main_hard", "3", "0");
+    let event = MissionRule::parse("event_battle_count_unique", "1", "500101");
+
+    assert_eq!(unique.progress(&clear(QuestKind::Main, 0, 101011, 1), 0, now), 2);
+    assert_eq!(unique.progress(&clear(QuestKind::Main, 0, 101021, 1), 0, now), 0);
+    assert_eq!(unique.progress(&clear(QuestKind::Hunting, 0, 101011, 0), 0, now), 0);
+    assert_eq!(hard.progress(&clear(QuestKind::Main, 0, 101012, 2), 0, now), 2);
+    assert_eq!(hard.progress(&clear(QuestKind::Main, 0, 101011, 1), 0, now), 0);
+    assert_eq!(
+      event.progress(&clear(QuestKind::Event, 20031, 500101, 0), 20031, now),
+      2
+    );
+    assert_eq!(
+      event.progress(&clear(QuestKind::Event, 20031, 500101, 0), 20032, now),
+      0
+    );
+  }
+
+  #[test]
+  fn test_other_rules() {
+    let now = date("2024/1/1 12:00");
+    let member = MissionRule::parse("member_lv", "5", "20");
+    let time = MissionRule::parse("time", "0:00", "23:59");
+
+    assert_eq!(
+      MissionRule::parse("gacha_count", "1", "0").progress(&MissionEvent::GachaPull { count: 10 }, 0, now),
+      10
+    );
+    assert_eq!(member.progress(&MissionEvent::GradeUp { from: 15, to: 20 }, 0, now), 1);
+    assert_eq!(member.progress(&MissionEvent::GradeUp { from: 20, to: 25 }, 0, now), 0);
+    assert_eq!(time.progress(&MissionEvent::Login, 0, now), 1);
+    assert_eq!(time.progress(&MissionEvent::Greeting, 0, now), 0);
+    assert_eq!(MissionRule::parse("player_lv", "2", "0"), MissionRule::Unsupported);
+  }
+
+  #[test]
+  fn test_total_rules() {
+    let now = date("2024/1/1 12:00");
+    let star = MissionRule::parse("battle_star", "30", "0");
+    let tasks = |kind, total| MissionEvent::QuestTasks { kind, total };
+
+    assert_eq!(star.total(&tasks(QuestKind::Main, 42)), Some(42));
+    assert_eq!(star.total(&tasks(QuestKind::Hunting, 42)), None);
+    assert_eq!(star.progress(&tasks(QuestKind::Main, 42), 0, now), 0);
+    assert_eq!(
+      MissionRule::parse("gacha_count", "1", "0").total(&tasks(QuestKind::Main, 42)),
+      None
+    );
+  }
+
+  #[test]
+  fn test_daily_reset_at() {
+    let now = Utc.from_utc_datetime(&date("2024/1/1 12:34"));
+    let early = Utc.from_utc_datetime(&date("2024/1/1 3:59"));
+    let settings = DailyResetSettings { hour: 4 };
+
+    assert_eq!(
+      daily_reset_at(now, &DailyResetSettings::default()),
+      Utc.from_utc_datetime(&date("2024/1/1 0:00"))
+    );
+    assert_eq!(
+      daily_reset_at(now, &settings),
+      Utc.from_utc_datetime(&date("2024/1/1 4:00"))
+    );
+    assert_eq!(
+      daily_reset_at(early, &settings),
+      Utc.from_utc_datetime(&date("2023/12/31 4:00"))
+    );
+  }
+}
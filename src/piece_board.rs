@@ -0,0 +1,320 @@
+//! Character Potential boards from `character_piece_board*` masters.
+//!
+//! Each character has several boards of stages released in order with character pieces, the character's own
+//! pieces are spent before universal ones. Released stages add flat stats to every member of the character,
+//! elemental bonuses are not applied. Releasing enough stages of a character grants the packs of
+//! `character_piece_board_reward`, and some later stages require one of these rewards to be reached first.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use chrono::NaiveDateTime;
+use tokio_postgres::Statement;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::database::QueryExecutor;
+use crate::member::MemberStats;
+use crate::user::id::UserId;
+
+#[derive(Debug, Clone)]
+pub struct PieceBoardStagePrototype {
+  pub stage_id: i64,
+  pub character_id: i64,
+  pub board_id: i64,
+  /// Position of the stage in its board, starting from 1
+  pub board_stage_id: i32,
+  pub unlock_at: Option<NaiveDateTime>,
+  /// Reward that must be reached before the stage can be released, zero if none
+  pub unlock_reward_id: i64,
+  pub stats: MemberStats,
+  pub piece_num: i32,
+  /// Piece IDs in the order they are spent
+  pub usable_piece_ids: Vec<i64>,
+}
+
+impl PieceBoardStagePrototype {
+  /// Whether the stage can be released after [released] stages, with [rewards] reached.
+  pub fn is_releasable(&self, released: &HashSet<i64>, rewards: &HashSet<i64>, now: NaiveDateTime) -> bool {
+    let previous_released = self.board_stage_id == 1
+      || get_piece_board_stages().iter().any(|stage| {
+        stage.board_id == self.board_id
+          && stage.board_stage_id == self.board_stage_id - 1
+          && released.contains(&stage.stage_id)
+      });
+
+    previous_released
+      && !released.contains(&self.stage_id)
+      && self.unlock_at.is_none_or(|unlock_at| now >= unlock_at)
+      && (self.unlock_reward_id == 0 || rewards.contains(&self.unlock_reward_id))
+  }
+}
+
+/// Returns all stages of all boards, parsed once.
+pub fn get_piece_board_stages() -> &'static [PieceBoardStagePrototype] {
+  static STAGES: OnceLock<Vec<PieceBoardStagePrototype>> = OnceLock::new();
+
+  STAGES.get_or_init(|| {
+    get_master_manager()
+      .get_master("character_piece_board_stage")
+      .iter()
+      .map(|stage| PieceBoardStagePrototype {
+        stage_id: parse_i64(&stage["stage_id"]),
+        character_id: parse_i64(&stage["character_id"]),
+        board_id: parse_i64(&stage["board_id"]),
+        board_stage_id: parse_i64(&stage["board_stage_id"]) as i32,
+        unlock_at: parse_date(stage["unlock_date_start"].as_str().unwrap()),
+        unlock_reward_id: parse_i64(&stage["unlock_reward_id"]),
+        stats: MemberStats {
+          hp: parse_i64(&stage["hp"]) as i32,
+          attack: parse_i64(&stage["attack"]) as i32,
+          magicattack: parse_i64(&stage["magicattack"]) as i32,
+          defense: parse_i64(&stage["defense"]) as i32,
+          magicdefence: parse_i64(&stage["magicdefence"]) as i32,
+          agility: parse_i64(&stage["agility"]) as i32,
+          dexterity: parse_i64(&stage["dexterity"]) as i32,
+          luck: parse_i64(&stage["luck"]) as i32,
+        },
+        piece_num: parse_i64(&stage["piece_num"]) as i32,
+        usable_piece_ids: [&stage["usable_piece_id1"], &stage["usable_piece_id2"]]
+          .into_iter()
+          .map(parse_i64)
+          .filter(|piece_id| *piece_id != 0)
+          .collect(),
+      })
+      .collect()
+  })
+}
+
+pub fn get_piece_board_stage(stage_id: i64) -> Option<&'static PieceBoardStagePrototype> {
+  get_piece_board_stages().iter().find(|stage| stage.stage_id == stage_id)
+}
+
+/// Returns board IDs of a character.
+pub fn get_piece_boards(character_id: i64) -> Vec<i64> {
+  get_master_manager()
+    .get_master("character_piece_board")
+    .iter()
+    .filter(|board| parse_i64(&board["chara_id"]) == character_id)
+    .map(|board| parse_i64(&board["board_id"]))
+    .collect()
+}
+
+/// Returns the last released stage of each board with any progress.
+pub fn get_current_stages(released: &HashSet<i64>) -> HashMap<i64, &'static PieceBoardStagePrototype> {
+  let mut current: HashMap<i64, &PieceBoardStagePrototype> = HashMap::new();
+  for stage in get_piece_board_stages()
+    .iter()
+    .filter(|stage| released.contains(&stage.stage_id))
+  {
+    current
+      .entry(stage.board_id)
+      .and_modify(|other| {
+        if stage.board_stage_id > other.board_stage_id {
+          *other = stage;
+        }
+      })
+      .or_insert(stage);
+  }
+  current
+}
+
+/// Stats added to every member of a character with [released] stages.
+pub fn get_stats_bonus(released: &HashSet<i64>) -> MemberStats {
+  get_piece_board_stages()
+    .iter()
+    .filter(|stage| released.contains(&stage.stage_id))
+    .map(|stage| stage.stats)
+    .sum()
+}
+
+#[derive(Debug, Clone)]
+pub struct PieceBoardRewardPrototype {
+  pub reward_id: i64,
+  pub character_id: i64,
+  /// Number of released stages of the character needed to reach the reward
+  pub require_release_count: i32,
+  pub pack_id: i64,
+}
+
+/// Returns all board rewards, parsed once.
+pub fn get_piece_board_rewards() -> &'static [PieceBoardRewardPrototype] {
+  static REWARDS: OnceLock<Vec<PieceBoardRewardPrototype>> = OnceLock::new();
+
+  REWARDS.get_or_init(|| {
+    get_master_manager()
+      .get_master("character_piece_board_reward")
+      .iter()
+      .map(|reward| PieceBoardRewardPrototype {
+        reward_id: parse_i64(&reward["reward_id"]),
+        character_id: parse_i64(&reward["character_id"]),
+        require_release_count: parse_i64(&reward["require_release_count"]) as i32,
+        pack_id: parse_i64(&reward["pack_id"]),
+      })
+      .collect()
+  })
+}
+
+/// Released stages of all characters of a user.
+#[derive(Debug, Default)]
+pub struct UserPieceBoards {
+  /// Released stage IDs by character
+  pub released: HashMap<i64, HashSet<i64>>,
+  /// Received reward IDs
+  pub rewards: HashSet<i64>,
+}
+
+impl UserPieceBoards {
+  pub fn released(&self, character_id: i64) -> HashSet<i64> {
+    self.released.get(&character_id).cloned().unwrap_or_default()
+  }
+
+  /// Received rewards and rewards whose release count is met, even if their pack contents are unknown.
+  pub fn reached_rewards(&self, character_id: i64) -> HashSet<i64> {
+    let release_count = self.released.get(&character_id).map_or(0, HashSet::len) as i32;
+    get_piece_board_rewards()
+      .iter()
+      .filter(|reward| reward.character_id == character_id && reward.require_release_count <= release_count)
+      .map(|reward| reward.reward_id)
+      .chain(self.rewards.iter().copied())
+      .collect()
+  }
+
+  /// Stage IDs sent in `character_piece_board_stage_id_list`.
+  pub fn current_stage_ids(&self, character_id: i64) -> Vec<i32> {
+    let mut stage_ids = get_current_stages(&self.released(character_id))
+      .into_values()
+      .map(|stage| stage.stage_id as i32)
+      .collect::<Vec<_>>();
+    stage_ids.sort();
+    stage_ids
+  }
+}
+
+pub struct FetchUserPieceBoards<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserPieceBoards<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select 'stage' as kind, character_id, stage_id as id
+        from user_character_piece_board_stages
+        where user_id = $1
+        union all
+        select 'reward' as kind, character_id, reward_id as id
+        from user_character_piece_board_rewards
+        where user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<UserPieceBoards> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+
+    let mut boards = UserPieceBoards::default();
+    for row in rows {
+      let kind: &str = row.get("kind");
+      let id: i64 = row.get("id");
+      if kind == "reward" {
+        boards.rewards.insert(id);
+      } else {
+        boards.released.entry(row.get("character_id")).or_default().insert(id);
+      }
+    }
+    Ok(boards)
+  }
+}
+
+/// Releases a stage, returns whether it was not released before.
+pub struct ReleaseUserPieceBoardStage<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ReleaseUserPieceBoardStage<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_character_piece_board_stages (user_id, character_id, board_id, stage_id)
+        values ($1, $2, $3, $4)
+        on conflict (user_id, stage_id) do nothing
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, stage: &PieceBoardStagePrototype) -> anyhow::Result<bool> {
+    let rows_affected = self
+      .executor
+      .client()
+      .execute(
+        &self.statement,
+        &[&user_id, &stage.character_id, &stage.board_id, &stage.stage_id],
+      )
+      .await?;
+    Ok(rows_affected != 0)
+  }
+}
+
+/// Marks a reward as reached, returns whether it was not reached before.
+pub struct ReachUserPieceBoardReward<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ReachUserPieceBoardReward<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_character_piece_board_rewards (user_id, character_id, reward_id)
+        values ($1, $2, $3)
+        on conflict (user_id, reward_id) do nothing
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, reward: &PieceBoardRewardPrototype) -> anyhow::Result<bool> {
+    let rows_affected = self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &reward.character_id, &reward.reward_id])
+      .await?;
+    Ok(rows_affected != 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_releasable() {
+    let stage = PieceBoardStagePrototype {
+      stage_id: 1,
+      character_id: 100,
+      board_id: 100001,
+      board_stage_id: 1,
+      unlock_at: None,
+      unlock_reward_id: 100024,
+      stats: MemberStats::default(),
+      piece_num: 10,
+      usable_piece_ids: vec![100, 1],
+    };
+    let now = NaiveDateTime::default();
+
+    assert!(!stage.is_releasable(&HashSet::new(), &HashSet::new(), now));
+    assert!(stage.is_releasable(&HashSet::new(), &HashSet::from([100024]), now));
+    assert!(!stage.is_releasable(&HashSet::from([1]), &HashSet::from([100024]), now));
+  }
+}
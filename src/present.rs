@@ -0,0 +1,168 @@
+//! Present box: rewards that are not granted directly, the user receives them from the present list.
+
+use chrono::{DateTime, Utc};
+use tokio_postgres::{Row, Statement};
+
+use crate::database::QueryExecutor;
+use crate::user::id::UserId;
+
+#[derive(Debug, Clone)]
+pub struct UserPresent {
+  pub id: i64,
+  pub item_type: i32,
+  pub item_id: i64,
+  pub item_num: i32,
+  pub message: String,
+  pub sent_at: DateTime<Utc>,
+  pub expires_at: Option<DateTime<Utc>>,
+  pub received_at: Option<DateTime<Utc>>,
+}
+
+impl UserPresent {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      id: row.get("id"),
+      item_type: row.get("item_type"),
+      item_id: row.get("item_id"),
+      item_num: row.get("item_num"),
+      message: row.get("message"),
+      sent_at: row.get("sent_at"),
+      expires_at: row.get("expires_at"),
+      received_at: row.get("received_at"),
+    }
+  }
+}
+
+/// Item to put into the present box, see [SendUserPresent].
+#[derive(Debug, Clone)]
+pub struct NewPresent {
+  pub item_type: i32,
+  pub item_id: i64,
+  pub item_num: i32,
+  pub message: String,
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+pub struct SendUserPresent<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> SendUserPresent<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_presents (user_id, item_type, item_id, item_num, message, expires_at)
+        values ($1, $2, $3, $4, $5, $6)
+        returning id, item_type, item_id, item_num, message, sent_at, expires_at, received_at
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, present: &NewPresent) -> anyhow::Result<UserPresent> {
+    let row = self
+      .executor
+      .client()
+      .query_one(
+        &self.statement,
+        &[
+          &user_id,
+          &present.item_type,
+          &present.item_id,
+          &present.item_num,
+          &present.message,
+          &present.expires_at,
+        ],
+      )
+      .await?;
+    Ok(UserPresent::from_row(&row))
+  }
+}
+
+/// Returns presents that can still be received, newest first.
+pub struct FetchUserPresents<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserPresents<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select id, item_type, item_id, item_num, message, sent_at, expires_at, received_at
+        from user_presents
+        where user_id = $1 and received_at is null and (expires_at is null or expires_at > now())
+        order by sent_at desc, id desc
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<Vec<UserPresent>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(rows.iter().map(UserPresent::from_row).collect())
+  }
+}
+
+/// Returns already received presents, most recently received first.
+pub struct FetchUserPresentLog<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserPresentLog<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select id, item_type, item_id, item_num, message, sent_at, expires_at, received_at
+        from user_presents
+        where user_id = $1 and received_at is not null
+        order by received_at desc, id desc
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<Vec<UserPresent>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(rows.iter().map(UserPresent::from_row).collect())
+  }
+}
+
+/// Marks presents as received, returns only the ones that could be received,
+/// already received and expired presents are skipped.
+pub struct ReceiveUserPresents<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ReceiveUserPresents<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_presents
+        set received_at = now()
+        where user_id = $1
+          and id = any($2)
+          and received_at is null
+          and (expires_at is null or expires_at > now())
+        returning id, item_type, item_id, item_num, message, sent_at, expires_at, received_at
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, ids: &[i64]) -> anyhow::Result<Vec<UserPresent>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id, &ids]).await?;
+    Ok(rows.iter().map(UserPresent::from_row).collect())
+  }
+}
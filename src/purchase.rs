@@ -0,0 +1,345 @@
+//! In-app purchases of shop items sold for real money.
+//!
+//! A [PurchaseProvider] decides whether a purchase was paid for. Approved purchases are credited:
+//! the shop item bundle is granted, quartz in it as paid quartz, and its price counts towards the
+//! user's charge total. Bundles of packs that are not dumped are replaced with paid quartz. Only a local provider exists, a store receipt verifier can implement the same trait.
+//!
+//! With [PurchaseProviderKind::AdminApproved], purchases stay pending until an administrator sets
+//! `approved` in the `user_purchases` table, they are credited by [credit_approved_purchases].
+
+use std::future::Future;
+use std::pin::Pin;
+
+use chrono::{DateTime, Utc};
+use tokio_postgres::{Row, Statement};
+use tracing::{info, warn};
+
+use crate::api::battle::grant_rewards;
+use crate::api::quest::QuestRewardItem;
+use crate::api::{RemoteData, RemoteDataItemType};
+use crate::database::QueryExecutor;
+use crate::settings::{PurchaseProviderKind, PurchaseSettings, Settings};
+use crate::shop::{FetchUserShopPurchases, ShopItemPrototype, UpdateUserShopPurchase, get_shop_item};
+use crate::user::id::UserId;
+use crate::user::session::Session;
+
+/// Purchase reported by the client after paying in the platform store.
+#[derive(Debug, Clone)]
+pub struct PurchaseReceipt {
+  /// `external_id` of the shop item
+  pub product_id: String,
+  pub receipt: Option<String>,
+  pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PurchaseVerdict {
+  Approved,
+  /// Decided later, outside of the purchase request
+  Pending,
+  Rejected,
+  /// Purchases are not accepted at all
+  Unavailable,
+}
+
+impl PurchaseVerdict {
+  /// Value of `user_purchases.approved`.
+  pub fn approved(&self) -> Option<bool> {
+    match self {
+      PurchaseVerdict::Approved => Some(true),
+      PurchaseVerdict::Pending => None,
+      PurchaseVerdict::Rejected | PurchaseVerdict::Unavailable => Some(false),
+    }
+  }
+}
+
+type VerifyFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<PurchaseVerdict>> + Send + 'a>>;
+
+pub trait PurchaseProvider: Send + Sync {
+  /// Checks that [receipt] for [prototype] was paid for by [user_id].
+  fn verify<'a>(
+    &'a self,
+    user_id: UserId,
+    prototype: &'a ShopItemPrototype,
+    receipt: &'a PurchaseReceipt,
+  ) -> VerifyFuture<'a>;
+}
+
+/// Does not verify anything, see [PurchaseProviderKind].
+pub struct LocalPurchaseProvider {
+  pub kind: PurchaseProviderKind,
+}
+
+impl PurchaseProvider for LocalPurchaseProvider {
+  fn verify<'a>(
+    &'a self,
+    _user_id: UserId,
+    _prototype: &'a ShopItemPrototype,
+    _receipt: &'a PurchaseReceipt,
+  ) -> VerifyFuture<'a> {
+    Box::pin(async move {
+      Ok(match self.kind {
+        PurchaseProviderKind::Disabled => PurchaseVerdict::Unavailable,
+        PurchaseProviderKind::Free => PurchaseVerdict::Approved,
+        PurchaseProviderKind::AdminApproved => PurchaseVerdict::Pending,
+      })
+    })
+  }
+}
+
+pub fn create_purchase_provider(settings: &PurchaseSettings) -> Box<dyn PurchaseProvider> {
+  info!(provider = ?settings.provider, "using local purchase provider");
+  Box::new(LocalPurchaseProvider {
+    kind: settings.provider,
+  })
+}
+
+/// Items granted for a purchase, quartz bought for real money is always paid quartz.
+/// Products with unknown pack contents are credited as paid quartz by [PurchaseSettings::quartz_per_yen].
+pub fn purchase_items(prototype: &ShopItemPrototype, settings: &PurchaseSettings) -> Vec<QuestRewardItem> {
+  let bundle = prototype.bundle();
+  if bundle.is_empty() {
+    let quartz = (prototype.google_yen_price as f32 * settings.quartz_per_yen).round() as i32;
+    return if quartz > 0 {
+      vec![QuestRewardItem {
+        item_type: RemoteDataItemType::RealMoney.into(),
+        item_id: 0,
+        item_num: quartz,
+        item_rare: false,
+        probability: None,
+      }]
+    } else {
+      Vec::new()
+    };
+  }
+
+  bundle
+    .into_iter()
+    .map(|item| {
+      if RemoteDataItemType::from(item.item_type) == RemoteDataItemType::RealMoneyFree {
+        QuestRewardItem {
+          item_type: RemoteDataItemType::RealMoney.into(),
+          ..item
+        }
+      } else {
+        item
+      }
+    })
+    .collect()
+}
+
+/// Grants the items of an approved purchase and counts it towards the shop item limit.
+/// Returns `None` and leaves the purchase uncredited if the contents of the bundle are unknown.
+pub async fn credit_purchase(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  settings: &Settings,
+  session: &Session,
+  purchase_id: i64,
+  prototype: &ShopItemPrototype,
+) -> anyhow::Result<Option<Vec<RemoteData>>> {
+  let items = purchase_items(prototype, &settings.purchase);
+  if items.is_empty() {
+    warn!(user_id = ?session.user_id, ?purchase_id, ?prototype.id, "purchase has no known contents, not crediting");
+    return Ok(None);
+  }
+
+  let now = Utc::now();
+  let bought = FetchUserShopPurchases::new(transaction)
+    .await?
+    .run(session.user_id)
+    .await?
+    .get(&prototype.id)
+    .map_or(0, |purchase| purchase.current_count(prototype, now, &settings.daily_reset));
+  UpdateUserShopPurchase::new(transaction)
+    .await?
+    .run(session.user_id, prototype.id, bought + 1)
+    .await?;
+
+  let remote_data = grant_rewards(transaction, &settings.inventory, session, &items).await?;
+  MarkUserPurchaseCredited::new(transaction)
+    .await?
+    .run(session.user_id, purchase_id)
+    .await?;
+  info!(user_id = ?session.user_id, ?purchase_id, ?prototype.id, "credited purchase");
+
+  Ok(Some(remote_data))
+}
+
+/// Credits purchases that were approved after they were made.
+pub async fn credit_approved_purchases(
+  transaction: &deadpool_postgres::Transaction<'_>,
+  settings: &Settings,
+  session: &Session,
+) -> anyhow::Result<Vec<RemoteData>> {
+  let purchases = FetchApprovedUserPurchases::new(transaction)
+    .await?
+    .run(session.user_id)
+    .await?;
+
+  let mut remote_data = Vec::new();
+  for purchase in purchases {
+    let Some(prototype) = get_shop_item(purchase.shop_item_id) else {
+      continue;
+    };
+    remote_data.extend(
+      credit_purchase(transaction, settings, session, purchase.id, prototype)
+        .await?
+        .unwrap_or_default(),
+    );
+  }
+
+  Ok(remote_data)
+}
+
+#[derive(Debug, Clone)]
+pub struct UserPurchase {
+  pub id: i64,
+  pub shop_item_id: i64,
+  pub product_id: String,
+  pub price: i32,
+  pub approved: Option<bool>,
+  pub created_at: DateTime<Utc>,
+  pub credited_at: Option<DateTime<Utc>>,
+}
+
+impl UserPurchase {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      id: row.get("id"),
+      shop_item_id: row.get("shop_item_id"),
+      product_id: row.get("product_id"),
+      price: row.get("price"),
+      approved: row.get("approved"),
+      created_at: row.get("created_at"),
+      credited_at: row.get("credited_at"),
+    }
+  }
+}
+
+pub struct AddUserPurchase<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserPurchase<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_purchases (user_id, shop_item_id, product_id, receipt, price, approved)
+        values ($1, $2, $3, $4, $5, $6)
+        returning id, shop_item_id, product_id, price, approved, created_at, credited_at
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    prototype: &ShopItemPrototype,
+    receipt: &PurchaseReceipt,
+    approved: Option<bool>,
+  ) -> anyhow::Result<UserPurchase> {
+    let row = self
+      .executor
+      .client()
+      .query_one(
+        &self.statement,
+        &[
+          &user_id,
+          &prototype.id,
+          &receipt.product_id,
+          &receipt.receipt,
+          &prototype.google_yen_price,
+          &approved,
+        ],
+      )
+      .await?;
+    Ok(UserPurchase::from_row(&row))
+  }
+}
+
+/// Returns approved purchases that were not credited yet, locking them.
+pub struct FetchApprovedUserPurchases<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchApprovedUserPurchases<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select id, shop_item_id, product_id, price, approved, created_at, credited_at
+        from user_purchases
+        where user_id = $1 and approved and credited_at is null
+        order by created_at
+        for update
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<Vec<UserPurchase>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(rows.iter().map(UserPurchase::from_row).collect())
+  }
+}
+
+pub struct MarkUserPurchaseCredited<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> MarkUserPurchaseCredited<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_purchases
+        set credited_at = now()
+        where user_id = $1 and id = $2 and credited_at is null
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, purchase_id: i64) -> anyhow::Result<()> {
+    self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &purchase_id])
+      .await?;
+    Ok(())
+  }
+}
+
+/// Returns the sum of prices of credited purchases, in yen.
+pub struct FetchUserChargeTotal<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserChargeTotal<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select coalesce(sum(price), 0)::integer
+        from user_purchases
+        where user_id = $1 and credited_at is not null
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<i32> {
+    let row = self.executor.client().query_one(&self.statement, &[&user_id]).await?;
+    Ok(row.get(0))
+  }
+}
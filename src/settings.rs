@@ -12,6 +12,18 @@ pub struct Settings {
   pub static_server: StaticServerSettings,
   pub api_server: ApiServerSettings,
   pub database: DatabaseSettings,
+  #[serde(default)]
+  pub drops: DropSettings,
+  #[serde(default)]
+  pub daily_reset: DailyResetSettings,
+  #[serde(default)]
+  pub purchase: PurchaseSettings,
+  #[serde(default)]
+  pub ad_reward: AdRewardSettings,
+  #[serde(default)]
+  pub inventory: InventorySettings,
+  #[serde(default)]
+  pub story: StorySettings,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,6 +57,112 @@ pub struct DatabaseTlsSettings {
   pub client_key: PathBuf,
 }
 
+/// Quest drop rolls, see [crate::api::quest::drop].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct DropSettings {
+  /// Chance of a rare reward slot to drop, used when the master has no probability.
+  pub rare_chance: f32,
+  /// Makes drop rolls reproducible, the generator is seeded once per process.
+  pub seed: Option<u64>,
+}
+
+impl Default for DropSettings {
+  fn default() -> Self {
+    Self {
+      rare_chance: 0.1,
+      seed: None,
+    }
+  }
+}
+
+/// Start of a game day, see [crate::mission::daily_reset_at].
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct DailyResetSettings {
+  /// Hour (UTC) at which daily missions, shop, exchange and ad reward limits and login bonuses reset.
+  pub hour: u32,
+}
+
+/// In-app purchases, see [crate::purchase].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PurchaseSettings {
+  pub provider: PurchaseProviderKind,
+  /// Paid quartz per yen of the store price, credited when the pack of a product is unknown.
+  pub quartz_per_yen: f32,
+}
+
+impl Default for PurchaseSettings {
+  fn default() -> Self {
+    Self {
+      provider: PurchaseProviderKind::default(),
+      quartz_per_yen: 1.0,
+    }
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PurchaseProviderKind {
+  /// All purchases are rejected
+  #[default]
+  Disabled,
+  /// All purchases are approved without payment
+  Free,
+  /// Purchases wait until approved in the database, see [crate::purchase]
+  AdminApproved,
+}
+
+/// Ad rewards, claimed without ads, see [crate::ad_reward].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AdRewardSettings {
+  pub enabled: bool,
+  /// Claims per slot per day, overrides `limit` of `ad_reward` master.
+  pub daily_limit: Option<i32>,
+  /// Quartz per claim, used when the pack of a slot is unknown.
+  pub quartz: i32,
+  /// Stamina per claim, used when the pack of a slot is unknown.
+  pub stamina: i32,
+}
+
+impl Default for AdRewardSettings {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      daily_limit: None,
+      quartz: 50,
+      stamina: 100,
+    }
+  }
+}
+
+/// Inventory capacity, see [crate::inventory].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct InventorySettings {
+  pub equipment_capacity: i32,
+  pub reserve_member_capacity: i32,
+  /// Slots added by a single expansion.
+  pub expansion_step: i32,
+  /// Quartz spent for a single expansion.
+  pub expansion_cost: i32,
+  pub max_expansions: i32,
+}
+
+impl Default for InventorySettings {
+  fn default() -> Self {
+    Self {
+      equipment_capacity: 300,
+      reserve_member_capacity: 200,
+      expansion_step: 10,
+      expansion_cost: 50,
+      max_expansions: 50,
+    }
+  }
+}
+
 impl Settings {
   pub fn new() -> Result<Self, ConfigError> {
     let settings = Config::builder()
@@ -55,3 +173,17 @@ impl Settings {
     settings.try_deserialize()
   }
 }
+
+/// Story rewards, see [crate::story].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct StorySettings {
+  /// Free quartz for reading a story for the first time, used when its pack contents are unknown.
+  pub first_read_quartz: i32,
+}
+
+impl Default for StorySettings {
+  fn default() -> Self {
+    Self { first_read_quartz: 10 }
+  }
+}
@@ -0,0 +1,407 @@
+//! In-game shop from `shop_item` master: prices per money type, buy limits and their reset periods.
+//!
+//! Items sold for real money are bought through [crate::purchase], everything else with Eris or quartz (or free).
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Datelike, Months, NaiveDateTime, TimeDelta, Utc};
+use serde_json::Value;
+use tokio_postgres::Statement;
+use tracing::warn;
+
+use crate::api::interaction::parse_date;
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::api::quest::QuestRewardItem;
+use crate::api::RemoteDataItemType;
+use crate::call::{
+  STATUS_SHOP_BUY_LIMIT, STATUS_SHOP_DAILY_LIMIT, STATUS_SHOP_MONTHLY_LIMIT, STATUS_SHOP_ONE_PURCHASE,
+  STATUS_SHOP_WEEKLY_LIMIT,
+};
+use crate::database::QueryExecutor;
+use crate::mission::daily_reset_at;
+use crate::settings::DailyResetSettings;
+use crate::user::id::UserId;
+
+/// Most purchases of one item in a single `buy` request.
+pub const MAX_BUY_COUNT: i32 = 99;
+
+/// `money_type` request parameter.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ShopMoneyType {
+  /// Eris, `pay_money_*` columns
+  Money,
+  /// Free quartz is spent first, then paid quartz, `pay_realmoneyfree_*` columns
+  RealMoney,
+  /// Paid quartz only, `pay_realmoney_*` columns
+  RealMoneyPaid,
+}
+
+impl TryFrom<i32> for ShopMoneyType {
+  type Error = i32;
+
+  fn try_from(value: i32) -> Result<Self, Self::Error> {
+    match value {
+      1 => Ok(ShopMoneyType::Money),
+      2 => Ok(ShopMoneyType::RealMoney),
+      3 => Ok(ShopMoneyType::RealMoneyPaid),
+      value => Err(value),
+    }
+  }
+}
+
+/// Price of the first purchase in a limit period, and of every other purchase.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShopPrice {
+  pub first: i32,
+  pub second: i32,
+}
+
+impl ShopPrice {
+  fn parse(item: &Value, column: &str) -> Self {
+    Self {
+      first: parse_i64(&item[format!("{column}_first")]) as i32,
+      second: parse_i64(&item[format!("{column}_second")]) as i32,
+    }
+  }
+
+  pub fn is_free(&self) -> bool {
+    self.first == 0 && self.second == 0
+  }
+
+  /// Total price of [count] purchases when [bought] were already made in the current period,
+  /// `None` if it overflows.
+  pub fn total(&self, bought: i32, count: i32) -> Option<i32> {
+    match count {
+      ..=0 => Some(0),
+      _ if bought == 0 => self.second.checked_mul(count - 1)?.checked_add(self.first),
+      _ => self.second.checked_mul(count),
+    }
+  }
+}
+
+/// `limit_type` column.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ShopLimitKind {
+  None,
+  /// Never resets
+  BuyLimit,
+  /// Can be bought only once, `limit` column is ignored
+  OnePurchase,
+  Daily,
+  Weekly,
+  Monthly,
+}
+
+impl ShopLimitKind {
+  /// Parses `limit_type` column, e.g. `DAILYLIMIT`.
+  pub fn parse(value: &str) -> Self {
+    match value {
+      "BUYLIMIT" => ShopLimitKind::BuyLimit,
+      "ONEPURCHASE" => ShopLimitKind::OnePurchase,
+      "DAILYLIMIT" => ShopLimitKind::Daily,
+      "WEEKLYLIMIT" => ShopLimitKind::Weekly,
+      "MONTHLYLIMIT" => ShopLimitKind::Monthly,
+      _ => ShopLimitKind::None,
+    }
+  }
+
+  /// Start of the period containing [now], buy counts from before it are not counted.
+  pub fn period_start(&self, now: DateTime<Utc>, settings: &DailyResetSettings) -> Option<DateTime<Utc>> {
+    let today = daily_reset_at(now, settings);
+    match self {
+      ShopLimitKind::None | ShopLimitKind::BuyLimit | ShopLimitKind::OnePurchase => None,
+      ShopLimitKind::Daily => Some(today),
+      ShopLimitKind::Weekly => Some(today - TimeDelta::days(today.weekday().num_days_from_monday() as i64)),
+      ShopLimitKind::Monthly => Some(today - TimeDelta::days(today.day0() as i64)),
+    }
+  }
+
+  /// End of the period containing [now], `None` if buy counts never reset.
+  pub fn next_reset(&self, now: DateTime<Utc>, settings: &DailyResetSettings) -> Option<DateTime<Utc>> {
+    let start = self.period_start(now, settings)?;
+    Some(match self {
+      ShopLimitKind::Daily => start + TimeDelta::days(1),
+      ShopLimitKind::Weekly => start + TimeDelta::weeks(1),
+      ShopLimitKind::Monthly => start + Months::new(1),
+      _ => unreachable!(),
+    })
+  }
+
+  /// Status returned when the limit is reached.
+  pub fn limit_status(&self) -> i32 {
+    match self {
+      ShopLimitKind::None | ShopLimitKind::BuyLimit => STATUS_SHOP_BUY_LIMIT,
+      ShopLimitKind::OnePurchase => STATUS_SHOP_ONE_PURCHASE,
+      ShopLimitKind::Daily => STATUS_SHOP_DAILY_LIMIT,
+      ShopLimitKind::Weekly => STATUS_SHOP_WEEKLY_LIMIT,
+      ShopLimitKind::Monthly => STATUS_SHOP_MONTHLY_LIMIT,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShopItemPrototype {
+  pub id: i64,
+  pub shop_id: i64,
+  /// `type` column, e.g. `PACK` or `STAMINA`
+  pub kind: String,
+  pub sale_type: String,
+  pub pay_money: ShopPrice,
+  pub pay_realmoney: ShopPrice,
+  pub pay_realmoneyfree: ShopPrice,
+  pub pack_id: i64,
+  /// Platform store product ID, for items sold for real money
+  pub external_id: String,
+  pub google_yen_price: i32,
+  pub limit_kind: ShopLimitKind,
+  /// Zero if unlimited
+  pub limit: i32,
+  pub start_at: Option<NaiveDateTime>,
+  pub end_at: Option<NaiveDateTime>,
+  pub enable: bool,
+}
+
+impl ShopItemPrototype {
+  pub fn is_open(&self, now: NaiveDateTime) -> bool {
+    self.enable && self.start_at.is_none_or(|start| now >= start) && self.end_at.is_none_or(|end| now < end)
+  }
+
+  /// Sold for real money through the platform store, not with [ShopMoneyType].
+  pub fn is_real_money_sale(&self) -> bool {
+    self.sale_type == "SALE_REALMONEY"
+  }
+
+  pub fn limit(&self) -> Option<i32> {
+    match self.limit_kind {
+      ShopLimitKind::OnePurchase => Some(1),
+      _ if self.limit > 0 => Some(self.limit),
+      _ => None,
+    }
+  }
+
+  /// Price for [money_type], `None` if the item can not be bought with it.
+  pub fn price(&self, money_type: ShopMoneyType) -> Option<ShopPrice> {
+    let price = match money_type {
+      ShopMoneyType::Money => self.pay_money,
+      ShopMoneyType::RealMoney => self.pay_realmoneyfree,
+      ShopMoneyType::RealMoneyPaid => self.pay_realmoney,
+    };
+
+    // Free items can be "bought" with anything, others only with the money types they have a price in
+    let is_free = self.pay_money.is_free() && self.pay_realmoney.is_free() && self.pay_realmoneyfree.is_free();
+    (is_free || !price.is_free()).then_some(price)
+  }
+
+  /// Items received for a single purchase.
+  pub fn bundle(&self) -> Vec<QuestRewardItem> {
+    if self.kind == "STAMINA" {
+      // Restores the same amount as the first stamina item
+      let stamina = get_master_manager()
+        .get_master("stamina_item")
+        .first()
+        .map_or(0, |item| parse_i64(&item["stamina"]) as i32);
+      return vec![QuestRewardItem {
+        item_type: RemoteDataItemType::Stamina.into(),
+        item_id: 0,
+        item_num: stamina,
+        item_rare: false,
+        probability: None,
+      }];
+    }
+
+    let items = get_pack_items(self.pack_id);
+    if items.is_empty() {
+      warn!(?self.id, ?self.pack_id, "shop item has no known pack contents");
+    }
+    items.to_vec()
+  }
+}
+
+/// Returns all shop items, parsed once.
+pub fn get_shop_items() -> &'static [ShopItemPrototype] {
+  static SHOP_ITEMS: OnceLock<Vec<ShopItemPrototype>> = OnceLock::new();
+
+  SHOP_ITEMS.get_or_init(|| {
+    get_master_manager()
+      .get_master("shop_item")
+      .iter()
+      .map(|item| ShopItemPrototype {
+        id: parse_i64(&item["id"]),
+        shop_id: parse_i64(&item["shop_id"]),
+        kind: item["type"].as_str().unwrap().to_owned(),
+        sale_type: item["sale_type"].as_str().unwrap().to_owned(),
+        pay_money: ShopPrice::parse(item, "pay_money"),
+        pay_realmoney: ShopPrice::parse(item, "pay_realmoney"),
+        pay_realmoneyfree: ShopPrice::parse(item, "pay_realmoneyfree"),
+        pack_id: parse_i64(&item["pack_id"]),
+        external_id: item["external_id"].as_str().unwrap().to_owned(),
+        google_yen_price: parse_i64(&item["google_yen_price"]) as i32,
+        limit_kind: ShopLimitKind::parse(item["limit_type"].as_str().unwrap()),
+        limit: parse_i64(&item["limit"]) as i32,
+        start_at: parse_date(item["start_at"].as_str().unwrap()),
+        end_at: parse_date(item["end_at"].as_str().unwrap()),
+        enable: item["enable"].as_str().unwrap() == "1",
+      })
+      .collect()
+  })
+}
+
+pub fn get_shop_item(id: i64) -> Option<&'static ShopItemPrototype> {
+  get_shop_items().iter().find(|item| item.id == id)
+}
+
+/// Item currently sold for real money under store product [external_id].
+pub fn get_real_money_item(external_id: &str, now: NaiveDateTime) -> Option<&'static ShopItemPrototype> {
+  get_shop_items()
+    .iter()
+    .find(|item| item.is_real_money_sale() && item.external_id == external_id && item.is_open(now))
+}
+
+/// Contents of a pack from `pack` master, empty if it is not dumped to the masters directory.
+pub fn get_pack_items(pack_id: i64) -> &'static [QuestRewardItem] {
+  static PACK_ITEMS: OnceLock<HashMap<i64, Vec<QuestRewardItem>>> = OnceLock::new();
+
+  PACK_ITEMS
+    .get_or_init(|| {
+      let mut packs: HashMap<i64, Vec<QuestRewardItem>> = HashMap::new();
+      for item in get_master_manager().try_get_master("pack").into_iter().flatten() {
+        packs
+          .entry(parse_i64(&item["pack_id"]))
+          .or_default()
+          .push(QuestRewardItem {
+            item_type: parse_i64(&item["item_type"]) as i32,
+            item_id: parse_i64(&item["item_id"]),
+            item_num: parse_i64(&item["item_num"]) as i32,
+            item_rare: false,
+            probability: None,
+          });
+      }
+      packs
+    })
+    .get(&pack_id)
+    .map_or(&[], |items| items.as_slice())
+}
+
+#[derive(Debug, Clone)]
+pub struct UserShopPurchase {
+  pub buy_count: i32,
+  pub last_bought_at: DateTime<Utc>,
+}
+
+impl UserShopPurchase {
+  /// Purchases counted towards the limit of [prototype] at [now].
+  pub fn current_count(&self, prototype: &ShopItemPrototype, now: DateTime<Utc>, settings: &DailyResetSettings) -> i32 {
+    match prototype.limit_kind.period_start(now, settings) {
+      Some(start) if self.last_bought_at < start => 0,
+      _ => self.buy_count,
+    }
+  }
+}
+
+pub struct FetchUserShopPurchases<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserShopPurchases<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select shop_item_id, buy_count, last_bought_at
+        from user_shop_purchases
+        where user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<HashMap<i64, UserShopPurchase>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| {
+          let purchase = UserShopPurchase {
+            buy_count: row.get("buy_count"),
+            last_bought_at: row.get("last_bought_at"),
+          };
+          (row.get("shop_item_id"), purchase)
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Sets the buy count of the current period, see [UserShopPurchase::current_count].
+pub struct UpdateUserShopPurchase<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> UpdateUserShopPurchase<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_shop_purchases (user_id, shop_item_id, buy_count)
+        values ($1, $2, $3)
+        on conflict (user_id, shop_item_id)
+          do update
+          set buy_count = excluded.buy_count,
+              last_bought_at = now()
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, shop_item_id: i64, buy_count: i32) -> anyhow::Result<()> {
+    self
+      .executor
+      .client()
+      .execute(&self.statement, &[&user_id, &shop_item_id, &buy_count])
+      .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  #[test]
+  fn test_price_total() {
+    let price = ShopPrice { first: 0, second: 50 };
+
+    assert_eq!(price.total(0, 1), Some(0));
+    assert_eq!(price.total(0, 3), Some(100));
+    assert_eq!(price.total(2, 2), Some(100));
+    assert_eq!(price.total(2, i32::MAX), None);
+  }
+
+  #[test]
+  fn test_limit_periods() {
+    // Wednesday
+    let now = Utc.with_ymd_and_hms(2026, 1, 14, 15, 30, 0).unwrap();
+    let settings = DailyResetSettings::default();
+
+    assert_eq!(
+      ShopLimitKind::Weekly.period_start(now, &settings),
+      Some(Utc.with_ymd_and_hms(2026, 1, 12, 0, 0, 0).unwrap())
+    );
+    assert_eq!(
+      ShopLimitKind::Monthly.next_reset(now, &settings),
+      Some(Utc.with_ymd_and_hms(2026, 2, 1, 0, 0, 0).unwrap())
+    );
+    assert_eq!(
+      ShopLimitKind::Daily.next_reset(now, &DailyResetSettings { hour: 16 }),
+      Some(Utc.with_ymd_and_hms(2026, 1, 14, 16, 0, 0).unwrap())
+    );
+    assert_eq!(ShopLimitKind::BuyLimit.next_reset(now, &settings), None);
+  }
+}
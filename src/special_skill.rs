@@ -0,0 +1,206 @@
+//! Character special (SP) skills from `skill_sp*` masters.
+//!
+//! Skills of a group share a level. The level cap of a group depends on its `levelup_place`: affinity groups
+//! follow `sp_lv` of `character_intimacy_details` at the current affinity rank, trial groups follow the
+//! highest `sp_lv` of released Trial of the Ancients stages. `skill_sp_details` has no costs, leveling only
+//! checks the cap and `unlock_intimacy_lv` of the new level.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use tokio_postgres::Statement;
+
+use crate::api::master_all::{get_master_manager, parse_i64};
+use crate::database::QueryExecutor;
+use crate::user::id::UserId;
+
+/// Where levels of a skill group come from, `levelup_place` of `skill_sp_group` master.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SpSkillLevelUpPlace {
+  Affinity,
+  Trial,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpSkillPrototype {
+  pub skill_id: i64,
+  pub character_id: i64,
+  pub group_id: i32,
+  pub level_up_place: SpSkillLevelUpPlace,
+  /// Required affinity rank of each level, starting from level 1
+  pub unlock_ranks: Vec<i32>,
+}
+
+impl SpSkillPrototype {
+  pub fn max_level(&self) -> i32 {
+    self.unlock_ranks.len() as i32
+  }
+
+  /// Returns whether the skill can reach [level] with [cap] from [get_sp_level_cap] at affinity [rank].
+  pub fn can_reach(&self, level: i32, cap: i32, rank: i32) -> bool {
+    level >= 1
+      && level <= cap
+      && self
+        .unlock_ranks
+        .get(level as usize - 1)
+        .is_some_and(|unlock_rank| rank >= *unlock_rank)
+  }
+}
+
+/// Returns skills by skill ID, parsed once.
+fn get_sp_skills() -> &'static HashMap<i64, SpSkillPrototype> {
+  static SKILLS: OnceLock<HashMap<i64, SpSkillPrototype>> = OnceLock::new();
+
+  SKILLS.get_or_init(|| {
+    let masters = get_master_manager();
+    let places = masters
+      .get_master("skill_sp_group")
+      .iter()
+      .map(|group| {
+        let place = match parse_i64(&group["levelup_place"]) {
+          2 => SpSkillLevelUpPlace::Trial,
+          _ => SpSkillLevelUpPlace::Affinity,
+        };
+        (parse_i64(&group["skill_group_id"]) as i32, place)
+      })
+      .collect::<HashMap<_, _>>();
+
+    // Details have a row for each effect of a level
+    let mut unlock_ranks: HashMap<i64, HashMap<i32, i32>> = HashMap::new();
+    for details in masters.get_master("skill_sp_details") {
+      let level = parse_i64(&details["skill_lv"]) as i32;
+      let rank = parse_i64(&details["unlock_intimacy_lv"]) as i32;
+      unlock_ranks
+        .entry(parse_i64(&details["skill_id"]))
+        .or_default()
+        .insert(level, rank);
+    }
+
+    masters
+      .get_master("skill_sp")
+      .iter()
+      .map(|skill| {
+        let skill_id = parse_i64(&skill["skill_id"]);
+        let group_id = parse_i64(&skill["skill_group_id"]) as i32;
+        let mut ranks = unlock_ranks
+          .remove(&skill_id)
+          .unwrap_or_default()
+          .into_iter()
+          .collect::<Vec<_>>();
+        ranks.sort();
+
+        let prototype = SpSkillPrototype {
+          skill_id,
+          character_id: parse_i64(&skill["character_id"]),
+          group_id,
+          level_up_place: places.get(&group_id).copied().unwrap_or(SpSkillLevelUpPlace::Affinity),
+          unlock_ranks: ranks.into_iter().map(|(_, rank)| rank).collect(),
+        };
+        (skill_id, prototype)
+      })
+      .collect()
+  })
+}
+
+pub fn get_sp_skill(skill_id: i64) -> Option<&'static SpSkillPrototype> {
+  get_sp_skills().get(&skill_id)
+}
+
+/// Special skill a character starts with, `specialattack` of `character` master.
+pub fn get_default_sp_skill_id(character_id: i64) -> Option<i64> {
+  get_master_manager()
+    .get_master("character")
+    .iter()
+    .find(|character| parse_i64(&character["id"]) == character_id)
+    .map(|character| parse_i64(&character["specialattack"]))
+}
+
+/// Returns `sp_lv` of `character_intimacy_details` master for affinity skills of [character_id] at [rank].
+pub fn get_affinity_sp_level(character_id: i64, rank: i32) -> i32 {
+  static LEVELS: OnceLock<HashMap<(i64, i32), i32>> = OnceLock::new();
+
+  let levels = LEVELS.get_or_init(|| {
+    get_master_manager()
+      .get_master("character_intimacy_details")
+      .iter()
+      .map(|details| {
+        (
+          (
+            parse_i64(&details["chara_id"]),
+            parse_i64(&details["character_level"]) as i32,
+          ),
+          parse_i64(&details["sp_lv"]) as i32,
+        )
+      })
+      .collect()
+  });
+  levels.get(&(character_id, rank)).copied().unwrap_or(1)
+}
+
+/// Level cap of [skill], given [trial_sp_level] from released Trial of the Ancients stages of its group.
+pub fn get_sp_level_cap(skill: &SpSkillPrototype, rank: i32, trial_sp_level: i32) -> i32 {
+  match skill.level_up_place {
+    SpSkillLevelUpPlace::Affinity => get_affinity_sp_level(skill.character_id, rank),
+    SpSkillLevelUpPlace::Trial => trial_sp_level.max(1),
+  }
+}
+
+/// Sets the level of all skills of a group of a character.
+pub struct SetUserSpSkillGroupLevel<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> SetUserSpSkillGroupLevel<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_character_special_skills
+        set level = $4
+        where user_id = $1 and character_id = $2 and skill_id = any($3)
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, skill: &SpSkillPrototype, level: i32) -> anyhow::Result<u64> {
+    let skill_ids = get_sp_skills()
+      .values()
+      .filter(|other| other.character_id == skill.character_id && other.group_id == skill.group_id)
+      .map(|other| other.skill_id)
+      .collect::<Vec<_>>();
+    Ok(
+      self
+        .executor
+        .client()
+        .execute(&self.statement, &[&user_id, &skill.character_id, &skill_ids, &level])
+        .await?,
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_can_reach() {
+    let skill = SpSkillPrototype {
+      skill_id: 100001,
+      character_id: 100,
+      group_id: 10000,
+      level_up_place: SpSkillLevelUpPlace::Affinity,
+      unlock_ranks: vec![1, 1, 5],
+    };
+
+    assert_eq!(skill.max_level(), 3);
+    assert!(skill.can_reach(2, 2, 1));
+    assert!(!skill.can_reach(3, 2, 5));
+    assert!(!skill.can_reach(3, 3, 4));
+    assert!(skill.can_reach(3, 3, 5));
+    assert!(!skill.can_reach(4, 10, 50));
+    assert!(!skill.can_reach(0, 10, 50));
+  }
+}
@@ -0,0 +1,380 @@
+//! Stories from `story_*` masters: unlock conditions, read state, dialogue selections and first-read rewards.
+//!
+//! A story is unlocked once the previous story (`before_id`) is read and its quest, member and affinity rank
+//! conditions are met. Reading a story with a `pack_id` for the first time grants the pack contents,
+//! or [StorySettings::first_read_quartz] if the `pack` master is not dumped.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use serde_json::Value;
+use tokio_postgres::types::Json;
+use tokio_postgres::Statement;
+use tracing::debug;
+
+use crate::api::master_all::{get_master_manager, parse_i64_or_zero};
+use crate::api::quest::QuestRewardItem;
+use crate::api::story::{StoryStatus, StoryType};
+use crate::api::RemoteDataItemType;
+use crate::database::QueryExecutor;
+use crate::level::get_intimacy_level_calculator;
+use crate::member::FetchUserMembers;
+use crate::settings::StorySettings;
+use crate::shop::get_pack_items;
+use crate::user::id::UserId;
+use crate::user::quest_progress::{FetchUserClearedQuests, QuestKind};
+
+#[derive(Debug, Clone)]
+pub struct StoryPrototype {
+  /// Position in [get_stories], sent to the client as `user_story_id`
+  pub user_story_id: i32,
+  pub story_type: StoryType,
+  pub story_id: i64,
+  /// Story that must be read first, zero if none
+  pub before_id: i64,
+  pub unlock_quest: Option<(QuestKind, i64)>,
+  /// Member that must be owned, for member and gacha stories
+  pub unlock_member_id: Option<i64>,
+  /// Required affinity rank of each character
+  pub intimacy: Vec<(i64, i32)>,
+  /// Required sum of affinity ranks of all characters
+  pub total_intimacy: i32,
+  pub pack_id: i64,
+}
+
+impl StoryPrototype {
+  fn parse(story: &Value, story_type: StoryType, quest_kind: QuestKind) -> Self {
+    let unlock_member_id = match story_type {
+      // Gacha stories are about the member in their icon
+      StoryType::Gacha if story["list_icon_type"].as_str() == Some("member") => {
+        Some(parse_i64_or_zero(&story["list_icon_id"]))
+      }
+      _ => Some(parse_i64_or_zero(&story["unlock_member_id"])),
+    };
+
+    Self {
+      user_story_id: 0,
+      story_type,
+      story_id: parse_i64_or_zero(&story["id"]),
+      before_id: parse_i64_or_zero(&story["before_id"]),
+      unlock_quest: Some((quest_kind, parse_i64_or_zero(&story["unlock_quest_id"]))).filter(|(_, id)| *id != 0),
+      unlock_member_id: unlock_member_id.filter(|id| *id != 0),
+      intimacy: (1..=3)
+        .map(|index| {
+          (
+            parse_i64_or_zero(&story[format!("chara{index}")]),
+            parse_i64_or_zero(&story[format!("chara{index}_intimacy")]) as i32,
+          )
+        })
+        .filter(|(character_id, _)| *character_id != 0)
+        .collect(),
+      total_intimacy: parse_i64_or_zero(&story["total_intimacy"]) as i32,
+      pack_id: parse_i64_or_zero(&story["pack_id"]),
+    }
+  }
+
+  pub fn status(&self, state: &UserStoryState) -> StoryStatus {
+    if state.read.contains_key(&(self.story_type, self.story_id)) {
+      return StoryStatus::Done;
+    }
+
+    let is_unlocked = (self.before_id == 0 || state.read.contains_key(&(self.story_type, self.before_id)))
+      && self
+        .unlock_quest
+        .is_none_or(|(kind, quest_id)| state.cleared_quests(kind).contains(&quest_id))
+      && self
+        .unlock_member_id
+        .is_none_or(|member_id| state.members.contains(&member_id))
+      && self
+        .intimacy
+        .iter()
+        .all(|(character_id, rank)| state.intimacy_rank(*character_id) >= *rank)
+      && state.total_intimacy_rank() >= self.total_intimacy;
+    if is_unlocked {
+      StoryStatus::Unlocked
+    } else {
+      StoryStatus::Locked
+    }
+  }
+
+  /// Items received for reading the story for the first time.
+  pub fn first_read_rewards(&self, settings: &StorySettings) -> Vec<QuestRewardItem> {
+    if self.pack_id == 0 {
+      return Vec::new();
+    }
+
+    let items = get_pack_items(self.pack_id);
+    if !items.is_empty() {
+      return items.to_vec();
+    }
+    debug!(?self.story_id, ?self.pack_id, "story has no known pack contents, granting quartz");
+    if settings.first_read_quartz <= 0 {
+      return Vec::new();
+    }
+    vec![QuestRewardItem {
+      item_type: RemoteDataItemType::RealMoneyFree.into(),
+      item_id: 0,
+      item_num: settings.first_read_quartz,
+      item_rare: false,
+      probability: None,
+    }]
+  }
+}
+
+/// Returns all stories, parsed once, in the order of `user_story_id`.
+pub fn get_stories() -> &'static [StoryPrototype] {
+  static STORIES: OnceLock<Vec<StoryPrototype>> = OnceLock::new();
+
+  STORIES.get_or_init(|| {
+    let masters = [
+      ("story_main", StoryType::Main, QuestKind::Main),
+      ("story_reminiscence", StoryType::Reminiscence, QuestKind::Main),
+      ("story_etc", StoryType::Other, QuestKind::Main),
+      ("story_event", StoryType::Event, QuestKind::Event),
+      ("story_gacha", StoryType::Gacha, QuestKind::Main),
+      ("story_member", StoryType::Member, QuestKind::Main),
+      ("story_unique", StoryType::Reminiscence, QuestKind::Event),
+    ];
+
+    masters
+      .into_iter()
+      .flat_map(|(master, story_type, quest_kind)| {
+        get_master_manager()
+          .get_master(master)
+          .iter()
+          .map(|story| StoryPrototype::parse(story, story_type, quest_kind))
+          .collect::<Vec<_>>()
+      })
+      .enumerate()
+      .map(|(index, story)| StoryPrototype {
+        user_story_id: index as i32,
+        ..story
+      })
+      .collect()
+  })
+}
+
+pub fn get_story(user_story_id: i32) -> Option<&'static StoryPrototype> {
+  get_stories().iter().find(|story| story.user_story_id == user_story_id)
+}
+
+/// Parses `selections` request parameter, e.g. `[[1,0],[0,1]]`. A flat list is a single selection.
+pub fn parse_selections(value: &str) -> Vec<Vec<bool>> {
+  let as_bool = |value: &Value| {
+    value
+      .as_bool()
+      .unwrap_or_else(|| value.as_i64().is_some_and(|value| value != 0))
+  };
+
+  match serde_json::from_str::<Value>(value) {
+    Ok(Value::Array(items)) if items.iter().all(Value::is_array) => items
+      .iter()
+      .map(|selection| selection.as_array().unwrap().iter().map(as_bool).collect())
+      .collect(),
+    Ok(Value::Array(items)) if !items.is_empty() => vec![items.iter().map(as_bool).collect()],
+    _ => Vec::new(),
+  }
+}
+
+/// Everything that decides story status, see [StoryPrototype::status].
+#[derive(Debug, Default)]
+pub struct UserStoryState {
+  /// Selections of read stories
+  pub read: HashMap<(StoryType, i64), Vec<Vec<bool>>>,
+  pub main_quests: HashSet<i64>,
+  pub event_quests: HashSet<i64>,
+  pub members: HashSet<i64>,
+  /// Affinity rank by character
+  pub intimacy: HashMap<i64, i32>,
+}
+
+impl UserStoryState {
+  pub async fn fetch<'a>(executor: impl Into<QueryExecutor<'a>> + Copy, user_id: UserId) -> anyhow::Result<Self> {
+    let cleared_quests = FetchUserClearedQuests::new(executor).await?;
+    let calculator = get_intimacy_level_calculator();
+    Ok(Self {
+      read: FetchUserStories::new(executor).await?.run(user_id).await?,
+      main_quests: cleared_quests.run(user_id, QuestKind::Main).await?,
+      event_quests: cleared_quests.run(user_id, QuestKind::Event).await?,
+      members: FetchUserMembers::new(executor)
+        .await?
+        .run(user_id)
+        .await?
+        .into_iter()
+        .map(|member| member.prototype.id)
+        .collect(),
+      intimacy: FetchUserCharacterIntimacy::new(executor)
+        .await?
+        .run(user_id)
+        .await?
+        .into_iter()
+        .map(|(character_id, intimacy)| (character_id, calculator.get_level(intimacy)))
+        .collect(),
+    })
+  }
+
+  fn cleared_quests(&self, kind: QuestKind) -> &HashSet<i64> {
+    match kind {
+      QuestKind::Event => &self.event_quests,
+      _ => &self.main_quests,
+    }
+  }
+
+  fn intimacy_rank(&self, character_id: i64) -> i32 {
+    self.intimacy.get(&character_id).copied().unwrap_or(1)
+  }
+
+  fn total_intimacy_rank(&self) -> i32 {
+    self.intimacy.values().sum()
+  }
+}
+
+/// Returns affinity (intimacy experience) by character.
+pub struct FetchUserCharacterIntimacy<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserCharacterIntimacy<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select character_id, intimacy
+        from user_characters
+        where user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<HashMap<i64, i32>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| (row.get("character_id"), row.get("intimacy")))
+        .collect(),
+    )
+  }
+}
+
+/// Adds affinity (intimacy experience) to a character without exceeding [max], returns the new affinity or
+/// [None] if the character is not owned.
+pub struct AddUserCharacterIntimacy<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> AddUserCharacterIntimacy<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        update user_characters
+        set intimacy = least(intimacy + $3, $4)
+        where user_id = $1 and character_id = $2
+        returning intimacy
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, character_id: i64, amount: i32, max: i32) -> anyhow::Result<Option<i32>> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &character_id, &amount, &max])
+      .await?;
+    Ok(row.map(|row| row.get("intimacy")))
+  }
+}
+
+/// Returns selections of read stories.
+pub struct FetchUserStories<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserStories<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select story_type, story_id, selections
+        from user_stories
+        where user_id = $1
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId) -> anyhow::Result<HashMap<(StoryType, i64), Vec<Vec<bool>>>> {
+    let rows = self.executor.client().query(&self.statement, &[&user_id]).await?;
+    Ok(
+      rows
+        .iter()
+        .filter_map(|row| {
+          let story_type = StoryType::try_from(row.get::<_, i32>("story_type")).ok()?;
+          let Json(selections) = row.get("selections");
+          Some(((story_type, row.get("story_id")), selections))
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Marks a story as read, keeping the latest selections. Returns whether it is the first read.
+pub struct ReadUserStory<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> ReadUserStory<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_stories (user_id, story_type, story_id, selections)
+        values ($1, $2, $3, $4)
+        on conflict (user_id, story_type, story_id)
+          do update
+          set selections = excluded.selections,
+              last_read_at = now()
+        returning (xmax = 0) as first_read
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, story: &StoryPrototype, selections: &[Vec<bool>]) -> anyhow::Result<bool> {
+    let row = self
+      .executor
+      .client()
+      .query_one(
+        &self.statement,
+        &[&user_id, &(story.story_type as i32), &story.story_id, &Json(selections)],
+      )
+      .await?;
+    Ok(row.get("first_read"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_selections() {
+    assert_eq!(parse_selections("[]"), Vec::<Vec<bool>>::new());
+    assert_eq!(parse_selections("[1,0]"), vec![vec![true, false]]);
+    assert_eq!(
+      parse_selections("[[0,1],[true,false]]"),
+      vec![vec![false, true], vec![true, false]]
+    );
+  }
+}
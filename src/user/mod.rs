@@ -2,3 +2,4 @@ pub mod id;
 pub mod session;
 pub mod uuid;
 pub mod overrides;
+pub mod quest_progress;
@@ -0,0 +1,225 @@
+use crate::database::QueryExecutor;
+use crate::user::id::UserId;
+use tokio_postgres::{Row, Statement};
+use std::collections::{HashMap, HashSet};
+
+/// Quest category the stage belongs to, stage IDs are only unique within a single category.
+/// Values match `quest_type` of `campaign` master.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(i32)]
+pub enum QuestKind {
+  Main = 1,
+  Hunting = 2,
+  Event = 4,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuestProgress {
+  pub clear_count: i32,
+  /// Main mission and two sub missions ("crowns")
+  pub tasks: [bool; 3],
+}
+
+impl QuestProgress {
+  fn from_row(row: &Row) -> Self {
+    Self {
+      clear_count: row.get("clear_count"),
+      tasks: [row.get("task1"), row.get("task2"), row.get("task3")],
+    }
+  }
+
+  /// Whether the stage was cleared with all three crowns, required to use Skip Tickets.
+  pub fn is_all_clear(&self) -> bool {
+    self.clear_count > 0 && self.tasks.iter().all(|task| *task)
+  }
+}
+
+/// Converts `clearquestmission` battle result parameter (e.g. `[12,0,15]`) to completed tasks.
+pub fn tasks_from_clear_missions(missions: &[i32]) -> [bool; 3] {
+  let mut tasks = [false; 3];
+  for (task, mission) in tasks.iter_mut().zip(missions) {
+    *task = *mission != 0;
+  }
+  tasks
+}
+
+pub struct FetchUserQuestProgress<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserQuestProgress<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select clear_count, task1, task2, task3
+        from user_quest_progress
+        where user_id = $1 and quest_kind = $2 and quest_id = $3
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, kind: QuestKind, quest_id: i64) -> anyhow::Result<QuestProgress> {
+    let row = self
+      .executor
+      .client()
+      .query_opt(&self.statement, &[&user_id, &(kind as i32), &quest_id])
+      .await?;
+    Ok(row.map_or_else(QuestProgress::default, |row| QuestProgress::from_row(&row)))
+  }
+}
+
+/// Returns progress of all cleared event stages, by quest ID.
+pub struct FetchUserEventQuestProgress<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserEventQuestProgress<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select quest_id, clear_count, task1, task2, task3
+        from user_quest_progress
+        where user_id = $1 and quest_kind = $2 and event_id = $3
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, event_id: i64) -> anyhow::Result<HashMap<i64, QuestProgress>> {
+    let rows = self
+      .executor
+      .client()
+      .query(&self.statement, &[&user_id, &(QuestKind::Event as i32), &event_id])
+      .await?;
+    Ok(
+      rows
+        .iter()
+        .map(|row| (row.get("quest_id"), QuestProgress::from_row(row)))
+        .collect(),
+    )
+  }
+}
+
+/// Returns IDs of all cleared stages of [QuestKind].
+pub struct FetchUserClearedQuests<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserClearedQuests<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select quest_id
+        from user_quest_progress
+        where user_id = $1 and quest_kind = $2 and clear_count > 0
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, kind: QuestKind) -> anyhow::Result<HashSet<i64>> {
+    let rows = self
+      .executor
+      .client()
+      .query(&self.statement, &[&user_id, &(kind as i32)])
+      .await?;
+    Ok(rows.iter().map(|row| row.get("quest_id")).collect())
+  }
+}
+
+/// Returns the number of completed tasks ("crowns") over all stages of [QuestKind].
+pub struct FetchUserQuestTaskCount<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> FetchUserQuestTaskCount<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        select coalesce(sum(task1::integer + task2::integer + task3::integer), 0)::integer
+        from user_quest_progress
+        where user_id = $1 and quest_kind = $2
+      "#).await?,
+      executor,
+    })
+  }
+
+  pub async fn run(&self, user_id: UserId, kind: QuestKind) -> anyhow::Result<i32> {
+    let row = self
+      .executor
+      .client()
+      .query_one(&self.statement, &[&user_id, &(kind as i32)])
+      .await?;
+    Ok(row.get(0))
+  }
+}
+
+/// Records a stage clear. Tasks are only ever set, never reset, so the best result is kept.
+pub struct RecordUserQuestClear<'a> {
+  executor: QueryExecutor<'a>,
+  statement: Statement,
+}
+
+impl<'a> RecordUserQuestClear<'a> {
+  pub async fn new(executor: impl Into<QueryExecutor<'a>>) -> anyhow::Result<Self> {
+    let executor = executor.into();
+    Ok(Self {
+      #[rustfmt::skip]
+      statement: executor.prepare(/* language=postgresql */ r#"
+        insert into user_quest_progress (user_id, quest_kind, event_id, quest_id, clear_count, task1, task2, task3)
+        values ($1, $2, $3, $4, $5, $6, $7, $8)
+        on conflict (user_id, quest_kind, quest_id)
+          do update
+          set clear_count = user_quest_progress.clear_count + excluded.clear_count,
+              task1 = user_quest_progress.task1 or excluded.task1,
+              task2 = user_quest_progress.task2 or excluded.task2,
+              task3 = user_quest_progress.task3 or excluded.task3
+        returning clear_count, task1, task2, task3
+      "#).await?,
+      executor,
+    })
+  }
+
+  /// [event_id] is zero for non-event quests.
+  pub async fn run(
+    &self,
+    user_id: UserId,
+    kind: QuestKind,
+    event_id: i64,
+    quest_id: i64,
+    tasks: [bool; 3],
+    count: i32,
+  ) -> anyhow::Result<QuestProgress> {
+    let row = self
+      .executor
+      .client()
+      .query_one(
+        &self.statement,
+        &[
+          &user_id,
+          &(kind as i32),
+          &event_id,
+          &quest_id,
+          &count,
+          &tasks[0],
+          &tasks[1],
+          &tasks[2],
+        ],
+      )
+      .await?;
+    Ok(QuestProgress::from_row(&row))
+  }
+}